@@ -10,5 +10,6 @@ pub mod error;
 pub mod method;
 pub mod address_family;
 pub mod nexthop;
+pub mod mpls;
 
 pub mod acl;
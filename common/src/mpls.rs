@@ -0,0 +1,124 @@
+//
+// ReZe.Rs - Common
+//   Copyright (C) 2018-2020 Toshiaki Takada
+//
+// MPLS label.
+//
+
+use std::fmt;
+
+use serde::Serialize;
+use serde::Serializer;
+
+/// Reserved label values.
+pub const MPLS_LABEL_IPV4_EXPLICIT_NULL: u32 = 0;
+pub const MPLS_LABEL_IPV6_EXPLICIT_NULL: u32 = 2;
+pub const MPLS_LABEL_IMPLICIT_NULL: u32 = 3;
+
+/// Smallest label not reserved.
+pub const MPLS_LABEL_UNRESERVED_MIN: u32 = 16;
+
+/// Largest label value, 20 bits.
+pub const MPLS_LABEL_MAX: u32 = 0xFFFFF;
+
+/// Label operation applied to packets forwarded to a nexthop.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum LabelOp {
+
+    /// Impose label stack on IP packet.
+    Push(Vec<u32>),
+
+    /// Replace top label with label stack.
+    Swap(Vec<u32>),
+
+    /// Remove top label.
+    Pop,
+}
+
+impl LabelOp {
+
+    /// Return outgoing label stack, top first.
+    pub fn labels(&self) -> &[u32] {
+        match self {
+            LabelOp::Push(labels) | LabelOp::Swap(labels) => labels,
+            LabelOp::Pop => &[],
+        }
+    }
+
+    /// Construct operation on labeled packet from outgoing label string,
+    /// implicit-null means the top label is popped.
+    pub fn from_out_label_str(s: &str) -> Option<LabelOp> {
+        match labels_from_str(s) {
+            Some(ref labels) if labels == &[MPLS_LABEL_IMPLICIT_NULL] => Some(LabelOp::Pop),
+            Some(labels) => Some(LabelOp::Swap(labels)),
+            None => None,
+        }
+    }
+}
+
+impl fmt::Display for LabelOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LabelOp::Push(labels) => write!(f, "push {}", labels_to_string(labels)),
+            LabelOp::Swap(labels) => write!(f, "swap {}", labels_to_string(labels)),
+            LabelOp::Pop => write!(f, "pop"),
+        }
+    }
+}
+
+impl Serialize for LabelOp {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// Parse single label, either value or reserved label name.
+pub fn label_from_str(s: &str) -> Option<u32> {
+    match s {
+        "implicit-null" => Some(MPLS_LABEL_IMPLICIT_NULL),
+        "explicit-null" => Some(MPLS_LABEL_IPV4_EXPLICIT_NULL),
+        _ => match s.parse::<u32>() {
+            Ok(label) if label <= MPLS_LABEL_MAX => Some(label),
+            _ => None,
+        }
+    }
+}
+
+/// Parse label stack delimited by "/", such as "100/200".
+pub fn labels_from_str(s: &str) -> Option<Vec<u32>> {
+    let mut labels = Vec::new();
+
+    for label in s.split('/') {
+        labels.push(label_from_str(label)?);
+    }
+
+    Some(labels)
+}
+
+/// Return label stack string delimited by "/".
+pub fn labels_to_string(labels: &[u32]) -> String {
+    labels.iter().map(|label| label.to_string()).collect::<Vec<String>>().join("/")
+}
+
+///
+/// Unit Tests.
+///
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_labels_from_str() {
+        assert_eq!(labels_from_str("100"), Some(vec![100]));
+        assert_eq!(labels_from_str("100/200"), Some(vec![100, 200]));
+        assert_eq!(labels_from_str("implicit-null"), Some(vec![3]));
+        assert_eq!(labels_from_str("1048576"), None);
+        assert_eq!(labels_from_str("100/"), None);
+
+        assert_eq!(LabelOp::from_out_label_str("implicit-null"), Some(LabelOp::Pop));
+        assert_eq!(LabelOp::from_out_label_str("16/17"), Some(LabelOp::Swap(vec![16, 17])));
+        assert_eq!(LabelOp::Push(vec![16, 17]).to_string(), "push 16/17");
+    }
+}
@@ -24,8 +24,17 @@ where T: Addressable
     /// Interface Name.
     Ifname(String),
 
+    /// IP Address with outgoing Interface Name.
+    AddressIfname(T, String),
+
     /// Network Prefix - TBD: floating nexthop.
     Network(Prefix<T>),
+
+    /// Null0, silently discard packets.
+    Blackhole,
+
+    /// Null0, discard packets and emit ICMP unreachable.
+    Reject,
 }
 
 impl<T> Serialize for Nexthop<T>
@@ -34,13 +43,28 @@ where T: Addressable
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where S: Serializer
     {
-        let mut s = serializer.serialize_struct("Nexthop", 1)?;
-        let (name, str) = match self {
-            Nexthop::<T>::Address(addr) => ("address", addr.to_string()),
-            Nexthop::<T>::Ifname(ifname) => ("interface", ifname.to_string()),
-            Nexthop::<T>::Network(prefix) => ("network", prefix.to_string()),
-        };
-        s.serialize_field(name, &str)?;
+        let mut s = serializer.serialize_struct("Nexthop", 2)?;
+        match self {
+            Nexthop::<T>::Address(addr) => {
+                s.serialize_field("address", &addr.to_string())?;
+            },
+            Nexthop::<T>::Ifname(ifname) => {
+                s.serialize_field("interface", &ifname.to_string())?;
+            },
+            Nexthop::<T>::AddressIfname(addr, ifname) => {
+                s.serialize_field("address", &addr.to_string())?;
+                s.serialize_field("interface", &ifname.to_string())?;
+            },
+            Nexthop::<T>::Network(prefix) => {
+                s.serialize_field("network", &prefix.to_string())?;
+            },
+            Nexthop::<T>::Blackhole => {
+                s.serialize_field("blackhole", "Null0")?;
+            },
+            Nexthop::<T>::Reject => {
+                s.serialize_field("reject", "Null0")?;
+            },
+        }
         s.end()
     }
 }
@@ -65,6 +89,37 @@ where T: Addressable
     pub fn from_ifname(ifname: &str) -> Nexthop<T> {
         Nexthop::<T>::Ifname(String::from(ifname))
     }
+
+    /// Construct Nexthop from IP address and Interface name.
+    pub fn from_address_ifname(address: &T, ifname: &str) -> Nexthop<T> {
+        Nexthop::<T>::AddressIfname(address.clone(), String::from(ifname))
+    }
+
+    /// Return gateway address if any.
+    pub fn address(&self) -> Option<&T> {
+        match self {
+            Nexthop::<T>::Address(address) => Some(address),
+            Nexthop::<T>::AddressIfname(address, _) => Some(address),
+            _ => None,
+        }
+    }
+
+    /// Return outgoing interface name if any.
+    pub fn ifname(&self) -> Option<&str> {
+        match self {
+            Nexthop::<T>::Ifname(ifname) => Some(ifname),
+            Nexthop::<T>::AddressIfname(_, ifname) => Some(ifname),
+            _ => None,
+        }
+    }
+
+    /// Return true if the nexthop discards packets.
+    pub fn is_null(&self) -> bool {
+        match self {
+            Nexthop::<T>::Blackhole | Nexthop::<T>::Reject => true,
+            _ => false,
+        }
+    }
 }
 
 impl<T> fmt::Display for Nexthop<T>
@@ -78,9 +133,18 @@ where T: Addressable
             Nexthop::<T>::Ifname(ifname) => {
                 write!(f, "{}", ifname)
             },
+            Nexthop::<T>::AddressIfname(address, ifname) => {
+                write!(f, "{:?} {}", address, ifname)
+            },
             Nexthop::<T>::Network(prefix) => {
                 write!(f, "{:?}", prefix)
             },
+            Nexthop::<T>::Blackhole => {
+                write!(f, "Null0")
+            },
+            Nexthop::<T>::Reject => {
+                write!(f, "Null0 reject")
+            },
         }
     }
 }
@@ -96,9 +160,18 @@ where T: Addressable
             Nexthop::<T>::Ifname(ifname) => {
                 write!(f, "{}", ifname)
             },
+            Nexthop::<T>::AddressIfname(address, ifname) => {
+                write!(f, "{:?} {}", address, ifname)
+            },
             Nexthop::<T>::Network(prefix) => {
                 write!(f, "{:?}", prefix)
             },
+            Nexthop::<T>::Blackhole => {
+                write!(f, "Null0")
+            },
+            Nexthop::<T>::Reject => {
+                write!(f, "Null0 reject")
+            },
         }
     }
 }
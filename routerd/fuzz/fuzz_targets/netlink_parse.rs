@@ -0,0 +1,17 @@
+//
+// ReZe.Rs - Router Daemon
+//   Copyright (C) 2018-2020 Toshiaki Takada
+//
+// Fuzz target for Netlink message parser.
+//   Run from routerd directory, seeded with captured dumps:
+//     cargo +nightly fuzz run netlink_parse fuzz/corpus/netlink_parse tests/fixtures/netlink
+//
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use routerd::zebra::linux::parse::parse_buffer;
+
+fuzz_target!(|data: &[u8]| {
+    parse_buffer(data);
+});
@@ -71,7 +71,7 @@ impl ProtocolMaster {
                  sender_p2n: mpsc::Sender<ProtoToNexus>,
                  receiver_n2p: mpsc::Receiver<NexusToProto>,
                  sender_p2z: mpsc::Sender<ProtoToZebra>,
-                 _receiver_z2p: mpsc::Receiver<ZebraToProto>) {
+                 receiver_z2p: mpsc::Receiver<ZebraToProto>) {
         if let Some(ref mut inner) = *self.inner.borrow_mut() {
             self.sender_p2n.borrow_mut().replace(sender_p2n);
             self.sender_p2z.borrow_mut().replace(sender_p2z);
@@ -79,10 +79,13 @@ impl ProtocolMaster {
             // Take care of protocol specific stuff.
             inner.start();
 
-            // TODO take care of receiver_z2p.try_recv()
-
             // 
             'main: loop {
+                // Process ZebraToProto messages through the channel.
+                while let Ok(d) = receiver_z2p.try_recv() {
+                    inner.zebra_message(d);
+                }
+
                 while let Ok(d) = receiver_n2p.try_recv() {
                     match d {
                         NexusToProto::TimerExpiration(token) => {
@@ -142,6 +145,15 @@ impl ProtocolMaster {
         }
     }
 
+    /// Send message to zebra, such as route add/delete and redistribution request.
+    pub fn zebra_send(&self, msg: ProtoToZebra) {
+        if let Some(ref sender) = *self.sender_p2z.borrow() {
+            if let Err(err) = sender.send(msg) {
+                error!("Send message to zebra {:?}", err);
+            }
+        }
+    }
+
     /// Set inner to master.
     pub fn inner_set(&self, inner: Box<dyn MasterInner>) {
         self.inner.borrow_mut().replace(inner);
@@ -157,5 +169,9 @@ impl ProtocolMaster {
 pub trait MasterInner {
     fn start(&self);
 //    fn finish(&self);
+
+    /// Handle message from zebra, such as redistributed routes.
+    fn zebra_message(&self, _msg: ZebraToProto) {
+    }
 }
 
@@ -4,25 +4,260 @@
 //
 // Zebra Message
 // - ProtoToZebra
+//   - Protocol Registration
+//   - Route Add/Delete
+//   - Redistribute Add/Delete
+//   - Nexthop Register/Unregister
+//   - Neighbor Query
 //
 // - ZebraToProto
+//   - Interface Add/Delete/Up/Down
+//   - Interface Address Add/Delete
+//   - Redistributed Route Add/Delete
+//   - Nexthop Update
+//   - Neighbor
 //
 
 use std::sync::mpsc;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use rtable::prefix::*;
+
+use common::nexthop::*;
 
 use crate::core::protocols::ProtocolType;
+use crate::zebra::rib::RibType;
+use crate::zebra::rib::RibSubType;
+
+/// Route exchanged between Zebra and Protocol.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ZebraRoute<T: Addressable> {
+
+    /// Destination prefix.
+    pub prefix: Prefix<T>,
+
+    /// Kernel table ID of VRF the route belongs to.
+    pub table_id: u32,
+
+    /// RIB type.
+    pub rib_type: RibType,
+
+    /// Administrative distance.
+    pub distance: u8,
+
+    /// Metric.
+    pub metric: u32,
+
+    /// Route tag.
+    pub tag: u32,
+
+    /// Protocol sub-type.
+    pub sub_type: RibSubType,
+
+    /// Router-ID of the originator, if protocol provides.
+    pub router_id: Option<Ipv4Addr>,
+
+    /// Nexthops.
+    pub nexthops: Vec<Nexthop<T>>,
+}
+
+/// Interface exchanged between Zebra and Protocol.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ZebraInterface {
+
+    /// Interface index.
+    pub index: i32,
+
+    /// Interface name.
+    pub name: String,
+
+    /// Hardware address.
+    pub hwaddr: [u8; 6],
+
+    /// MTU.
+    pub mtu: u32,
+
+    /// Interface flags.
+    pub flags: u32,
+
+    /// Operationally up.
+    pub up: bool,
+
+    /// Kernel table ID of VRF the interface belongs to.
+    pub table_id: u32,
+}
+
+/// Interface address exchanged between Zebra and Protocol.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ZebraInterfaceAddr<T: Addressable> {
+
+    /// Interface index.
+    pub index: i32,
+
+    /// Address prefix.
+    pub address: Prefix<T>,
+
+    /// Destination address prefix for peer.
+    pub destination: Option<Prefix<T>>,
+
+    /// Secondary address.
+    pub secondary: bool,
+}
+
+/// Resolution of tracked nexthop address.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ZebraNexthop<T: Addressable> {
+
+    /// Tracked address.
+    pub address: T,
+
+    /// Prefix of the route resolving the address, None if unreachable.
+    pub prefix: Option<Prefix<T>>,
+
+    /// RIB type of the resolving route.
+    pub rib_type: Option<RibType>,
+
+    /// Metric of the resolving route.
+    pub metric: u32,
+
+    /// Nexthops the address is resolved to.
+    pub nexthops: Vec<Nexthop<T>>,
+}
+
+/// Neighbor reachability of nexthop, answer to query.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ZebraNeighbor<T: Addressable> {
+
+    /// Queried nexthop.
+    pub nexthop: Nexthop<T>,
+
+    /// Interface index the neighbor is found on, None if not found.
+    pub index: Option<i32>,
+
+    /// Link layer address, if resolved.
+    pub hwaddr: Option<[u8; 6]>,
 
+    /// Packets can be forwarded to the neighbor.
+    pub reachable: bool,
+}
+
+/// Message from Protocol to Zebra.
 pub enum ProtoToZebra {
-    // Register ZebraToProto channel
+    /// Register ZebraToProto channel.
+    ///   Protocol registers its channel to receive messages from Zebra,
+    ///   and is notified all interfaces and addresses immediately.
+    ///     ProtocolType: Type of protocol
+    ///     Sender: ZebraToProto channel
     RegisterProto((ProtocolType, mpsc::Sender<ZebraToProto>)),
 
-    RouteAdd(i32),
-    RouteLookup(i32)
+    /// Unregister protocol.
+    ///   Routes added and nexthops registered by the protocol are withdrawn,
+    ///   as well as when Zebra finds its channel disconnected.
+    ///     ProtocolType: Type of protocol
+    UnregisterProto(ProtocolType),
+
+    /// Add IPv4 route.
+    ///   Protocol adds or replaces a route in RIB of the VRF for the table ID, keyed by prefix,
+    ///   RIB type and distance. RIB type must be the one originated by the protocol.
+    ///     ProtocolType: Type of protocol
+    ///     ZebraRoute: Route to be added
+    RouteAddIpv4((ProtocolType, ZebraRoute<Ipv4Addr>)),
+
+    /// Delete IPv4 route.
+    ///   Protocol withdraws all routes of the RIB type for the prefix, nexthops are ignored.
+    RouteDeleteIpv4((ProtocolType, ZebraRoute<Ipv4Addr>)),
+
+    /// Add IPv6 route.
+    RouteAddIpv6((ProtocolType, ZebraRoute<Ipv6Addr>)),
+
+    /// Delete IPv6 route.
+    RouteDeleteIpv6((ProtocolType, ZebraRoute<Ipv6Addr>)),
+
+    /// Subscribe redistribution.
+    ///   Protocol requests routes of the RIB type selected in Zebra RIB,
+    ///   and is notified current routes immediately, and changes afterwards.
+    ///     ProtocolType: Type of protocol
+    ///     RibType: RIB type to be redistributed
+    RedistributeAdd((ProtocolType, RibType)),
+
+    /// Unsubscribe redistribution.
+    ///   Routes redistributed so far are notified to be deleted.
+    ///     ProtocolType: Type of protocol
+    ///     RibType: RIB type not to be redistributed
+    RedistributeDelete((ProtocolType, RibType)),
+
+    /// Register IPv4 nexthop tracking.
+    ///   Protocol is notified resolution of the address immediately, and whenever it changes.
+    ///     ProtocolType: Type of protocol
+    ///     Ipv4Addr: Address to be tracked
+    NexthopRegisterIpv4((ProtocolType, Ipv4Addr)),
+
+    /// Unregister IPv4 nexthop tracking.
+    NexthopUnregisterIpv4((ProtocolType, Ipv4Addr)),
+
+    /// Register IPv6 nexthop tracking.
+    NexthopRegisterIpv6((ProtocolType, Ipv6Addr)),
+
+    /// Unregister IPv6 nexthop tracking.
+    NexthopUnregisterIpv6((ProtocolType, Ipv6Addr)),
+
+    /// Query IPv4 neighbor reachability.
+    ///   Protocol is notified the neighbor for the nexthop address, on the interface if given.
+    ///     ProtocolType: Type of protocol
+    ///     Nexthop: Nexthop to be queried
+    NeighborQueryIpv4((ProtocolType, Nexthop<Ipv4Addr>)),
+
+    /// Query IPv6 neighbor reachability.
+    NeighborQueryIpv6((ProtocolType, Nexthop<Ipv6Addr>)),
 }
 
+/// Message from Zebra to Protocol.
 pub enum ZebraToProto {
-    Interface(i32),
-    InterfaceAddr(i32),
-    InterfaceState(i32),
-    Route(i32)
+    /// Interface is added or its attributes are updated, such as name, MTU or VRF.
+    InterfaceAdd(ZebraInterface),
+
+    /// Interface is deleted, addresses on it are deleted implicitly.
+    InterfaceDelete(ZebraInterface),
+
+    /// Interface becomes operationally up.
+    InterfaceUp(ZebraInterface),
+
+    /// Interface becomes operationally down.
+    InterfaceDown(ZebraInterface),
+
+    /// IPv4 address is added to interface.
+    InterfaceAddrAddIpv4(ZebraInterfaceAddr<Ipv4Addr>),
+
+    /// IPv4 address is deleted from interface.
+    InterfaceAddrDeleteIpv4(ZebraInterfaceAddr<Ipv4Addr>),
+
+    /// IPv6 address is added to interface.
+    InterfaceAddrAddIpv6(ZebraInterfaceAddr<Ipv6Addr>),
+
+    /// IPv6 address is deleted from interface.
+    InterfaceAddrDeleteIpv6(ZebraInterfaceAddr<Ipv6Addr>),
+
+    /// Redistributed IPv4 route is selected or updated.
+    RouteAddIpv4(ZebraRoute<Ipv4Addr>),
+
+    /// Redistributed IPv4 route is no longer selected.
+    RouteDeleteIpv4(ZebraRoute<Ipv4Addr>),
+
+    /// Redistributed IPv6 route is selected or updated.
+    RouteAddIpv6(ZebraRoute<Ipv6Addr>),
+
+    /// Redistributed IPv6 route is no longer selected.
+    RouteDeleteIpv6(ZebraRoute<Ipv6Addr>),
+
+    /// Resolution of registered IPv4 nexthop, reachability, metric or nexthops, is changed.
+    NexthopUpdateIpv4(ZebraNexthop<Ipv4Addr>),
+
+    /// Resolution of registered IPv6 nexthop is changed.
+    NexthopUpdateIpv6(ZebraNexthop<Ipv6Addr>),
+
+    /// IPv4 neighbor reachability, answer to query.
+    NeighborIpv4(ZebraNeighbor<Ipv4Addr>),
+
+    /// IPv6 neighbor reachability, answer to query.
+    NeighborIpv6(ZebraNeighbor<Ipv6Addr>),
 }
@@ -44,6 +44,7 @@ use super::master::ProtocolMaster;
 use super::mds::*;
 
 use crate::zebra::master::ZebraMaster;
+use crate::zebra::kernel::*;
 use crate::bgp::master::BgpMaster;
 use crate::ospf::master::OspfMasterInner;
 
@@ -78,6 +79,9 @@ pub struct RouterNexus {
 
     /// UdsServer for Exec.
     exec_server: Mutex<Option<Arc<UdsServer>>>,
+
+    /// Kernel driver name for Zebra.
+    kernel_driver: Mutex<String>,
 }
 
 impl RouterNexus {
@@ -91,6 +95,7 @@ impl RouterNexus {
             sender_p2z: Mutex::new(None),
             config_server: Mutex::new(None),
             exec_server: Mutex::new(None),
+            kernel_driver: Mutex::new(String::from(KERNEL_DRIVER_NETLINK)),
         }
     }
 
@@ -112,15 +117,32 @@ impl RouterNexus {
         self.exec_server.lock().unwrap().replace(uds_server);
     }
 
-    /// Construct MasterInner instance and spawn a thread.
+    /// Set kernel driver name for Zebra.
+    pub fn set_kernel_driver(&self, name: &str) {
+        *self.kernel_driver.lock().unwrap() = String::from(name);
+    }
+
+    /// Construct MasterInner instance and spawn a thread, fail if kernel is not initialized.
     fn spawn_zebra(&self, sender_p2n: mpsc::Sender<ProtoToNexus>)
-                   -> (JoinHandle<()>, mpsc::Sender<NexusToProto>, mpsc::Sender<ProtoToZebra>) {
+                   -> Result<(JoinHandle<()>, mpsc::Sender<NexusToProto>, mpsc::Sender<ProtoToZebra>), CoreError> {
 
         // Create channel from RouterNexus to MasterInner
         let (sender_n2p, receiver_n2p) = mpsc::channel::<NexusToProto>();
         let (sender_p2z, receiver_p2z) = mpsc::channel::<ProtoToZebra>();
+        let (sender_init, receiver_init) = mpsc::channel::<Result<(), String>>();
+        let kernel_driver = self.kernel_driver.lock().unwrap().clone();
         let handle = thread::spawn(move || {
-            let zebra = Rc::new(ZebraMaster::new());
+            // Driver is not Send, construct it in the thread.
+            let kernel = match Kernel::new(&kernel_driver) {
+                Ok(kernel) => kernel,
+                Err(err) => {
+                    let _ = sender_init.send(Err(err.to_string()));
+                    return;
+                }
+            };
+            let _ = sender_init.send(Ok(()));
+
+            let zebra = Rc::new(ZebraMaster::new(kernel));
             ZebraMaster::init(zebra.clone());
             zebra.start(sender_p2n, receiver_n2p, receiver_p2z);
 
@@ -128,7 +150,17 @@ impl RouterNexus {
             ()
         });
 
-        (handle, sender_n2p, sender_p2z)
+        // Wait for kernel initialized, no point to run without it.
+        let result = match receiver_init.recv() {
+            Ok(result) => result,
+            Err(_) => Err(String::from("Zebra thread terminated")),
+        };
+        if let Err(err) = result {
+            let _ = handle.join();
+            return Err(CoreError::GenericError(format!("Failed to initialize kernel {}", err)));
+        }
+
+        Ok((handle, sender_n2p, sender_p2z))
     }
 
     /// Construct MasterInner instance and spawn a thread.
@@ -187,13 +219,22 @@ impl RouterNexus {
     }
 
     /// Clone ProtoToZebra mpsc::Sender.
-    fn _clone_sender_p2z(&self) -> mpsc::Sender<ProtoToZebra> {
+    fn clone_sender_p2z(&self) -> mpsc::Sender<ProtoToZebra> {
         if let Some(ref mut sender_p2z) = *self.sender_p2z.lock().unwrap() {
             return mpsc::Sender::clone(&sender_p2z)
         }
         panic!("failed to clone");
     }
 
+    /// Register ZebraToProto channel of protocol to zebra.
+    fn register_zebra_client(&self, proto: ProtocolType, sender_z2p: mpsc::Sender<ZebraToProto>) {
+        if let Some(ref sender_p2z) = *self.sender_p2z.lock().unwrap() {
+            if let Err(err) = sender_p2z.send(ProtoToZebra::RegisterProto((proto, sender_z2p))) {
+                error!("Register protocol {} to zebra {:?}", proto, err);
+            }
+        }
+    }
+
     /// Entry point to start RouterNexus.
     pub fn start(nexus: Arc<RouterNexus>, event_manager: Arc<Mutex<EventManager>>) -> Result<(), CoreError> {
         // Create multi sender channel from MasterInner to RouterNexus
@@ -201,16 +242,17 @@ impl RouterNexus {
         nexus.sender_p2n.lock().unwrap().replace(sender_p2n);
 
         // Spawn zebra instance
-        let (handle, sender, sender_p2z) = nexus.spawn_zebra(nexus.clone_sender_p2n());
+        let (handle, sender, sender_p2z) = nexus.spawn_zebra(nexus.clone_sender_p2n())?;
         nexus.sender_p2z.lock().unwrap().replace(sender_p2z);
         nexus.masters.lock().unwrap().insert(ProtocolType::Zebra, MasterTuple { handle, sender });
 
 
         // XXX spawn OSPF
-        let (handle, sender, _sender_z2p) = nexus.spawn_protocol(ProtocolType::Ospf,
-                                                               nexus.clone_sender_p2n(),
-                                                               nexus._clone_sender_p2z());
+        let (handle, sender, sender_z2p) = nexus.spawn_protocol(ProtocolType::Ospf,
+                                                              nexus.clone_sender_p2n(),
+                                                              nexus.clone_sender_p2z());
         nexus.masters.lock().unwrap().insert(ProtocolType::Ospf, MasterTuple { handle, sender });
+        nexus.register_zebra_client(ProtocolType::Ospf, sender_z2p);
 
         // Register channel handler to event manager.
         let channel_handler = ProtoToNexusChannelHandler::new(nexus.clone(), receiver);
@@ -450,6 +492,11 @@ impl NexusConfig {
         let zebra_handler = Rc::new(MdsProtocolHandler::new(ProtocolType::Zebra, nexus.clone()));
         MdsNode::register_handler(mds.clone(), "/config/route_ipv4", zebra_handler.clone());
         MdsNode::register_handler(mds.clone(), "/config/route_ipv6", zebra_handler.clone());
+        MdsNode::register_handler(mds.clone(), "/config/interfaces", zebra_handler.clone());
+        MdsNode::register_handler(mds.clone(), "/config/vrfs", zebra_handler.clone());
+        MdsNode::register_handler(mds.clone(), "/config/mpls", zebra_handler.clone());
+        MdsNode::register_handler(mds.clone(), "/config/graceful_restart", zebra_handler.clone());
+        MdsNode::register_handler(mds.clone(), "/config/interface_statistics", zebra_handler.clone());
 
         NexusConfig {
             mds: RefCell::new(mds),
@@ -538,7 +585,13 @@ impl NexusExec {
         let zebra_handler = Rc::new(MdsProtocolHandler::new_exec(ProtocolType::Zebra, nexus.clone()));
         MdsNode::register_handler(mds.clone(), "/exec/show/route_ipv4", zebra_handler.clone());
         MdsNode::register_handler(mds.clone(), "/exec/show/route_ipv6", zebra_handler.clone());
+        MdsNode::register_handler(mds.clone(), "/exec/show/fib_failures", zebra_handler.clone());
         MdsNode::register_handler(mds.clone(), "/exec/show/interface", zebra_handler.clone());
+        MdsNode::register_handler(mds.clone(), "/exec/show/interfaces", zebra_handler.clone());
+        MdsNode::register_handler(mds.clone(), "/exec/show/vrfs", zebra_handler.clone());
+        MdsNode::register_handler(mds.clone(), "/exec/show/mpls", zebra_handler.clone());
+        MdsNode::register_handler(mds.clone(), "/exec/show/arp", zebra_handler.clone());
+        MdsNode::register_handler(mds.clone(), "/exec/show/ipv6/neighbors", zebra_handler.clone());
 
         NexusExec {
             mds: RefCell::new(mds),
@@ -7,6 +7,7 @@ use std::env;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::fs;
+use std::process;
 
 use log::info;
 use log::error;
@@ -21,6 +22,7 @@ use common::error::*;
 
 use routerd::core::signal::*;
 use routerd::core::nexus::*;
+use routerd::zebra::kernel::{KERNEL_DRIVER_NETLINK, KERNEL_DRIVER_MOCK};
 
 const ROUTERD_VERSION: &str = "0.1.0";
 
@@ -45,6 +47,7 @@ fn main() {
 
     let mut opts = Options::new();
     opts.optopt("l", "loglevel", "Set log level (default debug)", "LOGLEVEL");
+    opts.optopt("k", "kernel", "Set kernel driver, netlink or mock (default netlink)", "DRIVER");
     opts.optflag("h", "help", "Display this help and exit");
     opts.optflag("v", "version", "Print program version");
 
@@ -79,6 +82,18 @@ fn main() {
         LevelFilter::Debug
     };
 
+    let kernel_driver = match matches.opt_str("k") {
+        Some(driver) => match driver.as_ref() {
+            KERNEL_DRIVER_NETLINK | KERNEL_DRIVER_MOCK => driver,
+            _ => {
+                println!("Invalid kernel driver {}", driver);
+                print_help(&program, opts);
+                return;
+            }
+        },
+        None => String::from(KERNEL_DRIVER_NETLINK),
+    };
+
     // Init logger
     CombinedLogger::init(
         vec![
@@ -92,18 +107,19 @@ fn main() {
     // Start daemon
     info!("ReZe Router Daemon started.");
 
-    start();
+    start(&kernel_driver);
 
     info!("ReZe Router Daemon terminated.");
 }
 
 // Initialize objects and associate them.
 // TODO: probably take config or command line parameters.
-fn start() {
+fn start(kernel_driver: &str) {
 
     // Event Manager and Nexus.
     let event_manager = Arc::new(Mutex::new(EventManager::new()));
     let nexus = Arc::new(RouterNexus::new(event_manager.clone()));
+    nexus.set_kernel_driver(kernel_driver);
 
     // UDS to accept config request.
     let mut config_uds_path = env::temp_dir();
@@ -122,14 +138,20 @@ fn start() {
     nexus.set_exec_server(uds_server);
 
     // Start nexus.
-    match RouterNexus::start(nexus, event_manager) {
+    let failed = match RouterNexus::start(nexus, event_manager) {
         Err(CoreError::SystemShutdown) => {
-            info!("Nexus terminated")
+            info!("Nexus terminated");
+            false
+        },
+        Err(err) => {
+            error!("Nexus stopped: {}", err);
+            true
         },
         _ => {
-            error!("Nexus stopped unexpectedly")
+            error!("Nexus stopped unexpectedly");
+            true
         }
-    }
+    };
 
     // Cleanup.
     if let Err(_) = fs::remove_file(config_uds_path) {
@@ -139,6 +161,8 @@ fn start() {
         // Nothing we can do?
     }
 
-    ()
+    if failed {
+        process::exit(1);
+    }
 }
 
@@ -5,9 +5,16 @@
 // Zebra - IPv4 and IPv6 address handler.
 //
 
+use serde::Serialize;
+use serde::Serializer;
+use serde::ser::SerializeStruct;
+
 use rtable::prefix::*;
 
+use crate::core::message::zebra::ZebraInterfaceAddr;
+
 use super::kernel::KernelAddr;
+use super::rib::prefix_from_address;
 
 /// Connected Address.
 pub struct Connected<T: Addressable> {
@@ -58,6 +65,16 @@ impl<T: Addressable> Connected<T> {
         &self.address
     }
 
+    /// Return network prefix for connected route, peer prefix is used if any.
+    pub fn network(&self) -> Prefix<T> {
+        let p = match self.destination {
+            Some(ref destination) => destination,
+            None => &self.address,
+        };
+
+        prefix_from_address(&T::from_slice(p.octets()), p.len())
+    }
+
     ///
     pub fn destination(&self) -> &Option<Prefix<T>> {
         &self.destination
@@ -74,4 +91,34 @@ impl<T: Addressable> Connected<T> {
     pub fn label(&self) -> Option<&String> {
         self.label.as_ref()
     }
+
+    /// Construct interface address to be notified to protocol.
+    pub fn to_zebra_interface_addr(&self, index: i32) -> ZebraInterfaceAddr<T> {
+        ZebraInterfaceAddr::<T> {
+            index: index,
+            address: self.address.clone(),
+            destination: self.destination.clone(),
+            secondary: self.secondary,
+        }
+    }
+}
+
+/// Serializer for Connected.
+impl<T> Serialize for Connected<T>
+where T: Addressable
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer
+    {
+        let mut s = serializer.serialize_struct("Connected", 5)?;
+        let address_str = self.address.to_string();
+        let peer_str = self.destination.as_ref().map(|p| p.to_string());
+
+        s.serialize_field("address", &address_str)?;
+        s.serialize_field("peer", &peer_str)?;
+        s.serialize_field("secondary", &self.secondary)?;
+        s.serialize_field("unnumbered", &self.unnumbered)?;
+        s.serialize_field("label", &self.label)?;
+        s.end()
+    }
 }
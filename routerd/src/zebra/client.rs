@@ -0,0 +1,180 @@
+//
+// ReZe.Rs - Router Daemon
+//   Copyright (C) 2018-2020 Toshiaki Takada
+//
+// Zebra - Protocol clients and redistribution.
+//
+
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::sync::mpsc;
+
+use log::error;
+use rtable::prefix::*;
+
+use crate::core::protocols::ProtocolType;
+use crate::core::message::zebra::ZebraToProto;
+
+use super::rib::RibType;
+
+/// Zebra client, protocol registered its channel to Zebra.
+pub struct ZebraClient {
+
+    /// Protocol type.
+    proto: ProtocolType,
+
+    /// Channel sender from Zebra to Protocol.
+    sender: mpsc::Sender<ZebraToProto>,
+
+    /// RIB types subscribed for redistribution.
+    redistribute: RefCell<BTreeSet<RibType>>,
+
+    /// IPv4 routes added by protocol, per table ID and prefix.
+    routes_ipv4: RefCell<BTreeSet<(u32, Prefix<Ipv4Addr>)>>,
+
+    /// IPv6 routes added by protocol, per table ID and prefix.
+    routes_ipv6: RefCell<BTreeSet<(u32, Prefix<Ipv6Addr>)>>,
+
+    /// False once protocol has dropped its channel.
+    connected: Cell<bool>,
+}
+
+impl ZebraClient {
+
+    /// Constructor.
+    pub fn new(proto: ProtocolType, sender: mpsc::Sender<ZebraToProto>) -> ZebraClient {
+        ZebraClient {
+            proto: proto,
+            sender: sender,
+            redistribute: RefCell::new(BTreeSet::new()),
+            routes_ipv4: RefCell::new(BTreeSet::new()),
+            routes_ipv6: RefCell::new(BTreeSet::new()),
+            connected: Cell::new(true),
+        }
+    }
+
+    pub fn proto(&self) -> ProtocolType {
+        self.proto
+    }
+
+    /// Subscribe redistribution of RIB type, return false if already subscribed.
+    pub fn redistribute_add(&self, rib_type: RibType) -> bool {
+        self.redistribute.borrow_mut().insert(rib_type)
+    }
+
+    /// Unsubscribe redistribution of RIB type, return false if not subscribed.
+    pub fn redistribute_delete(&self, rib_type: RibType) -> bool {
+        self.redistribute.borrow_mut().remove(&rib_type)
+    }
+
+    /// Return true if routes of RIB type are redistributed to this client.
+    /// Routes originated by the protocol itself are never sent back.
+    pub fn is_redistributed(&self, rib_type: RibType) -> bool {
+        proto_rib_type(self.proto) != Some(rib_type) &&
+            self.redistribute.borrow().contains(&rib_type)
+    }
+
+    /// Record IPv4 route added by protocol, or forget it when deleted.
+    pub fn route_ipv4(&self, table_id: u32, prefix: &Prefix<Ipv4Addr>, add: bool) {
+        if add {
+            self.routes_ipv4.borrow_mut().insert((table_id, prefix.clone()));
+        } else {
+            self.routes_ipv4.borrow_mut().remove(&(table_id, prefix.clone()));
+        }
+    }
+
+    /// Record IPv6 route added by protocol, or forget it when deleted.
+    pub fn route_ipv6(&self, table_id: u32, prefix: &Prefix<Ipv6Addr>, add: bool) {
+        if add {
+            self.routes_ipv6.borrow_mut().insert((table_id, prefix.clone()));
+        } else {
+            self.routes_ipv6.borrow_mut().remove(&(table_id, prefix.clone()));
+        }
+    }
+
+    /// Return IPv4 routes added by protocol.
+    pub fn routes_ipv4(&self) -> Vec<(u32, Prefix<Ipv4Addr>)> {
+        self.routes_ipv4.borrow().iter().cloned().collect()
+    }
+
+    /// Return IPv6 routes added by protocol.
+    pub fn routes_ipv6(&self) -> Vec<(u32, Prefix<Ipv6Addr>)> {
+        self.routes_ipv6.borrow().iter().cloned().collect()
+    }
+
+    /// Return false if protocol has gone.
+    pub fn is_connected(&self) -> bool {
+        self.connected.get()
+    }
+
+    /// Send message to protocol.
+    pub fn send(&self, msg: ZebraToProto) {
+        if !self.connected.get() {
+            return;
+        }
+
+        if let Err(err) = self.sender.send(msg) {
+            error!("Send message to {} {:?}", self.proto, err);
+            self.connected.set(false);
+        }
+    }
+}
+
+/// Return RIB type of routes originated by protocol.
+pub fn proto_rib_type(proto: ProtocolType) -> Option<RibType> {
+    match proto {
+        ProtocolType::Rip | ProtocolType::Ripng => Some(RibType::Rip),
+        ProtocolType::Ospf | ProtocolType::OspfV3 => Some(RibType::Ospf),
+        ProtocolType::Isis => Some(RibType::Isis),
+        ProtocolType::Eigrp => Some(RibType::Eigrp),
+        ProtocolType::Bgp => Some(RibType::Bgp),
+        _ => None,
+    }
+}
+
+///
+/// Unit tests for Zebra client.
+///
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::message::zebra::ZebraInterface;
+
+    #[test]
+    pub fn test_client_redistribute() {
+        let (sender, receiver) = mpsc::channel::<ZebraToProto>();
+        let client = ZebraClient::new(ProtocolType::Ospf, sender);
+
+        assert!(!client.is_redistributed(RibType::Static));
+
+        assert!(client.redistribute_add(RibType::Static));
+        assert!(!client.redistribute_add(RibType::Static));
+        assert!(client.is_redistributed(RibType::Static));
+        assert!(!client.is_redistributed(RibType::Connected));
+
+        // Own routes are filtered out.
+        assert!(client.redistribute_add(RibType::Ospf));
+        assert!(!client.is_redistributed(RibType::Ospf));
+
+        assert!(client.redistribute_delete(RibType::Static));
+        assert!(!client.redistribute_delete(RibType::Static));
+        assert!(!client.is_redistributed(RibType::Static));
+
+        let zi = ZebraInterface {
+            index: 2,
+            name: "eth0".to_string(),
+            hwaddr: [0; 6],
+            mtu: 1500,
+            flags: 0,
+            up: false,
+            table_id: 254,
+        };
+        client.send(ZebraToProto::InterfaceDown(zi.clone()));
+        match receiver.try_recv() {
+            Ok(ZebraToProto::InterfaceDown(v)) => assert_eq!(v, zi),
+            _ => panic!("InterfaceDown is not received"),
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//
+// ReZe.Rs - Router Daemon
+//   Copyright (C) 2018-2020 Toshiaki Takada
+//
+// Zebra - Graceful restart.
+//   Routes left in kernel by previous instance are kept as stale, until protocols
+//   reconverge within stale time, and remaining ones are swept afterwards.
+//
+
+use std::rc::Rc;
+
+use log::debug;
+use serde_json;
+
+use common::error::*;
+
+use crate::core::mds::*;
+use super::master::ZebraMaster;
+
+/// Default time in seconds to keep stale routes.
+pub const ZEBRA_STALE_TIME_DEFAULT: u64 = 60;
+
+/// Maximum time in seconds to keep stale routes.
+pub const ZEBRA_STALE_TIME_MAX: u64 = 3600;
+
+/// Graceful restart config.
+pub struct GracefulRestartConfig {
+
+    /// Zebra master.
+    master: Rc<ZebraMaster>,
+}
+
+impl GracefulRestartConfig {
+
+    /// Constructor.
+    pub fn new(master: Rc<ZebraMaster>) -> GracefulRestartConfig {
+        GracefulRestartConfig {
+            master: master,
+        }
+    }
+}
+
+impl MdsHandler for GracefulRestartConfig {
+
+    /// Handle PUT method.
+    ///   /config/graceful_restart with {"stale_time": SECONDS}.
+    fn handle_put(&self, _path: &str, params: Option<Box<String>>) -> Result<Option<String>, CoreError> {
+        let json: serde_json::Value = match params {
+            Some(json_str) => match serde_json::from_str(&json_str) {
+                Ok(json) => json,
+                Err(_) => return Err(CoreError::CommandExec(format!("Invalid JSON"))),
+            },
+            None => return Err(CoreError::CommandExec(format!("No parameters"))),
+        };
+
+        // Time could be given as number or string.
+        let stale_time = match json.get("stale_time") {
+            Some(v) => match v.as_u64() {
+                Some(v) => Some(v),
+                None => v.as_str().and_then(|v| v.parse::<u64>().ok()),
+            },
+            None => return Err(CoreError::CommandExec(format!("No stale time"))),
+        };
+
+        match stale_time {
+            Some(stale_time) if stale_time > 0 && stale_time <= ZEBRA_STALE_TIME_MAX => {
+                debug!("Configuring stale time {}", stale_time);
+                self.master.set_stale_time(stale_time);
+                Ok(None)
+            },
+            _ => Err(CoreError::CommandExec(format!("Invalid stale time {}", json["stale_time"]))),
+        }
+    }
+
+    /// Handle DELETE method, stale time is reset to default.
+    fn handle_delete(&self, _path: &str, _params: Option<Box<String>>) -> Result<Option<String>, CoreError> {
+        debug!("Unconfiguring stale time");
+        self.master.set_stale_time(ZEBRA_STALE_TIME_DEFAULT);
+
+        Ok(None)
+    }
+}
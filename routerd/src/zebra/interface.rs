@@ -0,0 +1,320 @@
+//
+// ReZe.Rs - Router Daemon
+//   Copyright (C) 2018-2020 Toshiaki Takada
+//
+// Zebra - Interface config and show handler.
+//
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use serde_json;
+use log::{debug, error};
+
+use rtable::prefix::*;
+use common::error::*;
+
+use crate::core::mds::*;
+use super::master::ZebraMaster;
+use super::link::*;
+
+/// Interface configs.
+pub struct InterfaceConfig {
+
+    /// Zebra master.
+    master: Rc<ZebraMaster>,
+
+    /// Configs for interfaces not present yet, applied when they appear.
+    pending: RefCell<HashMap<String, Vec<serde_json::Value>>>,
+}
+
+impl InterfaceConfig {
+
+    /// Constructor.
+    pub fn new(master: Rc<ZebraMaster>) -> InterfaceConfig {
+        InterfaceConfig {
+            master: master,
+            pending: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Apply configs pending for the link name, when link is added or renamed.
+    pub fn link_add(&self, link: &Link) {
+        let configs = match self.pending.borrow_mut().remove(&link.name()) {
+            Some(configs) => configs,
+            None => return,
+        };
+
+        for json in configs {
+            debug!("Applying pending config to interface {}", link.name());
+
+            if let Err(err) = self.apply(link, &json, true) {
+                error!("Interface {} config {}", link.name(), err);
+            }
+        }
+    }
+
+    /// Store config for interface not present yet.
+    fn pending_add(&self, ifname: &str, json: serde_json::Value) {
+        self.pending.borrow_mut().entry(ifname.to_string()).or_insert_with(Vec::new).push(json);
+    }
+
+    /// Remove stored config matching unset config, MTU and shutdown by key, addresses by value.
+    fn pending_delete(&self, ifname: &str, json: &serde_json::Value) {
+        let mut pending = self.pending.borrow_mut();
+
+        if let Some(configs) = pending.get_mut(ifname) {
+            configs.retain(|config| {
+                !["mtu", "shutdown", "ipv4_address", "ipv6_address"].iter().any(|key| {
+                    match (json.get(key), config.get(key)) {
+                        (Some(_), Some(_)) if *key == "mtu" || *key == "shutdown" => true,
+                        (Some(v), Some(c)) => v == c,
+                        _ => false,
+                    }
+                })
+            });
+
+            if configs.is_empty() {
+                pending.remove(ifname);
+            }
+        }
+    }
+
+    /// Apply config to link, set or unset.
+    fn apply(&self, link: &Link, json: &serde_json::Value, set: bool) -> Result<(), CoreError> {
+        let kernel = self.master.kernel();
+        let ifindex = link.index();
+
+        // MTU.
+        if let Some(mtu) = json.get("mtu") {
+            let mtu = if set {
+                match mtu.as_u64() {
+                    Some(mtu) => mtu as u32,
+                    None => return Err(CoreError::CommandExec(format!("Invalid MTU"))),
+                }
+            } else {
+                link.mtu_default()
+            };
+
+            kernel.link_set_mtu(ifindex, mtu).map_err(|err| CoreError::CommandExec(err.to_string()))?;
+        }
+
+        // Shutdown.
+        if let Some(_) = json.get("shutdown") {
+            let ret = if set {
+                kernel.link_set_down(ifindex)
+            } else {
+                kernel.link_set_up(ifindex)
+            };
+
+            ret.map_err(|err| CoreError::CommandExec(err.to_string()))?;
+        }
+
+        // IPv4 address.
+        if let Some(address) = json.get("ipv4_address") {
+            let prefix = match address.as_str().map(|a| Prefix::<Ipv4Addr>::from_str(a)) {
+                Some(Ok(prefix)) => prefix,
+                _ => return Err(CoreError::CommandExec(format!("Invalid IPv4 address"))),
+            };
+
+            let ret = if set {
+                kernel.ipv4_address_add(ifindex, &prefix, json.get("label").and_then(|l| l.as_str()))
+            } else {
+                kernel.ipv4_address_delete(ifindex, &prefix)
+            };
+
+            ret.map_err(|err| CoreError::CommandExec(err.to_string()))?;
+        }
+
+        // IPv6 address.
+        if let Some(address) = json.get("ipv6_address") {
+            let prefix = match address.as_str().map(|a| Prefix::<Ipv6Addr>::from_str(a)) {
+                Some(Ok(prefix)) => prefix,
+                _ => return Err(CoreError::CommandExec(format!("Invalid IPv6 address"))),
+            };
+
+            let ret = if set {
+                kernel.ipv6_address_add(ifindex, &prefix)
+            } else {
+                kernel.ipv6_address_delete(ifindex, &prefix)
+            };
+
+            ret.map_err(|err| CoreError::CommandExec(err.to_string()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Handle PUT and DELETE.
+    fn handle(&self, path: &str, params: Option<Box<String>>, set: bool) -> Result<Option<String>, CoreError> {
+        let pat = "/config/interfaces";
+        if !path.starts_with(pat) {
+            return Err(CoreError::CommandExec(format!("Invalid path")));
+        }
+        let path = &path[pat.len()..];
+
+        let ifname = match split_id_and_path(path) {
+            Some((ifname, _)) => ifname,
+            None => return Err(CoreError::CommandExec(format!("Invalid path"))),
+        };
+
+        // Entering interface mode only.
+        let json_str = match params {
+            Some(json_str) => json_str,
+            None => return Ok(None),
+        };
+
+        let json: serde_json::Value = match serde_json::from_str(&json_str) {
+            Ok(json) => json,
+            Err(_) => return Err(CoreError::CommandExec(format!("Invalid JSON"))),
+        };
+
+        let link = match self.master.link_lookup_by_name(&ifname) {
+            Some(link) => link,
+            None => {
+                debug!("Interface {} not present, config {}", ifname, if set { "stored" } else { "removed" });
+
+                if set {
+                    self.pending_add(&ifname, json);
+                } else {
+                    self.pending_delete(&ifname, &json);
+                }
+                return Ok(None)
+            }
+        };
+
+        debug!("Configuring interface {} {}", ifname, if set { "set" } else { "unset" });
+
+        self.apply(&link, &json, set)?;
+
+        Ok(None)
+    }
+}
+
+impl MdsHandler for InterfaceConfig {
+
+    /// Handle PUT method.
+    fn handle_put(&self, path: &str, params: Option<Box<String>>) -> Result<Option<String>, CoreError> {
+        self.handle(path, params, true)
+    }
+
+    /// Handle DELETE method.
+    fn handle_delete(&self, path: &str, params: Option<Box<String>>) -> Result<Option<String>, CoreError> {
+        self.handle(path, params, false)
+    }
+}
+
+/// Interface statistics config.
+pub struct InterfaceStatsConfig {
+
+    /// Zebra master.
+    master: Rc<ZebraMaster>,
+}
+
+impl InterfaceStatsConfig {
+
+    /// Constructor.
+    pub fn new(master: Rc<ZebraMaster>) -> InterfaceStatsConfig {
+        InterfaceStatsConfig {
+            master: master,
+        }
+    }
+}
+
+impl MdsHandler for InterfaceStatsConfig {
+
+    /// Handle PUT method.
+    ///   /config/interface_statistics with {"interval": SECONDS}.
+    fn handle_put(&self, _path: &str, params: Option<Box<String>>) -> Result<Option<String>, CoreError> {
+        let json: serde_json::Value = match params {
+            Some(json_str) => match serde_json::from_str(&json_str) {
+                Ok(json) => json,
+                Err(_) => return Err(CoreError::CommandExec(format!("Invalid JSON"))),
+            },
+            None => return Err(CoreError::CommandExec(format!("No parameters"))),
+        };
+
+        // Interval could be given as number or string.
+        let interval = match json.get("interval") {
+            Some(v) => match v.as_u64() {
+                Some(v) => Some(v),
+                None => v.as_str().and_then(|v| v.parse::<u64>().ok()),
+            },
+            None => return Err(CoreError::CommandExec(format!("No interval"))),
+        };
+
+        match interval {
+            Some(interval) if interval > 0 && interval <= LINK_STATS_INTERVAL_MAX => {
+                debug!("Configuring interface statistics interval {}", interval);
+                self.master.set_stats_interval(interval);
+                Ok(None)
+            },
+            _ => Err(CoreError::CommandExec(format!("Invalid interval {}", json["interval"]))),
+        }
+    }
+
+    /// Handle DELETE method, interval is reset to default.
+    fn handle_delete(&self, _path: &str, _params: Option<Box<String>>) -> Result<Option<String>, CoreError> {
+        debug!("Unconfiguring interface statistics interval");
+        self.master.set_stats_interval(LINK_STATS_INTERVAL_DEFAULT);
+
+        Ok(None)
+    }
+}
+
+/// Show interfaces.
+pub struct ShowInterface {
+
+    /// Zebra master.
+    master: Rc<ZebraMaster>,
+}
+
+impl ShowInterface {
+
+    /// Constructor.
+    pub fn new(master: Rc<ZebraMaster>) -> ShowInterface {
+        ShowInterface {
+            master: master,
+        }
+    }
+}
+
+impl MdsHandler for ShowInterface {
+
+    /// Handle GET method.
+    ///   /exec/show/interface returns all links, /exec/show/interface/IFNAME returns one link,
+    ///   and /exec/show/interfaces returns list of interface names.
+    fn handle_get(&self, path: &str, _params: Option<Box<String>>) -> Result<Option<String>, CoreError> {
+        let pat = "/exec/show/interfaces";
+        if path.starts_with(pat) {
+            let names: Vec<String> = self.master.links().iter().map(|link| link.name()).collect();
+            let s = serde_json::to_string(&names).unwrap();
+
+            return Ok(Some(s))
+        }
+
+        let pat = "/exec/show/interface";
+        if !path.starts_with(pat) {
+            return Err(CoreError::CommandExec(format!("Invalid path")));
+        }
+
+        let s = match split_id_and_path(&path[pat.len()..]) {
+            Some((ifname, _)) => {
+                match self.master.link_lookup_by_name(&ifname) {
+                    Some(link) => serde_json::to_string(&*link).unwrap(),
+                    None => return Err(CoreError::CommandExec(format!("No such interface {}", ifname))),
+                }
+            },
+            None => {
+                let links = self.master.links();
+                let links: Vec<&Link> = links.iter().map(|link| link.as_ref()).collect();
+
+                serde_json::to_string(&links).unwrap()
+            },
+        };
+
+        Ok(Some(s))
+    }
+}
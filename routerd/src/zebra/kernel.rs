@@ -6,15 +6,22 @@
 //
 
 use std::net::{Ipv4Addr, Ipv6Addr};
+use std::os::unix::io::RawFd;
 use std::sync::Arc;
+use std::time;
 
 use log::error;
 use quick_error::*;
+use serde::Serialize;
+use serde::Serializer;
+use serde::ser::SerializeStruct;
 use rtable::prefix::*;
 
 use common::nexthop::*;
 
 use super::rib::*;
+use super::mpls::Lsp;
+use super::mock::MockKernelDriver;
 
 #[cfg(target_os = "linux")]
 use super::linux::netlink::*;
@@ -43,13 +50,115 @@ quick_error! {
             description("Address error")
             display(r#"Address error {}"#, s)
         }
+        Neighbor(s: String) {
+            description("Neighbor error")
+            display(r#"Neighbor error {}"#, s)
+        }
         Encode(s: String) {
             description("Encode error")
             display(r#"Encode error {}"#, s)
         }
+        Decode(s: String) {
+            description("Decode error")
+            display(r#"Decode error {}"#, s)
+        }
+    }
+}
+
+/// FIB operation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FibOp {
+    Install,
+    Update,
+    Uninstall,
+}
+
+/// Result of FIB operation, reported by driver asynchronously when kernel acknowledges it.
+pub struct KernelFibResult<T: Addressable> {
+
+    /// Kernel table ID.
+    pub table_id: u32,
+
+    /// Destination prefix.
+    pub prefix: Prefix<T>,
+
+    /// Operation.
+    pub op: FibOp,
+
+    /// Error from kernel, None if succeeded.
+    pub error: Option<String>,
+
+    /// True if ACK was lost, kernel state is unknown and the operation is retried.
+    pub lost: bool,
+}
+
+/// Failed FIB operation, kept until the prefix is programmed successfully.
+pub struct FibFailure {
+
+    /// Kernel table ID.
+    pub table_id: u32,
+
+    /// Destination prefix.
+    pub prefix: String,
+
+    /// RIB type, unknown if the route is already gone from RIB.
+    pub rib_type: Option<RibType>,
+
+    /// Operation.
+    pub op: FibOp,
+
+    /// Error from kernel.
+    pub error: String,
+
+    /// Time failed.
+    pub instant: time::Instant,
+}
+
+/// Serializer for FibFailure.
+impl Serialize for FibFailure {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer
+    {
+        let mut s = serializer.serialize_struct("FibFailure", 6)?;
+        s.serialize_field("table", &self.table_id)?;
+        s.serialize_field("prefix", &self.prefix)?;
+        s.serialize_field("type", &self.rib_type.map(|rib_type| format!("{:?}", rib_type)))?;
+        s.serialize_field("op", &format!("{:?}", self.op))?;
+        s.serialize_field("error", &self.error)?;
+        s.serialize_field("age", &self.instant.elapsed().as_secs())?;
+        s.end()
     }
 }
 
+/// Kernel Link statistics, counters from IFLA_STATS64.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct KernelLinkStats {
+
+    /// Packets received.
+    pub rx_packets: u64,
+
+    /// Packets transmitted.
+    pub tx_packets: u64,
+
+    /// Bytes received.
+    pub rx_bytes: u64,
+
+    /// Bytes transmitted.
+    pub tx_bytes: u64,
+
+    /// Receive errors.
+    pub rx_errors: u64,
+
+    /// Transmit errors.
+    pub tx_errors: u64,
+
+    /// Received packets dropped.
+    pub rx_dropped: u64,
+
+    /// Transmit packets dropped.
+    pub tx_dropped: u64,
+}
+
 /// Kernel Link Abstraction.
 pub struct KernelLink {
 
@@ -67,6 +176,24 @@ pub struct KernelLink {
 
     /// MTU.
     pub mtu: u32,
+
+    /// Interface flags.
+    pub flags: u32,
+
+    /// Operational state.
+    pub operstate: u8,
+
+    /// Link kind.
+    pub kind: Option<String>,
+
+    /// Master device index, such as VRF or bridge.
+    pub master: Option<i32>,
+
+    /// Kernel table ID, if the link is a VRF device.
+    pub vrf_table: Option<u32>,
+
+    /// Statistics, if kernel reports them.
+    pub stats: Option<KernelLinkStats>,
 }
 
 impl KernelLink {
@@ -79,6 +206,12 @@ impl KernelLink {
             hwtype: hwtype,
             hwaddr: hwaddr,
             mtu: mtu,
+            flags: 0,
+            operstate: 0,
+            kind: None,
+            master: None,
+            vrf_table: None,
+            stats: None,
         }
     }
 }
@@ -121,6 +254,83 @@ impl<T: Addressable> KernelAddr<T> {
     }
 }
 
+/// Neighbor state, as Linux NUD states.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NeighState {
+
+    /// Resolution is in progress.
+    Incomplete,
+
+    /// Confirmed reachable recently.
+    Reachable,
+
+    /// Resolved, but not confirmed recently.
+    Stale,
+
+    /// Waiting for upper layer confirmation before probing.
+    Delay,
+
+    /// Being probed.
+    Probe,
+
+    /// Resolution failed.
+    Failed,
+
+    /// No resolution needed on the link.
+    Noarp,
+
+    /// Statically configured.
+    Permanent,
+
+    /// Unknown state.
+    None,
+}
+
+impl NeighState {
+
+    /// Return true if link layer address is usable to forward packets.
+    pub fn is_valid(&self) -> bool {
+        match self {
+            NeighState::Reachable | NeighState::Stale | NeighState::Delay |
+            NeighState::Probe | NeighState::Noarp | NeighState::Permanent => true,
+            _ => false,
+        }
+    }
+}
+
+/// Kernel Neighbor Abstraction, ARP or IPv6 ND entry.
+pub struct KernelNeigh<T: Addressable> {
+
+    /// Interface Index.
+    pub ifindex: i32,
+
+    /// Neighbor address.
+    pub address: T,
+
+    /// Link layer address, if resolved.
+    pub hwaddr: Option<[u8; 6]>,
+
+    /// State.
+    pub state: NeighState,
+
+    /// Neighbor is a router, IPv6 only.
+    pub router: bool,
+}
+
+impl<T: Addressable> KernelNeigh<T> {
+
+    /// Constructor.
+    pub fn new(ifindex: i32, address: T, hwaddr: Option<[u8; 6]>, state: NeighState) -> KernelNeigh<T> {
+        KernelNeigh::<T> {
+            ifindex: ifindex,
+            address: address,
+            hwaddr: hwaddr,
+            state: state,
+            router: false,
+        }
+    }
+}
+
 /// Kernel Route Abstraction.
 pub struct KernelRoute<T: Addressable> {
 
@@ -195,18 +405,74 @@ pub trait KernelDriver {
     /// Register Delete IPv6 route callback function.
     fn register_delete_ipv6_route(&self, f: Box<dyn Fn(KernelRoute<Ipv6Addr>)>);
 
+    /// Register Add IPv4 neighbor callback function.
+    fn register_add_ipv4_neighbor(&self, f: Box<dyn Fn(KernelNeigh<Ipv4Addr>)>);
+
+    /// Register Delete IPv4 neighbor callback function.
+    fn register_delete_ipv4_neighbor(&self, f: Box<dyn Fn(KernelNeigh<Ipv4Addr>)>);
+
+    /// Register Add IPv6 neighbor callback function.
+    fn register_add_ipv6_neighbor(&self, f: Box<dyn Fn(KernelNeigh<Ipv6Addr>)>);
+
+    /// Register Delete IPv6 neighbor callback function.
+    fn register_delete_ipv6_neighbor(&self, f: Box<dyn Fn(KernelNeigh<Ipv6Addr>)>);
+
+    /// Register IPv4 FIB result callback function.
+    fn register_ipv4_fib_result(&self, f: Box<dyn Fn(KernelFibResult<Ipv4Addr>)>);
+
+    /// Register IPv6 FIB result callback function.
+    fn register_ipv6_fib_result(&self, f: Box<dyn Fn(KernelFibResult<Ipv6Addr>)>);
+
+    /// Register Link statistics callback function.
+    fn register_link_stats(&self, f: Box<dyn Fn(i32, KernelLinkStats)>);
+
+
+    /// Return file descriptor to watch kernel events, if driver has one.
+    fn event_fd(&self) -> Option<RawFd>;
+
+    /// Process kernel events when file descriptor is ready.
+    fn process_event(&self) -> Result<(), KernelError>;
+
+    /// Return file descriptor to watch FIB results, if driver programs FIB asynchronously.
+    fn fib_fd(&self) -> Option<RawFd>;
+
+    /// Send route messages queued so far.
+    fn fib_flush(&self) -> Result<(), KernelError>;
+
+    /// Process FIB results when file descriptor is ready.
+    fn process_fib_ack(&self) -> Result<(), KernelError>;
+
+    /// Delete routes left in kernel by previous instance, which are not replaced so far.
+    fn fib_sweep(&self) -> Result<(), KernelError>;
+
 
     /// Send a command to kernel to retrieve all link information.
     fn get_link_all(&self) -> Result<(), KernelError>;
 
+    /// Send a command to kernel to retrieve statistics of all links.
+    fn get_link_stats_all(&self) -> Result<(), KernelError>;
+
     /// Set MTU.
-    fn set_mtu(&self, mtu: u16) -> bool; // ? Error
+    fn set_mtu(&self, ifindex: i32, mtu: u32) -> Result<(), KernelError>;
 
     /// Set link up.
-    fn set_link_up(&self) -> bool;
+    fn set_link_up(&self, ifindex: i32) -> Result<(), KernelError>;
 
     /// Set link down.
-    fn set_link_down(&self) -> bool;
+    fn set_link_down(&self, ifindex: i32) -> Result<(), KernelError>;
+
+
+    /// Add an IPv4 address to link.
+    fn add_ipv4_address(&self, ifindex: i32, prefix: &Prefix<Ipv4Addr>, label: Option<&str>) -> Result<(), KernelError>;
+
+    /// Delete an IPv4 address from link.
+    fn delete_ipv4_address(&self, ifindex: i32, prefix: &Prefix<Ipv4Addr>) -> Result<(), KernelError>;
+
+    /// Add an IPv6 address to link.
+    fn add_ipv6_address(&self, ifindex: i32, prefix: &Prefix<Ipv6Addr>) -> Result<(), KernelError>;
+
+    /// Delete an IPv6 address from link.
+    fn delete_ipv6_address(&self, ifindex: i32, prefix: &Prefix<Ipv6Addr>) -> Result<(), KernelError>;
 
 
     /// Get all IPv4 addresses from system.
@@ -216,42 +482,81 @@ pub trait KernelDriver {
     fn get_ipv6_address_all(&self) -> Result<(), KernelError>;
 
 
-    /// Add an IPv4 route to system.
-    fn add_ipv4_route(&self, prefix: &Prefix<Ipv4Addr>, rib: &Rib<Ipv4Addr>);
+    /// Get all ARP entries from system.
+    fn get_ipv4_neighbor_all(&self) -> Result<(), KernelError>;
+
+    /// Get all IPv6 neighbor entries from system.
+    fn get_ipv6_neighbor_all(&self) -> Result<(), KernelError>;
+
+
+    /// Get all IPv4 routes from system.
+    fn get_ipv4_route_all(&self) -> Result<(), KernelError>;
+
+    /// Get all IPv6 routes from system.
+    fn get_ipv6_route_all(&self) -> Result<(), KernelError>;
+
+
+    /// Add an IPv4 route to the kernel table, or replace existing one atomically.
+    /// Driver may queue the request, and report the result through FIB result callback.
+    fn add_ipv4_route(&self, table_id: u32, prefix: &Prefix<Ipv4Addr>, rib: &Rib<Ipv4Addr>) -> Result<(), KernelError>;
+
+    /// Delete an IPv4 route from the kernel table.
+    fn delete_ipv4_route(&self, table_id: u32, prefix: &Prefix<Ipv4Addr>, rib: &Rib<Ipv4Addr>) -> Result<(), KernelError>;
+
+    /// Add an IPv6 route to the kernel table, or replace existing one atomically.
+    /// Driver may queue the request, and report the result through FIB result callback.
+    fn add_ipv6_route(&self, table_id: u32, prefix: &Prefix<Ipv6Addr>, rib: &Rib<Ipv6Addr>) -> Result<(), KernelError>;
 
-    /// Delete an IPv4 route from system.
-    fn delete_ipv4_route(&self, prefix: &Prefix<Ipv4Addr>, rib: &Rib<Ipv4Addr>);
+    /// Delete an IPv6 route from the kernel table.
+    fn delete_ipv6_route(&self, table_id: u32, prefix: &Prefix<Ipv6Addr>, rib: &Rib<Ipv6Addr>) -> Result<(), KernelError>;
 
-    /// Add an IPv6 route to system.
-    fn add_ipv6_route(&self, prefix: &Prefix<Ipv6Addr>, rib: &Rib<Ipv6Addr>);
+    /// Add an MPLS route to the kernel label table, or replace existing one atomically.
+    fn add_mpls_route(&self, lsp: &Lsp) -> Result<(), KernelError>;
 
-    /// Delete an IPv6 route from system.
-    fn delete_ipv6_route(&self, prefix: &Prefix<Ipv6Addr>, rib: &Rib<Ipv6Addr>);
+    /// Delete an MPLS route from the kernel label table.
+    fn delete_mpls_route(&self, lsp: &Lsp) -> Result<(), KernelError>;
 }
 
+/// Kernel driver talking to Linux kernel through Netlink.
+pub const KERNEL_DRIVER_NETLINK: &str = "netlink";
+
+/// Kernel driver keeping routes in memory, for testing.
+pub const KERNEL_DRIVER_MOCK: &str = "mock";
+
 /// Kernel driver.
 pub struct Kernel {
 
-    /// Kernel driver for Linux.
+    /// Kernel driver.
     driver: Arc<dyn KernelDriver>,
 }
 
 /// Kernel implementation.
 impl Kernel {
 
-    /// Constructor.
-    pub fn new() -> Kernel {
-        if let Some(driver) = get_driver() {
-            Kernel {
-                driver: Arc::new(driver),
-            }
-        } else {
-            panic!("Failed to intitialize Kernel");
+    /// Constructor, with driver selected by name.
+    pub fn new(name: &str) -> Result<Kernel, KernelError> {
+        let driver: Arc<dyn KernelDriver> = match name {
+            #[cfg(target_os = "linux")]
+            KERNEL_DRIVER_NETLINK => match get_driver() {
+                Some(driver) => Arc::new(driver),
+                None => return Err(KernelError::System(format!("Failed to initialize {} driver", name))),
+            },
+            KERNEL_DRIVER_MOCK => Arc::new(MockKernelDriver::new()),
+            _ => return Err(KernelError::Other(format!("Unknown kernel driver {}", name))),
+        };
+
+        Ok(Kernel::from_driver(driver))
+    }
+
+    /// Constructor, with given driver.
+    pub fn from_driver(driver: Arc<dyn KernelDriver>) -> Kernel {
+        Kernel {
+            driver: driver,
         }
     }
 
     /// Initialization.
-    pub fn init(&mut self) {
+    pub fn init(&self) {
 
         if let Err(err) = self.driver.get_link_all() {
             error!("Kernel get_link_all error {}", err);
@@ -265,8 +570,21 @@ impl Kernel {
             error!("Kernel get_get_ipv6_address_all error {}", err);
         }
 
-        // route ipv4
-        // route ipv6
+        if let Err(err) = self.driver.get_ipv4_neighbor_all() {
+            error!("Kernel get_ipv4_neighbor_all error {}", err);
+        }
+
+        if let Err(err) = self.driver.get_ipv6_neighbor_all() {
+            error!("Kernel get_ipv6_neighbor_all error {}", err);
+        }
+
+        if let Err(err) = self.driver.get_ipv4_route_all() {
+            error!("Kernel get_ipv4_route_all error {}", err);
+        }
+
+        if let Err(err) = self.driver.get_ipv6_route_all() {
+            error!("Kernel get_ipv6_route_all error {}", err);
+        }
     }
 
     /// Return driver.
@@ -274,35 +592,141 @@ impl Kernel {
         self.driver.clone()
     }
 
+    /// Return file descriptor to watch kernel events.
+    pub fn event_fd(&self) -> Option<RawFd> {
+        self.driver.event_fd()
+    }
+
+    /// Process kernel events.
+    pub fn process_event(&self) {
+        if let Err(err) = self.driver.process_event() {
+            error!("Kernel process_event error {}", err);
+        }
+    }
+
+    /// Return file descriptor to watch FIB results.
+    pub fn fib_fd(&self) -> Option<RawFd> {
+        self.driver.fib_fd()
+    }
+
+    /// Send queued route messages.
+    pub fn fib_flush(&self) {
+        if let Err(err) = self.driver.fib_flush() {
+            error!("Kernel fib_flush error {}", err);
+        }
+    }
+
+    /// Process FIB results.
+    pub fn process_fib_ack(&self) {
+        if let Err(err) = self.driver.process_fib_ack() {
+            error!("Kernel process_fib_ack error {}", err);
+        }
+    }
+
+    /// Delete stale routes through driver.
+    pub fn fib_sweep(&self) {
+        if let Err(err) = self.driver.fib_sweep() {
+            error!("Kernel fib_sweep error {}", err);
+        }
+    }
+
+    /// Retrieve statistics of all links through driver.
+    pub fn link_stats_refresh(&self) {
+        if let Err(err) = self.driver.get_link_stats_all() {
+            error!("Kernel get_link_stats_all error {}", err);
+        }
+    }
+
+    /// Set MTU on link through driver.
+    pub fn link_set_mtu(&self, ifindex: i32, mtu: u32) -> Result<(), KernelError> {
+        self.driver.set_mtu(ifindex, mtu)
+    }
+
+    /// Set link up through driver.
+    pub fn link_set_up(&self, ifindex: i32) -> Result<(), KernelError> {
+        self.driver.set_link_up(ifindex)
+    }
+
+    /// Set link down through driver.
+    pub fn link_set_down(&self, ifindex: i32) -> Result<(), KernelError> {
+        self.driver.set_link_down(ifindex)
+    }
+
+    /// Add an IPv4 address to link through driver.
+    pub fn ipv4_address_add(&self, ifindex: i32, prefix: &Prefix<Ipv4Addr>, label: Option<&str>) -> Result<(), KernelError> {
+        self.driver.add_ipv4_address(ifindex, prefix, label)
+    }
+
+    /// Delete an IPv4 address from link through driver.
+    pub fn ipv4_address_delete(&self, ifindex: i32, prefix: &Prefix<Ipv4Addr>) -> Result<(), KernelError> {
+        self.driver.delete_ipv4_address(ifindex, prefix)
+    }
+
+    /// Add an IPv6 address to link through driver.
+    pub fn ipv6_address_add(&self, ifindex: i32, prefix: &Prefix<Ipv6Addr>) -> Result<(), KernelError> {
+        self.driver.add_ipv6_address(ifindex, prefix)
+    }
+
+    /// Delete an IPv6 address from link through driver.
+    pub fn ipv6_address_delete(&self, ifindex: i32, prefix: &Prefix<Ipv6Addr>) -> Result<(), KernelError> {
+        self.driver.delete_ipv6_address(ifindex, prefix)
+    }
+
     /// Install an IPv4 route through driver.
-    pub fn ipv4_route_install(&self, prefix: &Prefix<Ipv4Addr>, new: &Rib<Ipv4Addr>) {
-        self.driver.add_ipv4_route(prefix, new);
+    pub fn ipv4_route_install(&self, table_id: u32, prefix: &Prefix<Ipv4Addr>, new: &Rib<Ipv4Addr>) -> Result<(), KernelError> {
+        self.driver.add_ipv4_route(table_id, prefix, new)
     }
 
-    /// Update an IPv4 route through driver.
-    pub fn ipv4_route_update(&self, prefix: &Prefix<Ipv4Addr>, new: &Rib<Ipv4Addr>, old: &Rib<Ipv4Addr>) {
-        self.driver.delete_ipv4_route(prefix, old);
-        self.driver.add_ipv4_route(prefix, new);
+    /// Update an IPv4 route through driver, replacing it without withdrawing first.
+    /// Metric is part of route identity in kernel, so old route is deleted afterwards if metric differs.
+    pub fn ipv4_route_update(&self, table_id: u32, prefix: &Prefix<Ipv4Addr>, new: &Rib<Ipv4Addr>, old: &Rib<Ipv4Addr>) -> Result<(), KernelError> {
+        self.driver.add_ipv4_route(table_id, prefix, new)?;
+
+        if new.metric() != old.metric() {
+            if let Err(err) = self.driver.delete_ipv4_route(table_id, prefix, old) {
+                error!("Kernel delete old route {} error {}", prefix, err);
+            }
+        }
+
+        Ok(())
     }
 
     /// Uninstall an IPv4 route through driver.
-    pub fn ipv4_route_uninstall(&self, prefix: &Prefix<Ipv4Addr>, old: &Rib<Ipv4Addr>) {
-        self.driver.delete_ipv4_route(prefix, old);
+    pub fn ipv4_route_uninstall(&self, table_id: u32, prefix: &Prefix<Ipv4Addr>, old: &Rib<Ipv4Addr>) -> Result<(), KernelError> {
+        self.driver.delete_ipv4_route(table_id, prefix, old)
     }
 
     /// Install an IPv6 route through driver.
-    pub fn ipv6_route_install(&self, prefix: &Prefix<Ipv6Addr>, new: &Rib<Ipv6Addr>) {
-        self.driver.add_ipv6_route(prefix, new);
+    pub fn ipv6_route_install(&self, table_id: u32, prefix: &Prefix<Ipv6Addr>, new: &Rib<Ipv6Addr>) -> Result<(), KernelError> {
+        self.driver.add_ipv6_route(table_id, prefix, new)
     }
 
-    /// Update an IPv6 route through driver.
-    pub fn ipv6_route_update(&self, prefix: &Prefix<Ipv6Addr>, new: &Rib<Ipv6Addr>, old: &Rib<Ipv6Addr>) {
-        self.driver.delete_ipv6_route(prefix, old);
-        self.driver.add_ipv6_route(prefix, new);
+    /// Update an IPv6 route through driver, replacing it without withdrawing first.
+    /// Metric is part of route identity in kernel, so old route is deleted afterwards if metric differs.
+    pub fn ipv6_route_update(&self, table_id: u32, prefix: &Prefix<Ipv6Addr>, new: &Rib<Ipv6Addr>, old: &Rib<Ipv6Addr>) -> Result<(), KernelError> {
+        self.driver.add_ipv6_route(table_id, prefix, new)?;
+
+        if new.metric() != old.metric() {
+            if let Err(err) = self.driver.delete_ipv6_route(table_id, prefix, old) {
+                error!("Kernel delete old route {} error {}", prefix, err);
+            }
+        }
+
+        Ok(())
     }
 
     /// Uninstall an IPv6 route through driver.
-    pub fn ipv6_route_uninstall(&self, prefix: &Prefix<Ipv6Addr>, old: &Rib<Ipv6Addr>) {
-        self.driver.delete_ipv6_route(prefix, old);
+    pub fn ipv6_route_uninstall(&self, table_id: u32, prefix: &Prefix<Ipv6Addr>, old: &Rib<Ipv6Addr>) -> Result<(), KernelError> {
+        self.driver.delete_ipv6_route(table_id, prefix, old)
+    }
+
+    /// Install an MPLS route through driver.
+    pub fn mpls_route_install(&self, lsp: &Lsp) -> Result<(), KernelError> {
+        self.driver.add_mpls_route(lsp)
+    }
+
+    /// Uninstall an MPLS route through driver.
+    pub fn mpls_route_uninstall(&self, lsp: &Lsp) -> Result<(), KernelError> {
+        self.driver.delete_mpls_route(lsp)
     }
 }
@@ -7,13 +7,59 @@
 
 use std::collections::HashMap;
 use std::rc::Rc;
+use std::cell::Cell;
+use std::cell::Ref;
 use std::cell::RefCell;
 use std::net::{Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, Instant};
 
 use log::error;
+use serde::Serialize;
+use serde::Serializer;
+use serde::ser::SerializeStruct;
+
+use crate::core::message::zebra::ZebraInterface;
 
 use super::address::*;
 use super::kernel::KernelLink;
+use super::kernel::KernelLinkStats;
+use super::kernel::KernelNeigh;
+use super::neighbor::NeighborTable;
+
+/// Default interval in seconds to poll link statistics and calculate rates.
+pub const LINK_STATS_INTERVAL_DEFAULT: u64 = 30;
+
+/// Maximum interval in seconds to poll link statistics.
+pub const LINK_STATS_INTERVAL_MAX: u64 = 3600;
+
+/// Rates are calculated when this fraction of interval is left, as polls do not happen exactly on time.
+const LINK_STATS_TOLERANCE_DIV: u32 = 10;
+
+/// Link change event, notified to the rest of zebra.
+#[derive(Debug, PartialEq, Clone)]
+pub enum LinkEvent {
+
+    /// New link.
+    Add,
+
+    /// Link is deleted.
+    Delete,
+
+    /// Link becomes operationally up.
+    Up,
+
+    /// Link becomes operationally down.
+    Down,
+
+    /// Link is renamed, with old name.
+    Rename(String),
+
+    /// Link is enslaved to another master device such as VRF, with old master index.
+    Master(Option<i32>),
+
+    /// Other attributes are changed, such as MTU or hardware address.
+    Update,
+}
 
 /// Link Master.
 pub struct LinkMaster {
@@ -21,8 +67,11 @@ pub struct LinkMaster {
     /// Ifindex to Link map.
     index_map: RefCell<HashMap<i32, Rc<Link>>>,
 
-    /// TBD
+    /// Name to Link map.
     name_map: RefCell<HashMap<String, Rc<Link>>>,
+
+    /// Interval in seconds to calculate statistics rates over.
+    stats_interval: Cell<u64>,
 }
 
 impl LinkMaster {
@@ -32,20 +81,111 @@ impl LinkMaster {
         LinkMaster {
             index_map: RefCell::new(HashMap::new()),
             name_map: RefCell::new(HashMap::new()),
+            stats_interval: Cell::new(LINK_STATS_INTERVAL_DEFAULT),
         }
     }
 
-    /// Add link to table.
-    pub fn add_link(&mut self, link: Link) {
-        let link = Rc::new(link);
+    /// Set interval to calculate statistics rates over.
+    pub fn set_stats_interval(&self, interval: u64) {
+        self.stats_interval.set(interval);
+    }
 
-        self.index_map.borrow_mut().insert(link.index(), link.clone());
-        self.name_map.borrow_mut().insert(String::from(link.name()), link.clone());
+    /// Return interval to calculate statistics rates over.
+    pub fn stats_interval(&self) -> u64 {
+        self.stats_interval.get()
     }
 
-    /// Delete link from tables.
-    pub fn delete_link(&mut self, _link: Link) {
-        // TBD
+    /// Add link to table, or update existing one with the same index.
+    /// Return the link and list of changes.
+    pub fn add_link(&mut self, kl: KernelLink) -> (Rc<Link>, Vec<LinkEvent>) {
+        let link = self.lookup_by_index(kl.ifindex);
+        let stats = kl.stats;
+        let interval = Duration::from_secs(self.stats_interval.get());
+
+        let (link, events) = match link {
+            Some(link) => {
+                let events = link.update(kl);
+
+                for event in events.iter() {
+                    if let LinkEvent::Rename(old) = event {
+                        self.name_map.borrow_mut().remove(old);
+                        self.name_map.borrow_mut().insert(link.name(), link.clone());
+                    }
+                }
+
+                (link, events)
+            },
+            None => {
+                let link = Rc::new(Link::from_kernel(kl));
+                let mut events = vec![LinkEvent::Add];
+
+                if link.is_up() {
+                    events.push(LinkEvent::Up);
+                }
+
+                self.index_map.borrow_mut().insert(link.index(), link.clone());
+                self.name_map.borrow_mut().insert(link.name(), link.clone());
+
+                (link, events)
+            }
+        };
+
+        if let Some(stats) = stats {
+            link.update_stats(stats, interval);
+        }
+
+        (link, events)
+    }
+
+    /// Update statistics of link with counters from kernel.
+    pub fn update_stats(&self, index: i32, counters: KernelLinkStats) {
+        if let Some(link) = self.lookup_by_index(index) {
+            link.update_stats(counters, Duration::from_secs(self.stats_interval.get()));
+        }
+    }
+
+    /// Delete link from tables, return deleted link if any.
+    pub fn delete_link(&mut self, index: i32) -> Option<Rc<Link>> {
+        let link = self.index_map.borrow_mut().remove(&index);
+
+        if let Some(ref link) = link {
+            let mut name_map = self.name_map.borrow_mut();
+
+            // Name might be already taken by another link.
+            let same = match name_map.get(&link.name()) {
+                Some(l) => l.index() == index,
+                None => false,
+            };
+            if same {
+                name_map.remove(&link.name());
+            }
+        }
+
+        link
+    }
+
+    /// Lookup link by name.
+    pub fn lookup_by_name(&self, name: &str) -> Option<Rc<Link>> {
+        match self.name_map.borrow().get(name) {
+            Some(link) => Some(link.clone()),
+            None => None,
+        }
+    }
+
+    /// Lookup link by index.
+    pub fn lookup_by_index(&self, index: i32) -> Option<Rc<Link>> {
+        match self.index_map.borrow().get(&index) {
+            Some(link) => Some(link.clone()),
+            None => None,
+        }
+    }
+
+    /// Return all links sorted by index.
+    pub fn links(&self) -> Vec<Rc<Link>> {
+        let mut links: Vec<Rc<Link>> = self.index_map.borrow().values().cloned().collect();
+
+        links.sort_by_key(|link| link.index());
+        links
     }
 
     /// Add IPv4 Address to link with the index.
@@ -79,6 +219,162 @@ impl LinkMaster {
             None => error!("No link found with index {}", index),
         }
     }
+
+    /// Add IPv4 neighbor to the link.
+    pub fn add_ipv4_neighbor(&mut self, kn: KernelNeigh<Ipv4Addr>) {
+        match self.index_map.borrow_mut().get(&kn.ifindex) {
+            Some(link) => link.add_ipv4_neighbor(kn),
+            None => error!("No link found with index {}", kn.ifindex),
+        }
+    }
+
+    /// Delete IPv4 neighbor from the link.
+    pub fn delete_ipv4_neighbor(&mut self, kn: KernelNeigh<Ipv4Addr>) {
+        match self.index_map.borrow_mut().get(&kn.ifindex) {
+            Some(link) => link.delete_ipv4_neighbor(&kn.address),
+            None => error!("No link found with index {}", kn.ifindex),
+        }
+    }
+
+    /// Add IPv6 neighbor to the link.
+    pub fn add_ipv6_neighbor(&mut self, kn: KernelNeigh<Ipv6Addr>) {
+        match self.index_map.borrow_mut().get(&kn.ifindex) {
+            Some(link) => link.add_ipv6_neighbor(kn),
+            None => error!("No link found with index {}", kn.ifindex),
+        }
+    }
+
+    /// Delete IPv6 neighbor from the link.
+    pub fn delete_ipv6_neighbor(&mut self, kn: KernelNeigh<Ipv6Addr>) {
+        match self.index_map.borrow_mut().get(&kn.ifindex) {
+            Some(link) => link.delete_ipv6_neighbor(&kn.address),
+            None => error!("No link found with index {}", kn.ifindex),
+        }
+    }
+}
+
+/// Link statistics rates per second.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LinkRates {
+
+    /// Bits received per second.
+    pub rx_bps: u64,
+
+    /// Bits transmitted per second.
+    pub tx_bps: u64,
+
+    /// Packets received per second.
+    pub rx_pps: u64,
+
+    /// Packets transmitted per second.
+    pub tx_pps: u64,
+
+    /// Receive errors and drops per second.
+    pub rx_errors: u64,
+
+    /// Transmit errors and drops per second.
+    pub tx_errors: u64,
+
+    /// Seconds the rates are calculated over.
+    pub interval: u64,
+}
+
+impl LinkRates {
+
+    /// Calculate rates from counters at the start and the end of interval.
+    pub fn calculate(start: &KernelLinkStats, end: &KernelLinkStats, elapsed: Duration) -> Option<LinkRates> {
+        let secs = elapsed.as_secs_f64();
+        if secs <= 0.0 {
+            return None
+        }
+
+        // Counters go backwards only when they are reset.
+        let rate = |start: u64, end: u64| end.checked_sub(start).map(|delta| (delta as f64 / secs) as u64);
+
+        Some(LinkRates {
+            rx_bps: rate(start.rx_bytes, end.rx_bytes)? * 8,
+            tx_bps: rate(start.tx_bytes, end.tx_bytes)? * 8,
+            rx_pps: rate(start.rx_packets, end.rx_packets)?,
+            tx_pps: rate(start.tx_packets, end.tx_packets)?,
+            rx_errors: rate(start.rx_errors + start.rx_dropped, end.rx_errors + end.rx_dropped)?,
+            tx_errors: rate(start.tx_errors + start.tx_dropped, end.tx_errors + end.tx_dropped)?,
+            interval: secs.round() as u64,
+        })
+    }
+}
+
+/// Link statistics.
+pub struct LinkStats {
+
+    /// Latest counters.
+    counters: KernelLinkStats,
+
+    /// Counters and time at the start of current interval.
+    sample: (KernelLinkStats, Instant),
+
+    /// Rates over last interval.
+    rates: Option<LinkRates>,
+}
+
+impl LinkStats {
+
+    /// Constructor.
+    pub fn new(counters: KernelLinkStats, now: Instant) -> LinkStats {
+        LinkStats {
+            counters: counters,
+            sample: (counters, now),
+            rates: None,
+        }
+    }
+
+    /// Update counters, and calculate rates over actual elapsed time once interval mostly passed
+    /// since the last sample.
+    pub fn update(&mut self, counters: KernelLinkStats, interval: Duration, now: Instant) {
+        let (start, instant) = self.sample;
+        let elapsed = now.saturating_duration_since(instant);
+
+        self.counters = counters;
+
+        if elapsed > Duration::from_secs(0) && elapsed + interval / LINK_STATS_TOLERANCE_DIV >= interval {
+            self.rates = LinkRates::calculate(&start, &counters, elapsed);
+            self.sample = (counters, now);
+        }
+    }
+
+    pub fn counters(&self) -> &KernelLinkStats {
+        &self.counters
+    }
+
+    pub fn rates(&self) -> Option<LinkRates> {
+        self.rates
+    }
+}
+
+/// Serializer for LinkStats.
+impl Serialize for LinkStats {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer
+    {
+        let mut s = serializer.serialize_struct("LinkStats", 15)?;
+        let rates = self.rates.unwrap_or_default();
+
+        s.serialize_field("rx_packets", &self.counters.rx_packets)?;
+        s.serialize_field("tx_packets", &self.counters.tx_packets)?;
+        s.serialize_field("rx_bytes", &self.counters.rx_bytes)?;
+        s.serialize_field("tx_bytes", &self.counters.tx_bytes)?;
+        s.serialize_field("rx_errors", &self.counters.rx_errors)?;
+        s.serialize_field("tx_errors", &self.counters.tx_errors)?;
+        s.serialize_field("rx_dropped", &self.counters.rx_dropped)?;
+        s.serialize_field("tx_dropped", &self.counters.tx_dropped)?;
+        s.serialize_field("rx_bps", &rates.rx_bps)?;
+        s.serialize_field("tx_bps", &rates.tx_bps)?;
+        s.serialize_field("rx_pps", &rates.rx_pps)?;
+        s.serialize_field("tx_pps", &rates.tx_pps)?;
+        s.serialize_field("rx_error_rate", &rates.rx_errors)?;
+        s.serialize_field("tx_error_rate", &rates.tx_errors)?;
+        s.serialize_field("rate_interval", &self.rates.map(|rates| rates.interval))?;
+        s.end()
+    }
 }
 
 /// Link.
@@ -88,20 +384,45 @@ pub struct Link {
     index: i32,
 
     /// Name from kernel.
-    name: String,
-    
+    name: RefCell<String>,
+
     /// Hardware type.
     hwtype: u16,
 
     /// Hardware address.
-    hwaddr: [u8; 6],
+    hwaddr: Cell<[u8; 6]>,
 
     /// MTU.
-    mtu: u32,
+    mtu: Cell<u32>,
+
+    /// MTU when the link is added, restored when configured MTU is removed.
+    mtu_default: u32,
+
+    /// Interface flags, IFF_UP, IFF_RUNNING, etc.
+    flags: Cell<u32>,
+
+    /// RFC2863 operational state.
+    operstate: Cell<u8>,
+
+    /// Link kind, such as "vlan", "bridge", etc.
+    kind: RefCell<Option<String>>,
+
+    /// Master device index, such as VRF or bridge.
+    master: Cell<Option<i32>>,
+
+    /// Kernel table ID, if the link is a VRF device.
+    vrf_table: Cell<Option<u32>>,
+
+    /// Statistics, if kernel reports them.
+    stats: RefCell<Option<LinkStats>>,
 
     /// Connected addresses.
     addr4: RefCell<Vec<Connected<Ipv4Addr>>>,
     addr6: RefCell<Vec<Connected<Ipv6Addr>>>,
+
+    /// ARP and IPv6 neighbor tables.
+    neigh4: RefCell<NeighborTable<Ipv4Addr>>,
+    neigh6: RefCell<NeighborTable<Ipv6Addr>>,
 }
 
 impl Link {
@@ -110,12 +431,21 @@ impl Link {
     pub fn new(index: i32, name: &str, hwtype: u16, hwaddr: [u8; 6], mtu: u32) -> Link {
         Link {
             index,
-            name: name.to_string(),
+            name: RefCell::new(name.to_string()),
             hwtype: hwtype,
-            hwaddr: hwaddr,
-            mtu: mtu,
+            hwaddr: Cell::new(hwaddr),
+            mtu: Cell::new(mtu),
+            mtu_default: mtu,
+            flags: Cell::new(0),
+            operstate: Cell::new(0),
+            kind: RefCell::new(None),
+            master: Cell::new(None),
+            vrf_table: Cell::new(None),
+            stats: RefCell::new(None),
             addr4: RefCell::new(Vec::new()),
             addr6: RefCell::new(Vec::new()),
+            neigh4: RefCell::new(NeighborTable::new()),
+            neigh6: RefCell::new(NeighborTable::new()),
         }
     }
 
@@ -124,32 +454,147 @@ impl Link {
         Link {
             index: kl.ifindex,
             hwtype: kl.hwtype,
-            name: kl.name,
-            hwaddr: kl.hwaddr,
-            mtu: kl.mtu,
+            name: RefCell::new(kl.name),
+            hwaddr: Cell::new(kl.hwaddr),
+            mtu: Cell::new(kl.mtu),
+            mtu_default: kl.mtu,
+            flags: Cell::new(kl.flags),
+            operstate: Cell::new(kl.operstate),
+            kind: RefCell::new(kl.kind),
+            master: Cell::new(kl.master),
+            vrf_table: Cell::new(kl.vrf_table),
+            stats: RefCell::new(None),
             addr4: RefCell::new(Vec::new()),
             addr6: RefCell::new(Vec::new()),
+            neigh4: RefCell::new(NeighborTable::new()),
+            neigh6: RefCell::new(NeighborTable::new()),
         }
     }
 
+    /// Update link with KernelLink, and return list of changes.
+    pub fn update(&self, kl: KernelLink) -> Vec<LinkEvent> {
+        let mut events = Vec::new();
+        let was_up = self.is_up();
+
+        if *self.name.borrow() != kl.name {
+            let old = self.name.replace(kl.name);
+            events.push(LinkEvent::Rename(old));
+        }
+
+        if self.mtu.get() != kl.mtu || self.hwaddr.get() != kl.hwaddr {
+            self.mtu.set(kl.mtu);
+            self.hwaddr.set(kl.hwaddr);
+            events.push(LinkEvent::Update);
+        }
+
+        self.flags.set(kl.flags);
+        self.operstate.set(kl.operstate);
+        self.kind.replace(kl.kind);
+        self.vrf_table.set(kl.vrf_table);
+
+        if self.master.get() != kl.master {
+            let old = self.master.replace(kl.master);
+            events.push(LinkEvent::Master(old));
+        }
+
+        match (was_up, self.is_up()) {
+            (false, true) => events.push(LinkEvent::Up),
+            (true, false) => events.push(LinkEvent::Down),
+            _ => {}
+        }
+
+        events
+    }
+
     pub fn index(&self) -> i32 {
         self.index
     }
 
-    pub fn name(&self) -> &str {
-        &self.name
+    pub fn name(&self) -> String {
+        self.name.borrow().clone()
     }
 
     pub fn hwtype(&self) -> u16 {
         self.hwtype
     }
 
-    pub fn hwaddr(&self) -> &[u8; 6] {
-        &self.hwaddr
+    pub fn hwaddr(&self) -> [u8; 6] {
+        self.hwaddr.get()
     }
 
     pub fn mtu(&self) -> u32 {
-        self.mtu
+        self.mtu.get()
+    }
+
+    pub fn mtu_default(&self) -> u32 {
+        self.mtu_default
+    }
+
+    pub fn flags(&self) -> u32 {
+        self.flags.get()
+    }
+
+    pub fn operstate(&self) -> u8 {
+        self.operstate.get()
+    }
+
+    pub fn kind(&self) -> Option<String> {
+        self.kind.borrow().clone()
+    }
+
+    pub fn master(&self) -> Option<i32> {
+        self.master.get()
+    }
+
+    pub fn vrf_table(&self) -> Option<u32> {
+        self.vrf_table.get()
+    }
+
+    pub fn stats(&self) -> Ref<Option<LinkStats>> {
+        self.stats.borrow()
+    }
+
+    /// Update statistics with counters from kernel.
+    pub fn update_stats(&self, counters: KernelLinkStats, interval: Duration) {
+        self.update_stats_at(counters, interval, Instant::now());
+    }
+
+    /// Update statistics with counters retrieved at given time.
+    fn update_stats_at(&self, counters: KernelLinkStats, interval: Duration, now: Instant) {
+        let mut stats = self.stats.borrow_mut();
+
+        match stats.as_mut() {
+            Some(stats) => stats.update(counters, interval, now),
+            None => *stats = Some(LinkStats::new(counters, now)),
+        }
+    }
+
+    /// Return true if link is administratively up.
+    pub fn is_admin_up(&self) -> bool {
+        self.flags.get() & libc::IFF_UP as u32 != 0
+    }
+
+    /// Return true if link is operationally up, administratively up and running.
+    pub fn is_up(&self) -> bool {
+        self.is_admin_up() && self.flags.get() & libc::IFF_RUNNING as u32 != 0
+    }
+
+    /// Return true if link is loopback.
+    pub fn is_loopback(&self) -> bool {
+        self.flags.get() & libc::IFF_LOOPBACK as u32 != 0
+    }
+
+    /// Construct interface to be notified to protocol.
+    pub fn to_zebra_interface(&self, table_id: u32) -> ZebraInterface {
+        ZebraInterface {
+            index: self.index,
+            name: self.name(),
+            hwaddr: self.hwaddr(),
+            mtu: self.mtu(),
+            flags: self.flags(),
+            up: self.is_up(),
+            table_id: table_id,
+        }
     }
 
     pub fn add_ipv4_address(&self, conn: Connected<Ipv4Addr>) {
@@ -157,33 +602,212 @@ impl Link {
     }
 
     pub fn delete_ipv4_address(&self, conn: Connected<Ipv4Addr>) {
-        let len = self.addr4.borrow().len();
-        let list = self.addr4.borrow_mut();
-
-        for i in 0..len {
-            let c = &list[i];
-            if c.address() == conn.address() {
-                self.addr4.borrow_mut().remove(i);
-                break;
-            }
+        let mut list = self.addr4.borrow_mut();
+
+        if let Some(i) = list.iter().position(|c| c.address() == conn.address()) {
+            list.remove(i);
         }
     }
 
+    pub fn addr4(&self) -> Ref<Vec<Connected<Ipv4Addr>>> {
+        self.addr4.borrow()
+    }
+
     pub fn add_ipv6_address(&self, conn: Connected<Ipv6Addr>) {
         self.addr6.borrow_mut().push(conn);
     }
 
     pub fn delete_ipv6_address(&self, conn: Connected<Ipv6Addr>) {
-        let len = self.addr6.borrow().len();
-        let list = self.addr6.borrow_mut();
-
-        for i in 0..len {
-            let c = &list[i];
-            if c.address() == conn.address() {
-                self.addr6.borrow_mut().remove(i);
-                break;
-            }
+        let mut list = self.addr6.borrow_mut();
+
+        if let Some(i) = list.iter().position(|c| c.address() == conn.address()) {
+            list.remove(i);
         }
     }
+
+    pub fn addr6(&self) -> Ref<Vec<Connected<Ipv6Addr>>> {
+        self.addr6.borrow()
+    }
+
+    pub fn add_ipv4_neighbor(&self, kn: KernelNeigh<Ipv4Addr>) {
+        self.neigh4.borrow_mut().add(kn);
+    }
+
+    pub fn delete_ipv4_neighbor(&self, address: &Ipv4Addr) {
+        self.neigh4.borrow_mut().delete(address);
+    }
+
+    pub fn neigh4(&self) -> Ref<NeighborTable<Ipv4Addr>> {
+        self.neigh4.borrow()
+    }
+
+    pub fn add_ipv6_neighbor(&self, kn: KernelNeigh<Ipv6Addr>) {
+        self.neigh6.borrow_mut().add(kn);
+    }
+
+    pub fn delete_ipv6_neighbor(&self, address: &Ipv6Addr) {
+        self.neigh6.borrow_mut().delete(address);
+    }
+
+    pub fn neigh6(&self) -> Ref<NeighborTable<Ipv6Addr>> {
+        self.neigh6.borrow()
+    }
 }
 
+/// Serializer for Link.
+impl Serialize for Link {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer
+    {
+        let mut s = serializer.serialize_struct("Link", 15)?;
+        let hwaddr_str = self.hwaddr.get().iter()
+            .map(|b| format!("{:02x}", b)).collect::<Vec<String>>().join(":");
+
+        s.serialize_field("index", &self.index)?;
+        s.serialize_field("name", &*self.name.borrow())?;
+        s.serialize_field("hwtype", &self.hwtype)?;
+        s.serialize_field("hwaddr", &hwaddr_str)?;
+        s.serialize_field("mtu", &self.mtu.get())?;
+        s.serialize_field("flags", &self.flags.get())?;
+        s.serialize_field("operstate", &self.operstate.get())?;
+        s.serialize_field("kind", &*self.kind.borrow())?;
+        s.serialize_field("master", &self.master.get())?;
+        s.serialize_field("vrf_table", &self.vrf_table.get())?;
+        s.serialize_field("admin_up", &self.is_admin_up())?;
+        s.serialize_field("up", &self.is_up())?;
+        s.serialize_field("ipv4_addresses", &*self.addr4.borrow())?;
+        s.serialize_field("ipv6_addresses", &*self.addr6.borrow())?;
+        s.serialize_field("statistics", &*self.stats.borrow())?;
+        s.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rtable::prefix::*;
+    use super::*;
+
+    fn kernel_link(index: i32, name: &str, flags: u32) -> KernelLink {
+        let mut kl = KernelLink::new(index, name, 1, [0, 1, 2, 3, 4, 5], 1500);
+        kl.flags = flags;
+        kl
+    }
+
+    #[test]
+    pub fn test_link_events() {
+        let mut lm = LinkMaster::new();
+        let up = (libc::IFF_UP | libc::IFF_RUNNING) as u32;
+
+        let (_, events) = lm.add_link(kernel_link(2, "eth0", 0));
+        assert_eq!(events, vec![LinkEvent::Add]);
+
+        let (link, events) = lm.add_link(kernel_link(2, "eth0", up));
+        assert_eq!(events, vec![LinkEvent::Up]);
+        assert!(link.is_up());
+
+        let (_, events) = lm.add_link(kernel_link(2, "wan0", libc::IFF_UP as u32));
+        assert_eq!(events, vec![LinkEvent::Rename("eth0".to_string()), LinkEvent::Down]);
+        assert!(lm.lookup_by_name("eth0").is_none());
+        assert_eq!(lm.lookup_by_name("wan0").unwrap().index(), 2);
+
+        let mut kl = kernel_link(2, "wan0", libc::IFF_UP as u32);
+        kl.master = Some(10);
+        let (link, events) = lm.add_link(kl);
+        assert_eq!(events, vec![LinkEvent::Master(None)]);
+        assert_eq!(link.master(), Some(10));
+
+        let link = lm.delete_link(2).unwrap();
+        assert_eq!(link.name(), "wan0");
+        assert!(lm.lookup_by_index(2).is_none());
+        assert!(lm.lookup_by_name("wan0").is_none());
+    }
+
+    #[test]
+    pub fn test_link_serialize() {
+        let link = Link::from_kernel(kernel_link(2, "eth0", libc::IFF_UP as u32));
+        let prefix = Prefix::<Ipv4Addr>::from_str("10.0.0.1/24").unwrap();
+        link.add_ipv4_address(Connected::new(prefix));
+
+        let json: serde_json::Value = serde_json::to_value(&link).unwrap();
+        assert_eq!(json["name"], "eth0");
+        assert_eq!(json["hwaddr"], "00:01:02:03:04:05");
+        assert_eq!(json["admin_up"], true);
+        assert_eq!(json["up"], false);
+        assert_eq!(json["ipv4_addresses"][0]["address"], "10.0.0.1/24");
+        assert_eq!(json["ipv4_addresses"][0]["peer"], serde_json::Value::Null);
+        assert_eq!(json["statistics"], serde_json::Value::Null);
+    }
+
+    #[test]
+    pub fn test_link_stats() {
+        let mut lm = LinkMaster::new();
+        let mut kl = kernel_link(2, "eth0", libc::IFF_UP as u32);
+        let mut counters = KernelLinkStats::default();
+
+        kl.stats = Some(counters);
+        let (link, _) = lm.add_link(kl);
+        assert_eq!(link.stats().as_ref().unwrap().rates(), None);
+
+        let interval = Duration::from_secs(10);
+        let start = link.stats().as_ref().unwrap().sample.1;
+
+        // Counters are updated, but rates are not until interval passes.
+        counters.rx_packets = 10;
+        counters.rx_bytes = 1000;
+        link.update_stats_at(counters, interval, start + Duration::from_secs(5));
+        assert_eq!(link.stats().as_ref().unwrap().counters().rx_bytes, 1000);
+        assert_eq!(link.stats().as_ref().unwrap().rates(), None);
+
+        counters.rx_packets = 25;
+        counters.rx_bytes = 2500;
+        counters.tx_dropped = 10;
+        link.update_stats_at(counters, interval, start + Duration::from_secs(10));
+
+        let rates = link.stats().as_ref().unwrap().rates().unwrap();
+        assert_eq!(rates.rx_bps, 2000);
+        assert_eq!(rates.rx_pps, 2);
+        assert_eq!(rates.tx_bps, 0);
+        assert_eq!(rates.tx_errors, 1);
+        assert_eq!(rates.interval, 10);
+
+        let json: serde_json::Value = serde_json::to_value(&*link).unwrap();
+        assert_eq!(json["statistics"]["rx_bytes"], 2500);
+        assert_eq!(json["statistics"]["rx_bps"], 2000);
+        assert_eq!(json["statistics"]["rate_interval"], 10);
+
+        // Poll slightly early still calculates rates, over actual elapsed time.
+        counters.rx_packets = 43;
+        link.update_stats_at(counters, interval, start + Duration::from_secs(19));
+        let rates = link.stats().as_ref().unwrap().rates().unwrap();
+        assert_eq!(rates.rx_pps, 2);
+        assert_eq!(rates.interval, 9);
+
+        // Counters polled separately from link.
+        counters.rx_packets = 63;
+        lm.update_stats(2, counters);
+        assert_eq!(link.stats().as_ref().unwrap().counters().rx_packets, 63);
+
+        // Counters reset, such as device re-created.
+        link.update_stats_at(KernelLinkStats::default(), interval, start + Duration::from_secs(30));
+        assert_eq!(link.stats().as_ref().unwrap().rates(), None);
+    }
+
+    #[test]
+    pub fn test_link_zebra_interface() {
+        let link = Link::from_kernel(kernel_link(2, "eth0", (libc::IFF_UP | libc::IFF_RUNNING) as u32));
+        let prefix = Prefix::<Ipv4Addr>::from_str("10.0.0.1/24").unwrap();
+        let conn = Connected::new(prefix.clone());
+
+        let zi = link.to_zebra_interface(254);
+        assert_eq!(zi.index, 2);
+        assert_eq!(zi.name, "eth0");
+        assert_eq!(zi.mtu, 1500);
+        assert!(zi.up);
+        assert_eq!(zi.table_id, 254);
+
+        let za = conn.to_zebra_interface_addr(link.index());
+        assert_eq!(za.index, 2);
+        assert_eq!(za.address, prefix);
+        assert_eq!(za.destination, None);
+    }
+}
@@ -3,7 +3,7 @@
 //   Copyright (C) 2018-2020 Toshiaki Takada
 //
 // Encode:
-//  Low level utility functions to set/get arbitrary value into/from buffer.
+//  Low level utility functions to set arbitrary value into buffer.
 //  with unsafe operation.  All integer values are host byte order.
 //
 
@@ -24,10 +24,3 @@ pub fn encode_num<T>(buf: &mut [u8], v: T) {
         *dst = v;
     }
 }
-
-/// Return integer value in host byte order.
-pub fn decode_num<T>(buf: &[u8]) -> T
-where T: Copy
-{
-    unsafe { *(buf as *const _ as *const T) }
-}
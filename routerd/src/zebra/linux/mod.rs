@@ -5,5 +5,7 @@
 
 pub mod encode;
 pub mod netlink;
+pub mod nexthop;
+pub mod parse;
 pub mod rtnetlink;
     
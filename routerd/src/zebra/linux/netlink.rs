@@ -7,11 +7,14 @@
 
 use std::io;
 use std::str;
+use std::os::unix::io::RawFd;
+use std::ffi::CString;
 use std::mem::{size_of, zeroed};
 use std::cell::Cell;
 use std::cell::RefCell;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use libc::{self, c_int/*, c_void, c_uchar*/};
 use log::debug;
 use log::info;
@@ -24,20 +27,52 @@ use common::nexthop::*;
 
 use super::rtnetlink::*;
 use super::encode::*;
+use super::nexthop::*;
+use super::parse::*;
 use super::super::kernel::*;
 use super::super::rib::*;
+use super::super::mpls::Lsp;
 
 
 const RTMGRP_LINK: libc::c_int = 1;
+const RTMGRP_NEIGH: libc::c_int = 4;
 const RTMGRP_IPV4_IFADDR: libc::c_int = 0x10;
 const RTMGRP_IPV4_ROUTE: libc::c_int = 0x40;
 const RTMGRP_IPV6_IFADDR: libc::c_int = 0x100;
 const RTMGRP_IPV6_ROUTE: libc::c_int = 0x400;
 
-const RTPROT_ZEBRA: libc::c_int = 11;
+const RTM_NEWNEXTHOP: libc::c_int = 104;
+const RTM_DELNEXTHOP: libc::c_int = 105;
+const RTM_GETNEXTHOP: libc::c_int = 106;
+
+const NHA_ID: libc::c_int = 1;
+const NHA_GROUP: libc::c_int = 2;
+const NHA_OIF: libc::c_int = 5;
+const NHA_GATEWAY: libc::c_int = 6;
+
+const RTA_NH_ID: libc::c_int = 30;
+
+/// Prefix length of MPLS route, a single label.
+const MPLS_LABEL_BITLEN: u8 = 20;
+
+/// Nexthop object IDs are allocated from here, to avoid IDs used by other applications.
+const NEXTHOP_ID_BASE: u32 = 0x1000_0000;
 
 const NETLINK_RECV_BUFSIZ: usize = 4096;
 
+/// Size of batched route messages sent at once.
+const NETLINK_FIB_BATCH_SIZ: usize = 65536;
+
+/// Receive buffer size of FIB socket, enough to hold ACKs for a full batch.
+/// Each ACK is queued as its own skb, accounted around 1KiB, for a message of 64 octets or more.
+const NETLINK_FIB_RCVBUF: usize = NETLINK_FIB_BATCH_SIZ / 64 * 1024;
+
+/// Initial receive buffer size of event socket.
+const NETLINK_EVENT_RCVBUF: usize = 1024 * 1024;
+
+/// Receive buffer is doubled on overflow, up to this size.
+const NETLINK_RCVBUF_MAX: usize = 32 * 1024 * 1024;
+
 const NLMSG_ALIGNTO: usize = 4usize;
 
 /// Dump Netlink message.
@@ -66,55 +101,6 @@ fn nlmsg_length(len: usize) -> usize {
     nlmsg_hdrlen() + len
 }
 
-fn nlmsg_data() -> usize {
-    size_of::<libc::nlmsghdr>()
-}
-
-fn nlmsg_attr<T>() -> usize {
-    nlmsg_data() + nlmsg_align(size_of::<T>())
-}
-
-fn nlmsg_attr_ok(buf: &[u8]) -> bool {
-    // Ensure the size of buffer has enough space for Rtattr.
-    if buf.len() >= size_of::<Rtattr>() {
-        let rta = buf as *const _ as *const Rtattr;
-        let rta_len = unsafe { (*rta).rta_len as usize };
-        if rta_len >= size_of::<Rtattr>() && rta_len <= buf.len() {
-            true
-        } else {
-            false
-        }
-    } else {
-        false
-    }
-}
-
-fn nlmsg_parse_attr<'a>(buf: &'a [u8]) -> AttrMap {
-    let mut m = AttrMap::new();
-    let mut b = &buf[..];
-
-    while nlmsg_attr_ok(b) {
-        let rta = b as *const _ as *const Rtattr;
-        unsafe {
-            let rta_len = (*rta).rta_len as usize;
-            let rta_type = (*rta).rta_type as i32;
-            let _payload_len = rta_len - size_of::<Rtattr>();
-
-            m.insert(rta_type, &b[size_of::<Rtattr>()..rta_len]);
-
-            b = &b[nlmsg_align(rta_len)..];
-        }
-    }
-
-    debug!("Attrs: {:?}", m.keys().collect::<Vec<&i32>>());
-
-    m
-}
-
-/// Typedefs.
-pub type AttrMap<'a> = HashMap<c_int, &'a [u8]>;
-
-
 /// struct nlmsghdr from /usr/include/linux/netlink.h.
 ///
 ///   0                   1                   2                   3
@@ -173,6 +159,16 @@ struct Rtgenmsg {
     rtgen_family: libc::c_uchar
 }
 
+/// struct if_stats_msg from if_link.h.
+#[repr(C)]
+struct IfStatsmsg {
+    family: u8,
+    pad1: u8,
+    pad2: u16,
+    ifindex: u32,
+    filter_mask: u32,
+}
+
 /// struct ifinfomsg from rtnetlink.h.
 #[repr(C)]
 struct Ifinfomsg {
@@ -194,9 +190,23 @@ struct Ifaddrmsg {
     ifa_index: u32,
 }
 
-/// Dummy placeholder for netlink_talk
+/// struct nhmsg from nexthop.h.
+///
+///   0                   1                   2                   3
+///   0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+///  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+///  |   nh_family   |   nh_scope    |  nh_protocol  |     resvd     |
+///  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+///  |                           nh_flags                            |
+///  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+///
 #[repr(C)]
-struct NlDummy {
+struct Nhmsg {
+    nh_family: u8,
+    nh_scope: u8,
+    nh_protocol: u8,
+    resvd: u8,
+    nh_flags: u32,
 }
 
 struct Buffer {
@@ -244,6 +254,27 @@ pub struct NetlinkKernelCallback {
 
     /// Delete IPv6 Route callback.
     pub delete_ipv6_route: Option<Box<dyn Fn(KernelRoute<Ipv6Addr>)>>,
+
+    /// Add IPv4 Neighbor callback.
+    pub add_ipv4_neighbor: Option<Box<dyn Fn(KernelNeigh<Ipv4Addr>)>>,
+
+    /// Delete IPv4 Neighbor callback.
+    pub delete_ipv4_neighbor: Option<Box<dyn Fn(KernelNeigh<Ipv4Addr>)>>,
+
+    /// Add IPv6 Neighbor callback.
+    pub add_ipv6_neighbor: Option<Box<dyn Fn(KernelNeigh<Ipv6Addr>)>>,
+
+    /// Delete IPv6 Neighbor callback.
+    pub delete_ipv6_neighbor: Option<Box<dyn Fn(KernelNeigh<Ipv6Addr>)>>,
+
+    /// IPv4 FIB result callback.
+    pub ipv4_fib_result: Option<Box<dyn Fn(KernelFibResult<Ipv4Addr>)>>,
+
+    /// IPv6 FIB result callback.
+    pub ipv6_fib_result: Option<Box<dyn Fn(KernelFibResult<Ipv6Addr>)>>,
+
+    /// Link statistics callback.
+    pub link_stats: Option<Box<dyn Fn(i32, KernelLinkStats)>>,
 }
 
 impl NetlinkKernelCallback {
@@ -260,6 +291,13 @@ impl NetlinkKernelCallback {
             delete_ipv4_route: None,
             add_ipv6_route: None,
             delete_ipv6_route: None,
+            add_ipv4_neighbor: None,
+            delete_ipv4_neighbor: None,
+            add_ipv6_neighbor: None,
+            delete_ipv6_neighbor: None,
+            ipv4_fib_result: None,
+            ipv6_fib_result: None,
+            link_stats: None,
         }
     }
 
@@ -342,14 +380,126 @@ impl NetlinkKernelCallback {
             debug!("Delete IPv6 route callback function is not set.");
         }
     }
+
+    pub fn call_add_ipv4_neighbor(&self, neigh: KernelNeigh<Ipv4Addr>) {
+        if let Some(f) = &self.add_ipv4_neighbor {
+            (*f)(neigh);
+        } else {
+            debug!("Add IPv4 neighbor callback function is not set.");
+        }
+    }
+
+    pub fn call_delete_ipv4_neighbor(&self, neigh: KernelNeigh<Ipv4Addr>) {
+        if let Some(f) = &self.delete_ipv4_neighbor {
+            (*f)(neigh);
+        } else {
+            debug!("Delete IPv4 neighbor callback function is not set.");
+        }
+    }
+
+    pub fn call_add_ipv6_neighbor(&self, neigh: KernelNeigh<Ipv6Addr>) {
+        if let Some(f) = &self.add_ipv6_neighbor {
+            (*f)(neigh);
+        } else {
+            debug!("Add IPv6 neighbor callback function is not set.");
+        }
+    }
+
+    pub fn call_delete_ipv6_neighbor(&self, neigh: KernelNeigh<Ipv6Addr>) {
+        if let Some(f) = &self.delete_ipv6_neighbor {
+            (*f)(neigh);
+        } else {
+            debug!("Delete IPv6 neighbor callback function is not set.");
+        }
+    }
+
+    pub fn call_ipv4_fib_result(&self, result: KernelFibResult<Ipv4Addr>) {
+        if let Some(f) = &self.ipv4_fib_result {
+            (*f)(result);
+        } else {
+            debug!("IPv4 FIB result callback function is not set.");
+        }
+    }
+
+    pub fn call_ipv6_fib_result(&self, result: KernelFibResult<Ipv6Addr>) {
+        if let Some(f) = &self.ipv6_fib_result {
+            (*f)(result);
+        } else {
+            debug!("IPv6 FIB result callback function is not set.");
+        }
+    }
+
+    pub fn call_link_stats(&self, ifindex: i32, stats: KernelLinkStats) {
+        if let Some(f) = &self.link_stats {
+            (*f)(ifindex, stats);
+        } else {
+            debug!("Link statistics callback function is not set.");
+        }
+    }
+}
+
+/// Route message sent to kernel, waiting for ACK.
+struct FibPending {
+
+    /// Kernel table ID.
+    table_id: u32,
+
+    /// Address family.
+    family: c_int,
+
+    /// Destination address.
+    dst: Vec<u8>,
+
+    /// Destination prefix length.
+    len: u8,
+
+    /// Route priority.
+    metric: u32,
+
+    /// Operation.
+    op: FibOp,
+
+    /// Nexthop object ID referenced by route, or the object itself for nexthop message,
+    /// which has AF_UNSPEC family.
+    nhid: Option<u32>,
+
+    /// Generation of route message for the same route, assigned when queued.
+    generation: u64,
+
+    /// True if message deletes stale route, result is not reported to application.
+    stale: bool,
+}
+
+impl FibPending {
+    /// Return kernel route key the message is for.
+    fn route_key(&self) -> NexthopRouteKey {
+        NexthopRouteKey {
+            table_id: self.table_id,
+            family: self.family,
+            dst: self.dst.clone(),
+            len: self.len,
+            metric: self.metric,
+        }
+    }
+
+    /// Return stale route key the message is for.
+    fn stale_key(&self) -> StaleRouteKey {
+        (self.table_id, self.family, self.dst.clone(), self.len)
+    }
 }
 
+/// Route left by previous instance, keyed by table ID, family, destination and prefix length.
+type StaleRouteKey = (u32, c_int, Vec<u8>, u8);
+
 /// Netlink Socket handler.
 pub struct Netlink {
 
     /// File descriptor for Netlink socket.
     sock: c_int,
-    
+
+    /// File descriptor for Netlink event socket, subscribed to multicast groups.
+    event_sock: c_int,
+
     /// PID associated with this Netlink socket.
     pid: u32,
 
@@ -359,6 +509,45 @@ pub struct Netlink {
     /// Receive buffer.
     buf: RefCell<Buffer>,
 
+    /// Receive buffer for event socket.
+    event_buf: RefCell<Buffer>,
+
+    /// File descriptor for Netlink FIB socket, route messages are sent in batch
+    /// and acknowledged asynchronously.
+    fib_sock: c_int,
+
+    /// Receive buffer for FIB socket.
+    fib_buf: RefCell<Buffer>,
+
+    /// Route messages to be sent in next batch.
+    fib_batch: RefCell<Vec<u8>>,
+
+    /// Route messages waiting for ACK, per sequence number.
+    fib_pending: RefCell<HashMap<u32, FibPending>>,
+
+    /// Latest generation of route message queued per route, ACK for older one is ignored.
+    fib_generation: RefCell<HashMap<NexthopRouteKey, u64>>,
+
+    /// True while unused nexthop objects are queued for deletion, a batch sent meanwhile
+    /// does not collect them again.
+    fib_gc_running: Cell<bool>,
+
+    /// True if kernel supports nexthop objects.
+    nh_supported: Cell<bool>,
+
+    /// Nexthop objects shared by routes.
+    nh_table: RefCell<NexthopTable>,
+
+    /// True if kernel supports RTM_GETSTATS.
+    stats_supported: Cell<bool>,
+
+    /// Routes left in kernel by previous instance, not replaced or deleted so far, with priorities
+    /// they have in kernel.
+    stale_routes: RefCell<HashMap<StaleRouteKey, BTreeSet<u32>>>,
+
+    /// Nexthop objects left in kernel by previous instance.
+    stale_nexthops: RefCell<Vec<u32>>,
+
     /// Kernel callback functions.
     callback: RefCell<NetlinkKernelCallback>,
 }
@@ -377,92 +566,211 @@ impl Request {
     }
 }
 
+#[repr(C)]
+struct NhRequest {
+    nlmsghdr: Nlmsghdr,
+    nhmsg: Nhmsg,
+    buf: [u8; 4096],
+}
+
+impl NhRequest {
+    pub fn offset(&self) -> usize {
+        (self.nlmsghdr.nlmsg_len as usize) - (size_of::<Nlmsghdr>() + size_of::<Nhmsg>())
+    }
+}
+
+#[repr(C)]
+struct LinkRequest {
+    nlmsghdr: Nlmsghdr,
+    ifinfomsg: Ifinfomsg,
+    buf: [u8; 256],
+}
+
+impl LinkRequest {
+    pub fn offset(&self) -> usize {
+        (self.nlmsghdr.nlmsg_len as usize) - (size_of::<Nlmsghdr>() + size_of::<Ifinfomsg>())
+    }
+}
+
+#[repr(C)]
+struct AddrRequest {
+    nlmsghdr: Nlmsghdr,
+    ifaddrmsg: Ifaddrmsg,
+    buf: [u8; 256],
+}
+
+impl AddrRequest {
+    pub fn offset(&self) -> usize {
+        (self.nlmsghdr.nlmsg_len as usize) - (size_of::<Nlmsghdr>() + size_of::<Ifaddrmsg>())
+    }
+}
+
 impl Netlink {
-    /// Constructor - open Netlink socket and bind.
+    /// Constructor - open Netlink sockets for command and event.
     fn new() -> Result<Netlink, io::Error> {
-        let sock = unsafe {
-            libc::socket(libc::AF_NETLINK, libc::SOCK_RAW, libc::NETLINK_ROUTE)
-        };
-        if sock < 0 {
-            return Err(io::Error::last_os_error());
+        let (sock, pid) = netlink_socket(0)?;
+        let (event_sock, _) = netlink_socket(RTMGRP_LINK as u32 | RTMGRP_NEIGH as u32 |
+                                             RTMGRP_IPV4_IFADDR as u32 | RTMGRP_IPV4_ROUTE as u32 |
+                                             RTMGRP_IPV6_IFADDR as u32 | RTMGRP_IPV6_ROUTE as u32)?;
+
+        let (fib_sock, _) = netlink_socket(0)?;
+
+        // Event and FIB sockets are read when they are ready, never block.
+        netlink_set_nonblock(event_sock)?;
+        netlink_set_nonblock(fib_sock)?;
+
+        if let Err(err) = netlink_set_rcvbuf(event_sock, NETLINK_EVENT_RCVBUF) {
+            error!("Failed to set event socket receive buffer {}", err);
+        }
+        if let Err(err) = netlink_set_rcvbuf(fib_sock, NETLINK_FIB_RCVBUF) {
+            error!("Failed to set FIB socket receive buffer {}", err);
+        }
+
+        let netlink = Netlink {
+            sock,
+            event_sock,
+            pid,
+            seq: Cell::new(0u32),
+            buf: RefCell::new(Buffer::new()),
+            event_buf: RefCell::new(Buffer::new()),
+            fib_sock,
+            fib_buf: RefCell::new(Buffer::new()),
+            fib_batch: RefCell::new(Vec::with_capacity(NETLINK_FIB_BATCH_SIZ)),
+            fib_pending: RefCell::new(HashMap::new()),
+            fib_generation: RefCell::new(HashMap::new()),
+            fib_gc_running: Cell::new(false),
+            nh_supported: Cell::new(false),
+            nh_table: RefCell::new(NexthopTable::new(NEXTHOP_ID_BASE)),
+            stats_supported: Cell::new(false),
+            stale_routes: RefCell::new(HashMap::new()),
+            stale_nexthops: RefCell::new(Vec::new()),
+            callback: RefCell::new(NetlinkKernelCallback::new()),
         };
 
+        // Fall back to inline nexthops if kernel does not know nexthop objects.
+        let supported = netlink.nexthop_probe();
+        info!("Kernel nexthop objects {}", if supported { "supported" } else { "not supported" });
+        netlink.nh_supported.set(supported);
+
+        // Kernel older than 4.7 does not know RTM_GETSTATS, statistics come with links instead.
+        let supported = netlink.stats_probe();
+        info!("Kernel link statistics request {}", if supported { "supported" } else { "not supported" });
+        netlink.stats_supported.set(supported);
+
+        Ok(netlink)
+    }
+
+    /// Return true if kernel accepts link statistics dump request.
+    fn stats_probe(&self) -> bool {
+        if self.send_stats_request().is_err() {
+            return false
+        }
+
+        self.parse_info(&Netlink::parse_dummy).is_ok()
+    }
+
+    /// Return true if kernel accepts nexthop dump request.
+    /// Objects left by previous instance are kept until swept, new IDs are allocated after them.
+    fn nexthop_probe(&self) -> bool {
+        let seq = self.seq.get() + 1;
+        self.seq.set(seq);
+
         let mut snl = unsafe { zeroed::<libc::sockaddr_nl>() };
         snl.nl_family = libc::AF_NETLINK as u16;
-        snl.nl_groups = RTMGRP_LINK as u32 |
-                        RTMGRP_IPV4_IFADDR as u32 | RTMGRP_IPV4_ROUTE as u32 |
-                        RTMGRP_IPV6_IFADDR as u32 | RTMGRP_IPV6_ROUTE as u32;
-        let mut socklen: libc::socklen_t = size_of::<libc::sockaddr_nl>() as u32;
+
+        let mut req = unsafe { zeroed::<NhRequest>() };
+        req.nlmsghdr.nlmsg_len = nlmsg_length(size_of::<Nhmsg>()) as u32;
+        req.nlmsghdr.nlmsg_type = RTM_GETNEXTHOP as u16;
+        req.nlmsghdr.nlmsg_flags = libc::NLM_F_ROOT as u16 |
+                                   libc::NLM_F_MATCH as u16 |
+                                   libc::NLM_F_REQUEST as u16;
+        req.nlmsghdr.nlmsg_pid = self.pid;
+        req.nlmsghdr.nlmsg_seq = seq;
+        req.nhmsg.nh_family = libc::AF_UNSPEC as u8;
+
         let ret = unsafe {
-            libc::bind(
-                sock,
-                &snl as *const _ as *const libc::sockaddr,
-                socklen,
-            )
+            libc::sendto(self.sock,
+                         &req as *const _ as *const libc::c_void,
+                         req.nlmsghdr.nlmsg_len as usize, 0,
+                         &snl as *const _ as *const libc::sockaddr,
+                         size_of::<libc::sockaddr_nl>() as u32)
         };
         if ret < 0 {
-            return Err(io::Error::last_os_error());
+            return false
         }
 
-        let ret = unsafe {
-            libc::getsockname(
-                sock,
-                &mut snl as *const _ as *mut libc::sockaddr,
-                &mut socklen)
+        self.parse_info(&Netlink::parse_nexthop).is_ok()
+    }
+
+    /// Parse nexthop object in dump, and remember one created by previous instance.
+    fn parse_nexthop(&self, msg: &NlMsg) -> bool {
+        let (nhm, attr) = match msg.parse::<NhInfo>() {
+            Ok(v) => v,
+            Err(err) => {
+                error!("Nlmsg type {}: {}", msg.nlmsg_type, err);
+                return false
+            }
         };
-        if ret < 0 || socklen != size_of::<libc::sockaddr_nl>() as u32 {
-            return Err(io::Error::last_os_error());
+
+        if msg.nlmsg_type != RTM_NEWNEXTHOP as u16 || nhm.protocol != RTPROT_ZEBRA as u8 {
+            return true
         }
 
-        // TODO: set socket non-blocking. only for event socket.
+        if let Some(id) = attr_u32(&attr, NHA_ID) {
+            debug!("Stale nexthop object {}", id);
 
-        Ok(Netlink {
-            sock,
-            pid: snl.nl_pid,
-            seq: Cell::new(0u32),
-            buf: RefCell::new(Buffer::new()),
-            callback: RefCell::new(NetlinkKernelCallback::new()),
-        })
+            self.nh_table.borrow_mut().reserve(id);
+            self.stale_nexthops.borrow_mut().push(id);
+        }
+
+        true
     }
 
-    /// Install route to kernel.
-    fn install<T>(&self, prefix: &Prefix<T>, rib: &Rib<T>)
-    where T: Addressable
+    /// Install route to kernel, replace existing one if any.
+    fn install<T>(&self, table_id: u32, prefix: &Prefix<T>, rib: &Rib<T>) -> Result<(), KernelError>
+    where T: AddressFamily + Addressable
     {
-        match self.route_msg::<T>(libc::RTM_NEWROUTE as i32, prefix, rib) {
-            Ok(_) => {},
-            Err(err) => error!("{}", err.to_string())
-        }
+        self.route_msg::<T>(libc::RTM_NEWROUTE as i32, table_id, prefix, rib)
     }
 
     /// Unnstall route to kernel.
-    fn uninstall<T>(&self, prefix: &Prefix<T>, rib: &Rib<T>)
-    where T: Addressable
+    fn uninstall<T>(&self, table_id: u32, prefix: &Prefix<T>, rib: &Rib<T>) -> Result<(), KernelError>
+    where T: AddressFamily + Addressable
     {
-        match self.route_msg::<T>(libc::RTM_DELROUTE as i32, prefix, rib) {
-            Ok(_) => {},
-            Err(err) => error!("{}", err.to_string())
-        }
+        self.route_msg::<T>(libc::RTM_DELROUTE as i32, table_id, prefix, rib)
     }
 
     /// Build singlpath nexthop attrbute.
-    fn route_single_path<T>(&self, req: &mut Request, nexthops: &Vec<Nexthop<T>>) -> Result<usize, KernelError>
+    fn route_single_path<T>(&self, req: &mut Request, rib: &Rib<T>, nexthops: &Vec<Nexthop<T>>) -> Result<usize, KernelError>
     where T: Addressable
     {
-        let pos = req.offset();
         let mut len = 0;
 
         for nexthop in nexthops {
-            match nexthop  {
-                Nexthop::Address::<T>(address) => {
-                    let octets: &[u8] = address.octets_ref();
+            if let Some(address) = nexthop.address() {
+                let pos = req.offset();
+                let octets: &[u8] = address.octets_ref();
 
-                    len = nlmsg_addattr_l(&mut req.nlmsghdr.nlmsg_len,
-                                          &mut req.buf[pos..], libc::RTA_GATEWAY as i32,
-                                          &octets[..], T::byte_len() as usize)?;
-                },
-                Nexthop::Ifname(_ifname) => { },
-                Nexthop::Network::<T>(_prefix) => { },
+                len += nlmsg_addattr_l(&mut req.nlmsghdr.nlmsg_len,
+                                       &mut req.buf[pos..], libc::RTA_GATEWAY as i32,
+                                       &octets[..], T::byte_len() as usize)?;
+            }
+
+            if let Some(ifname) = nexthop.ifname() {
+                let pos = req.offset();
+                let ifindex = ifname_to_index(ifname)?;
+
+                len += nlmsg_addattr32(&mut req.nlmsghdr.nlmsg_len,
+                                       &mut req.buf[pos..], libc::RTA_OIF as i32, ifindex as u32)?;
+            }
+
+            if let Some(op) = rib.resolved_label(nexthop) {
+                if op.labels().len() > 0 {
+                    let pos = req.offset();
+
+                    len += nlmsg_addattr_mpls_encap(&mut req.nlmsghdr.nlmsg_len,
+                                                    &mut req.buf[pos..], op.labels())?;
+                }
             }
 
             break;
@@ -472,7 +780,7 @@ impl Netlink {
     }
 
     /// Build multipath nexthop attrbute.
-    fn route_multi_path<T>(&self, req: &mut Request, nexthops: &Vec<Nexthop<T>>) -> Result<usize, KernelError>
+    fn route_multi_path<T>(&self, req: &mut Request, rib: &Rib<T>, nexthops: &Vec<Nexthop<T>>) -> Result<usize, KernelError>
     where T: Addressable
     {
         let offset = req.offset();
@@ -482,13 +790,26 @@ impl Netlink {
             let mut rta_len = 0;
 
             for nexthop in nexthops {
+                let labels = match rib.resolved_label(nexthop) {
+                    Some(op) => op.labels().to_vec(),
+                    None => Vec::new(),
+                };
+
                 match nexthop  {
                     Nexthop::Address::<T>(address) => {
-                        let add_len = nlmsg_add_rtnexthop(&mut buf[rta_len..], address)?;
+                        let add_len = nlmsg_add_rtnexthop(&mut buf[rta_len..], Some(address), 0, &labels)?;
+                        rta_len += add_len;
+                    },
+                    Nexthop::Ifname(ifname) => {
+                        let add_len = nlmsg_add_rtnexthop::<T>(&mut buf[rta_len..], None, ifname_to_index(ifname)?, &labels)?;
+                        rta_len += add_len;
+                    },
+                    Nexthop::AddressIfname::<T>(address, ifname) => {
+                        let add_len = nlmsg_add_rtnexthop(&mut buf[rta_len..], Some(address), ifname_to_index(ifname)?, &labels)?;
                         rta_len += add_len;
                     },
-                    Nexthop::Ifname(_ifname) => { },
                     Nexthop::Network::<T>(_prefix) => { },
+                    Nexthop::Blackhole | Nexthop::Reject => { },
                 }
             }
 
@@ -497,8 +818,8 @@ impl Netlink {
     }
 
     /// Build route message.
-    fn route_msg<T>(&self, cmd: libc::c_int, prefix: &Prefix<T>, rib: &Rib<T>) -> Result<(), KernelError>
-    where T: Addressable
+    fn route_msg<T>(&self, cmd: libc::c_int, table_id: u32, prefix: &Prefix<T>, rib: &Rib<T>) -> Result<(), KernelError>
+    where T: AddressFamily + Addressable
     {
         debug!("Route message");
 
@@ -506,18 +827,30 @@ impl Netlink {
         let mut req = unsafe { zeroed::<Request>() };
 
         req.nlmsghdr.nlmsg_len = nlmsg_length(size_of::<Rtmsg>()) as u32;
-        req.nlmsghdr.nlmsg_flags = libc::NLM_F_CREATE as u16 |
-                                   libc::NLM_F_REPLACE as u16 |
-                                   libc::NLM_F_REQUEST as u16;
+        req.nlmsghdr.nlmsg_flags = libc::NLM_F_REQUEST as u16;
+        if cmd == libc::RTM_NEWROUTE as i32 {
+            req.nlmsghdr.nlmsg_flags |= libc::NLM_F_CREATE as u16 | libc::NLM_F_REPLACE as u16;
+        }
         req.nlmsghdr.nlmsg_type = cmd as u16;
-        req.rtmsg.rtm_family = libc::AF_INET as u8;  // XXX
-        req.rtmsg.rtm_table = 0u8;     //from rib->table. libc::RT_TABLE_MAIN as u8;
+        req.rtmsg.rtm_family = T::address_family() as u8;
+        // Table ID beyond 8 bits is only carried by RTA_TABLE.
+        req.rtmsg.rtm_table = if table_id < 256 { table_id as u8 } else { libc::RT_TABLE_UNSPEC };
         req.rtmsg.rtm_dst_len = prefix.len();
         req.rtmsg.rtm_protocol = RTPROT_ZEBRA as u8;
-        req.rtmsg.rtm_scope = libc::RT_SCOPE_LINK as u8;
+        req.rtmsg.rtm_scope = libc::RT_SCOPE_UNIVERSE as u8;
+
+        // Install resolved nexthops, Null0 nexthop takes precedence over others.
+        let nexthops = rib.resolved().clone();
+        let rtm_type = if nexthops.iter().any(|nh| *nh == Nexthop::Blackhole) {
+            libc::RTN_BLACKHOLE
+        } else if nexthops.iter().any(|nh| *nh == Nexthop::Reject) {
+            libc::RTN_UNREACHABLE
+        } else {
+            libc::RTN_UNICAST
+        };
 
         if cmd == libc::RTM_NEWROUTE as i32 {
-            req.rtmsg.rtm_type = libc::RTN_UNICAST;
+            req.rtmsg.rtm_type = rtm_type;
         }
 
         // Destination address.
@@ -525,61 +858,679 @@ impl Netlink {
         nlmsg_addattr_l(&mut req.nlmsghdr.nlmsg_len,
                         &mut req.buf[pos..], libc::RTA_DST as i32,
                         prefix.octets(), T::byte_len() as usize)?;
+        // Table ID.
+        let pos = req.offset();
+        nlmsg_addattr32(&mut req.nlmsghdr.nlmsg_len,
+                        &mut req.buf[pos..], libc::RTA_TABLE as i32, table_id)?;
         // Metric.
+        let metric = rib.metric();
         let pos = req.offset();
         nlmsg_addattr32(&mut req.nlmsghdr.nlmsg_len,
-                        &mut req.buf[pos..], libc::RTA_PRIORITY as i32, 20)?;
+                        &mut req.buf[pos..], libc::RTA_PRIORITY as i32, metric)?;
+
+        let route = NexthopRouteKey {
+            table_id: table_id,
+            family: T::address_family(),
+            dst: prefix.octets().to_vec(),
+            len: prefix.len(),
+            metric: metric,
+        };
+        let mut nhid = None;
 
-        req.rtmsg.rtm_scope = libc::RT_SCOPE_UNIVERSE as u8; // XXX
+        if rtm_type == libc::RTN_UNICAST {
+            // Directly connected to interface.
+            if nexthops.iter().all(|nh| nh.address().is_none()) {
+                req.rtmsg.rtm_scope = libc::RT_SCOPE_LINK as u8;
+            }
 
-        // Singlepath.
-        if rib.nexthops().len() == 1 {
-            self.route_single_path(&mut req, &rib.nexthops())?;
-        // Multipath.
-        } else if rib.nexthops().len() > 1 {
-            self.route_multi_path(&mut req, &rib.nexthops())?;
-        // TBD
-        } else {
+            // Shared nexthop object, labeled nexthops are encoded inline.
+            let labeled = nexthops.iter().any(|nh| rib.resolved_label(nh).is_some());
+            if cmd == libc::RTM_NEWROUTE as i32 && self.nh_supported.get() && !labeled {
+                if let Some(keys) = nexthop_keys(T::address_family(), &nexthops)? {
+                    let (id, created) = self.nh_table.borrow_mut().acquire(&keys);
+
+                    let pos = req.offset();
+                    let ret = created.iter()
+                        .try_for_each(|(id, key)| self.nexthop_msg(RTM_NEWNEXTHOP, *id, Some(key)))
+                        .and_then(|_| nlmsg_addattr32(&mut req.nlmsghdr.nlmsg_len,
+                                                      &mut req.buf[pos..], RTA_NH_ID, id));
+
+                    // Route never refers to the object, give back the reference.
+                    if let Err(err) = ret {
+                        self.nh_table.borrow_mut().release(id);
+                        return Err(err)
+                    }
+                    nhid = Some(id);
+                }
+            }
 
+            if nhid.is_none() {
+                // Singlepath.
+                if nexthops.len() == 1 {
+                    self.route_single_path(&mut req, rib, &nexthops)?;
+                // Multipath.
+                } else if nexthops.len() > 1 {
+                    self.route_multi_path(&mut req, rib, &nexthops)?;
+                }
+            }
         }
 
-        // Send command message through Netlink socket.
-        self.send_command(&mut req.nlmsghdr)
-    }
-
-    /// Send a command through Netlink.
-    /// Not expect to receive response, but ACK.
-    fn send_command(&self, mut h: &mut Nlmsghdr) -> Result<(), KernelError> {
-        let mut snl = unsafe { zeroed::<libc::sockaddr_nl>() };
-        snl.nl_family = libc::AF_NETLINK as u16;
+        // Queue command message to be sent in batch.
+        let pending = FibPending {
+            table_id: table_id,
+            family: T::address_family(),
+            dst: prefix.octets().to_vec(),
+            len: prefix.len(),
+            metric: metric,
+            op: if cmd == libc::RTM_NEWROUTE as i32 { FibOp::Install } else { FibOp::Uninstall },
+            nhid: nhid,
+            generation: 0,
+            stale: false,
+        };
 
-        let mut iov = unsafe { zeroed::<libc::iovec>() };
-        iov.iov_base = h as *const _ as *mut libc::c_void;
-        iov.iov_len = h.nlmsg_len as usize;
+        if let Err(err) = self.fib_queue(&mut req.nlmsghdr, pending) {
+            if let Some(id) = nhid {
+                self.nh_table.borrow_mut().release(id);
+            }
+            return Err(err)
+        }
 
-        let mut msg = unsafe { zeroed::<libc::msghdr>() };
-        msg.msg_name = &mut snl as *const _ as *mut libc::c_void;
-        msg.msg_namelen = size_of::<libc::sockaddr_nl>() as u32;
-        msg.msg_iov = &mut iov as *const _ as *mut libc::iovec;
-        msg.msg_iovlen = 1;
+        // Object referenced previously is released after the route no longer uses it.
+        self.nh_table.borrow_mut().route_set(route, nhid);
 
-        let seq = self.seq.get() + 1;
-        self.seq.set(seq);
+        Ok(())
+    }
 
-        h.nlmsg_seq = seq;
-        h.nlmsg_flags |= libc::NLM_F_ACK as u16;
+    /// Build route message to delete stale route, matching any nexthop and scope.
+    fn stale_route_msg(&self, route: &StaleRouteKey, metric: u32) -> Result<(), KernelError> {
+        let (table_id, family, dst, len) = route;
+        debug!("Stale route message");
 
-        // Debug Netlink message.
-        nlmsg_dump(h);
+        let mut req = unsafe { zeroed::<Request>() };
 
-        let ret = unsafe {
-            libc::sendmsg(self.sock,
-                          &msg as *const _ as *mut libc::msghdr, 0)
-        };
+        req.nlmsghdr.nlmsg_len = nlmsg_length(size_of::<Rtmsg>()) as u32;
+        req.nlmsghdr.nlmsg_flags = libc::NLM_F_REQUEST as u16;
+        req.nlmsghdr.nlmsg_type = libc::RTM_DELROUTE;
+        req.rtmsg.rtm_family = *family as u8;
+        req.rtmsg.rtm_table = if *table_id < 256 { *table_id as u8 } else { libc::RT_TABLE_UNSPEC };
+        req.rtmsg.rtm_dst_len = *len;
+        req.rtmsg.rtm_protocol = RTPROT_ZEBRA as u8;
+        req.rtmsg.rtm_scope = libc::RT_SCOPE_NOWHERE as u8;
 
-        if ret < 0 {
-            return Err(KernelError::System(io::Error::last_os_error().to_string()))
-        }
+        let pos = req.offset();
+        nlmsg_addattr_l(&mut req.nlmsghdr.nlmsg_len,
+                        &mut req.buf[pos..], libc::RTA_DST as i32,
+                        &dst[..], dst.len())?;
+        let pos = req.offset();
+        nlmsg_addattr32(&mut req.nlmsghdr.nlmsg_len,
+                        &mut req.buf[pos..], libc::RTA_TABLE as i32, *table_id)?;
+        if metric > 0 {
+            let pos = req.offset();
+            nlmsg_addattr32(&mut req.nlmsghdr.nlmsg_len,
+                            &mut req.buf[pos..], libc::RTA_PRIORITY as i32, metric)?;
+        }
+
+        let pending = FibPending {
+            table_id: *table_id,
+            family: *family,
+            dst: dst.clone(),
+            len: *len,
+            metric: metric,
+            op: FibOp::Uninstall,
+            nhid: None,
+            generation: 0,
+            stale: true,
+        };
+
+        self.fib_queue(&mut req.nlmsghdr, pending)
+    }
+
+    /// Delete routes and nexthop objects left by previous instance, not replaced so far.
+    fn stale_sweep(&self) -> Result<(), KernelError> {
+        let routes: Vec<(StaleRouteKey, BTreeSet<u32>)> = self.stale_routes.borrow_mut().drain().collect();
+        let nexthops = std::mem::replace(&mut *self.stale_nexthops.borrow_mut(), Vec::new());
+
+        info!("Sweep {} stale routes and {} stale nexthop objects", routes.len(), nexthops.len());
+
+        for (route, metrics) in routes.iter() {
+            for metric in metrics {
+                // Route being replaced with the same priority is not deleted.
+                let (table_id, family, dst, len) = route.clone();
+                let key = NexthopRouteKey { table_id, family, dst, len, metric: *metric };
+                if self.fib_generation.borrow().contains_key(&key) {
+                    continue;
+                }
+
+                self.stale_route_msg(route, *metric)?;
+            }
+        }
+
+        // Objects go after routes, as kernel deletes routes still referencing them.
+        for id in nexthops {
+            self.nexthop_msg(RTM_DELNEXTHOP, id, None)?;
+        }
+
+        Ok(())
+    }
+
+    /// Build MPLS route message, swap or pop incoming label and forward to nexthops.
+    fn mpls_route_msg(&self, cmd: libc::c_int, lsp: &Lsp) -> Result<(), KernelError> {
+        debug!("MPLS route message");
+
+        let mut req = unsafe { zeroed::<Request>() };
+
+        req.nlmsghdr.nlmsg_len = nlmsg_length(size_of::<Rtmsg>()) as u32;
+        req.nlmsghdr.nlmsg_flags = libc::NLM_F_REQUEST as u16;
+        if cmd == libc::RTM_NEWROUTE as i32 {
+            req.nlmsghdr.nlmsg_flags |= libc::NLM_F_CREATE as u16 | libc::NLM_F_REPLACE as u16;
+            req.rtmsg.rtm_type = libc::RTN_UNICAST;
+        }
+        req.nlmsghdr.nlmsg_type = cmd as u16;
+        req.rtmsg.rtm_family = libc::AF_MPLS as u8;
+        req.rtmsg.rtm_table = libc::RT_TABLE_MAIN;
+        req.rtmsg.rtm_dst_len = MPLS_LABEL_BITLEN;
+        req.rtmsg.rtm_protocol = RTPROT_ZEBRA as u8;
+        req.rtmsg.rtm_scope = libc::RT_SCOPE_UNIVERSE as u8;
+
+        // Incoming label.
+        let dst = mpls_label_stack(&[lsp.in_label()]);
+        let pos = req.offset();
+        nlmsg_addattr_l(&mut req.nlmsghdr.nlmsg_len,
+                        &mut req.buf[pos..], libc::RTA_DST as i32,
+                        &dst, dst.len())?;
+
+        if cmd == libc::RTM_NEWROUTE as i32 {
+            let nexthops = lsp.nexthops().clone();
+
+            // Singlepath.
+            if nexthops.len() == 1 {
+                for (nexthop, op) in nexthops.iter() {
+                    let via = rtvia(&nexthop.address);
+                    let pos = req.offset();
+                    nlmsg_addattr_l(&mut req.nlmsghdr.nlmsg_len,
+                                    &mut req.buf[pos..], libc::RTA_VIA as i32,
+                                    &via, via.len())?;
+
+                    // No label is pushed back when the top label is popped.
+                    if op.labels().len() > 0 {
+                        let stack = mpls_label_stack(op.labels());
+                        let pos = req.offset();
+                        nlmsg_addattr_l(&mut req.nlmsghdr.nlmsg_len,
+                                        &mut req.buf[pos..], libc::RTA_NEWDST as i32,
+                                        &stack, stack.len())?;
+                    }
+
+                    if let Some(ref ifname) = nexthop.ifname {
+                        let pos = req.offset();
+                        nlmsg_addattr32(&mut req.nlmsghdr.nlmsg_len,
+                                        &mut req.buf[pos..], libc::RTA_OIF as i32, ifname_to_index(ifname)? as u32)?;
+                    }
+                }
+            // Multipath.
+            } else if nexthops.len() > 1 {
+                let mut paths = Vec::new();
+                for (nexthop, op) in nexthops.iter() {
+                    let ifindex = match nexthop.ifname {
+                        Some(ref ifname) => ifname_to_index(ifname)?,
+                        None => 0,
+                    };
+                    paths.push((rtvia(&nexthop.address), ifindex, op.labels().to_vec()));
+                }
+
+                let offset = req.offset();
+                nlmsg_addattr_payload(&mut req.nlmsghdr.nlmsg_len, &mut req.buf[offset..], libc::RTA_MULTIPATH as i32,
+                                      |buf: &mut [u8]| -> Result<usize, KernelError> {
+                    let mut rta_len = 0;
+
+                    for (via, ifindex, labels) in paths.iter() {
+                        rta_len += nlmsg_add_mpls_rtnexthop(&mut buf[rta_len..], via, *ifindex, labels)?;
+                    }
+
+                    Ok(rta_len)
+                })?;
+            }
+        }
+
+        let pending = FibPending {
+            table_id: libc::RT_TABLE_MAIN as u32,
+            family: libc::AF_MPLS,
+            dst: dst,
+            len: MPLS_LABEL_BITLEN,
+            metric: 0,
+            op: if cmd == libc::RTM_NEWROUTE as i32 { FibOp::Install } else { FibOp::Uninstall },
+            nhid: None,
+            generation: 0,
+            stale: false,
+        };
+
+        self.fib_queue(&mut req.nlmsghdr, pending)
+    }
+
+    /// Build nexthop object message, single nexthop or group.
+    fn nexthop_msg(&self, cmd: libc::c_int, id: u32, key: Option<&NexthopObjectKey>) -> Result<(), KernelError> {
+        debug!("Nexthop message");
+
+        let mut req = unsafe { zeroed::<NhRequest>() };
+
+        req.nlmsghdr.nlmsg_len = nlmsg_length(size_of::<Nhmsg>()) as u32;
+        req.nlmsghdr.nlmsg_flags = libc::NLM_F_REQUEST as u16;
+        if cmd == RTM_NEWNEXTHOP {
+            req.nlmsghdr.nlmsg_flags |= libc::NLM_F_CREATE as u16 | libc::NLM_F_REPLACE as u16;
+        }
+        req.nlmsghdr.nlmsg_type = cmd as u16;
+        req.nhmsg.nh_family = libc::AF_UNSPEC as u8;
+        req.nhmsg.nh_protocol = RTPROT_ZEBRA as u8;
+
+        let pos = req.offset();
+        nlmsg_addattr32(&mut req.nlmsghdr.nlmsg_len,
+                        &mut req.buf[pos..], NHA_ID, id)?;
+
+        match key {
+            Some(NexthopObjectKey::Single(nexthop)) => {
+                req.nhmsg.nh_family = nexthop.family as u8;
+
+                let pos = req.offset();
+                nlmsg_addattr32(&mut req.nlmsghdr.nlmsg_len,
+                                &mut req.buf[pos..], NHA_OIF, nexthop.ifindex as u32)?;
+
+                if let Some(ref gateway) = nexthop.gateway {
+                    let pos = req.offset();
+                    nlmsg_addattr_l(&mut req.nlmsghdr.nlmsg_len,
+                                    &mut req.buf[pos..], NHA_GATEWAY,
+                                    &gateway[..], gateway.len())?;
+                }
+            },
+            Some(NexthopObjectKey::Group(members)) => {
+                // struct nexthop_grp, ID followed by weight and reserved octets.
+                // Kernel takes weight minus one.
+                let mut group = vec![0u8; members.len() * 8];
+                for (i, (member, weight)) in members.iter().enumerate() {
+                    encode_num::<u32>(&mut group[i * 8..], *member);
+                    group[i * 8 + 4] = weight.saturating_sub(1);
+                }
+
+                let pos = req.offset();
+                nlmsg_addattr_l(&mut req.nlmsghdr.nlmsg_len,
+                                &mut req.buf[pos..], NHA_GROUP,
+                                &group[..], group.len())?;
+            },
+            None => {},
+        }
+
+        let pending = FibPending {
+            table_id: 0,
+            family: libc::AF_UNSPEC,
+            dst: Vec::new(),
+            len: 0,
+            metric: 0,
+            op: if cmd == RTM_NEWNEXTHOP { FibOp::Install } else { FibOp::Uninstall },
+            nhid: Some(id),
+            generation: 0,
+            stale: false,
+        };
+
+        self.fib_queue(&mut req.nlmsghdr, pending)
+    }
+
+    /// Append a route message to the batch, and send the batch if it is full.
+    fn fib_queue(&self, h: &mut Nlmsghdr, mut pending: FibPending) -> Result<(), KernelError> {
+        let len = nlmsg_align(h.nlmsg_len as usize);
+
+        if self.fib_batch.borrow().len() + len > NETLINK_FIB_BATCH_SIZ {
+            self.fib_send()?;
+        }
+
+        let seq = self.seq.get() + 1;
+        self.seq.set(seq);
+
+        h.nlmsg_seq = seq;
+        h.nlmsg_flags |= libc::NLM_F_ACK as u16;
+
+        // Debug Netlink message.
+        nlmsg_dump(h);
+
+        let data = unsafe { std::slice::from_raw_parts(h as *const _ as *const u8, h.nlmsg_len as usize) };
+        let mut batch = self.fib_batch.borrow_mut();
+        let pos = batch.len();
+        batch.extend_from_slice(data);
+        batch.resize(pos + len, 0);
+
+        // Newer message supersedes result of older ones for the same route.
+        if !pending.stale && (pending.family == libc::AF_INET || pending.family == libc::AF_INET6) {
+            let mut generation = self.fib_generation.borrow_mut();
+            let latest = generation.entry(pending.route_key()).or_insert(0);
+            *latest += 1;
+            pending.generation = *latest;
+        }
+
+        self.fib_pending.borrow_mut().insert(seq, pending);
+
+        Ok(())
+    }
+
+    /// Send batched route messages at once, and read ACKs available.
+    fn fib_send(&self) -> Result<(), KernelError> {
+        // Delete nexthop objects no longer used, after routes stopped referencing them.
+        // Queueing them may fill the batch and send it from here.
+        if !self.fib_gc_running.replace(true) {
+            let unused = self.nh_table.borrow_mut().gc();
+            let ret = unused.into_iter()
+                .try_for_each(|id| self.nexthop_msg(RTM_DELNEXTHOP, id, None));
+            self.fib_gc_running.set(false);
+            ret?;
+        }
+
+        let batch = self.fib_batch.replace(Vec::with_capacity(NETLINK_FIB_BATCH_SIZ));
+        if batch.len() == 0 {
+            return Ok(())
+        }
+
+        let mut snl = unsafe { zeroed::<libc::sockaddr_nl>() };
+        snl.nl_family = libc::AF_NETLINK as u16;
+
+        let ret = unsafe {
+            libc::sendto(self.fib_sock,
+                         batch.as_ptr() as *const libc::c_void,
+                         batch.len(), 0,
+                         &snl as *const _ as *const libc::sockaddr,
+                         size_of::<libc::sockaddr_nl>() as u32)
+        };
+
+        if ret < 0 {
+            // Whole batch is lost, report failure for every message in it.
+            let err = io::Error::last_os_error().to_string();
+            let mut p = 0;
+
+            while p + size_of::<Nlmsghdr>() <= batch.len() {
+                let header = &batch[p..] as *const _ as *const Nlmsghdr;
+                let (seq, len) = unsafe { ((*header).nlmsg_seq, (*header).nlmsg_len as usize) };
+
+                self.fib_ack(seq, Some(err.clone()), false);
+                p += nlmsg_align(len);
+            }
+
+            return Err(KernelError::System(err))
+        }
+
+        // Kernel processes messages on send, so ACKs are already queued.
+        self.read_fib_ack()
+    }
+
+    /// Read all pending ACKs from FIB socket.
+    fn read_fib_ack(&self) -> Result<(), KernelError> {
+        let mut overrun = false;
+
+        loop {
+            let mut buffer = self.fib_buf.borrow_mut();
+
+            let ret = unsafe {
+                libc::recv(self.fib_sock, &mut buffer.p as *const _ as *mut libc::c_void,
+                           NETLINK_RECV_BUFSIZ, 0)
+            };
+
+            if ret < 0 {
+                let err = io::Error::last_os_error();
+                match err.kind() {
+                    io::ErrorKind::WouldBlock => break,
+                    io::ErrorKind::Interrupted => continue,
+                    _ if err.raw_os_error() == Some(libc::ENOBUFS) => {
+                        // Some ACKs are dropped, read the rest queued after them.
+                        overrun = true;
+                        continue;
+                    },
+                    _ => return Err(KernelError::System(err.to_string())),
+                }
+            } else if ret == 0 {
+                break;
+            }
+
+            let recvlen = ret as usize;
+            for msg in nlmsg_iter(&buffer.p[..recvlen]) {
+                let msg = match msg {
+                    Ok(msg) => msg,
+                    Err(err) => {
+                        error!("Nlmsg ACK: {}", err);
+                        break;
+                    }
+                };
+
+                if msg.nlmsg_type as c_int == libc::NLMSG_ERROR {
+                    // Error code 0 means ACK.
+                    let error = match msg.error() {
+                        Ok(0) => None,
+                        Ok(error) => Some(io::Error::from_raw_os_error(-error).to_string()),
+                        Err(err) => Some(err.to_string()),
+                    };
+
+                    self.fib_ack(msg.nlmsg_seq, error, false);
+                }
+            }
+        }
+
+        if overrun {
+            self.fib_overrun();
+        }
+
+        Ok(())
+    }
+
+    /// ACKs were dropped by socket overflow. Kernel has processed all messages sent,
+    /// so messages still pending are reported as lost to be retried, and buffer is grown.
+    fn fib_overrun(&self) {
+        let size = match netlink_get_rcvbuf(self.fib_sock) {
+            Ok(size) => size.min(NETLINK_RCVBUF_MAX),
+            Err(_) => NETLINK_FIB_RCVBUF,
+        };
+
+        let mut seqs: Vec<u32> = self.fib_pending.borrow().keys().cloned().collect();
+        seqs.sort();
+
+        error!("Netlink FIB socket overflow, {} ACKs lost, receive buffer {}", seqs.len(), size);
+
+        if let Err(err) = netlink_set_rcvbuf(self.fib_sock, size) {
+            error!("Failed to set FIB socket receive buffer {}", err);
+        }
+
+        for seq in seqs {
+            self.fib_ack(seq, Some(String::from("ACK lost")), true);
+        }
+    }
+
+    /// Report result of route message to application.
+    fn fib_ack(&self, seq: u32, error: Option<String>, lost: bool) {
+        let pending = match self.fib_pending.borrow_mut().remove(&seq) {
+            Some(pending) => pending,
+            None => {
+                debug!("Unknown FIB ACK seq {}", seq);
+                return;
+            }
+        };
+
+        if pending.stale {
+            if let Some(error) = error {
+                error!("Stale route {:?} delete failed: {}", pending.route_key(), error);
+            }
+            return;
+        }
+
+        if pending.family == libc::AF_UNSPEC {
+            // Object is most likely in kernel, keep it as ACK is just lost.
+            if lost {
+                return;
+            }
+
+            if let (Some(id), Some(error)) = (pending.nhid, error) {
+                error!("Nexthop object {} {:?} failed: {}", id, pending.op, error);
+
+                // Do not let other routes refer to object not in kernel.
+                self.nh_table.borrow_mut().invalidate(id);
+            }
+            return;
+        }
+
+        if pending.family == libc::AF_MPLS {
+            if let Some(error) = error {
+                let label = decode_mpls_label(&pending.dst);
+                error!("MPLS route {} {:?} failed: {}", label, pending.op, error);
+            }
+            return;
+        }
+
+        // Route left by previous instance is now replaced or deleted.
+        if error.is_none() {
+            let mut stale_routes = self.stale_routes.borrow_mut();
+            let key = pending.stale_key();
+            let empty = match stale_routes.get_mut(&key) {
+                Some(metrics) => {
+                    metrics.remove(&pending.metric);
+                    metrics.is_empty()
+                },
+                None => false,
+            };
+            if empty {
+                stale_routes.remove(&key);
+            }
+        }
+
+        // Result of superseded message must not override the newer one.
+        let route = pending.route_key();
+        {
+            let mut generation = self.fib_generation.borrow_mut();
+            if generation.get(&route) != Some(&pending.generation) {
+                debug!("Superseded FIB ACK seq {}", seq);
+                return;
+            }
+            generation.remove(&route);
+        }
+
+        if let (Some(id), FibOp::Install, Some(_), false) = (pending.nhid, &pending.op, &error, lost) {
+            let mut nh_table = self.nh_table.borrow_mut();
+            nh_table.invalidate(id);
+            nh_table.route_set(route, None);
+        }
+
+        match pending.family {
+            libc::AF_INET => {
+                let result = KernelFibResult {
+                    table_id: pending.table_id,
+                    prefix: Prefix::<Ipv4Addr>::from_slice(&pending.dst, pending.len),
+                    op: pending.op,
+                    error: error,
+                    lost: lost,
+                };
+                self.callback.borrow().call_ipv4_fib_result(result);
+            },
+            libc::AF_INET6 => {
+                let result = KernelFibResult {
+                    table_id: pending.table_id,
+                    prefix: Prefix::<Ipv6Addr>::from_slice(&pending.dst, pending.len),
+                    op: pending.op,
+                    error: error,
+                    lost: lost,
+                };
+                self.callback.borrow().call_ipv6_fib_result(result);
+            },
+            _ => {},
+        }
+    }
+
+    /// Build and send link message to change flags and MTU.
+    fn link_msg(&self, ifindex: i32, flags: u32, change: u32, mtu: Option<u32>) -> Result<(), KernelError> {
+        debug!("Link message");
+
+        let mut req = unsafe { zeroed::<LinkRequest>() };
+
+        req.nlmsghdr.nlmsg_len = nlmsg_length(size_of::<Ifinfomsg>()) as u32;
+        req.nlmsghdr.nlmsg_flags = libc::NLM_F_REQUEST as u16;
+        req.nlmsghdr.nlmsg_type = libc::RTM_SETLINK;
+        req.ifinfomsg.ifi_family = libc::AF_UNSPEC as u8;
+        req.ifinfomsg.ifi_index = ifindex;
+        req.ifinfomsg.ifi_flags = flags;
+        req.ifinfomsg.ifi_change = change;
+
+        // MTU.
+        if let Some(mtu) = mtu {
+            let pos = req.offset();
+            nlmsg_addattr32(&mut req.nlmsghdr.nlmsg_len,
+                            &mut req.buf[pos..], libc::IFLA_MTU as i32, mtu)?;
+        }
+
+        self.send_command(&mut req.nlmsghdr)
+    }
+
+    /// Build and send address message.
+    fn address_msg<T>(&self, cmd: libc::c_int, ifindex: i32, prefix: &Prefix<T>, label: Option<&str>) -> Result<(), KernelError>
+    where T: AddressFamily + Addressable
+    {
+        debug!("Address message");
+
+        let mut req = unsafe { zeroed::<AddrRequest>() };
+
+        req.nlmsghdr.nlmsg_len = nlmsg_length(size_of::<Ifaddrmsg>()) as u32;
+        req.nlmsghdr.nlmsg_flags = libc::NLM_F_REQUEST as u16;
+        if cmd == libc::RTM_NEWADDR as i32 {
+            req.nlmsghdr.nlmsg_flags |= libc::NLM_F_CREATE as u16 | libc::NLM_F_REPLACE as u16;
+        }
+        req.nlmsghdr.nlmsg_type = cmd as u16;
+        req.ifaddrmsg.ifa_family = T::address_family() as u8;
+        req.ifaddrmsg.ifa_prefixlen = prefix.len();
+        req.ifaddrmsg.ifa_index = ifindex as u32;
+
+        // Local and interface address.
+        for rta_type in &[libc::IFA_LOCAL, libc::IFA_ADDRESS] {
+            let pos = req.offset();
+            nlmsg_addattr_l(&mut req.nlmsghdr.nlmsg_len,
+                            &mut req.buf[pos..], *rta_type as i32,
+                            prefix.octets(), T::byte_len() as usize)?;
+        }
+
+        // Label, NUL terminated and only for IPv4.
+        if let Some(label) = label {
+            let mut data = label.as_bytes().to_vec();
+            data.push(0);
+
+            let pos = req.offset();
+            nlmsg_addattr_l(&mut req.nlmsghdr.nlmsg_len,
+                            &mut req.buf[pos..], libc::IFA_LABEL as i32,
+                            &data, data.len())?;
+        }
+
+        self.send_command(&mut req.nlmsghdr)
+    }
+
+    /// Send a command through Netlink.
+    /// Not expect to receive response, but ACK.
+    fn send_command(&self, mut h: &mut Nlmsghdr) -> Result<(), KernelError> {
+        let mut snl = unsafe { zeroed::<libc::sockaddr_nl>() };
+        snl.nl_family = libc::AF_NETLINK as u16;
+
+        let mut iov = unsafe { zeroed::<libc::iovec>() };
+        iov.iov_base = h as *const _ as *mut libc::c_void;
+        iov.iov_len = h.nlmsg_len as usize;
+
+        let mut msg = unsafe { zeroed::<libc::msghdr>() };
+        msg.msg_name = &mut snl as *const _ as *mut libc::c_void;
+        msg.msg_namelen = size_of::<libc::sockaddr_nl>() as u32;
+        msg.msg_iov = &mut iov as *const _ as *mut libc::iovec;
+        msg.msg_iovlen = 1;
+
+        let seq = self.seq.get() + 1;
+        self.seq.set(seq);
+
+        h.nlmsg_seq = seq;
+        h.nlmsg_flags |= libc::NLM_F_ACK as u16;
+
+        // Debug Netlink message.
+        nlmsg_dump(h);
+
+        let ret = unsafe {
+            libc::sendmsg(self.sock,
+                          &msg as *const _ as *mut libc::msghdr, 0)
+        };
+
+        if ret < 0 {
+            return Err(KernelError::System(io::Error::last_os_error().to_string()))
+        }
 
         self.parse_info(&Netlink::parse_dummy)
     }
@@ -622,8 +1573,46 @@ impl Netlink {
         Ok(())
     }
 
-    /// Parse Netlink header and call parser to parse message payload.
-    fn parse_info<T>(&self, parser: &dyn Fn(&Netlink, &Nlmsghdr, &T, &AttrMap) -> bool) -> Result<(), KernelError> {
+    /// Send a request to dump 64-bit statistics of all links.
+    fn send_stats_request(&self) -> Result<(), io::Error> {
+        struct Request {
+            nlmsghdr: Nlmsghdr,
+            ifstatsmsg: IfStatsmsg,
+        }
+
+        let seq = self.seq.get() + 1;
+        self.seq.set(seq);
+
+        let mut snl = unsafe { zeroed::<libc::sockaddr_nl>() };
+        snl.nl_family = libc::AF_NETLINK as u16;
+
+        let mut req = unsafe { zeroed::<Request>() };
+        req.nlmsghdr.nlmsg_len = size_of::<Request>() as u32;
+        req.nlmsghdr.nlmsg_type = RTM_GETSTATS;
+        req.nlmsghdr.nlmsg_flags = libc::NLM_F_ROOT as u16 |
+                                   libc::NLM_F_MATCH as u16 |
+                                   libc::NLM_F_REQUEST as u16;
+        req.nlmsghdr.nlmsg_pid = self.pid;
+        req.nlmsghdr.nlmsg_seq = seq;
+        req.ifstatsmsg.family = libc::AF_UNSPEC as u8;
+        req.ifstatsmsg.filter_mask = 1 << (IFLA_STATS_LINK_64 - 1);
+
+        let ret = unsafe {
+            libc::sendto(self.sock,
+                         &req as *const _ as *const libc::c_void,
+                         size_of::<Request>(), 0,
+                         &snl as *const _ as *const libc::sockaddr,
+                         size_of::<libc::sockaddr_nl>() as u32)
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    /// Receive response messages and call parser for each.
+    fn parse_info(&self, parser: &dyn Fn(&Netlink, &NlMsg) -> bool) -> Result<(), KernelError> {
         'outer: loop {
             let mut buffer = self.buf.borrow_mut();
 
@@ -659,291 +1648,235 @@ impl Netlink {
             if msg.msg_namelen != size_of::<libc::sockaddr_nl>() as u32 {
                 // sender address length error
                 break 'outer;
-            }
-
-            let recvlen = ret as usize;
-            let recvbuf = &buffer.p[..recvlen];
-            let mut p = 0;
-            while p < recvlen {
-                if p + size_of::<Nlmsghdr>() > recvlen {
-                    error!("No enough space for Nlmsghdr in recv buffer.");
-                    break;
-                }
-
-                let mut buf = &recvbuf[p..];
-                let header = buf as *const _ as *const Nlmsghdr;
-                let nlmsg_len = unsafe { (*header).nlmsg_len };
-                let nlmsg_type = unsafe { (*header).nlmsg_type as i32 };
-                buf = &buf[..nlmsg_len as usize];
-
-                match nlmsg_type  {
-                    libc::NLMSG_DONE => break 'outer,
-                    libc::NLMSG_ERROR => {
-                        let _errbuf = &buf[nlmsg_data()..];
-                        let _nlmsgerr = buf as *const _ as *const libc::nlmsgerr;
-
-                        return Err(KernelError::System("Error from kernel".to_string()))
-                    },
-                    _ => {
-                    }
-                }
-
-                debug!("Nlmsg: type: {}, len: {}", nlmsg_type, nlmsg_len);
-
-                if (nlmsg_len as usize) < nlmsg_attr::<T>() {
-                    return Err(KernelError::Other("Insufficient Nlmsg length".to_string()))
-                }
-
-                let databuf = &buf[nlmsg_data()..];
-                let data = databuf as *const _ as *const T;
-                let attrbuf = &buf[nlmsg_attr::<T>()..];
-                let map = nlmsg_parse_attr(attrbuf);
-                let _ret = unsafe { parser(self, &(*header), &(*data), &map) };
-                // TODO check return value?
-
-                p += nlmsg_len as usize;
-            }
-        }
-
-        Ok(())
-    }
-
-    fn parse_dummy(&self, h: &Nlmsghdr, _ifi: &NlDummy, _attr: &AttrMap) -> bool {
-        debug!("Nlmsg type {}", h.nlmsg_type);
-
-        true
-    }
-
-    fn parse_interface(&self, h: &Nlmsghdr, ifi: &Ifinfomsg, attr: &AttrMap) -> bool {
-        assert!(h.nlmsg_type == libc::RTM_NEWLINK);
-
-        let ifindex = ifi.ifi_index;
-        let hwaddr: [u8; 6] = match attr.get(&(libc::IFLA_ADDRESS as i32)) {
-            Some(hwaddr) if hwaddr.len() == 6 => {
-                [hwaddr[0], hwaddr[1], hwaddr[2], hwaddr[3], hwaddr[4], hwaddr[5]]
-            },
-            Some(hwaddr) => {
-                error!("Invalid hwaddr length {}", hwaddr.len());
-                [0, 0, 0, 0, 0, 0]
-            },
-            None => {
-                [0, 0, 0, 0, 0, 0]
-            }
-        };
-
-        let mtu = match attr.get(&(libc::IFLA_MTU as i32)) {
-            Some(mtu) => decode_num::<u32>(*mtu),
-            None => 0u32,  // maybe set default?
-        };
-        let ifname = match attr.get(&(libc::IFLA_IFNAME as i32)) {
-            Some(ifname) => {
-                match str::from_utf8(ifname) {
-                    Ok(ifname) => ifname,
-                    Err(_) => "(Non-utf8)",
-                }
-            },
-            None => "(Unknown)"
-        };
-
-        debug!("parse_interface() {} {} {} {:?} {}",
-               ifindex, ifname, ifi.ifi_type, hwaddr, mtu);
-
-        // Callback to add Link.
-        let kc = self.callback.borrow();
-        let ka = KernelLink::new(ifi.ifi_index, ifname, ifi.ifi_type as u16, hwaddr, mtu);
-        kc.call_add_link(ka);
+            }
 
-        true
-    }
+            let recvlen = ret as usize;
+            for msg in nlmsg_iter(&buffer.p[..recvlen]) {
+                let msg = msg?;
 
-    fn parse_interface_address<T>(&self, h: &Nlmsghdr, ifa: &Ifaddrmsg, attr: &AttrMap) -> bool
-    where T: AddressFamily + Addressable {
-        assert!(h.nlmsg_type == libc::RTM_NEWADDR || h.nlmsg_type == libc::RTM_DELADDR);
+                match msg.nlmsg_type as c_int {
+                    libc::NLMSG_DONE => break 'outer,
+                    libc::NLMSG_ERROR => {
+                        // Error code 0 means ACK.
+                        let error = msg.error()?;
+                        if error == 0 {
+                            break 'outer;
+                        }
 
-        if ifa.ifa_family as i32 != T::address_family() {
-            return false
-        }
+                        return Err(KernelError::System(io::Error::from_raw_os_error(-error).to_string()))
+                    },
+                    _ => {
+                    }
+                }
 
-        let mut local = attr.get(&(libc::IFA_LOCAL as i32));
-        let mut address = attr.get(&(libc::IFA_ADDRESS as i32));
-        if let None = local {
-            local = address;
-        }
-        if let None = address {
-            address = local;
-        }
+                debug!("Nlmsg: type: {}, len: {}", msg.nlmsg_type, msg.payload.len());
 
-        let _broad = match address {
-            Some(address) if address == local.unwrap() => {
-                Some(address)
-            },
-            _ => {
-                attr.get(&(libc::IFA_BROADCAST as i32))
+                let _ret = parser(self, &msg);
+                // TODO check return value?
             }
-        };
-
-        let index = ifa.ifa_index as i32;
-        let kc = self.callback.borrow();
+        }
 
-        match (ifa.ifa_family as i32, h.nlmsg_type) {
-            (libc::AF_INET, libc::RTM_NEWADDR) => {
-                let prefix = Prefix::<Ipv4Addr>::from_slice(address.unwrap(), ifa.ifa_prefixlen);
-                let ka = KernelAddr::<Ipv4Addr>::new(index, prefix, None, false, false, None);
+        Ok(())
+    }
 
-                kc.call_add_ipv4_address(ka);
-            }
-            (libc::AF_INET, libc::RTM_DELADDR) => {
-                let prefix = Prefix::<Ipv4Addr>::from_slice(address.unwrap(), ifa.ifa_prefixlen);
-                let ka = KernelAddr::<Ipv4Addr>::new(index, prefix, None, false, false, None);
+    /// Read all pending messages from event socket, and dispatch them per message type.
+    fn read_event(&self) -> Result<(), KernelError> {
+        loop {
+            let mut buffer = self.event_buf.borrow_mut();
 
-                kc.call_delete_ipv4_address(ka);
-            }
-            (libc::AF_INET6, libc::RTM_NEWADDR) => {
-                let prefix = Prefix::<Ipv6Addr>::from_slice(address.unwrap(), ifa.ifa_prefixlen);
-                let ka = KernelAddr::<Ipv6Addr>::new(index, prefix, None, false, false, None);
+            let ret = unsafe {
+                libc::recv(self.event_sock, &mut buffer.p as *const _ as *mut libc::c_void,
+                           NETLINK_RECV_BUFSIZ, 0)
+            };
 
-                kc.call_add_ipv6_address(ka);
+            if ret < 0 {
+                let err = io::Error::last_os_error();
+                match err.kind() {
+                    io::ErrorKind::WouldBlock => break,
+                    io::ErrorKind::Interrupted => continue,
+                    _ => {
+                        // ENOBUFS, kernel dropped events, we are out of sync.
+                        if err.raw_os_error() == Some(libc::ENOBUFS) {
+                            drop(buffer);
+                            self.event_resync();
+                            continue;
+                        }
+                        return Err(KernelError::System(err.to_string()))
+                    },
+                }
+            } else if ret == 0 {
+                break;
             }
-            (libc::AF_INET6, libc::RTM_DELADDR) => {
-                let prefix = Prefix::<Ipv6Addr>::from_slice(address.unwrap(), ifa.ifa_prefixlen);
-                let ka = KernelAddr::<Ipv6Addr>::new(index, prefix, None, false, false, None);
 
-                kc.call_delete_ipv6_address(ka);
-            }
-            _ => {
-                error!("Invalid family or message type");
-                return false
+            let recvlen = ret as usize;
+            for msg in nlmsg_iter(&buffer.p[..recvlen]) {
+                match msg {
+                    Ok(msg) => {
+                        debug!("Nlmsg event: type: {}, len: {}", msg.nlmsg_type, msg.payload.len());
+                        self.parse_kernel_msg(&msg);
+                    },
+                    Err(err) => {
+                        error!("Nlmsg event: {}", err);
+                        break;
+                    }
+                }
             }
         }
 
-        true
+        Ok(())
     }
 
-    fn parse_route<T>(&self, h: &Nlmsghdr, rtm: &Rtmsg, attr: &AttrMap) -> bool
-    where T: AddressFamily + Addressable {
-        assert!(h.nlmsg_type == libc::RTM_NEWROUTE || h.nlmsg_type == libc::RTM_DELROUTE);
+    /// Recover from event socket overflow, raise receive buffer and dump kernel state again.
+    fn event_resync(&self) {
+        // Kernel reports twice the size set, setting it back doubles the buffer.
+        let size = match netlink_get_rcvbuf(self.event_sock) {
+            Ok(size) => size.min(NETLINK_RCVBUF_MAX),
+            Err(_) => NETLINK_EVENT_RCVBUF,
+        };
 
-        //
-        if rtm.rtm_type != libc::RTN_UNICAST && rtm.rtm_type != libc::RTN_BLACKHOLE {
-            return true
-        }
+        error!("Netlink event socket overflow, resync with receive buffer {}", size);
 
-        //
-        if (rtm.rtm_flags & libc::RTM_F_CLONED) != 0 {
-            return true
+        if let Err(err) = netlink_set_rcvbuf(self.event_sock, size) {
+            error!("Failed to set event socket receive buffer {}", err);
         }
 
-        //
-        if rtm.rtm_protocol == libc::RTPROT_REDIRECT || rtm.rtm_protocol == libc::RTPROT_KERNEL {
-            return true
+        if let Err(err) = self.get_link_all() {
+            error!("Resync links {}", err);
         }
-
-        //
-        if rtm.rtm_src_len == 0 {
-            return true
+        if let Err(err) = self.get_address_all::<Ipv4Addr>() {
+            error!("Resync IPv4 addresses {}", err);
+        }
+        if let Err(err) = self.get_address_all::<Ipv6Addr>() {
+            error!("Resync IPv6 addresses {}", err);
+        }
+        if let Err(err) = self.get_neighbor_all::<Ipv4Addr>() {
+            error!("Resync IPv4 neighbors {}", err);
+        }
+        if let Err(err) = self.get_neighbor_all::<Ipv6Addr>() {
+            error!("Resync IPv6 neighbors {}", err);
         }
 
-        match rtm.rtm_family as i32 {
-            libc::AF_INET => self.parse_route_ipv4(h, rtm, attr),
-            libc::AF_INET => self.parse_route_ipv6(h, rtm, attr),
-            _ => true,
+        // Routes installed by us are not stale at this point, parse them as events.
+        if let Err(err) = self.dump_route::<Ipv4Addr>(&Netlink::parse_kernel_msg) {
+            error!("Resync IPv4 routes {}", err);
+        }
+        if let Err(err) = self.dump_route::<Ipv6Addr>(&Netlink::parse_kernel_msg) {
+            error!("Resync IPv6 routes {}", err);
         }
     }
 
-    fn parse_route_ipv4(&self, _h: &Nlmsghdr, rtm: &Rtmsg, attr: &AttrMap) -> bool {
-        let dest: Prefix<Ipv4Addr> = Prefix::from(
-            match attr.get(&(libc::RTA_DST as i32)) {
-                Some(dst) => Ipv4Addr::from_slice(dst),
-                None => Ipv4Addr::empty_new(),
-            },
-            rtm.rtm_dst_len);
+    fn parse_dummy(&self, msg: &NlMsg) -> bool {
+        debug!("Nlmsg type {}", msg.nlmsg_type);
 
-        // Prepare KernelRoute.
-        let mut kr = KernelRoute::new(dest);
-
-        // This route is self route originated earlier.
-        if rtm.rtm_protocol == RTPROT_ZEBRA as u8 {
-            kr.is_self = true;
-        }
+        true
+    }
 
-        // Get ifindex.
-        if let Some(index) = attr.get(&(libc::RTA_OIF as i32)) {
-            kr.ifindex = Some(decode_num::<u32>(*index) as i32);
-        }
+    /// Parse link, address or route message, and pass it up to application.
+    fn parse_kernel_msg(&self, msg: &NlMsg) -> bool {
+        let kmsg = match kernel_msg(msg, &ifindex_to_name) {
+            Ok(Some(kmsg)) => kmsg,
+            Ok(None) => return true,
+            Err(err) => {
+                error!("Nlmsg type {}: {}", msg.nlmsg_type, err);
+                return false
+            }
+        };
 
-        // Get metric.
-        if let Some(metric) = attr.get(&(libc::RTA_PRIORITY as i32)) {
-            kr.metric = Some(decode_num::<u32>(*metric));
+        let kc = self.callback.borrow();
+        match kmsg {
+            KernelMsg::NewLink(kl) => kc.call_add_link(kl),
+            KernelMsg::DelLink(kl) => kc.call_delete_link(kl),
+            KernelMsg::NewAddrIpv4(ka) => kc.call_add_ipv4_address(ka),
+            KernelMsg::DelAddrIpv4(ka) => kc.call_delete_ipv4_address(ka),
+            KernelMsg::NewAddrIpv6(ka) => kc.call_add_ipv6_address(ka),
+            KernelMsg::DelAddrIpv6(ka) => kc.call_delete_ipv6_address(ka),
+            KernelMsg::NewRouteIpv4(kr) => kc.call_add_ipv4_route(kr),
+            KernelMsg::DelRouteIpv4(kr) => kc.call_delete_ipv4_route(kr),
+            KernelMsg::NewRouteIpv6(kr) => kc.call_add_ipv6_route(kr),
+            KernelMsg::DelRouteIpv6(kr) => kc.call_delete_ipv6_route(kr),
+            KernelMsg::NewNeighIpv4(kn) => kc.call_add_ipv4_neighbor(kn),
+            KernelMsg::DelNeighIpv4(kn) => kc.call_delete_ipv4_neighbor(kn),
+            KernelMsg::NewNeighIpv6(kn) => kc.call_add_ipv6_neighbor(kn),
+            KernelMsg::DelNeighIpv6(kn) => kc.call_delete_ipv6_neighbor(kn),
+            KernelMsg::NewStats(ifindex, stats) => kc.call_link_stats(ifindex, stats),
         }
 
-        // Get gateway.
-        if let Some(gateway) = attr.get(&(libc::RTA_GATEWAY as i32)) {
-            kr.gateway = Some(Ipv4Addr::from_slice(gateway));
-        }
+        true
+    }
 
-        if kr.ifindex == None && kr.gateway == None && rtm.rtm_type != libc::RTN_BLACKHOLE {
+    /// Parse statistics in link dump, and pass them up to application.
+    fn parse_link_stats(&self, msg: &NlMsg) -> bool {
+        if msg.nlmsg_type != libc::RTM_NEWLINK {
             return true
         }
 
-        // Get table ID.
-        if let Some(table_id) = attr.get(&(libc::RTA_TABLE as i32)) {
-            kr.table_id = Some(decode_num::<u32>(*table_id) as i32);
+        match kernel_link_stats64(msg) {
+            Ok(Some((ifindex, stats))) => self.callback.borrow().call_link_stats(ifindex, stats),
+            Ok(None) => {},
+            Err(err) => {
+                error!("Nlmsg type {}: {}", msg.nlmsg_type, err);
+                return false
+            }
         }
 
-        debug!("parse_route_ipv4()");
-
-        let kc = self.callback.borrow();
-        kc.call_add_ipv4_route(kr);
-
         true
     }
 
-    fn parse_route_ipv6(&self, _h: &Nlmsghdr, rtm: &Rtmsg, attr: &AttrMap) -> bool {
-        let dest: Prefix<Ipv6Addr> = Prefix::from(
+    /// Parse route in dump, route originated by previous instance is kept as stale.
+    fn parse_route_dump<T>(&self, msg: &NlMsg) -> bool
+    where T: AddressFamily + Addressable {
+        let (rtm, attr) = match msg.parse::<RtInfo>() {
+            Ok(v) => v,
+            Err(err) => {
+                error!("Nlmsg type {}: {}", msg.nlmsg_type, err);
+                return false
+            }
+        };
+
+        if rtm.protocol != RTPROT_ZEBRA as u8 || rtm.family as i32 != T::address_family() {
+            return self.parse_kernel_msg(msg)
+        }
+
+        let route = (
+            route_table(&rtm, &attr),
+            T::address_family(),
             match attr.get(&(libc::RTA_DST as i32)) {
-                Some(dst) => Ipv6Addr::from_slice(dst),
-                None => Ipv6Addr::empty_new(),
+                Some(dst) => dst.to_vec(),
+                None => vec![0; T::byte_len() as usize],
             },
-            rtm.rtm_dst_len);
+            rtm.dst_len,
+        );
+        let metric = attr_u32(&attr, libc::RTA_PRIORITY as i32).unwrap_or(0);
 
-        // Prepare KernelRoute.
-        let mut kr = KernelRoute::new(dest);
+        debug!("Stale route {:?} metric {}", route, metric);
 
-        // This route is self route originated earlier.
-        if rtm.rtm_protocol == RTPROT_ZEBRA as u8 {
-            kr.is_self = true;
-        }
+        self.stale_routes.borrow_mut().entry(route).or_insert_with(BTreeSet::new).insert(metric);
 
-        // Get ifindex.
-        if let Some(index) = attr.get(&(libc::RTA_OIF as i32)) {
-            kr.ifindex = Some(decode_num::<u32>(*index) as i32);
-        }
+        true
+    }
 
-        // Get metric.
-        if let Some(metric) = attr.get(&(libc::RTA_PRIORITY as i32)) {
-            kr.metric = Some(decode_num::<u32>(*metric));
-        }
+    /// Get all routes per Address Family from kernel.
+    fn get_route_all<T>(&self) -> Result<(), KernelError>
+    where T: AddressFamily + Addressable {
+        self.dump_route::<T>(&Netlink::parse_route_dump::<T>)
+    }
 
-        // Get gateway.
-        if let Some(gateway) = attr.get(&(libc::RTA_GATEWAY as i32)) {
-            kr.gateway = Some(Ipv6Addr::from_slice(gateway));
-        }
+    /// Dump routes per Address Family from kernel, and parse them with given parser.
+    fn dump_route<T>(&self, parser: &dyn Fn(&Netlink, &NlMsg) -> bool) -> Result<(), KernelError>
+    where T: AddressFamily + Addressable {
+        debug!("Get route all");
 
-        if kr.ifindex == None && kr.gateway == None && rtm.rtm_type != libc::RTN_BLACKHOLE {
-            return true
+        if let Err(err) = self.send_request(T::address_family(), libc::RTM_GETROUTE as i32) {
+            error!("Send request: RTM_GETROUTE");
+            return Err(KernelError::Route(err.to_string()))
         }
 
-        // Get table ID.
-        if let Some(table_id) = attr.get(&(libc::RTA_TABLE as i32)) {
-            kr.table_id = Some(decode_num::<u32>(*table_id) as i32);
+        if let Err(err) = self.parse_info(parser) {
+            error!("Parse info: RTM_GETROUTE");
+            return Err(KernelError::Route(err.to_string()))
         }
 
-        debug!("parse_route_ipv6()");
-
-        let kc = self.callback.borrow();
-        kc.call_add_ipv6_route(kr);
-
-        true
+        Ok(())
     }
 
     /// Get all addresses per Address Family from kernel.
@@ -956,13 +1889,31 @@ impl Netlink {
             return Err(KernelError::Address(err.to_string()))
         }
 
-        if let Err(err) = self.parse_info(&Netlink::parse_interface_address::<T>) {
+        if let Err(err) = self.parse_info(&Netlink::parse_kernel_msg) {
             error!("Parse info: RTM_GETADDR");
             return Err(KernelError::Address(err.to_string()))
         }
 
         Ok(())
     }
+
+    /// Get all neighbors for address family.
+    fn get_neighbor_all<T>(&self) -> Result<(), KernelError>
+    where T: AddressFamily + Addressable {
+        debug!("Get neighbor all");
+
+        if let Err(err) = self.send_request(T::address_family(), libc::RTM_GETNEIGH as i32) {
+            error!("Send request: RTM_GETNEIGH");
+            return Err(KernelError::Neighbor(err.to_string()))
+        }
+
+        if let Err(err) = self.parse_info(&Netlink::parse_kernel_msg) {
+            error!("Parse info: RTM_GETNEIGH");
+            return Err(KernelError::Neighbor(err.to_string()))
+        }
+
+        Ok(())
+    }
 }
 
 impl KernelDriver for Netlink {
@@ -1017,6 +1968,41 @@ impl KernelDriver for Netlink {
         self.callback.borrow_mut().delete_ipv6_route.replace(f);
     }
 
+    /// Register Add IPv4 neighbor callback function.
+    fn register_add_ipv4_neighbor(&self, f: Box<dyn Fn(KernelNeigh<Ipv4Addr>)>) {
+        self.callback.borrow_mut().add_ipv4_neighbor.replace(f);
+    }
+
+    /// Register Delete IPv4 neighbor callback function.
+    fn register_delete_ipv4_neighbor(&self, f: Box<dyn Fn(KernelNeigh<Ipv4Addr>)>) {
+        self.callback.borrow_mut().delete_ipv4_neighbor.replace(f);
+    }
+
+    /// Register Add IPv6 neighbor callback function.
+    fn register_add_ipv6_neighbor(&self, f: Box<dyn Fn(KernelNeigh<Ipv6Addr>)>) {
+        self.callback.borrow_mut().add_ipv6_neighbor.replace(f);
+    }
+
+    /// Register Delete IPv6 neighbor callback function.
+    fn register_delete_ipv6_neighbor(&self, f: Box<dyn Fn(KernelNeigh<Ipv6Addr>)>) {
+        self.callback.borrow_mut().delete_ipv6_neighbor.replace(f);
+    }
+
+    /// Register IPv4 FIB result callback function.
+    fn register_ipv4_fib_result(&self, f: Box<dyn Fn(KernelFibResult<Ipv4Addr>)>) {
+        self.callback.borrow_mut().ipv4_fib_result.replace(f);
+    }
+
+    /// Register IPv6 FIB result callback function.
+    fn register_ipv6_fib_result(&self, f: Box<dyn Fn(KernelFibResult<Ipv6Addr>)>) {
+        self.callback.borrow_mut().ipv6_fib_result.replace(f);
+    }
+
+    /// Register Link statistics callback function.
+    fn register_link_stats(&self, f: Box<dyn Fn(i32, KernelLinkStats)>) {
+        self.callback.borrow_mut().link_stats.replace(f);
+    }
+
 
     /// Get all links from kernel.
     fn get_link_all(&self) -> Result<(), KernelError> {
@@ -1027,7 +2013,7 @@ impl KernelDriver for Netlink {
             return Err(KernelError::Link(err.to_string()))
         }
 
-        if let Err(err) = self.parse_info(&Netlink::parse_interface) {
+        if let Err(err) = self.parse_info(&Netlink::parse_kernel_msg) {
             error!("Parse info: RTM_GETLINK");
             return Err(KernelError::Link(err.to_string()))
         }
@@ -1035,19 +2021,70 @@ impl KernelDriver for Netlink {
         Ok(())
     }
 
-    // Set MTU.
-    fn set_mtu(&self, _mtu: u16) -> bool {
-        true
+    /// Get statistics of all links from kernel, without the rest of link information.
+    fn get_link_stats_all(&self) -> Result<(), KernelError> {
+        debug!("Get link stats all");
+
+        if !self.stats_supported.get() {
+            if let Err(err) = self.send_request(libc::AF_PACKET, libc::RTM_GETLINK as i32) {
+                error!("Send request: RTM_GETLINK");
+                return Err(KernelError::Link(err.to_string()))
+            }
+
+            if let Err(err) = self.parse_info(&Netlink::parse_link_stats) {
+                error!("Parse info: RTM_GETLINK");
+                return Err(KernelError::Link(err.to_string()))
+            }
+
+            return Ok(())
+        }
+
+        if let Err(err) = self.send_stats_request() {
+            error!("Send request: RTM_GETSTATS");
+            return Err(KernelError::Link(err.to_string()))
+        }
+
+        if let Err(err) = self.parse_info(&Netlink::parse_kernel_msg) {
+            error!("Parse info: RTM_GETSTATS");
+            return Err(KernelError::Link(err.to_string()))
+        }
+
+        Ok(())
     }
 
-    // Set link up.
-    fn set_link_up(&self) -> bool {
-        true
+    /// Set MTU.
+    fn set_mtu(&self, ifindex: i32, mtu: u32) -> Result<(), KernelError> {
+        self.link_msg(ifindex, 0, 0, Some(mtu))
     }
 
-    // Set link down.
-    fn set_link_down(&self) -> bool {
-        true
+    /// Set link up.
+    fn set_link_up(&self, ifindex: i32) -> Result<(), KernelError> {
+        self.link_msg(ifindex, libc::IFF_UP as u32, libc::IFF_UP as u32, None)
+    }
+
+    /// Set link down.
+    fn set_link_down(&self, ifindex: i32) -> Result<(), KernelError> {
+        self.link_msg(ifindex, 0, libc::IFF_UP as u32, None)
+    }
+
+    /// Add an IPv4 address to link.
+    fn add_ipv4_address(&self, ifindex: i32, prefix: &Prefix<Ipv4Addr>, label: Option<&str>) -> Result<(), KernelError> {
+        self.address_msg(libc::RTM_NEWADDR as i32, ifindex, prefix, label)
+    }
+
+    /// Delete an IPv4 address from link.
+    fn delete_ipv4_address(&self, ifindex: i32, prefix: &Prefix<Ipv4Addr>) -> Result<(), KernelError> {
+        self.address_msg(libc::RTM_DELADDR as i32, ifindex, prefix, None)
+    }
+
+    /// Add an IPv6 address to link.
+    fn add_ipv6_address(&self, ifindex: i32, prefix: &Prefix<Ipv6Addr>) -> Result<(), KernelError> {
+        self.address_msg(libc::RTM_NEWADDR as i32, ifindex, prefix, None)
+    }
+
+    /// Delete an IPv6 address from link.
+    fn delete_ipv6_address(&self, ifindex: i32, prefix: &Prefix<Ipv6Addr>) -> Result<(), KernelError> {
+        self.address_msg(libc::RTM_DELADDR as i32, ifindex, prefix, None)
     }
 
     /// Get all IPv4 addresses from system.
@@ -1060,24 +2097,238 @@ impl KernelDriver for Netlink {
         self.get_address_all::<Ipv6Addr>()
     }
 
-    /// Add an IPv4 route to system.
-    fn add_ipv4_route(&self, prefix: &Prefix<Ipv4Addr>, rib: &Rib<Ipv4Addr>) {
-        self.install(prefix, rib);
+    /// Get all ARP entries from system.
+    fn get_ipv4_neighbor_all(&self) -> Result<(), KernelError> {
+        self.get_neighbor_all::<Ipv4Addr>()
+    }
+
+    /// Get all IPv6 neighbor entries from system.
+    fn get_ipv6_neighbor_all(&self) -> Result<(), KernelError> {
+        self.get_neighbor_all::<Ipv6Addr>()
+    }
+
+    /// Return file descriptor to watch kernel events.
+    fn event_fd(&self) -> Option<RawFd> {
+        Some(self.event_sock)
+    }
+
+    /// Process kernel events.
+    fn process_event(&self) -> Result<(), KernelError> {
+        self.read_event()
+    }
+
+    /// Return FIB socket to watch ACKs.
+    fn fib_fd(&self) -> Option<RawFd> {
+        Some(self.fib_sock)
+    }
+
+    /// Send batched route messages.
+    fn fib_flush(&self) -> Result<(), KernelError> {
+        self.fib_send()
+    }
+
+    /// Process ACKs for route messages.
+    fn process_fib_ack(&self) -> Result<(), KernelError> {
+        self.read_fib_ack()
+    }
+
+    /// Delete routes left by previous instance.
+    fn fib_sweep(&self) -> Result<(), KernelError> {
+        self.stale_sweep()
+    }
+
+    /// Get all IPv4 routes from system.
+    fn get_ipv4_route_all(&self) -> Result<(), KernelError> {
+        self.get_route_all::<Ipv4Addr>()
+    }
+
+    /// Get all IPv6 routes from system.
+    fn get_ipv6_route_all(&self) -> Result<(), KernelError> {
+        self.get_route_all::<Ipv6Addr>()
+    }
+
+    /// Add an IPv4 route to kernel table.
+    fn add_ipv4_route(&self, table_id: u32, prefix: &Prefix<Ipv4Addr>, rib: &Rib<Ipv4Addr>) -> Result<(), KernelError> {
+        self.install(table_id, prefix, rib)
+    }
+
+    /// Delete an IPv4 route from kernel table.
+    fn delete_ipv4_route(&self, table_id: u32, prefix: &Prefix<Ipv4Addr>, rib: &Rib<Ipv4Addr>) -> Result<(), KernelError> {
+        self.uninstall(table_id, prefix, rib)
+    }
+
+    /// Add an IPv6 route to kernel table.
+    fn add_ipv6_route(&self, table_id: u32, prefix: &Prefix<Ipv6Addr>, rib: &Rib<Ipv6Addr>) -> Result<(), KernelError> {
+        self.install(table_id, prefix, rib)
+    }
+
+    /// Delete an IPv6 route from kernel table.
+    fn delete_ipv6_route(&self, table_id: u32, prefix: &Prefix<Ipv6Addr>, rib: &Rib<Ipv6Addr>) -> Result<(), KernelError> {
+        self.uninstall(table_id, prefix, rib)
+    }
+
+    fn add_mpls_route(&self, lsp: &Lsp) -> Result<(), KernelError> {
+        self.mpls_route_msg(libc::RTM_NEWROUTE as i32, lsp)
+    }
+
+    fn delete_mpls_route(&self, lsp: &Lsp) -> Result<(), KernelError> {
+        self.mpls_route_msg(libc::RTM_DELROUTE as i32, lsp)
+    }
+}
+
+/// Resolve interface name to kernel ifindex.
+fn ifname_to_index(ifname: &str) -> Result<i32, KernelError> {
+    let name = match CString::new(ifname) {
+        Ok(name) => name,
+        Err(_) => return Err(KernelError::Link(format!("Invalid interface name {}", ifname))),
+    };
+
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => Err(KernelError::Link(format!("No such interface {}", ifname))),
+        ifindex => Ok(ifindex as i32),
+    }
+}
+
+/// Open Netlink socket bound to given multicast groups, return socket and PID.
+fn netlink_socket(groups: u32) -> Result<(c_int, u32), io::Error> {
+    let sock = unsafe {
+        libc::socket(libc::AF_NETLINK, libc::SOCK_RAW, libc::NETLINK_ROUTE)
+    };
+    if sock < 0 {
+        return Err(io::Error::last_os_error());
+    };
+
+    let mut snl = unsafe { zeroed::<libc::sockaddr_nl>() };
+    snl.nl_family = libc::AF_NETLINK as u16;
+    snl.nl_groups = groups;
+    let mut socklen: libc::socklen_t = size_of::<libc::sockaddr_nl>() as u32;
+    let ret = unsafe {
+        libc::bind(
+            sock,
+            &snl as *const _ as *const libc::sockaddr,
+            socklen,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let ret = unsafe {
+        libc::getsockname(
+            sock,
+            &mut snl as *const _ as *mut libc::sockaddr,
+            &mut socklen)
+    };
+    if ret < 0 || socklen != size_of::<libc::sockaddr_nl>() as u32 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok((sock, snl.nl_pid))
+}
+
+/// Set socket non-blocking.
+fn netlink_set_nonblock(sock: c_int) -> Result<(), io::Error> {
+    let flags = unsafe { libc::fcntl(sock, libc::F_GETFL) };
+    if flags < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let ret = unsafe { libc::fcntl(sock, libc::F_SETFL, flags | libc::O_NONBLOCK) };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Set socket receive buffer size, beyond rmem_max if permitted.
+fn netlink_set_rcvbuf(sock: c_int, size: usize) -> Result<(), io::Error> {
+    let size = size as c_int;
+
+    for opt in &[libc::SO_RCVBUFFORCE, libc::SO_RCVBUF] {
+        let ret = unsafe {
+            libc::setsockopt(sock, libc::SOL_SOCKET, *opt,
+                             &size as *const _ as *const libc::c_void,
+                             size_of::<c_int>() as libc::socklen_t)
+        };
+        if ret == 0 {
+            return Ok(())
+        }
+    }
+
+    Err(io::Error::last_os_error())
+}
+
+/// Return socket receive buffer size.
+fn netlink_get_rcvbuf(sock: c_int) -> Result<usize, io::Error> {
+    let mut size: c_int = 0;
+    let mut socklen = size_of::<c_int>() as libc::socklen_t;
+
+    let ret = unsafe {
+        libc::getsockopt(sock, libc::SOL_SOCKET, libc::SO_RCVBUF,
+                         &mut size as *mut _ as *mut libc::c_void, &mut socklen)
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(size as usize)
+}
+
+/// Resolve kernel ifindex to interface name.
+fn ifindex_to_name(ifindex: i32) -> Option<String> {
+    let mut buf = [0u8; libc::IF_NAMESIZE];
+
+    let ret = unsafe { libc::if_indextoname(ifindex as u32, buf.as_mut_ptr() as *mut libc::c_char) };
+    if ret.is_null() {
+        return None
     }
 
-    /// Delete an IPv4 route from system.
-    fn delete_ipv4_route(&self, prefix: &Prefix<Ipv4Addr>, rib: &Rib<Ipv4Addr>) {
-        self.uninstall(prefix, rib);
+    attr_str(&buf).map(|ifname| ifname.to_string())
+}
+
+/// Encode struct rtvia, address family followed by gateway address.
+fn rtvia(address: &IpAddr) -> Vec<u8> {
+    let (family, octets) = match address {
+        IpAddr::V4(address) => (libc::AF_INET, address.octets().to_vec()),
+        IpAddr::V6(address) => (libc::AF_INET6, address.octets().to_vec()),
+    };
+    let mut v = (family as u16).to_ne_bytes().to_vec();
+
+    v.extend_from_slice(&octets);
+    v
+}
+
+/// Return label value of the top label stack entry.
+fn decode_mpls_label(buf: &[u8]) -> u32 {
+    if buf.len() < 4 {
+        return 0
     }
 
-    /// Add an IPv6 route to system.
-    fn add_ipv6_route(&self, prefix: &Prefix<Ipv6Addr>, rib: &Rib<Ipv6Addr>) {
-        self.install(prefix, rib);
+    u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) >> 12
+}
+
+/// Return nexthop object keys for resolved nexthops, or None if any of them has no interface.
+fn nexthop_keys<T: Addressable>(family: c_int, nexthops: &Vec<Nexthop<T>>) -> Result<Option<Vec<NexthopKey>>, KernelError> {
+    let mut keys = Vec::new();
+
+    for nexthop in nexthops {
+        let ifname = match nexthop.ifname() {
+            Some(ifname) => ifname,
+            None => return Ok(None),
+        };
+
+        keys.push(NexthopKey {
+            family: family,
+            gateway: nexthop.address().map(|address| address.octets_ref().to_vec()),
+            ifindex: ifname_to_index(ifname)?,
+        });
     }
 
-    /// Delete an IPv6 route from system.
-    fn delete_ipv6_route(&self, prefix: &Prefix<Ipv6Addr>, rib: &Rib<Ipv6Addr>) {
-        self.uninstall(prefix, rib);
+    if keys.len() == 0 {
+        Ok(None)
+    } else {
+        Ok(Some(keys))
     }
 }
 
@@ -1091,3 +2342,238 @@ pub fn get_driver() -> Option<Netlink> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+    use std::str::FromStr;
+    use std::time::Instant;
+
+    /// Move test thread to a new network namespace, so that routes do not leak to host.
+    fn netns_new() -> bool {
+        unsafe { libc::unshare(libc::CLONE_NEWNET) == 0 }
+    }
+
+    /// Open Netlink driver in a new network namespace, collecting IPv4 FIB results.
+    fn netlink_new() -> (Netlink, Rc<RefCell<Vec<KernelFibResult<Ipv4Addr>>>>) {
+        assert!(netns_new(), "Network namespace is not permitted");
+
+        let netlink = Netlink::new().unwrap();
+        let results = Rc::new(RefCell::new(Vec::new()));
+        let clone = results.clone();
+        netlink.callback.borrow_mut().ipv4_fib_result =
+            Some(Box::new(move |result| clone.borrow_mut().push(result)));
+
+        (netlink, results)
+    }
+
+    fn blackhole() -> Rib<Ipv4Addr> {
+        let rib = Rib::<Ipv4Addr>::new(RibType::Static, 1);
+        rib.set_resolved(vec![Nexthop::Blackhole]);
+        rib
+    }
+
+    fn prefix(i: u32) -> Prefix<Ipv4Addr> {
+        Prefix::<Ipv4Addr>::from(Ipv4Addr::from(0x0a00_0000 + i), 32)
+    }
+
+    /// Needs root to create network namespace, run with --ignored.
+    #[test]
+    #[ignore]
+    pub fn test_netlink_fib_batch() {
+        let (netlink, results) = netlink_new();
+        let rib = blackhole();
+        let count = 2000;
+
+        // Full batch is sent while queueing, rest waits for flush.
+        for i in 0..count {
+            netlink.route_msg(libc::RTM_NEWROUTE as i32, libc::RT_TABLE_MAIN as u32, &prefix(i), &rib).unwrap();
+        }
+        assert!(results.borrow().len() > 0);
+        assert!(results.borrow().len() < count as usize);
+        assert!(netlink.fib_batch.borrow().len() > 0);
+
+        netlink.fib_send().unwrap();
+        assert_eq!(results.borrow().len(), count as usize);
+        assert!(results.borrow().iter().all(|r| r.op == FibOp::Install && r.error.is_none() && !r.lost));
+        assert!(netlink.fib_pending.borrow().is_empty());
+        assert!(netlink.fib_generation.borrow().is_empty());
+    }
+
+    /// Needs root to create network namespace, run with --ignored.
+    #[test]
+    #[ignore]
+    pub fn test_netlink_fib_ack_nack() {
+        let (netlink, results) = netlink_new();
+        let rib = blackhole();
+
+        // Route not in kernel.
+        netlink.route_msg(libc::RTM_DELROUTE as i32, libc::RT_TABLE_MAIN as u32, &prefix(1), &rib).unwrap();
+        netlink.fib_send().unwrap();
+        {
+            let results = results.borrow();
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].op, FibOp::Uninstall);
+            assert!(results[0].error.is_some());
+        }
+        results.borrow_mut().clear();
+
+        // NACK for superseded message does not override ACK for the newer one.
+        netlink.route_msg(libc::RTM_DELROUTE as i32, libc::RT_TABLE_MAIN as u32, &prefix(2), &rib).unwrap();
+        netlink.route_msg(libc::RTM_NEWROUTE as i32, libc::RT_TABLE_MAIN as u32, &prefix(2), &rib).unwrap();
+        netlink.fib_send().unwrap();
+        {
+            let results = results.borrow();
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].prefix, prefix(2));
+            assert_eq!(results[0].op, FibOp::Install);
+            assert!(results[0].error.is_none());
+        }
+        assert!(netlink.fib_generation.borrow().is_empty());
+    }
+
+    /// Needs root to create network namespace, run with --ignored.
+    #[test]
+    #[ignore]
+    pub fn test_netlink_fib_overrun() {
+        let (netlink, results) = netlink_new();
+        let rib = blackhole();
+
+        for i in 0..3 {
+            netlink.route_msg(libc::RTM_NEWROUTE as i32, libc::RT_TABLE_MAIN as u32, &prefix(i), &rib).unwrap();
+        }
+
+        // Messages waiting for ACK are reported as lost, to be retried.
+        netlink.fib_overrun();
+        assert_eq!(results.borrow().len(), 3);
+        assert!(results.borrow().iter().all(|r| r.error.is_some() && r.lost));
+        assert!(netlink.fib_pending.borrow().is_empty());
+        assert!(netlink.fib_generation.borrow().is_empty());
+        assert!(netlink_get_rcvbuf(netlink.fib_sock).unwrap() >= NETLINK_FIB_RCVBUF);
+    }
+
+    /// Needs root to create network namespace, run with --ignored.
+    #[test]
+    #[ignore]
+    pub fn test_netlink_stale_sweep() {
+        let (netlink, results) = netlink_new();
+        let rib = blackhole();
+
+        // Routes left by previous instance.
+        for i in 0..3 {
+            netlink.route_msg(libc::RTM_NEWROUTE as i32, libc::RT_TABLE_MAIN as u32, &prefix(i), &rib).unwrap();
+        }
+        netlink.fib_send().unwrap();
+        results.borrow_mut().clear();
+
+        // Dump keeps them as stale, regardless of priority.
+        netlink.get_route_all::<Ipv4Addr>().unwrap();
+        assert_eq!(netlink.stale_routes.borrow().len(), 3);
+        let stale_key = |i: u32| (libc::RT_TABLE_MAIN as u32, libc::AF_INET, prefix(i).octets().to_vec(), 32);
+
+        // Replaced route is no longer stale once ACKed.
+        netlink.route_msg(libc::RTM_NEWROUTE as i32, libc::RT_TABLE_MAIN as u32, &prefix(0), &rib).unwrap();
+        assert!(netlink.stale_routes.borrow().contains_key(&stale_key(0)));
+        netlink.fib_send().unwrap();
+        assert!(!netlink.stale_routes.borrow().contains_key(&stale_key(0)));
+        results.borrow_mut().clear();
+
+        // Sweep deletes the rest, except one being replaced, without reporting results.
+        netlink.route_msg(libc::RTM_NEWROUTE as i32, libc::RT_TABLE_MAIN as u32, &prefix(1), &rib).unwrap();
+        netlink.stale_sweep().unwrap();
+        netlink.fib_send().unwrap();
+        {
+            let results = results.borrow();
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].prefix, prefix(1));
+            assert_eq!(results[0].op, FibOp::Install);
+            assert!(results[0].error.is_none());
+        }
+        assert!(netlink.stale_routes.borrow().is_empty());
+        assert!(netlink.fib_pending.borrow().is_empty());
+
+        // Kernel has replaced routes only.
+        netlink.get_route_all::<Ipv4Addr>().unwrap();
+        let stale = netlink.stale_routes.borrow();
+        assert_eq!(stale.len(), 2);
+        assert!(stale.contains_key(&stale_key(0)));
+        assert!(stale.contains_key(&stale_key(1)));
+    }
+
+    /// Needs root to create network namespace, run with --ignored.
+    #[test]
+    #[ignore]
+    pub fn test_netlink_link_stats() {
+        let (netlink, _) = netlink_new();
+        let stats = Rc::new(RefCell::new(Vec::new()));
+        let clone = stats.clone();
+        netlink.register_link_stats(Box::new(move |ifindex, _| clone.borrow_mut().push(ifindex)));
+
+        // Loopback is in every namespace.
+        netlink.get_link_stats_all().unwrap();
+        assert_eq!(*stats.borrow(), vec![1]);
+
+        // Statistics come with links on kernel without RTM_GETSTATS.
+        netlink.stats_supported.set(false);
+        netlink.get_link_stats_all().unwrap();
+        assert_eq!(*stats.borrow(), vec![1, 1]);
+    }
+
+    #[test]
+    pub fn test_netlink_nexthop_group_encode() {
+        // Messages are only queued, namespace is not needed.
+        let netlink = Netlink::new().unwrap();
+        let rib = Rib::<Ipv4Addr>::new(RibType::Static, 1);
+        let nexthop = |s: &str| Nexthop::from_address_ifname(&Ipv4Addr::from_str(s).unwrap(), "lo");
+        rib.set_resolved(vec![nexthop("10.0.0.1"), nexthop("10.0.0.2"), nexthop("10.0.0.1")]);
+
+        netlink.nh_supported.set(true);
+        netlink.route_msg(libc::RTM_NEWROUTE as i32, libc::RT_TABLE_MAIN as u32, &prefix(1), &rib).unwrap();
+
+        // Members, group with weights, and route referring to the group.
+        let batch = netlink.fib_batch.replace(Vec::new());
+        let msgs: Vec<NlMsg> = nlmsg_iter(&batch).map(|msg| msg.unwrap()).collect();
+        assert_eq!(msgs.len(), 4);
+
+        let ids: Vec<u32> = msgs[..3].iter().map(|msg| {
+            assert_eq!(msg.nlmsg_type, RTM_NEWNEXTHOP as u16);
+            let (_, attr) = msg.parse::<NhInfo>().unwrap();
+            attr_u32(&attr, NHA_ID).unwrap()
+        }).collect();
+
+        let (_, attr) = msgs[2].parse::<NhInfo>().unwrap();
+        let mut group = vec![0u8; 16];
+        encode_num::<u32>(&mut group[0..], ids[0]);
+        group[4] = 1;
+        encode_num::<u32>(&mut group[8..], ids[1]);
+        assert_eq!(attr.get(&NHA_GROUP), Some(&&group[..]));
+
+        assert_eq!(msgs[3].nlmsg_type, libc::RTM_NEWROUTE);
+        let (_, attr) = msgs[3].parse::<RtInfo>().unwrap();
+        assert_eq!(attr_u32(&attr, RTA_NH_ID), Some(ids[2]));
+    }
+
+    /// Install 1M prefixes in a network namespace, run with --ignored --nocapture as root.
+    #[test]
+    #[ignore]
+    pub fn bench_netlink_fib_1m() {
+        let (netlink, results) = netlink_new();
+        let rib = blackhole();
+        let count = 1_000_000;
+        let instant = Instant::now();
+
+        for i in 0..count {
+            netlink.route_msg(libc::RTM_NEWROUTE as i32, libc::RT_TABLE_MAIN as u32, &prefix(i), &rib).unwrap();
+        }
+        netlink.fib_send().unwrap();
+
+        let elapsed = instant.elapsed();
+        println!("{} routes installed in {:?}, {:.0} routes/s", count, elapsed,
+                 count as f64 / elapsed.as_secs_f64());
+
+        let results = results.borrow();
+        assert_eq!(results.len(), count as usize);
+        assert!(results.iter().all(|r| r.error.is_none()));
+    }
+}
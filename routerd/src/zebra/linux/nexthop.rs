@@ -0,0 +1,338 @@
+//
+// ReZe.Rs - Router Daemon
+//   Copyright (C) 2018-2020 Toshiaki Takada
+//
+// Zebra - Kernel nexthop objects and groups.
+//  Identical nexthop sets are shared by routes through a single kernel nexthop ID.
+//
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+use log::debug;
+
+/// Single kernel nexthop.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NexthopKey {
+
+    /// Address family.
+    pub family: i32,
+
+    /// Gateway address octets.
+    pub gateway: Option<Vec<u8>>,
+
+    /// Outgoing interface index.
+    pub ifindex: i32,
+}
+
+/// Kernel nexthop object, single nexthop or group of nexthop IDs and weights.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum NexthopObjectKey {
+    Single(NexthopKey),
+    Group(Vec<(u32, u8)>),
+}
+
+/// Kernel nexthop object and number of references from routes and groups.
+struct NexthopObject {
+
+    /// Object key.
+    key: NexthopObjectKey,
+
+    /// Reference count.
+    refcnt: u32,
+}
+
+/// Route referencing kernel nexthop object.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct NexthopRouteKey {
+
+    /// Kernel table ID.
+    pub table_id: u32,
+
+    /// Address family.
+    pub family: i32,
+
+    /// Destination address octets.
+    pub dst: Vec<u8>,
+
+    /// Destination prefix length.
+    pub len: u8,
+
+    /// Route priority.
+    pub metric: u32,
+}
+
+/// Kernel nexthop objects table.
+pub struct NexthopTable {
+
+    /// Next ID to be allocated.
+    next_id: u32,
+
+    /// Objects per ID.
+    objects: HashMap<u32, NexthopObject>,
+
+    /// Object key to ID map, for objects to be shared.
+    index: HashMap<NexthopObjectKey, u32>,
+
+    /// Object ID per route.
+    routes: HashMap<NexthopRouteKey, u32>,
+
+    /// Object IDs no longer referenced, to be deleted from kernel.
+    gc: Vec<u32>,
+}
+
+impl NexthopTable {
+
+    /// Constructor, IDs are allocated from base.
+    pub fn new(base: u32) -> NexthopTable {
+        NexthopTable {
+            next_id: base,
+            objects: HashMap::new(),
+            index: HashMap::new(),
+            routes: HashMap::new(),
+            gc: Vec::new(),
+        }
+    }
+
+    /// Reserve ID used by other owner, IDs are allocated after it.
+    pub fn reserve(&mut self, id: u32) {
+        if id >= self.next_id {
+            self.next_id = id + 1;
+        }
+    }
+
+    /// Return number of objects.
+    pub fn count(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// Return object ID for the key, allocate new one and add it to created list if not exist.
+    fn lookup_or_create(&mut self, key: NexthopObjectKey, created: &mut Vec<(u32, NexthopObjectKey)>) -> u32 {
+        if let Some(id) = self.index.get(&key) {
+            return *id
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.objects.insert(id, NexthopObject { key: key.clone(), refcnt: 0 });
+        self.index.insert(key.clone(), id);
+        created.push((id, key));
+
+        id
+    }
+
+    /// Take a reference to object for the nexthop set, return its ID and objects to be created
+    /// in kernel, in order that members precede group.
+    pub fn acquire(&mut self, keys: &[NexthopKey]) -> (u32, Vec<(u32, NexthopObjectKey)>) {
+        let mut created = Vec::new();
+
+        let id = if keys.len() == 1 {
+            self.lookup_or_create(NexthopObjectKey::Single(keys[0].clone()), &mut created)
+        } else {
+            // Weight of member is the number of times the same nexthop appears in the set.
+            let mut weights = BTreeMap::<u32, u8>::new();
+            for key in keys {
+                let member = self.lookup_or_create(NexthopObjectKey::Single(key.clone()), &mut created);
+                let weight = weights.entry(member).or_insert(0);
+                *weight = weight.saturating_add(1);
+            }
+            let members: Vec<(u32, u8)> = weights.into_iter().collect();
+
+            let len = created.len();
+            let id = self.lookup_or_create(NexthopObjectKey::Group(members.clone()), &mut created);
+
+            // New group holds references to its members.
+            if created.len() > len {
+                for (member, _) in members {
+                    if let Some(object) = self.objects.get_mut(&member) {
+                        object.refcnt += 1;
+                    }
+                }
+            }
+
+            id
+        };
+
+        if let Some(object) = self.objects.get_mut(&id) {
+            object.refcnt += 1;
+        }
+
+        (id, created)
+    }
+
+    /// Release a reference to object, and schedule it to be deleted if no longer referenced.
+    pub fn release(&mut self, id: u32) {
+        let unused = match self.objects.get_mut(&id) {
+            Some(object) => {
+                object.refcnt -= 1;
+                object.refcnt == 0
+            },
+            None => false,
+        };
+
+        if !unused {
+            return
+        }
+
+        if let Some(object) = self.objects.remove(&id) {
+            if self.index.get(&object.key) == Some(&id) {
+                self.index.remove(&object.key);
+            }
+
+            debug!("Nexthop object {} is unused", id);
+            self.gc.push(id);
+
+            // Group goes first, then its members.
+            if let NexthopObjectKey::Group(members) = object.key {
+                for (member, _) in members {
+                    self.release(member);
+                }
+            }
+        }
+    }
+
+    /// Set object ID referenced by route, and release the one referenced previously.
+    /// The reference to new object must be taken by acquire() beforehand.
+    pub fn route_set(&mut self, route: NexthopRouteKey, id: Option<u32>) {
+        let old = match id {
+            Some(id) => self.routes.insert(route, id),
+            None => self.routes.remove(&route),
+        };
+
+        if let Some(old) = old {
+            self.release(old);
+        }
+    }
+
+    /// Object failed to be created in kernel, do not share it any more.
+    pub fn invalidate(&mut self, id: u32) {
+        if let Some(object) = self.objects.get(&id) {
+            if self.index.get(&object.key) == Some(&id) {
+                self.index.remove(&object.key);
+            }
+        }
+    }
+
+    /// Return object IDs to be deleted from kernel.
+    pub fn gc(&mut self) -> Vec<u32> {
+        std::mem::replace(&mut self.gc, Vec::new())
+    }
+}
+
+///
+/// Unit tests for nexthop objects.
+///
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nexthop(gateway: u8, ifindex: i32) -> NexthopKey {
+        NexthopKey {
+            family: libc::AF_INET,
+            gateway: Some(vec![10, 0, 0, gateway]),
+            ifindex: ifindex,
+        }
+    }
+
+    fn route(dst: u8) -> NexthopRouteKey {
+        NexthopRouteKey {
+            table_id: 254,
+            family: libc::AF_INET,
+            dst: vec![192, 168, dst, 0],
+            len: 24,
+            metric: 20,
+        }
+    }
+
+    #[test]
+    pub fn test_nexthop_group_shared() {
+        let mut table = NexthopTable::new(100);
+        let nexthops = vec![nexthop(1, 2), nexthop(2, 3)];
+
+        let (id1, created) = table.acquire(&nexthops);
+        table.route_set(route(1), Some(id1));
+        assert_eq!(created.len(), 3);
+        assert_eq!(created[2], (id1, NexthopObjectKey::Group(vec![(100, 1), (101, 1)])));
+
+        // Same set in different order is shared.
+        let (id2, created) = table.acquire(&vec![nexthop(2, 3), nexthop(1, 2)]);
+        table.route_set(route(2), Some(id2));
+        assert_eq!(id1, id2);
+        assert!(created.is_empty());
+        assert_eq!(table.count(), 3);
+
+        // Single nexthop object is shared with the group.
+        let (id3, created) = table.acquire(&vec![nexthop(1, 2)]);
+        table.route_set(route(3), Some(id3));
+        assert_eq!(id3, 100);
+        assert!(created.is_empty());
+
+        table.route_set(route(1), None);
+        assert!(table.gc().is_empty());
+
+        // Group and member only used by group go away, in that order.
+        table.route_set(route(2), None);
+        assert_eq!(table.gc(), vec![id1, 101]);
+        assert_eq!(table.count(), 1);
+
+        table.route_set(route(3), None);
+        assert_eq!(table.gc(), vec![100]);
+        assert_eq!(table.count(), 0);
+    }
+
+    #[test]
+    pub fn test_nexthop_group_weight() {
+        let mut table = NexthopTable::new(100);
+
+        // Nexthop appearing twice has double weight, and it is a different group.
+        let (id1, created) = table.acquire(&vec![nexthop(1, 2), nexthop(2, 3), nexthop(1, 2)]);
+        assert_eq!(created.len(), 3);
+        assert_eq!(created[2], (id1, NexthopObjectKey::Group(vec![(100, 2), (101, 1)])));
+
+        let (id2, created) = table.acquire(&vec![nexthop(1, 2), nexthop(2, 3)]);
+        assert_ne!(id1, id2);
+        assert_eq!(created, vec![(id2, NexthopObjectKey::Group(vec![(100, 1), (101, 1)]))]);
+
+        // Reference taken but not used by route is released.
+        table.release(id2);
+        assert_eq!(table.gc(), vec![id2]);
+        assert_eq!(table.count(), 3);
+    }
+
+    #[test]
+    pub fn test_nexthop_route_replace() {
+        let mut table = NexthopTable::new(1);
+
+        let (id1, _) = table.acquire(&vec![nexthop(1, 2)]);
+        table.route_set(route(1), Some(id1));
+
+        // Replacing with the same set keeps the object.
+        let (id, created) = table.acquire(&vec![nexthop(1, 2)]);
+        table.route_set(route(1), Some(id));
+        assert_eq!(id, id1);
+        assert!(created.is_empty());
+        assert!(table.gc().is_empty());
+
+        // Replacing with other set releases old one.
+        let (id2, created) = table.acquire(&vec![nexthop(2, 2)]);
+        table.route_set(route(1), Some(id2));
+        assert_eq!(created.len(), 1);
+        assert_eq!(table.gc(), vec![id1]);
+
+        // Reserved ID is skipped.
+        table.reserve(id2 + 10);
+        let (id4, _) = table.acquire(&vec![nexthop(3, 2)]);
+        assert_eq!(id4, id2 + 11);
+        table.route_set(route(3), Some(id4));
+
+        // Invalidated object is not shared, but still released.
+        table.invalidate(id2);
+        let (id3, created) = table.acquire(&vec![nexthop(2, 2)]);
+        assert_ne!(id3, id2);
+        assert_eq!(created.len(), 1);
+        table.route_set(route(2), Some(id3));
+        table.route_set(route(1), None);
+        assert_eq!(table.gc(), vec![id2]);
+    }
+}
@@ -0,0 +1,1028 @@
+//
+// ReZe.Rs - Router Daemon
+//   Copyright (C) 2018-2020 Toshiaki Takada
+//
+// Zebra - Netlink message parser.
+//   Pure functions over received bytes.  Every length is checked against the buffer,
+//   so that malformed messages from kernel are rejected instead of crashing Zebra.
+//
+
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use libc::{self, c_int};
+use log::debug;
+
+use rtable::prefix::*;
+
+use common::nexthop::*;
+
+use super::super::kernel::*;
+
+/// Netlink routing protocol ID for Zebra.
+pub const RTPROT_ZEBRA: c_int = 11;
+
+/// Link statistics message types, not in libc.
+pub const RTM_NEWSTATS: u16 = 92;
+pub const RTM_GETSTATS: u16 = 94;
+
+/// 64-bit link statistics attribute in RTM_NEWSTATS, also selects it in filter mask.
+pub const IFLA_STATS_LINK_64: c_int = 1;
+
+const IFLA_MASTER: c_int = 10;
+const IFLA_OPERSTATE: c_int = 16;
+const IFLA_LINKINFO: c_int = 18;
+const IFLA_STATS64: c_int = 23;
+const IFLA_INFO_KIND: c_int = 1;
+const IFLA_INFO_DATA: c_int = 2;
+const IFLA_VRF_TABLE: c_int = 1;
+
+const NDA_DST: c_int = 1;
+const NDA_LLADDR: c_int = 2;
+
+const NUD_INCOMPLETE: u16 = 0x01;
+const NUD_REACHABLE: u16 = 0x02;
+const NUD_STALE: u16 = 0x04;
+const NUD_DELAY: u16 = 0x08;
+const NUD_PROBE: u16 = 0x10;
+const NUD_FAILED: u16 = 0x20;
+const NUD_NOARP: u16 = 0x40;
+const NUD_PERMANENT: u16 = 0x80;
+
+const NTF_ROUTER: u8 = 0x80;
+
+/// Length of struct nlmsghdr.
+const NLMSG_HDRLEN: usize = 16;
+
+/// Length of struct rtattr.
+const RTA_HDRLEN: usize = 4;
+
+/// Length of struct rtnexthop.
+const RTNH_HDRLEN: usize = 8;
+
+/// Flags carried in attribute type.
+const NLA_TYPE_MASK: u16 = 0x3fff;
+
+const NLMSG_ALIGNTO: usize = 4;
+
+/// Typedefs.
+pub type AttrMap<'a> = HashMap<c_int, &'a [u8]>;
+
+/// Align length to 4 bytes, as Netlink messages, attributes and nexthops are.
+fn align(len: usize) -> usize {
+    (len + NLMSG_ALIGNTO - 1) & !(NLMSG_ALIGNTO - 1)
+}
+
+/// Return u16 in host byte order, None if buffer is too short.
+pub fn decode_u16(buf: &[u8]) -> Option<u16> {
+    match buf.get(..2) {
+        Some(b) => Some(u16::from_ne_bytes([b[0], b[1]])),
+        None => None,
+    }
+}
+
+/// Return u32 in host byte order, None if buffer is too short.
+pub fn decode_u32(buf: &[u8]) -> Option<u32> {
+    match buf.get(..4) {
+        Some(b) => Some(u32::from_ne_bytes([b[0], b[1], b[2], b[3]])),
+        None => None,
+    }
+}
+
+/// Return u64 in host byte order, None if buffer is too short.
+pub fn decode_u64(buf: &[u8]) -> Option<u64> {
+    match buf.get(..8) {
+        Some(b) => Some(u64::from_ne_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])),
+        None => None,
+    }
+}
+
+/// Return i32 in host byte order, None if buffer is too short.
+pub fn decode_i32(buf: &[u8]) -> Option<i32> {
+    decode_u32(buf).map(|v| v as i32)
+}
+
+/// Return address if buffer has exactly its length.
+fn decode_addr<T: Addressable>(buf: &[u8]) -> Option<T> {
+    if buf.len() == T::byte_len() as usize {
+        Some(T::from_slice(buf))
+    } else {
+        None
+    }
+}
+
+/// Return string attribute without trailing NUL.
+pub fn attr_str(buf: &[u8]) -> Option<&str> {
+    let len = buf.iter().position(|c| *c == 0).unwrap_or(buf.len());
+
+    std::str::from_utf8(&buf[..len]).ok()
+}
+
+/// Return u32 attribute.
+pub fn attr_u32(attr: &AttrMap, rta_type: c_int) -> Option<u32> {
+    attr.get(&rta_type).and_then(|v| decode_u32(v))
+}
+
+/// Parse attributes, stop at the first one exceeding the buffer.
+pub fn parse_attrs(buf: &[u8]) -> AttrMap {
+    let mut m = AttrMap::new();
+    let mut b = buf;
+
+    while b.len() >= RTA_HDRLEN {
+        let rta_len = decode_u16(b).unwrap_or(0) as usize;
+        let rta_type = decode_u16(&b[2..]).unwrap_or(0) & NLA_TYPE_MASK;
+
+        if rta_len < RTA_HDRLEN || rta_len > b.len() {
+            debug!("Invalid rtattr length {}", rta_len);
+            break;
+        }
+
+        m.insert(rta_type as c_int, &b[RTA_HDRLEN..rta_len]);
+
+        b = b.get(align(rta_len)..).unwrap_or(&[]);
+    }
+
+    m
+}
+
+/// Netlink message, header decoded and payload borrowed from receive buffer.
+pub struct NlMsg<'a> {
+
+    /// Message type.
+    pub nlmsg_type: u16,
+
+    /// Flags.
+    pub nlmsg_flags: u16,
+
+    /// Sequence number.
+    pub nlmsg_seq: u32,
+
+    /// Port ID of sender.
+    pub nlmsg_pid: u32,
+
+    /// Payload following header.
+    pub payload: &'a [u8],
+}
+
+impl<'a> NlMsg<'a> {
+
+    /// Return address family of payload, which most of rtnetlink messages start with.
+    pub fn family(&self) -> c_int {
+        match self.payload.get(0) {
+            Some(family) => *family as c_int,
+            None => libc::AF_UNSPEC,
+        }
+    }
+
+    /// Return error code carried in NLMSG_ERROR, 0 means ACK.
+    pub fn error(&self) -> Result<i32, KernelError> {
+        match decode_i32(self.payload) {
+            Some(error) => Ok(error),
+            None => Err(KernelError::Decode(format!("Truncated Nlmsgerr"))),
+        }
+    }
+
+    /// Decode fixed header of payload, and attributes following it.
+    pub fn parse<P: NlPayload>(&self) -> Result<(P, AttrMap<'a>), KernelError> {
+        if self.payload.len() < P::LEN {
+            return Err(KernelError::Decode(format!("Insufficient payload length {} for type {}",
+                                                   self.payload.len(), self.nlmsg_type)))
+        }
+
+        let attr = parse_attrs(self.payload.get(align(P::LEN)..).unwrap_or(&[]));
+
+        Ok((P::decode(self.payload), attr))
+    }
+}
+
+/// Iterator over Netlink messages in receive buffer.
+/// It ends after the first malformed message, as following ones cannot be located.
+pub struct NlMsgIter<'a> {
+
+    /// Rest of buffer.
+    buf: &'a [u8],
+}
+
+impl<'a> Iterator for NlMsgIter<'a> {
+    type Item = Result<NlMsg<'a>, KernelError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let b = self.buf;
+        if b.is_empty() {
+            return None
+        }
+
+        let nlmsg_len = decode_u32(b).unwrap_or(0) as usize;
+        if b.len() < NLMSG_HDRLEN || nlmsg_len < NLMSG_HDRLEN || nlmsg_len > b.len() {
+            self.buf = &[];
+            return Some(Err(KernelError::Decode(format!("Invalid Nlmsg length {} in {} bytes",
+                                                        nlmsg_len, b.len()))))
+        }
+
+        let msg = NlMsg {
+            nlmsg_type: decode_u16(&b[4..]).unwrap_or(0),
+            nlmsg_flags: decode_u16(&b[6..]).unwrap_or(0),
+            nlmsg_seq: decode_u32(&b[8..]).unwrap_or(0),
+            nlmsg_pid: decode_u32(&b[12..]).unwrap_or(0),
+            payload: &b[NLMSG_HDRLEN..nlmsg_len],
+        };
+
+        self.buf = b.get(align(nlmsg_len)..).unwrap_or(&[]);
+
+        Some(Ok(msg))
+    }
+}
+
+/// Return iterator over Netlink messages in buffer.
+pub fn nlmsg_iter(buf: &[u8]) -> NlMsgIter {
+    NlMsgIter {
+        buf: buf,
+    }
+}
+
+/// Fixed header at the beginning of message payload.
+pub trait NlPayload: Sized {
+
+    /// Length of header.
+    const LEN: usize;
+
+    /// Decode header from buffer, at least LEN bytes long.
+    fn decode(buf: &[u8]) -> Self;
+}
+
+/// struct ifinfomsg from rtnetlink.h.
+pub struct IfInfo {
+    pub family: u8,
+    pub ifi_type: u16,
+    pub index: i32,
+    pub flags: u32,
+    pub change: u32,
+}
+
+impl NlPayload for IfInfo {
+    const LEN: usize = 16;
+
+    fn decode(buf: &[u8]) -> IfInfo {
+        IfInfo {
+            family: buf[0],
+            ifi_type: decode_u16(&buf[2..]).unwrap_or(0),
+            index: decode_i32(&buf[4..]).unwrap_or(0),
+            flags: decode_u32(&buf[8..]).unwrap_or(0),
+            change: decode_u32(&buf[12..]).unwrap_or(0),
+        }
+    }
+}
+
+/// struct ifaddrmsg from if_addr.h.
+pub struct IfAddr {
+    pub family: u8,
+    pub prefixlen: u8,
+    pub flags: u8,
+    pub scope: u8,
+    pub index: u32,
+}
+
+impl NlPayload for IfAddr {
+    const LEN: usize = 8;
+
+    fn decode(buf: &[u8]) -> IfAddr {
+        IfAddr {
+            family: buf[0],
+            prefixlen: buf[1],
+            flags: buf[2],
+            scope: buf[3],
+            index: decode_u32(&buf[4..]).unwrap_or(0),
+        }
+    }
+}
+
+/// struct rtmsg from rtnetlink.h.
+pub struct RtInfo {
+    pub family: u8,
+    pub dst_len: u8,
+    pub src_len: u8,
+    pub tos: u8,
+    pub table: u8,
+    pub protocol: u8,
+    pub scope: u8,
+    pub rtm_type: u8,
+    pub flags: u32,
+}
+
+impl NlPayload for RtInfo {
+    const LEN: usize = 12;
+
+    fn decode(buf: &[u8]) -> RtInfo {
+        RtInfo {
+            family: buf[0],
+            dst_len: buf[1],
+            src_len: buf[2],
+            tos: buf[3],
+            table: buf[4],
+            protocol: buf[5],
+            scope: buf[6],
+            rtm_type: buf[7],
+            flags: decode_u32(&buf[8..]).unwrap_or(0),
+        }
+    }
+}
+
+/// struct nhmsg from nexthop.h.
+pub struct NhInfo {
+    pub family: u8,
+    pub scope: u8,
+    pub protocol: u8,
+    pub flags: u32,
+}
+
+impl NlPayload for NhInfo {
+    const LEN: usize = 8;
+
+    fn decode(buf: &[u8]) -> NhInfo {
+        NhInfo {
+            family: buf[0],
+            scope: buf[1],
+            protocol: buf[2],
+            flags: decode_u32(&buf[4..]).unwrap_or(0),
+        }
+    }
+}
+
+/// struct ndmsg from neighbour.h.
+pub struct NdMsg {
+    pub family: u8,
+    pub index: i32,
+    pub state: u16,
+    pub flags: u8,
+    pub ndm_type: u8,
+}
+
+impl NlPayload for NdMsg {
+    const LEN: usize = 12;
+
+    fn decode(buf: &[u8]) -> NdMsg {
+        NdMsg {
+            family: buf[0],
+            index: decode_i32(&buf[4..]).unwrap_or(0),
+            state: decode_u16(&buf[8..]).unwrap_or(0),
+            flags: buf[10],
+            ndm_type: buf[11],
+        }
+    }
+}
+
+/// struct if_stats_msg from if_link.h.
+pub struct IfStatsMsg {
+    pub family: u8,
+    pub index: i32,
+    pub filter_mask: u32,
+}
+
+impl NlPayload for IfStatsMsg {
+    const LEN: usize = 12;
+
+    fn decode(buf: &[u8]) -> IfStatsMsg {
+        IfStatsMsg {
+            family: buf[0],
+            index: decode_i32(&buf[4..]).unwrap_or(0),
+            filter_mask: decode_u32(&buf[8..]).unwrap_or(0),
+        }
+    }
+}
+
+/// Message from kernel, to be passed up to application.
+pub enum KernelMsg {
+    NewLink(KernelLink),
+    DelLink(KernelLink),
+    NewAddrIpv4(KernelAddr<Ipv4Addr>),
+    DelAddrIpv4(KernelAddr<Ipv4Addr>),
+    NewAddrIpv6(KernelAddr<Ipv6Addr>),
+    DelAddrIpv6(KernelAddr<Ipv6Addr>),
+    NewRouteIpv4(KernelRoute<Ipv4Addr>),
+    DelRouteIpv4(KernelRoute<Ipv4Addr>),
+    NewRouteIpv6(KernelRoute<Ipv6Addr>),
+    DelRouteIpv6(KernelRoute<Ipv6Addr>),
+    NewNeighIpv4(KernelNeigh<Ipv4Addr>),
+    DelNeighIpv4(KernelNeigh<Ipv4Addr>),
+    NewNeighIpv6(KernelNeigh<Ipv6Addr>),
+    DelNeighIpv6(KernelNeigh<Ipv6Addr>),
+    NewStats(i32, KernelLinkStats),
+}
+
+/// Parse link, address, route, neighbor or link statistics message.
+/// None is returned for messages application is not interested in.
+pub fn kernel_msg(msg: &NlMsg, ifname: &dyn Fn(i32) -> Option<String>) -> Result<Option<KernelMsg>, KernelError> {
+    let kmsg = match (msg.nlmsg_type, msg.family()) {
+        (libc::RTM_NEWLINK, _) => KernelMsg::NewLink(kernel_link(msg)?),
+        (libc::RTM_DELLINK, _) => KernelMsg::DelLink(kernel_link(msg)?),
+        (libc::RTM_NEWADDR, libc::AF_INET) => KernelMsg::NewAddrIpv4(kernel_addr(msg)?),
+        (libc::RTM_DELADDR, libc::AF_INET) => KernelMsg::DelAddrIpv4(kernel_addr(msg)?),
+        (libc::RTM_NEWADDR, libc::AF_INET6) => KernelMsg::NewAddrIpv6(kernel_addr(msg)?),
+        (libc::RTM_DELADDR, libc::AF_INET6) => KernelMsg::DelAddrIpv6(kernel_addr(msg)?),
+        (libc::RTM_NEWROUTE, libc::AF_INET) => match kernel_route(msg, ifname)? {
+            Some(kr) => KernelMsg::NewRouteIpv4(kr),
+            None => return Ok(None),
+        },
+        (libc::RTM_DELROUTE, libc::AF_INET) => match kernel_route(msg, ifname)? {
+            Some(kr) => KernelMsg::DelRouteIpv4(kr),
+            None => return Ok(None),
+        },
+        (libc::RTM_NEWROUTE, libc::AF_INET6) => match kernel_route(msg, ifname)? {
+            Some(kr) => KernelMsg::NewRouteIpv6(kr),
+            None => return Ok(None),
+        },
+        (libc::RTM_DELROUTE, libc::AF_INET6) => match kernel_route(msg, ifname)? {
+            Some(kr) => KernelMsg::DelRouteIpv6(kr),
+            None => return Ok(None),
+        },
+        (libc::RTM_NEWNEIGH, libc::AF_INET) => KernelMsg::NewNeighIpv4(kernel_neigh(msg)?),
+        (libc::RTM_DELNEIGH, libc::AF_INET) => KernelMsg::DelNeighIpv4(kernel_neigh(msg)?),
+        (libc::RTM_NEWNEIGH, libc::AF_INET6) => KernelMsg::NewNeighIpv6(kernel_neigh(msg)?),
+        (libc::RTM_DELNEIGH, libc::AF_INET6) => KernelMsg::DelNeighIpv6(kernel_neigh(msg)?),
+        (RTM_NEWSTATS, _) => match kernel_link_stats(msg)? {
+            Some((index, stats)) => KernelMsg::NewStats(index, stats),
+            None => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+
+    Ok(Some(kmsg))
+}
+
+/// Parse RTM_NEWLINK or RTM_DELLINK.
+pub fn kernel_link(msg: &NlMsg) -> Result<KernelLink, KernelError> {
+    let (ifi, attr) = msg.parse::<IfInfo>()?;
+
+    let hwaddr: [u8; 6] = match attr.get(&(libc::IFLA_ADDRESS as c_int)) {
+        Some(hwaddr) if hwaddr.len() == 6 => {
+            [hwaddr[0], hwaddr[1], hwaddr[2], hwaddr[3], hwaddr[4], hwaddr[5]]
+        },
+        _ => [0, 0, 0, 0, 0, 0],
+    };
+
+    let mtu = attr_u32(&attr, libc::IFLA_MTU as c_int).unwrap_or(0);
+    let ifname = match attr.get(&(libc::IFLA_IFNAME as c_int)) {
+        Some(ifname) => attr_str(ifname).unwrap_or("(Non-utf8)"),
+        None => "(Unknown)",
+    };
+
+    let operstate = match attr.get(&IFLA_OPERSTATE) {
+        Some(operstate) if operstate.len() > 0 => operstate[0],
+        _ => 0u8,
+    };
+
+    let master = attr_u32(&attr, IFLA_MASTER).map(|master| master as i32);
+
+    let (kind, vrf_table) = match attr.get(&IFLA_LINKINFO) {
+        Some(linkinfo) => {
+            let info = parse_attrs(linkinfo);
+            let kind = info.get(&IFLA_INFO_KIND)
+                .and_then(|kind| attr_str(kind))
+                .map(|kind| kind.to_string());
+
+            // VRF device carries its table ID in info data.
+            let vrf_table = match (kind.as_ref().map(|kind| kind.as_str()), info.get(&IFLA_INFO_DATA)) {
+                (Some("vrf"), Some(data)) => attr_u32(&parse_attrs(data), IFLA_VRF_TABLE),
+                _ => None,
+            };
+
+            (kind, vrf_table)
+        },
+        None => (None, None),
+    };
+
+    let stats = attr.get(&IFLA_STATS64).and_then(|stats| link_stats(stats));
+
+    debug!("kernel_link() {} {} {} {:?} {} flags {:x} operstate {} kind {:?} master {:?} vrf_table {:?}",
+           ifi.index, ifname, ifi.ifi_type, hwaddr, mtu, ifi.flags, operstate, kind, master, vrf_table);
+
+    let mut kl = KernelLink::new(ifi.index, ifname, ifi.ifi_type, hwaddr, mtu);
+    kl.flags = ifi.flags;
+    kl.operstate = operstate;
+    kl.kind = kind;
+    kl.master = master;
+    kl.vrf_table = vrf_table;
+    kl.stats = stats;
+
+    Ok(kl)
+}
+
+/// Parse RTM_NEWSTATS, None if it carries no 64-bit link statistics.
+pub fn kernel_link_stats(msg: &NlMsg) -> Result<Option<(i32, KernelLinkStats)>, KernelError> {
+    let (ifsm, attr) = msg.parse::<IfStatsMsg>()?;
+
+    Ok(attr.get(&IFLA_STATS_LINK_64).and_then(|stats| link_stats(stats)).map(|stats| (ifsm.index, stats)))
+}
+
+/// Parse 64-bit link statistics out of RTM_NEWLINK, for kernel without RTM_GETSTATS.
+pub fn kernel_link_stats64(msg: &NlMsg) -> Result<Option<(i32, KernelLinkStats)>, KernelError> {
+    let (ifi, attr) = msg.parse::<IfInfo>()?;
+
+    Ok(attr.get(&IFLA_STATS64).and_then(|stats| link_stats(stats)).map(|stats| (ifi.index, stats)))
+}
+
+/// Decode leading counters of struct rtnl_link_stats64, None if buffer is too short.
+fn link_stats(buf: &[u8]) -> Option<KernelLinkStats> {
+    let counter = |i: usize| buf.get(i * 8..).and_then(|b| decode_u64(b));
+
+    Some(KernelLinkStats {
+        rx_packets: counter(0)?,
+        tx_packets: counter(1)?,
+        rx_bytes: counter(2)?,
+        tx_bytes: counter(3)?,
+        rx_errors: counter(4)?,
+        tx_errors: counter(5)?,
+        rx_dropped: counter(6)?,
+        tx_dropped: counter(7)?,
+    })
+}
+
+/// Convert NUD state bits to neighbor state.
+/// More than one bit may be set, such as NOARP|PERMANENT, then the first one in fixed priority order wins.
+/// PERMANENT and NOARP come first, as such entries are static and never go through NUD resolution.
+fn neigh_state(state: u16) -> NeighState {
+    const PRIORITY: [(u16, NeighState); 8] = [
+        (NUD_PERMANENT, NeighState::Permanent),
+        (NUD_NOARP, NeighState::Noarp),
+        (NUD_REACHABLE, NeighState::Reachable),
+        (NUD_STALE, NeighState::Stale),
+        (NUD_DELAY, NeighState::Delay),
+        (NUD_PROBE, NeighState::Probe),
+        (NUD_FAILED, NeighState::Failed),
+        (NUD_INCOMPLETE, NeighState::Incomplete),
+    ];
+
+    match PRIORITY.iter().find(|(bit, _)| state & bit != 0) {
+        Some((_, neigh_state)) => *neigh_state,
+        None => NeighState::None,
+    }
+}
+
+/// Parse RTM_NEWNEIGH or RTM_DELNEIGH.
+pub fn kernel_neigh<T: Addressable>(msg: &NlMsg) -> Result<KernelNeigh<T>, KernelError> {
+    let (ndm, attr) = msg.parse::<NdMsg>()?;
+
+    let address = match attr.get(&NDA_DST).and_then(|dst| decode_addr::<T>(dst)) {
+        Some(address) => address,
+        None => return Err(KernelError::Decode(format!("No valid neighbor address"))),
+    };
+
+    let hwaddr = match attr.get(&NDA_LLADDR) {
+        Some(hwaddr) if hwaddr.len() == 6 => {
+            Some([hwaddr[0], hwaddr[1], hwaddr[2], hwaddr[3], hwaddr[4], hwaddr[5]])
+        },
+        _ => None,
+    };
+
+    debug!("kernel_neigh() {} {} {:?} state {:x} flags {:x}", ndm.index, address, hwaddr, ndm.state, ndm.flags);
+
+    let mut kn = KernelNeigh::new(ndm.index, address, hwaddr, neigh_state(ndm.state));
+    kn.router = ndm.flags & NTF_ROUTER != 0;
+
+    Ok(kn)
+}
+
+/// Parse RTM_NEWADDR or RTM_DELADDR.
+pub fn kernel_addr<T: Addressable>(msg: &NlMsg) -> Result<KernelAddr<T>, KernelError> {
+    let (ifa, attr) = msg.parse::<IfAddr>()?;
+
+    // IFA_LOCAL is own address on peer link, IFA_ADDRESS is peer's then.
+    let local = attr.get(&(libc::IFA_LOCAL as c_int))
+        .or(attr.get(&(libc::IFA_ADDRESS as c_int)));
+
+    let address = match local.and_then(|local| decode_addr::<T>(local)) {
+        Some(address) => address,
+        None => return Err(KernelError::Decode(format!("No valid address for ifindex {}", ifa.index))),
+    };
+
+    if ifa.prefixlen > T::bit_len() {
+        return Err(KernelError::Decode(format!("Invalid prefix length {}", ifa.prefixlen)))
+    }
+
+    Ok(KernelAddr::<T>::new(ifa.index as i32, Prefix::from(address, ifa.prefixlen), None, false, false, None))
+}
+
+/// Return kernel table ID of route.
+pub fn route_table(rtm: &RtInfo, attr: &AttrMap) -> u32 {
+    attr_u32(attr, libc::RTA_TABLE as c_int).unwrap_or(rtm.table as u32)
+}
+
+/// Return true if application is interested in the route.
+fn route_interested(rtm: &RtInfo, attr: &AttrMap) -> bool {
+    // Only unicast and blackhole routes are interested.
+    if rtm.rtm_type != libc::RTN_UNICAST && rtm.rtm_type != libc::RTN_BLACKHOLE {
+        return false
+    }
+
+    // Ignore cloned routes.
+    if (rtm.flags & libc::RTM_F_CLONED) != 0 {
+        return false
+    }
+
+    // Ignore redirect, and connected routes maintained by kernel.
+    if rtm.protocol == libc::RTPROT_REDIRECT || rtm.protocol == libc::RTPROT_KERNEL {
+        return false
+    }
+
+    // Source specific routes are not supported.
+    if rtm.src_len != 0 {
+        return false
+    }
+
+    // Local table only has routes maintained by kernel, routes in other tables
+    // not bound to any VRF are ignored by application.
+    route_table(rtm, attr) != libc::RT_TABLE_LOCAL as u32
+}
+
+/// Parse RTM_NEWROUTE or RTM_DELROUTE, None if not interested or it has no nexthop.
+pub fn kernel_route<T: Addressable>(msg: &NlMsg, ifname: &dyn Fn(i32) -> Option<String>) -> Result<Option<KernelRoute<T>>, KernelError> {
+    let (rtm, attr) = msg.parse::<RtInfo>()?;
+
+    if !route_interested(&rtm, &attr) {
+        return Ok(None)
+    }
+
+    let dst = match attr.get(&(libc::RTA_DST as c_int)) {
+        Some(dst) => match decode_addr::<T>(dst) {
+            Some(dst) => dst,
+            None => return Err(KernelError::Decode(format!("Invalid RTA_DST length {}", dst.len()))),
+        },
+        None => T::empty_new(),
+    };
+
+    if rtm.dst_len > T::bit_len() {
+        return Err(KernelError::Decode(format!("Invalid prefix length {}", rtm.dst_len)))
+    }
+
+    // Prepare KernelRoute.
+    let mut kr = KernelRoute::new(Prefix::from(dst, rtm.dst_len));
+
+    // This route is self route originated earlier.
+    if rtm.protocol == RTPROT_ZEBRA as u8 {
+        kr.is_self = true;
+    }
+
+    kr.ifindex = attr_u32(&attr, libc::RTA_OIF as c_int).map(|index| index as i32);
+    kr.metric = attr_u32(&attr, libc::RTA_PRIORITY as c_int);
+    kr.gateway = match attr.get(&(libc::RTA_GATEWAY as c_int)) {
+        Some(gateway) => match decode_addr::<T>(gateway) {
+            Some(gateway) => Some(gateway),
+            None => return Err(KernelError::Decode(format!("Invalid RTA_GATEWAY length {}", gateway.len()))),
+        },
+        None => None,
+    };
+    kr.table_id = Some(route_table(&rtm, &attr) as i32);
+
+    // Get nexthops.
+    if rtm.rtm_type == libc::RTN_BLACKHOLE {
+        kr.nexthops.push(Nexthop::Blackhole);
+    } else if let Some(multipath) = attr.get(&(libc::RTA_MULTIPATH as c_int)) {
+        kr.nexthops = kernel_multipath::<T>(multipath, ifname)?;
+    } else if let Some(nexthop) = kernel_nexthop(kr.gateway.clone(), kr.ifindex, ifname) {
+        kr.nexthops.push(nexthop);
+    }
+
+    if kr.nexthops.len() == 0 {
+        return Ok(None)
+    }
+
+    Ok(Some(kr))
+}
+
+/// Parse RTA_MULTIPATH payload into nexthops.
+fn kernel_multipath<T: Addressable>(buf: &[u8], ifname: &dyn Fn(i32) -> Option<String>) -> Result<Vec<Nexthop<T>>, KernelError> {
+    let mut nexthops = Vec::new();
+    let mut b = buf;
+
+    while b.len() >= RTNH_HDRLEN {
+        let rtnh_len = decode_u16(b).unwrap_or(0) as usize;
+        if rtnh_len < RTNH_HDRLEN || rtnh_len > b.len() {
+            return Err(KernelError::Decode(format!("Invalid rtnexthop length {}", rtnh_len)))
+        }
+
+        let ifindex = decode_i32(&b[4..]).unwrap_or(0);
+        let attr = parse_attrs(&b[RTNH_HDRLEN..rtnh_len]);
+        let gateway = match attr.get(&(libc::RTA_GATEWAY as c_int)) {
+            Some(gateway) => match decode_addr::<T>(gateway) {
+                Some(gateway) => Some(gateway),
+                None => return Err(KernelError::Decode(format!("Invalid RTA_GATEWAY length {}", gateway.len()))),
+            },
+            None => None,
+        };
+
+        if let Some(nexthop) = kernel_nexthop::<T>(gateway, Some(ifindex), ifname) {
+            nexthops.push(nexthop);
+        }
+
+        b = b.get(align(rtnh_len)..).unwrap_or(&[]);
+    }
+
+    Ok(nexthops)
+}
+
+/// Construct a nexthop from gateway and ifindex given by kernel.
+fn kernel_nexthop<T: Addressable>(address: Option<T>, ifindex: Option<i32>,
+                                  ifname: &dyn Fn(i32) -> Option<String>) -> Option<Nexthop<T>> {
+    let ifname = match ifindex {
+        Some(ifindex) if ifindex > 0 => ifname(ifindex),
+        _ => None,
+    };
+
+    match (address, ifname) {
+        (Some(address), Some(ifname)) => Some(Nexthop::<T>::from_address_ifname(&address, &ifname)),
+        (Some(address), None) => Some(Nexthop::<T>::from_address(&address)),
+        (None, Some(ifname)) => Some(Nexthop::<T>::from_ifname(&ifname)),
+        (None, None) => None,
+    }
+}
+
+/// Parse all messages in buffer as application would, return number of messages passed up.
+/// Used by fuzz target, any input must be handled without panic.
+pub fn parse_buffer(buf: &[u8]) -> usize {
+    let ifname = |ifindex: i32| Some(format!("if{}", ifindex));
+    let mut count = 0;
+
+    for msg in nlmsg_iter(buf) {
+        match msg {
+            Ok(msg) => {
+                if let Ok(Some(_)) = kernel_msg(&msg, &ifname) {
+                    count += 1;
+                }
+            },
+            Err(_) => break,
+        }
+    }
+
+    count
+}
+
+///
+/// Unit tests for Netlink message parser.
+///
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    const LINK_DUMP: &[u8] = include_bytes!("../../../tests/fixtures/netlink/link_dump.bin");
+    const ADDR_IPV4_DUMP: &[u8] = include_bytes!("../../../tests/fixtures/netlink/addr_ipv4_dump.bin");
+    const ADDR_IPV6_DUMP: &[u8] = include_bytes!("../../../tests/fixtures/netlink/addr_ipv6_dump.bin");
+    const ROUTE_IPV4_DUMP: &[u8] = include_bytes!("../../../tests/fixtures/netlink/route_ipv4_dump.bin");
+    const ROUTE_IPV6_DUMP: &[u8] = include_bytes!("../../../tests/fixtures/netlink/route_ipv6_dump.bin");
+
+    const NEIGH_IPV4_DUMP: &[u8] = include_bytes!("../../../tests/fixtures/netlink/neigh_ipv4_dump.bin");
+    const NEIGH_IPV6_DUMP: &[u8] = include_bytes!("../../../tests/fixtures/netlink/neigh_ipv6_dump.bin");
+    const FIXTURES: [&[u8]; 7] = [LINK_DUMP, ADDR_IPV4_DUMP, ADDR_IPV6_DUMP, ROUTE_IPV4_DUMP, ROUTE_IPV6_DUMP,
+                                  NEIGH_IPV4_DUMP, NEIGH_IPV6_DUMP];
+
+    fn ifname(ifindex: i32) -> Option<String> {
+        match ifindex {
+            1 => Some("lo".to_string()),
+            4 => Some("eth0".to_string()),
+            _ => None,
+        }
+    }
+
+    fn parse_dump(buf: &[u8]) -> Vec<KernelMsg> {
+        let mut v = Vec::new();
+
+        for msg in nlmsg_iter(buf) {
+            let msg = msg.unwrap();
+            if msg.nlmsg_type == libc::NLMSG_DONE as u16 {
+                break;
+            }
+            if let Some(kmsg) = kernel_msg(&msg, &ifname).unwrap() {
+                v.push(kmsg);
+            }
+        }
+
+        v
+    }
+
+    // Fixtures are captured on little endian host.
+    #[cfg(target_endian = "little")]
+    #[test]
+    pub fn test_parse_link_dump() {
+        let links: Vec<KernelLink> = parse_dump(LINK_DUMP).into_iter().map(|kmsg| match kmsg {
+            KernelMsg::NewLink(kl) => kl,
+            _ => panic!("Unexpected message"),
+        }).collect();
+
+        assert_eq!(links.len(), 4);
+
+        let lo = links.iter().find(|kl| kl.name == "lo").unwrap();
+        assert_eq!(lo.flags & libc::IFF_LOOPBACK as u32, libc::IFF_LOOPBACK as u32);
+        assert_eq!(lo.hwaddr, [0; 6]);
+
+        // Loopback receives every packet it transmits.
+        let stats = lo.stats.unwrap();
+        assert_eq!(stats.rx_packets, 12622);
+        assert_eq!(stats.rx_packets, stats.tx_packets);
+        assert_eq!(stats.rx_bytes, stats.tx_bytes);
+
+        // Statistics alone are parsed the same.
+        let stats: Vec<(i32, KernelLinkStats)> = nlmsg_iter(LINK_DUMP)
+            .map(|msg| msg.unwrap())
+            .filter(|msg| msg.nlmsg_type == libc::RTM_NEWLINK)
+            .filter_map(|msg| kernel_link_stats64(&msg).unwrap())
+            .collect();
+        assert_eq!(stats.len(), 4);
+        assert!(stats.contains(&(lo.ifindex, lo.stats.unwrap())));
+
+        let eth0 = links.iter().find(|kl| kl.name == "eth0").unwrap();
+        assert_eq!(eth0.hwaddr, [0x02, 0xfc, 0x00, 0x00, 0x00, 0x01]);
+        assert_eq!(eth0.flags & libc::IFF_UP as u32, libc::IFF_UP as u32);
+        assert_eq!(eth0.mtu, 1400);
+        assert_eq!(eth0.kind, None);
+        assert_eq!(eth0.stats.unwrap().rx_bytes, 12563);
+        assert_eq!(eth0.stats.unwrap().tx_bytes, 14970);
+
+        let ifb0 = links.iter().find(|kl| kl.name == "ifb0").unwrap();
+        assert_eq!(ifb0.kind, Some("ifb".to_string()));
+        assert_eq!(ifb0.flags & libc::IFF_UP as u32, 0);
+    }
+
+    #[cfg(target_endian = "little")]
+    #[test]
+    pub fn test_parse_addr_dump() {
+        let v4: Vec<String> = parse_dump(ADDR_IPV4_DUMP).into_iter().map(|kmsg| match kmsg {
+            KernelMsg::NewAddrIpv4(ka) => format!("{} {}", ka.ifindex, ka.address),
+            _ => panic!("Unexpected message"),
+        }).collect();
+        assert_eq!(v4, vec!["1 127.0.0.1/8", "4 192.0.2.2/24"]);
+
+        let v6: Vec<String> = parse_dump(ADDR_IPV6_DUMP).into_iter().map(|kmsg| match kmsg {
+            KernelMsg::NewAddrIpv6(ka) => format!("{} {}", ka.ifindex, ka.address),
+            _ => panic!("Unexpected message"),
+        }).collect();
+        assert_eq!(v6, vec!["1 ::1/128", "4 fd00::2/64", "4 fe80::fc:ff:fe00:1/64"]);
+    }
+
+    #[cfg(target_endian = "little")]
+    #[test]
+    pub fn test_parse_route_dump() {
+        let routes: Vec<KernelRoute<Ipv4Addr>> = parse_dump(ROUTE_IPV4_DUMP).into_iter().map(|kmsg| match kmsg {
+            KernelMsg::NewRouteIpv4(kr) => kr,
+            _ => panic!("Unexpected message"),
+        }).collect();
+
+        // Connected and local routes maintained by kernel are filtered out.
+        assert_eq!(routes.len(), 4);
+
+        let lookup = |s: &str| {
+            let p = Prefix::<Ipv4Addr>::from_str(s).unwrap();
+            routes.iter().find(|kr| kr.destination == p).unwrap()
+        };
+
+        let kr = lookup("0.0.0.0/0");
+        assert_eq!(kr.gateway, Some(Ipv4Addr::new(192, 0, 2, 1)));
+        assert_eq!(kr.table_id, Some(254));
+        assert_eq!(kr.nexthops, vec![Nexthop::from_address_ifname(&Ipv4Addr::new(192, 0, 2, 1), "eth0")]);
+        assert!(!kr.is_self);
+
+        let kr = lookup("198.51.100.0/24");
+        assert_eq!(kr.nexthops, vec![Nexthop::Blackhole]);
+        assert_eq!(kr.metric, Some(20));
+        assert!(kr.is_self);
+
+        let kr = lookup("203.0.113.0/24");
+        assert_eq!(kr.nexthops, vec![Nexthop::from_address_ifname(&Ipv4Addr::new(192, 0, 2, 1), "eth0"),
+                                     Nexthop::from_address_ifname(&Ipv4Addr::new(192, 0, 2, 3), "eth0")]);
+
+        let kr = lookup("10.10.0.0/16");
+        assert_eq!(kr.table_id, Some(100));
+
+        let routes: Vec<KernelRoute<Ipv6Addr>> = parse_dump(ROUTE_IPV6_DUMP).into_iter().map(|kmsg| match kmsg {
+            KernelMsg::NewRouteIpv6(kr) => kr,
+            _ => panic!("Unexpected message"),
+        }).collect();
+
+        let p = Prefix::<Ipv6Addr>::from_str("2001:db8::/32").unwrap();
+        let kr = routes.iter().find(|kr| kr.destination == p).unwrap();
+        assert_eq!(kr.gateway, Some(Ipv6Addr::from_str("fd00::1").unwrap()));
+        assert!(kr.is_self);
+
+        let p = Prefix::<Ipv6Addr>::from_str("::/0").unwrap();
+        let kr = routes.iter().find(|kr| kr.destination == p).unwrap();
+        assert_eq!(kr.metric, Some(1024));
+    }
+
+    #[cfg(target_endian = "little")]
+    #[test]
+    pub fn test_parse_neigh_dump() {
+        let v4: Vec<KernelNeigh<Ipv4Addr>> = parse_dump(NEIGH_IPV4_DUMP).into_iter().map(|kmsg| match kmsg {
+            KernelMsg::NewNeighIpv4(kn) => kn,
+            _ => panic!("Unexpected message"),
+        }).collect();
+        assert_eq!(v4.len(), 3);
+
+        let kn = v4.iter().find(|kn| kn.address == Ipv4Addr::new(192, 0, 2, 1)).unwrap();
+        assert_eq!(kn.ifindex, 4);
+        assert_eq!(kn.hwaddr, Some([0x02, 0xfc, 0x00, 0x00, 0x00, 0x05]));
+        assert_eq!(kn.state, NeighState::Stale);
+
+        let kn = v4.iter().find(|kn| kn.address == Ipv4Addr::new(192, 0, 2, 10)).unwrap();
+        assert_eq!(kn.state, NeighState::Permanent);
+        assert!(!kn.router);
+
+        let v6: Vec<KernelNeigh<Ipv6Addr>> = parse_dump(NEIGH_IPV6_DUMP).into_iter().map(|kmsg| match kmsg {
+            KernelMsg::NewNeighIpv6(kn) => kn,
+            _ => panic!("Unexpected message"),
+        }).collect();
+        assert_eq!(v6.len(), 3);
+
+        let kn = v6.iter().find(|kn| kn.address == Ipv6Addr::from_str("fd00::10").unwrap()).unwrap();
+        assert_eq!(kn.hwaddr, Some([0x02, 0x00, 0x00, 0x00, 0x00, 0x0b]));
+        assert_eq!(kn.state, NeighState::Permanent);
+        assert!(kn.router);
+
+        // Multicast addresses need no resolution.
+        let kn = v6.iter().find(|kn| kn.address == Ipv6Addr::from_str("ff02::16").unwrap()).unwrap();
+        assert_eq!(kn.state, NeighState::Noarp);
+    }
+
+    #[test]
+    pub fn test_parse_neigh_state() {
+        assert_eq!(neigh_state(NUD_REACHABLE), NeighState::Reachable);
+        assert_eq!(neigh_state(NUD_NOARP | NUD_PERMANENT), NeighState::Permanent);
+        assert_eq!(neigh_state(NUD_NOARP | NUD_REACHABLE), NeighState::Noarp);
+        assert_eq!(neigh_state(NUD_STALE | NUD_PROBE), NeighState::Stale);
+        assert_eq!(neigh_state(NUD_INCOMPLETE | NUD_FAILED), NeighState::Failed);
+        assert_eq!(neigh_state(0), NeighState::None);
+    }
+
+    #[test]
+    pub fn test_parse_link_stats() {
+        let stats_msg = |counters: &[u64]| {
+            let attr_len = 4 + counters.len() * 8;
+            let mut buf = Vec::new();
+
+            buf.extend_from_slice(&((16 + 12 + attr_len) as u32).to_ne_bytes());
+            buf.extend_from_slice(&RTM_NEWSTATS.to_ne_bytes());
+            buf.extend_from_slice(&[0; 10]);
+            buf.extend_from_slice(&[libc::AF_UNSPEC as u8, 0, 0, 0]);
+            buf.extend_from_slice(&4i32.to_ne_bytes());
+            buf.extend_from_slice(&(1u32 << (IFLA_STATS_LINK_64 - 1)).to_ne_bytes());
+            buf.extend_from_slice(&(attr_len as u16).to_ne_bytes());
+            buf.extend_from_slice(&(IFLA_STATS_LINK_64 as u16).to_ne_bytes());
+            for counter in counters {
+                buf.extend_from_slice(&counter.to_ne_bytes());
+            }
+            buf
+        };
+
+        match parse_dump(&stats_msg(&[10, 20, 1000, 2000, 1, 2, 3, 4])).pop() {
+            Some(KernelMsg::NewStats(index, stats)) => {
+                assert_eq!(index, 4);
+                assert_eq!(stats.rx_packets, 10);
+                assert_eq!(stats.tx_bytes, 2000);
+                assert_eq!(stats.tx_dropped, 4);
+            },
+            _ => panic!("NewStats is not parsed"),
+        }
+
+        // Truncated counters are not passed up.
+        assert!(parse_dump(&stats_msg(&[10, 20, 1000])).is_empty());
+    }
+
+    #[test]
+    pub fn test_parse_malformed() {
+        // Every truncation and single byte corruption must be handled without panic.
+        for buf in FIXTURES.iter() {
+            for len in 0..buf.len() {
+                parse_buffer(&buf[..len]);
+            }
+
+            let mut b = buf.to_vec();
+            for i in 0..b.len() {
+                let orig = b[i];
+                for v in &[0x00, 0xff] {
+                    b[i] = *v;
+                    parse_buffer(&b);
+                }
+                b[i] = orig;
+            }
+        }
+
+        // Header claims more than buffer has.
+        let mut b = LINK_DUMP[..64].to_vec();
+        b[0] = 0xff;
+        let mut iter = nlmsg_iter(&b);
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+
+        // Attribute exceeding payload is dropped.
+        let attr = parse_attrs(&[8, 0, 3, 0, b'l', b'o', 0, 0, 0xff, 0, 1, 0]);
+        assert_eq!(attr.len(), 1);
+        assert_eq!(attr_str(attr[&3]), Some("lo"));
+
+        // Address of wrong length.
+        let msg = NlMsg {
+            nlmsg_type: libc::RTM_NEWADDR,
+            nlmsg_flags: 0,
+            nlmsg_seq: 0,
+            nlmsg_pid: 0,
+            payload: &[2, 24, 0, 0, 2, 0, 0, 0, 7, 0, 1, 0, 192, 0, 2, 0],
+        };
+        assert!(kernel_addr::<Ipv4Addr>(&msg).is_err());
+    }
+}
@@ -29,6 +29,12 @@ use super::super::kernel::KernelError;
 
 const RTA_ALIGNTO: usize = 4usize;
 
+const LWTUNNEL_ENCAP_MPLS: u16 = 1;
+const MPLS_IPTUNNEL_DST: libc::c_int = 1;
+
+const MPLS_LS_LABEL_SHIFT: u32 = 12;
+const MPLS_LS_S_SHIFT: u32 = 8;
+
 pub fn rta_align(len: usize) -> usize {
     (len + RTA_ALIGNTO - 1) & !(RTA_ALIGNTO - 1)
 }
@@ -52,7 +58,8 @@ pub fn nlmsg_addattr_l(nlmsg_len: &mut u32, buf: &mut [u8],
                        rta_type: i32, data: &[u8], alen: usize) -> Result<usize, KernelError> {
     let len = addattr_l(buf, rta_type, data, alen)?;
 
-    *nlmsg_len += len as u32;
+    // Next attribute starts at aligned offset.
+    *nlmsg_len += rta_align(len) as u32;
 
     Ok(len)
 }
@@ -140,6 +147,46 @@ pub fn addattr32(buf: &mut [u8], rta_type: libc::c_int, src: u32) -> Result<usiz
     }
 }
 
+/// Encode MPLS label stack entries in network byte order, top first,
+/// bottom of stack bit is set on the last entry.
+pub fn mpls_label_stack(labels: &[u32]) -> Vec<u8> {
+    let mut v = Vec::new();
+
+    for (i, label) in labels.iter().enumerate() {
+        let mut entry = label << MPLS_LS_LABEL_SHIFT;
+        if i == labels.len() - 1 {
+            entry |= 1 << MPLS_LS_S_SHIFT;
+        }
+        v.extend_from_slice(&entry.to_be_bytes());
+    }
+
+    v
+}
+
+pub fn nlmsg_addattr_mpls_encap(nlmsg_len: &mut u32, buf: &mut [u8], labels: &[u32]) -> Result<usize, KernelError> {
+    let len = addattr_mpls_encap(buf, labels)?;
+
+    *nlmsg_len += len as u32;
+
+    Ok(len)
+}
+
+/// Add MPLS encapsulation, RTA_ENCAP_TYPE and RTA_ENCAP with nested label stack to buffer.
+pub fn addattr_mpls_encap(buf: &mut [u8], labels: &[u32]) -> Result<usize, KernelError> {
+    let encap_type = LWTUNNEL_ENCAP_MPLS.to_ne_bytes();
+    let len = rta_align(addattr_l(buf, libc::RTA_ENCAP_TYPE as i32, &encap_type, encap_type.len())?);
+    if len > buf.len() {
+        return Err(KernelError::Encode("buffer overflow".to_string()))
+    }
+
+    let stack = mpls_label_stack(labels);
+    let encap_len = addattr_payload(&mut buf[len..], libc::RTA_ENCAP as i32, |buf: &mut [u8]| -> Result<usize, KernelError> {
+        addattr_l(buf, MPLS_IPTUNNEL_DST, &stack, stack.len())
+    })?;
+
+    Ok(len + rta_align(encap_len))
+}
+
 const RTNH_ALIGNTO: usize = 4usize;
 
 /// struct rtnexthop from rtnetlink.h.
@@ -172,28 +219,62 @@ pub fn rtnh_length(len: usize) -> usize {
     rtnh_align(size_of::<Rtnexthop>()) + len
 }
 
-pub fn nlmsg_add_rtnexthop<T: Addressable>(buf: &mut [u8], address: &T) -> Result<usize, KernelError> {
-    let octets: &[u8] = address.octets_ref();
-    let rtnh_len = size_of::<Rtnexthop>() + size_of::<Rtattr>() + T::byte_len() as usize;  // XXX probably should align.
+/// Add Rtnexthop with gateway and label stack to push.
+pub fn nlmsg_add_rtnexthop<T: Addressable>(buf: &mut [u8], address: Option<&T>, ifindex: i32, labels: &[u32]) -> Result<usize, KernelError> {
+    let mut rtnh_len = size_of::<Rtnexthop>();
 
     if rtnh_len > buf.len() {
-        Err(KernelError::Encode("buffer overflow".to_string()))
-    } else {
-        // rtnh_len
-        encode_num::<u16>(&mut buf[..], rtnh_len as u16);
+        return Err(KernelError::Encode("buffer overflow".to_string()))
+    }
+
+    // RTA Gataway.
+    if let Some(address) = address {
+        let octets: &[u8] = address.octets_ref();
+        rtnh_len += rta_align(addattr_l(&mut buf[rtnh_len..], libc::RTA_GATEWAY as i32, &octets[..], T::byte_len() as usize)?);
+    }
 
-        // rtnh_flags
-        encode_num::<u8>(&mut buf[2..], 0 as u8);
+    // MPLS encapsulation.
+    if labels.len() > 0 {
+        rtnh_len += addattr_mpls_encap(&mut buf[rtnh_len..], labels)?;
+    }
+
+    encode_rtnexthop(buf, rtnh_len, ifindex);
+
+    Ok(rtnh_align(rtnh_len))
+}
 
-        // rtnh_hops
-        encode_num::<u8>(&mut buf[3..], 0 as u8);
+/// Add Rtnexthop for MPLS route, with RTA_VIA and outgoing label stack.
+pub fn nlmsg_add_mpls_rtnexthop(buf: &mut [u8], via: &[u8], ifindex: i32, labels: &[u32]) -> Result<usize, KernelError> {
+    let mut rtnh_len = size_of::<Rtnexthop>();
 
-        // rtnh_index
-        encode_num::<u32>(&mut buf[4..], 0 as u32);
+    if rtnh_len > buf.len() {
+        return Err(KernelError::Encode("buffer overflow".to_string()))
+    }
 
-        // RTA Gataway.
-        addattr_l(&mut buf[8..], libc::RTA_GATEWAY as i32, &octets[..], T::byte_len() as usize)?;
+    rtnh_len += rta_align(addattr_l(&mut buf[rtnh_len..], libc::RTA_VIA as i32, via, via.len())?);
 
-        Ok(rtnh_len)
+    // No label is pushed back when the top label is popped.
+    if labels.len() > 0 {
+        let stack = mpls_label_stack(labels);
+        rtnh_len += rta_align(addattr_l(&mut buf[rtnh_len..], libc::RTA_NEWDST as i32, &stack, stack.len())?);
     }
+
+    encode_rtnexthop(buf, rtnh_len, ifindex);
+
+    Ok(rtnh_align(rtnh_len))
+}
+
+/// Encode Rtnexthop header.
+fn encode_rtnexthop(buf: &mut [u8], rtnh_len: usize, ifindex: i32) {
+    // rtnh_len
+    encode_num::<u16>(&mut buf[..], rtnh_len as u16);
+
+    // rtnh_flags
+    encode_num::<u8>(&mut buf[2..], 0 as u8);
+
+    // rtnh_hops
+    encode_num::<u8>(&mut buf[3..], 0 as u8);
+
+    // rtnh_index
+    encode_num::<i32>(&mut buf[4..], ifindex);
 }
@@ -6,37 +6,64 @@
 //
 
 use std::rc::Rc;
+use std::cell::Cell;
+use std::cell::Ref;
 use std::cell::RefCell;
-use std::cell::RefMut;
 use std::collections::HashMap;
-use std::thread;
+use std::collections::BTreeMap;
+use std::time;
 use std::time::Duration;
 use std::sync::Arc;
 use std::sync::mpsc;
+use std::thread;
 use std::net::{Ipv4Addr, Ipv6Addr};
 
 use log::{debug, error};
+use mio::{Events, Poll, PollOpt, Ready, Registration, Token};
+use mio::unix::EventedFd;
 use rtable::prefix::*;
 
+use common::nexthop::*;
+use common::mpls::LabelOp;
+
 use crate::core::protocols::ProtocolType;
 use crate::core::message::nexus::ProtoToNexus;
 use crate::core::message::nexus::NexusToProto;
 use crate::core::message::zebra::ProtoToZebra;
 use crate::core::message::zebra::ZebraToProto;
+use crate::core::message::zebra::ZebraRoute;
+use crate::core::message::zebra::ZebraNeighbor;
+use common::error::*;
+
 use crate::core::mds::*;
 
 use super::link::*;
+use super::neighbor::*;
+use super::interface::*;
 use super::address::*;
 use super::kernel::*;
 use super::static_route::*;
 use super::rib::*;
+use super::vrf::*;
+use super::mpls::*;
+use super::client::*;
+use super::nht::*;
+use super::graceful_restart::*;
 
-/// Store Zebra Client related information.
-struct ClientTuple {
+/// Token for kernel event fd.
+const ZEBRA_KERNEL_TOKEN: Token = Token(0);
 
-    /// Channel sender from Zebra to Protocol
-    _sender: mpsc::Sender<ZebraToProto>,
-}
+/// Token for kernel FIB result fd.
+const ZEBRA_FIB_TOKEN: Token = Token(1);
+
+/// Token for ProtoToZebra channel.
+const ZEBRA_PROTO_TOKEN: Token = Token(2);
+
+/// Token for NexusToProto channel.
+const ZEBRA_NEXUS_TOKEN: Token = Token(3);
+
+/// Number of events to be processed at once.
+const ZEBRA_EVENTS_CAPACITY: usize = 64;
 
 /// Zebra Master.
 pub struct ZebraMaster {
@@ -47,177 +74,1372 @@ pub struct ZebraMaster {
     /// Kernel interface.
     kernel: RefCell<Kernel>,
 
-    /// ProtocolType to Zebra Client Tuple Map.
-    clients: RefCell<HashMap<ProtocolType, ClientTuple>>,
+    /// ProtocolType to Zebra Client Map.
+    clients: RefCell<HashMap<ProtocolType, ZebraClient>>,
 
     /// Link Master.
     link_master: RefCell<LinkMaster>,
 
-    /// IPv4 RIB.
-    rib_ipv4: RefCell<RibTable<Ipv4Addr>>,
+    /// Interface config, applied to links when they appear.
+    interface_config: RefCell<Option<Rc<InterfaceConfig>>>,
+
+    /// Default VRF.
+    vrf_default: Rc<Vrf>,
+
+    /// VRFs per kernel table ID, including default VRF.
+    vrfs: RefCell<BTreeMap<u32, Rc<Vrf>>>,
+
+    /// IPv4 nexthop tracker for default VRF.
+    nht_ipv4: RefCell<NexthopTracker<Ipv4Addr>>,
+
+    /// IPv6 nexthop tracker for default VRF.
+    nht_ipv6: RefCell<NexthopTracker<Ipv6Addr>>,
+
+    /// MPLS label table, LSPs per incoming label.
+    lsps: RefCell<BTreeMap<u32, Rc<Lsp>>>,
+
+    /// Failed FIB operations per kernel table ID and prefix.
+    fib_failures: RefCell<BTreeMap<(u32, String), FibFailure>>,
+
+    /// Time in seconds to keep routes left in kernel by previous instance.
+    stale_time: Cell<u64>,
+
+    /// Time started, None once stale routes are swept.
+    stale_instant: Cell<Option<time::Instant>>,
+
+    /// Time link statistics are polled last.
+    stats_instant: Cell<time::Instant>,
+
+    /// IPv4 FIB results reported by kernel, to be applied to RIB.
+    fib_results_ipv4: RefCell<Vec<KernelFibResult<Ipv4Addr>>>,
+
+    /// IPv6 FIB results reported by kernel, to be applied to RIB.
+    fib_results_ipv6: RefCell<Vec<KernelFibResult<Ipv6Addr>>>,
+}
+
+impl ZebraMaster {
+
+    /// Constructor.
+    pub fn new(kernel: Kernel) -> ZebraMaster {
+        let vrf_default = Rc::new(Vrf::new(VRF_DEFAULT_NAME, VRF_DEFAULT_TABLE));
+        let mut vrfs = BTreeMap::new();
+
+        vrf_default.set_configured(true);
+        vrfs.insert(VRF_DEFAULT_TABLE, vrf_default.clone());
+
+        ZebraMaster {
+            mds: RefCell::new(Rc::new(MdsNode::new("ZebraMaster"))),
+            kernel: RefCell::new(kernel),
+            clients: RefCell::new(HashMap::new()),
+            link_master: RefCell::new(LinkMaster::new()),
+            interface_config: RefCell::new(None),
+            vrf_default: vrf_default,
+            vrfs: RefCell::new(vrfs),
+            nht_ipv4: RefCell::new(NexthopTracker::new()),
+            nht_ipv6: RefCell::new(NexthopTracker::new()),
+            lsps: RefCell::new(BTreeMap::new()),
+            fib_failures: RefCell::new(BTreeMap::new()),
+            stale_time: Cell::new(ZEBRA_STALE_TIME_DEFAULT),
+            stale_instant: Cell::new(Some(time::Instant::now())),
+            stats_instant: Cell::new(time::Instant::now()),
+            fib_results_ipv4: RefCell::new(Vec::new()),
+            fib_results_ipv6: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Kernel interface.
+    pub fn kernel(&self) -> Ref<Kernel> {
+        self.kernel.borrow()
+    }
+
+    /// Failed FIB operations.
+    pub fn fib_failures(&self) -> Ref<BTreeMap<(u32, String), FibFailure>> {
+        self.fib_failures.borrow()
+    }
+
+    /// Lookup link by name.
+    pub fn link_lookup_by_name(&self, ifname: &str) -> Option<Rc<Link>> {
+        self.link_master.borrow().lookup_by_name(ifname)
+    }
+
+    /// Return all links sorted by index.
+    pub fn links(&self) -> Vec<Rc<Link>> {
+        self.link_master.borrow().links()
+    }
+
+    /// Return default VRF.
+    pub fn vrf_default(&self) -> Rc<Vrf> {
+        self.vrf_default.clone()
+    }
+
+    /// Return all VRFs sorted by table ID.
+    pub fn vrfs(&self) -> Vec<Rc<Vrf>> {
+        self.vrfs.borrow().values().cloned().collect()
+    }
+
+    /// Add VRF, with state of links routes may go through.
+    fn vrf_insert(&self, vrf: &Rc<Vrf>) {
+        for link in self.links().iter().filter(|link| link.is_up()) {
+            vrf.rib_ipv4().link_set(&link.name(), true);
+            vrf.rib_ipv6().link_set(&link.name(), true);
+        }
+
+        self.vrfs.borrow_mut().insert(vrf.table_id(), vrf.clone());
+    }
+
+    /// Lookup VRF by name.
+    pub fn vrf_lookup_by_name(&self, name: &str) -> Option<Rc<Vrf>> {
+        self.vrfs.borrow().values().find(|vrf| vrf.name() == name).cloned()
+    }
+
+    /// Lookup VRF by kernel table ID.
+    pub fn vrf_lookup_by_table(&self, table_id: u32) -> Option<Rc<Vrf>> {
+        self.vrfs.borrow().get(&table_id).cloned()
+    }
+
+    /// Lookup VRF by VRF device index.
+    fn vrf_lookup_by_index(&self, ifindex: i32) -> Option<Rc<Vrf>> {
+        self.vrfs.borrow().values().find(|vrf| vrf.ifindex() == Some(ifindex)).cloned()
+    }
+
+    /// Return VRF for the master device index, default VRF if link is not enslaved to VRF.
+    fn vrf_lookup_by_master(&self, master: Option<i32>) -> Rc<Vrf> {
+        match master.and_then(|ifindex| self.vrf_lookup_by_index(ifindex)) {
+            Some(vrf) => vrf,
+            None => self.vrf_default(),
+        }
+    }
+
+    /// Return VRF the link belongs to, VRF device itself belongs to its own VRF.
+    pub fn link_vrf(&self, link: &Link) -> Rc<Vrf> {
+        if link.vrf_table().is_some() {
+            if let Some(vrf) = self.vrf_lookup_by_index(link.index()) {
+                return vrf
+            }
+        }
+
+        self.vrf_lookup_by_master(link.master())
+    }
+
+    /// Add VRF by config, table ID is required unless VRF already exists.
+    pub fn vrf_add(&self, name: &str, table_id: Option<u32>) -> Result<Rc<Vrf>, CoreError> {
+        if let Some(vrf) = self.vrf_lookup_by_name(name) {
+            match table_id {
+                Some(table_id) if table_id != vrf.table_id() => {
+                    return Err(CoreError::CommandExec(format!("VRF {} already uses table {}", name, vrf.table_id())))
+                },
+                _ => {
+                    vrf.set_configured(true);
+                    return Ok(vrf)
+                }
+            }
+        }
+
+        let table_id = match table_id {
+            Some(table_id) => table_id,
+            None => return Err(CoreError::CommandExec(format!("No table for VRF {}", name))),
+        };
+
+        if let Some(vrf) = self.vrf_lookup_by_table(table_id) {
+            return Err(CoreError::CommandExec(format!("Table {} is used by VRF {}", table_id, vrf.name())))
+        }
+
+        let vrf = Rc::new(Vrf::new(name, table_id));
+        vrf.set_configured(true);
+        self.vrf_insert(&vrf);
+
+        // VRF device may already exist.
+        let link = self.link_master.borrow().lookup_by_name(name);
+        if let Some(link) = link {
+            self.vrf_link_add(&link);
+        }
+
+        Ok(vrf)
+    }
+
+    /// Delete VRF by config, VRF stays as long as VRF device exists.
+    pub fn vrf_delete(&self, name: &str) -> Result<(), CoreError> {
+        let vrf = match self.vrf_lookup_by_name(name) {
+            Some(vrf) => vrf,
+            None => return Err(CoreError::CommandExec(format!("No such VRF {}", name))),
+        };
+
+        if vrf.is_default() {
+            return Err(CoreError::CommandExec(format!("Default VRF cannot be deleted")))
+        }
+
+        vrf.set_configured(false);
+        if vrf.ifindex().is_none() {
+            self.vrfs.borrow_mut().remove(&vrf.table_id());
+        }
+
+        Ok(())
+    }
+
+    /// Bind VRF device to VRF with the same name and table, create VRF if not configured.
+    fn vrf_link_add(&self, link: &Link) {
+        let table_id = match link.vrf_table() {
+            Some(table_id) => table_id,
+            None => return,
+        };
+
+        let vrf = match self.vrf_lookup_by_table(table_id) {
+            Some(vrf) if vrf.name() == link.name() => vrf,
+            Some(vrf) => {
+                error!("VRF device {} table {} conflicts with VRF {}", link.name(), table_id, vrf.name());
+                return
+            },
+            None => {
+                if let Some(vrf) = self.vrf_lookup_by_name(&link.name()) {
+                    error!("VRF device {} table {} conflicts with table {}", link.name(), table_id, vrf.table_id());
+                    return
+                }
+
+                let vrf = Rc::new(Vrf::new(&link.name(), table_id));
+                self.vrf_insert(&vrf);
+                vrf
+            }
+        };
+
+        if vrf.ifindex() == Some(link.index()) {
+            return
+        }
+
+        debug!("VRF {} bound to table {} ifindex {}", vrf.name(), table_id, link.index());
+
+        vrf.set_ifindex(Some(link.index()));
+
+        // Links enslaved before VRF device is known, move connected routes.
+        for l in self.links().iter().filter(|l| l.master() == Some(link.index()) || l.index() == link.index()) {
+            if l.is_up() {
+                self.connected_withdraw(&self.vrf_default, l, &l.name());
+                self.connected_restore(&vrf, l, &l.name());
+            }
+        }
+    }
+
+    /// Unbind VRF device from VRF, and delete VRF if not configured.
+    fn vrf_link_delete(&self, link: &Link) {
+        let vrf = match self.vrf_lookup_by_index(link.index()) {
+            Some(vrf) => vrf,
+            None => return,
+        };
+
+        debug!("VRF {} unbound from ifindex {}", vrf.name(), link.index());
+
+        // Move connected routes back to default VRF, until links are released.
+        for l in self.links().iter().filter(|l| l.master() == Some(link.index())) {
+            self.connected_withdraw(&vrf, l, &l.name());
+            if l.is_up() {
+                self.connected_restore(&self.vrf_default, l, &l.name());
+            }
+        }
+
+        vrf.set_ifindex(None);
+        if !vrf.is_configured() {
+            self.vrfs.borrow_mut().remove(&vrf.table_id());
+        }
+    }
+
+    /// Get link statistics from kernel.
+    pub fn get_link_stats(&self, ifindex: i32, stats: KernelLinkStats) {
+        self.link_master.borrow().update_stats(ifindex, stats);
+    }
+
+    /// Get Add link from kernel.
+    pub fn get_add_link(&self, kl: KernelLink) {
+        debug!("New Link");
+
+        let (link, events) = self.link_master.borrow_mut().add_link(kl);
+
+        self.vrf_link_add(&link);
+
+        let named = events.iter().any(|event| match event {
+            LinkEvent::Add | LinkEvent::Rename(_) => true,
+            _ => false,
+        });
+
+        for event in events {
+            self.link_event(&link, event);
+        }
+
+        if named {
+            let config = self.interface_config.borrow().clone();
+            if let Some(config) = config {
+                config.link_add(&link);
+            }
+        }
+    }
+
+    /// Get Delete link from kernel.
+    pub fn get_delete_link(&self, kl: KernelLink) {
+        debug!("Delete Link");
+
+        let link = self.link_master.borrow_mut().delete_link(kl.ifindex);
+        if let Some(link) = link {
+            self.link_event(&link, LinkEvent::Delete);
+            self.vrf_link_delete(&link);
+        }
+    }
+
+    /// Handle link change event.
+    fn link_event(&self, link: &Link, event: LinkEvent) {
+        debug!("Link {} event {:?}", link.name(), event);
+
+        let vrf = self.link_vrf(link);
+
+        match event {
+            LinkEvent::Up => self.connected_restore(&vrf, link, &link.name()),
+            LinkEvent::Down | LinkEvent::Delete => self.connected_withdraw(&vrf, link, &link.name()),
+            LinkEvent::Rename(ref old) => {
+                if link.is_up() {
+                    self.connected_withdraw(&vrf, link, old);
+                    self.connected_restore(&vrf, link, &link.name());
+                }
+            },
+            LinkEvent::Master(old) => {
+                // Withdraw regardless of link state, as link may go down at the same time.
+                self.connected_withdraw(&self.vrf_lookup_by_master(old), link, &link.name());
+                if link.is_up() {
+                    self.connected_restore(&vrf, link, &link.name());
+                }
+            },
+            LinkEvent::Add | LinkEvent::Update => {},
+        }
+
+        let zi = link.to_zebra_interface(vrf.table_id());
+        self.clients_send(|| match event {
+            LinkEvent::Delete => ZebraToProto::InterfaceDelete(zi.clone()),
+            LinkEvent::Up => ZebraToProto::InterfaceUp(zi.clone()),
+            LinkEvent::Down => ZebraToProto::InterfaceDown(zi.clone()),
+            _ => ZebraToProto::InterfaceAdd(zi.clone()),
+        });
+    }
+
+    /// Get Add IPv4 Adress from kernel.
+    pub fn get_add_ipv4_address(&self, ka: KernelAddr<Ipv4Addr>) {
+        debug!("Add Ipv4Address");
+
+        let index = ka.ifindex;
+
+        let conn = Connected::<Ipv4Addr>::from_kernel(ka);
+        let link = self.link_master.borrow().lookup_by_index(index);
+
+        if let Some(link) = link {
+            if link.is_up() {
+                self.connected_ipv4_add(&self.link_vrf(&link), &link.name(), &conn);
+            }
+
+            let za = conn.to_zebra_interface_addr(index);
+            self.clients_send(|| ZebraToProto::InterfaceAddrAddIpv4(za.clone()));
+        }
+
+        self.link_master.borrow_mut().add_ipv4_address(index, conn);
+    }
+
+    /// Get Delete IPv4 Adress from kernel.
+    pub fn get_delete_ipv4_address(&self, ka: KernelAddr<Ipv4Addr>) {
+        debug!("Add Ipv4Address");
+
+        let index = ka.ifindex;
+
+        let conn = Connected::<Ipv4Addr>::from_kernel(ka);
+        let network = conn.network();
+        let za = conn.to_zebra_interface_addr(index);
+
+        self.link_master.borrow_mut().delete_ipv4_address(index, conn);
+
+        // Keep connected route as long as other address in the same subnet exists on the link.
+        let link = self.link_master.borrow().lookup_by_index(index);
+        if let Some(link) = link {
+            if !link.addr4().iter().any(|c| c.network() == network) {
+                self.connected_ipv4_delete(&self.link_vrf(&link), &link.name(), &network);
+            }
+
+            self.clients_send(|| ZebraToProto::InterfaceAddrDeleteIpv4(za.clone()));
+        }
+    }
+
+    /// Get Add IPv6 Adress from kernel.
+    pub fn get_add_ipv6_address(&self, ka: KernelAddr<Ipv6Addr>) {
+        debug!("Add Ipv6Address");
+
+        let index = ka.ifindex;
+
+        let conn = Connected::<Ipv6Addr>::from_kernel(ka);
+        let link = self.link_master.borrow().lookup_by_index(index);
+
+        if let Some(link) = link {
+            if link.is_up() {
+                self.connected_ipv6_add(&self.link_vrf(&link), &link.name(), &conn);
+            }
+
+            let za = conn.to_zebra_interface_addr(index);
+            self.clients_send(|| ZebraToProto::InterfaceAddrAddIpv6(za.clone()));
+        }
+
+        self.link_master.borrow_mut().add_ipv6_address(index, conn);
+    }
+
+    /// Get Delete IPv6 Adress from kernel.
+    pub fn get_delete_ipv6_address(&self, ka: KernelAddr<Ipv6Addr>) {
+        debug!("Add Ipv6Address");
+
+        let index = ka.ifindex;
+
+        let conn = Connected::<Ipv6Addr>::from_kernel(ka);
+        let network = conn.network();
+        let za = conn.to_zebra_interface_addr(index);
+
+        self.link_master.borrow_mut().delete_ipv6_address(index, conn);
+
+        // Keep connected route as long as other address in the same subnet exists on the link.
+        let link = self.link_master.borrow().lookup_by_index(index);
+        if let Some(link) = link {
+            if !link.addr6().iter().any(|c| c.network() == network) {
+                self.connected_ipv6_delete(&self.link_vrf(&link), &link.name(), &network);
+            }
+
+            self.clients_send(|| ZebraToProto::InterfaceAddrDeleteIpv6(za.clone()));
+        }
+    }
+
+    /// Get Add IPv4 neighbor from kernel.
+    pub fn get_add_ipv4_neighbor(&self, kn: KernelNeigh<Ipv4Addr>) {
+        debug!("Add IPv4 neighbor {} state {:?}", kn.address, kn.state);
+
+        self.link_master.borrow_mut().add_ipv4_neighbor(kn);
+    }
+
+    /// Get Delete IPv4 neighbor from kernel.
+    pub fn get_delete_ipv4_neighbor(&self, kn: KernelNeigh<Ipv4Addr>) {
+        debug!("Delete IPv4 neighbor {}", kn.address);
+
+        self.link_master.borrow_mut().delete_ipv4_neighbor(kn);
+    }
+
+    /// Get Add IPv6 neighbor from kernel.
+    pub fn get_add_ipv6_neighbor(&self, kn: KernelNeigh<Ipv6Addr>) {
+        debug!("Add IPv6 neighbor {} state {:?}", kn.address, kn.state);
+
+        self.link_master.borrow_mut().add_ipv6_neighbor(kn);
+    }
+
+    /// Get Delete IPv6 neighbor from kernel.
+    pub fn get_delete_ipv6_neighbor(&self, kn: KernelNeigh<Ipv6Addr>) {
+        debug!("Delete IPv6 neighbor {}", kn.address);
+
+        self.link_master.borrow_mut().delete_ipv6_neighbor(kn);
+    }
+
+    /// Lookup IPv4 neighbor for the nexthop.
+    pub fn neighbor_lookup_ipv4(&self, nexthop: &Nexthop<Ipv4Addr>) -> ZebraNeighbor<Ipv4Addr> {
+        neighbor_resolve(nexthop, &self.links(), Link::neigh4)
+    }
+
+    /// Lookup IPv6 neighbor for the nexthop.
+    pub fn neighbor_lookup_ipv6(&self, nexthop: &Nexthop<Ipv6Addr>) -> ZebraNeighbor<Ipv6Addr> {
+        neighbor_resolve(nexthop, &self.links(), Link::neigh6)
+    }
+
+    /// Get Add IPv4 route from kernel.
+    pub fn get_add_ipv4_route(&self, kr: KernelRoute<Ipv4Addr>) {
+        // Ignore routes originated by ourselves.
+        if kr.is_self {
+            return;
+        }
+
+        // Ignore routes in table not bound to any VRF.
+        let vrf = match kr.table_id.and_then(|table_id| self.vrf_lookup_by_table(table_id as u32)) {
+            Some(vrf) => vrf,
+            None => return,
+        };
+
+        debug!("RIB add kernel IPv4 {:?} vrf {}", kr.destination, vrf.name());
+
+        let prefix = kr.destination.clone();
+        vrf.rib_ipv4().add(&prefix, Rib::<Ipv4Addr>::from_kernel_route(kr));
+        self.rib_ipv4_process(&vrf, &prefix);
+    }
+
+    /// Get Delete IPv4 route from kernel.
+    pub fn get_delete_ipv4_route(&self, kr: KernelRoute<Ipv4Addr>) {
+        if kr.is_self {
+            return;
+        }
+
+        // Ignore routes in table not bound to any VRF.
+        let vrf = match kr.table_id.and_then(|table_id| self.vrf_lookup_by_table(table_id as u32)) {
+            Some(vrf) => vrf,
+            None => return,
+        };
+
+        debug!("RIB delete kernel IPv4 {:?} vrf {}", kr.destination, vrf.name());
+
+        let prefix = kr.destination.clone();
+        vrf.rib_ipv4().delete(&prefix, Rib::<Ipv4Addr>::from_kernel_route(kr));
+        self.rib_ipv4_process(&vrf, &prefix);
+    }
+
+    /// Get Add IPv6 route from kernel.
+    pub fn get_add_ipv6_route(&self, kr: KernelRoute<Ipv6Addr>) {
+        // Ignore routes originated by ourselves.
+        if kr.is_self {
+            return;
+        }
+
+        // Ignore routes in table not bound to any VRF.
+        let vrf = match kr.table_id.and_then(|table_id| self.vrf_lookup_by_table(table_id as u32)) {
+            Some(vrf) => vrf,
+            None => return,
+        };
+
+        debug!("RIB add kernel IPv6 {:?} vrf {}", kr.destination, vrf.name());
+
+        let prefix = kr.destination.clone();
+        vrf.rib_ipv6().add(&prefix, Rib::<Ipv6Addr>::from_kernel_route(kr));
+        self.rib_ipv6_process(&vrf, &prefix);
+    }
+
+    /// Get Delete IPv6 route from kernel.
+    pub fn get_delete_ipv6_route(&self, kr: KernelRoute<Ipv6Addr>) {
+        if kr.is_self {
+            return;
+        }
+
+        // Ignore routes in table not bound to any VRF.
+        let vrf = match kr.table_id.and_then(|table_id| self.vrf_lookup_by_table(table_id as u32)) {
+            Some(vrf) => vrf,
+            None => return,
+        };
+
+        debug!("RIB delete kernel IPv6 {:?} vrf {}", kr.destination, vrf.name());
+
+        let prefix = kr.destination.clone();
+        vrf.rib_ipv6().delete(&prefix, Rib::<Ipv6Addr>::from_kernel_route(kr));
+        self.rib_ipv6_process(&vrf, &prefix);
+    }
+
+    /// Add connected IPv4 route for the address on the link.
+    fn connected_ipv4_add(&self, vrf: &Vrf, ifname: &str, conn: &Connected<Ipv4Addr>) {
+        let prefix = conn.network();
+        let rib = Rib::<Ipv4Addr>::new(RibType::Connected, 0);
+
+        debug!("RIB add connected IPv4 {:?} {}", prefix, ifname);
+
+        rib.add_nexthop(Nexthop::<Ipv4Addr>::from_ifname(ifname));
+        vrf.rib_ipv4().merge(&prefix, rib);
+        self.rib_ipv4_process(vrf, &prefix);
+    }
+
+    /// Delete connected IPv4 route for the network on the link.
+    fn connected_ipv4_delete(&self, vrf: &Vrf, ifname: &str, prefix: &Prefix<Ipv4Addr>) {
+        let rib = Rib::<Ipv4Addr>::new(RibType::Connected, 0);
+
+        debug!("RIB delete connected IPv4 {:?} {}", prefix, ifname);
+
+        rib.add_nexthop(Nexthop::<Ipv4Addr>::from_ifname(ifname));
+        vrf.rib_ipv4().delete(prefix, rib);
+        self.rib_ipv4_process(vrf, prefix);
+    }
+
+    /// Add connected IPv6 route for the address on the link, link-local address is ignored.
+    fn connected_ipv6_add(&self, vrf: &Vrf, ifname: &str, conn: &Connected<Ipv6Addr>) {
+        let prefix = conn.network();
+        let rib = Rib::<Ipv6Addr>::new(RibType::Connected, 0);
+
+        if is_ipv6_link_local(&prefix) {
+            return;
+        }
+
+        debug!("RIB add connected IPv6 {:?} {}", prefix, ifname);
+
+        rib.add_nexthop(Nexthop::<Ipv6Addr>::from_ifname(ifname));
+        vrf.rib_ipv6().merge(&prefix, rib);
+        self.rib_ipv6_process(vrf, &prefix);
+    }
+
+    /// Delete connected IPv6 route for the network on the link.
+    fn connected_ipv6_delete(&self, vrf: &Vrf, ifname: &str, prefix: &Prefix<Ipv6Addr>) {
+        let rib = Rib::<Ipv6Addr>::new(RibType::Connected, 0);
+
+        if is_ipv6_link_local(prefix) {
+            return;
+        }
+
+        debug!("RIB delete connected IPv6 {:?} {}", prefix, ifname);
+
+        rib.add_nexthop(Nexthop::<Ipv6Addr>::from_ifname(ifname));
+        vrf.rib_ipv6().delete(prefix, rib);
+        self.rib_ipv6_process(vrf, prefix);
+    }
+
+    /// Add connected routes for all addresses on the link, when link comes up.
+    /// Routes through the link become active as well.
+    fn connected_restore(&self, vrf: &Vrf, link: &Link, ifname: &str) {
+        self.link_rib_set(ifname, true);
+
+        for conn in link.addr4().iter() {
+            self.connected_ipv4_add(vrf, ifname, conn);
+        }
+
+        for conn in link.addr6().iter() {
+            self.connected_ipv6_add(vrf, ifname, conn);
+        }
+    }
+
+    /// Set link state in RIB tables, and process routes through the link again.
+    /// Routes may go through links in other VRFs, state is set in all of them.
+    fn link_rib_set(&self, ifname: &str, up: bool) {
+        for vrf in self.vrfs() {
+            let prefixes = vrf.rib_ipv4().link_set(ifname, up);
+            for prefix in prefixes {
+                self.rib_ipv4_process(&vrf, &prefix);
+            }
+
+            let prefixes = vrf.rib_ipv6().link_set(ifname, up);
+            for prefix in prefixes {
+                self.rib_ipv6_process(&vrf, &prefix);
+            }
+        }
+    }
+
+    /// Withdraw all connected routes on the link, when link goes down or is deleted.
+    /// Routes through the link become inactive as well.
+    fn connected_withdraw(&self, vrf: &Vrf, link: &Link, ifname: &str) {
+        self.link_rib_set(ifname, false);
+
+        let networks: Vec<Prefix<Ipv4Addr>> = link.addr4().iter().map(|c| c.network()).collect();
+        for network in networks {
+            self.connected_ipv4_delete(vrf, ifname, &network);
+        }
+
+        let networks: Vec<Prefix<Ipv6Addr>> = link.addr6().iter().map(|c| c.network()).collect();
+        for network in networks {
+            self.connected_ipv6_delete(vrf, ifname, &network);
+        }
+    }
+
+    /// Add RIB for IPv4 static route.
+    pub fn rib_add_static_ipv4(&self, vrf: &Vrf, sr: Arc<StaticRoute<Ipv4Addr>>) {
+        debug!("RIB add static IPv4 {:?}", sr.prefix());
+
+        let prefix = sr.prefix().clone();
+        let mut map = Rib::<Ipv4Addr>::from_static_route(sr);
+
+        for (_, rib) in map.drain() {
+            vrf.rib_ipv4().add(&prefix, rib);
+        }
+
+        self.rib_ipv4_process(vrf, &prefix);
+    }
+
+    /// Delete RIB for IPv4 static route.
+    pub fn rib_delete_static_ipv4(&self, vrf: &Vrf, sr: Arc<StaticRoute<Ipv4Addr>>) {
+        debug!("RIB delete static IPv4 {:?}", sr.prefix());
+
+        let prefix = sr.prefix().clone();
+        let mut map = Rib::<Ipv4Addr>::from_static_route(sr);
+
+        for (_, rib) in map.drain() {
+            vrf.rib_ipv4().delete(&prefix, rib);
+        }
+
+        self.rib_ipv4_process(vrf, &prefix);
+    }
+
+    /// Add RIB for IPv6 static route.
+    pub fn rib_add_static_ipv6(&self, vrf: &Vrf, sr: Arc<StaticRoute<Ipv6Addr>>) {
+        debug!("RIB add static IPv6 {:?}", sr.prefix());
+
+        let prefix = sr.prefix().clone();
+        let mut map = Rib::<Ipv6Addr>::from_static_route(sr);
+
+        for (_, rib) in map.drain() {
+            vrf.rib_ipv6().add(&prefix, rib);
+        }
+
+        self.rib_ipv6_process(vrf, &prefix);
+    }
+
+    /// Delete RIB for IPv6 static route.
+    pub fn rib_delete_static_ipv6(&self, vrf: &Vrf, sr: Arc<StaticRoute<Ipv6Addr>>) {
+        debug!("RIB delete static IPv6 {:?}", sr.prefix());
+
+        let prefix = sr.prefix().clone();
+        let mut map = Rib::<Ipv6Addr>::from_static_route(sr);
+
+        for (_, rib) in map.drain() {
+            vrf.rib_ipv6().delete(&prefix, rib);
+        }
+
+        self.rib_ipv6_process(vrf, &prefix);
+    }
+
+    /// Return VRF for route from protocol, None if protocol is not allowed to add it.
+    fn proto_route_vrf(&self, proto: ProtocolType, rib_type: RibType, table_id: u32) -> Option<Rc<Vrf>> {
+        if !self.clients.borrow().contains_key(&proto) {
+            error!("RIB {:?} from unregistered {}", rib_type, proto);
+            return None
+        }
+
+        if proto_rib_type(proto) != Some(rib_type) {
+            error!("RIB {:?} not allowed from {}", rib_type, proto);
+            return None
+        }
+
+        let vrf = self.vrf_lookup_by_table(table_id);
+        if vrf.is_none() {
+            error!("RIB {:?} from {} for unknown table {}", rib_type, proto, table_id);
+        }
+        vrf
+    }
+
+    /// Add IPv4 route from protocol to VRF, replacing the one previously added.
+    pub fn rib_add_proto_ipv4(&self, proto: ProtocolType, route: ZebraRoute<Ipv4Addr>) {
+        let vrf = match self.proto_route_vrf(proto, route.rib_type, route.table_id) {
+            Some(vrf) => vrf,
+            None => return,
+        };
+
+        debug!("RIB add {:?} IPv4 {:?} table {}", route.rib_type, route.prefix, route.table_id);
+
+        vrf.rib_ipv4().withdraw(&route.prefix, route.rib_type);
+        vrf.rib_ipv4().add(&route.prefix, Rib::<Ipv4Addr>::from_zebra_route(&route));
+        self.rib_ipv4_process(&vrf, &route.prefix);
+
+        if let Some(client) = self.clients.borrow().get(&proto) {
+            client.route_ipv4(route.table_id, &route.prefix, true);
+        }
+    }
+
+    /// Delete IPv4 route from protocol.
+    pub fn rib_delete_proto_ipv4(&self, proto: ProtocolType, route: ZebraRoute<Ipv4Addr>) {
+        let vrf = match self.proto_route_vrf(proto, route.rib_type, route.table_id) {
+            Some(vrf) => vrf,
+            None => return,
+        };
+
+        debug!("RIB delete {:?} IPv4 {:?} table {}", route.rib_type, route.prefix, route.table_id);
+
+        vrf.rib_ipv4().withdraw(&route.prefix, route.rib_type);
+        self.rib_ipv4_process(&vrf, &route.prefix);
+
+        if let Some(client) = self.clients.borrow().get(&proto) {
+            client.route_ipv4(route.table_id, &route.prefix, false);
+        }
+    }
+
+    /// Add IPv6 route from protocol to VRF, replacing the one previously added.
+    pub fn rib_add_proto_ipv6(&self, proto: ProtocolType, route: ZebraRoute<Ipv6Addr>) {
+        let vrf = match self.proto_route_vrf(proto, route.rib_type, route.table_id) {
+            Some(vrf) => vrf,
+            None => return,
+        };
+
+        debug!("RIB add {:?} IPv6 {:?} table {}", route.rib_type, route.prefix, route.table_id);
+
+        vrf.rib_ipv6().withdraw(&route.prefix, route.rib_type);
+        vrf.rib_ipv6().add(&route.prefix, Rib::<Ipv6Addr>::from_zebra_route(&route));
+        self.rib_ipv6_process(&vrf, &route.prefix);
+
+        if let Some(client) = self.clients.borrow().get(&proto) {
+            client.route_ipv6(route.table_id, &route.prefix, true);
+        }
+    }
+
+    /// Delete IPv6 route from protocol.
+    pub fn rib_delete_proto_ipv6(&self, proto: ProtocolType, route: ZebraRoute<Ipv6Addr>) {
+        let vrf = match self.proto_route_vrf(proto, route.rib_type, route.table_id) {
+            Some(vrf) => vrf,
+            None => return,
+        };
+
+        debug!("RIB delete {:?} IPv6 {:?} table {}", route.rib_type, route.prefix, route.table_id);
+
+        vrf.rib_ipv6().withdraw(&route.prefix, route.rib_type);
+        self.rib_ipv6_process(&vrf, &route.prefix);
+
+        if let Some(client) = self.clients.borrow().get(&proto) {
+            client.route_ipv6(route.table_id, &route.prefix, false);
+        }
+    }
+
+    /// Register protocol client, and send all interfaces and addresses.
+    fn client_register(&self, proto: ProtocolType, sender: mpsc::Sender<ZebraToProto>) {
+        debug!("Register Protocol {}", proto);
+
+        let client = ZebraClient::new(proto, sender);
+
+        for link in self.links() {
+            let index = link.index();
+
+            client.send(ZebraToProto::InterfaceAdd(link.to_zebra_interface(self.link_vrf(&link).table_id())));
+            for conn in link.addr4().iter() {
+                client.send(ZebraToProto::InterfaceAddrAddIpv4(conn.to_zebra_interface_addr(index)));
+            }
+            for conn in link.addr6().iter() {
+                client.send(ZebraToProto::InterfaceAddrAddIpv6(conn.to_zebra_interface_addr(index)));
+            }
+        }
+
+        // Routes of previous instance are flushed.
+        self.client_unregister(proto);
+        self.clients.borrow_mut().insert(proto, client);
+    }
+
+    /// Unregister protocol client, and withdraw routes and nexthop tracking it registered.
+    fn client_unregister(&self, proto: ProtocolType) {
+        let client = match self.clients.borrow_mut().remove(&proto) {
+            Some(client) => client,
+            None => return,
+        };
+
+        debug!("Unregister Protocol {}", proto);
+
+        self.nht_ipv4.borrow_mut().unregister_proto(proto);
+        self.nht_ipv6.borrow_mut().unregister_proto(proto);
+
+        let rib_type = match proto_rib_type(proto) {
+            Some(rib_type) => rib_type,
+            None => return,
+        };
+
+        for (table_id, prefix) in client.routes_ipv4() {
+            if let Some(vrf) = self.vrf_lookup_by_table(table_id) {
+                vrf.rib_ipv4().withdraw(&prefix, rib_type);
+                self.rib_ipv4_process(&vrf, &prefix);
+            }
+        }
+        for (table_id, prefix) in client.routes_ipv6() {
+            if let Some(vrf) = self.vrf_lookup_by_table(table_id) {
+                vrf.rib_ipv6().withdraw(&prefix, rib_type);
+                self.rib_ipv6_process(&vrf, &prefix);
+            }
+        }
+    }
+
+    /// Unregister clients which dropped their channel.
+    fn clients_cleanup(&self) {
+        let protos: Vec<ProtocolType> = self.clients.borrow().iter()
+            .filter(|(_, client)| !client.is_connected())
+            .map(|(proto, _)| *proto)
+            .collect();
+
+        for proto in protos {
+            self.client_unregister(proto);
+        }
+    }
+
+    /// Send message to the client.
+    fn client_send(&self, proto: ProtocolType, msg: ZebraToProto) {
+        match self.clients.borrow().get(&proto) {
+            Some(client) => client.send(msg),
+            None => error!("Send message to unregistered {}", proto),
+        }
+    }
+
+    /// Send message to all clients, message is constructed per client.
+    fn clients_send<F>(&self, f: F)
+    where F: Fn() -> ZebraToProto
+    {
+        for (_, client) in self.clients.borrow().iter() {
+            client.send(f());
+        }
+    }
+
+    /// Notify change of selected route to clients subscribing redistribution.
+    /// A client is notified deletion when the route it has been sent is replaced by
+    /// another type it does not subscribe.
+    fn redistribute<T>(&self, table_id: u32, prefix: &Prefix<T>, old: Option<&Rib<T>>, new: Option<&Rib<T>>,
+                       add: fn(ZebraRoute<T>) -> ZebraToProto, delete: fn(ZebraRoute<T>) -> ZebraToProto)
+    where T: Addressable
+    {
+        let old = old.map(|rib| rib.to_zebra_route(table_id, prefix));
+        let new = new.map(|rib| rib.to_zebra_route(table_id, prefix));
+
+        if old == new {
+            return;
+        }
+
+        for (_, client) in self.clients.borrow().iter() {
+            match (&old, &new) {
+                (_, Some(new)) if client.is_redistributed(new.rib_type) => {
+                    client.send(add(new.clone()));
+                },
+                (Some(old), _) if client.is_redistributed(old.rib_type) => {
+                    client.send(delete(old.clone()));
+                },
+                _ => {},
+            }
+        }
+    }
+
+    /// Subscribe redistribution of the RIB type, and send routes currently selected.
+    fn redistribute_add(&self, proto: ProtocolType, rib_type: RibType) {
+        let clients = self.clients.borrow();
+        let client = match clients.get(&proto) {
+            Some(client) => client,
+            None => {
+                error!("Redistribute {:?} to unregistered {}", rib_type, proto);
+                return;
+            }
+        };
+
+        debug!("Redistribute {:?} to {}", rib_type, proto);
+
+        if !client.redistribute_add(rib_type) || !client.is_redistributed(rib_type) {
+            return;
+        }
+
+        let vrf = self.vrf_default();
+        for (prefix, rib) in vrf.rib_ipv4().fib_routes(rib_type) {
+            client.send(ZebraToProto::RouteAddIpv4(rib.to_zebra_route(vrf.table_id(), &prefix)));
+        }
+        for (prefix, rib) in vrf.rib_ipv6().fib_routes(rib_type) {
+            client.send(ZebraToProto::RouteAddIpv6(rib.to_zebra_route(vrf.table_id(), &prefix)));
+        }
+    }
+
+    /// Unsubscribe redistribution of the RIB type, and withdraw routes sent so far.
+    fn redistribute_delete(&self, proto: ProtocolType, rib_type: RibType) {
+        let clients = self.clients.borrow();
+        let client = match clients.get(&proto) {
+            Some(client) => client,
+            None => return,
+        };
+
+        debug!("No redistribute {:?} to {}", rib_type, proto);
+
+        let redistributed = client.is_redistributed(rib_type);
+        if !client.redistribute_delete(rib_type) || !redistributed {
+            return;
+        }
+
+        let vrf = self.vrf_default();
+        for (prefix, rib) in vrf.rib_ipv4().fib_routes(rib_type) {
+            client.send(ZebraToProto::RouteDeleteIpv4(rib.to_zebra_route(vrf.table_id(), &prefix)));
+        }
+        for (prefix, rib) in vrf.rib_ipv6().fib_routes(rib_type) {
+            client.send(ZebraToProto::RouteDeleteIpv6(rib.to_zebra_route(vrf.table_id(), &prefix)));
+        }
+    }
+
+    /// Register IPv4 nexthop tracking, and notify current resolution.
+    fn nht_register_ipv4(&self, proto: ProtocolType, address: &Ipv4Addr) {
+        let state = self.nht_ipv4.borrow_mut().register(proto, address, &self.vrf_default.rib_ipv4());
+        self.client_send(proto, ZebraToProto::NexthopUpdateIpv4(state));
+    }
+
+    /// Register IPv6 nexthop tracking, and notify current resolution.
+    fn nht_register_ipv6(&self, proto: ProtocolType, address: &Ipv6Addr) {
+        let state = self.nht_ipv6.borrow_mut().register(proto, address, &self.vrf_default.rib_ipv6());
+        self.client_send(proto, ZebraToProto::NexthopUpdateIpv6(state));
+    }
+
+    /// Resolve tracked IPv4 nexthops covered by changed prefixes again, and notify changes to
+    /// registered protocols.
+    fn nht_evaluate_ipv4(&self, changed: &[Prefix<Ipv4Addr>]) {
+        let updates = self.nht_ipv4.borrow_mut().evaluate(changed, &self.vrf_default.rib_ipv4());
+
+        for (protos, state) in updates {
+            for proto in protos {
+                self.client_send(proto, ZebraToProto::NexthopUpdateIpv4(state.clone()));
+            }
+        }
+    }
+
+    /// Resolve tracked IPv6 nexthops covered by changed prefixes again, and notify changes to
+    /// registered protocols.
+    fn nht_evaluate_ipv6(&self, changed: &[Prefix<Ipv6Addr>]) {
+        let updates = self.nht_ipv6.borrow_mut().evaluate(changed, &self.vrf_default.rib_ipv6());
+
+        for (protos, state) in updates {
+            for proto in protos {
+                self.client_send(proto, ZebraToProto::NexthopUpdateIpv6(state.clone()));
+            }
+        }
+    }
+
+    /// Process IPv4 RIB for the prefix, and install/uninstall selected route to/from kernel.
+    fn rib_ipv4_process(&self, vrf: &Vrf, prefix: &Prefix<Ipv4Addr>) {
+        let table_id = vrf.table_id();
+
+        let changed = vrf.rib_ipv4().process(prefix, |prefix: &Prefix<Ipv4Addr>, entry: &RibEntry<Ipv4Addr>| {
+            let selected = entry.select();
+            let fib = (*entry.fib()).clone();
+
+            // Only routes in default VRF are redistributed.
+            if vrf.is_default() {
+                self.redistribute(table_id, prefix, fib.as_ref(), selected.as_ref(),
+                                  ZebraToProto::RouteAddIpv4, ZebraToProto::RouteDeleteIpv4);
+            }
+
+            match (fib, selected) {
+                (Some(old), Some(new)) => {
+                    // Retry if previous attempt failed.
+                    if entry.is_fib_same(&new) && old.is_fib() {
+                        new.set_fib(true);
+                    } else {
+                        self.rib_ipv4_update_kernel(table_id, prefix, &new, &old);
+                    }
+                    Some(new)
+                },
+                (Some(old), None) => {
+                    self.rib_ipv4_uninstall_kernel(table_id, prefix, &old);
+                    None
+                },
+                (None, Some(new)) => {
+                    self.rib_ipv4_install_kernel(table_id, prefix, &new);
+                    Some(new)
+                },
+                (None, None) => None,
+            }
+        });
+
+        if vrf.is_default() {
+            self.nht_evaluate_ipv4(&changed);
+        }
+    }
+
+    /// Process IPv6 RIB for the prefix, and install/uninstall selected route to/from kernel.
+    fn rib_ipv6_process(&self, vrf: &Vrf, prefix: &Prefix<Ipv6Addr>) {
+        let table_id = vrf.table_id();
+
+        let changed = vrf.rib_ipv6().process(prefix, |prefix: &Prefix<Ipv6Addr>, entry: &RibEntry<Ipv6Addr>| {
+            let selected = entry.select();
+            let fib = (*entry.fib()).clone();
 
-    /// IPv6 RIB.
-    rib_ipv6: RefCell<RibTable<Ipv6Addr>>,
-}
+            // Only routes in default VRF are redistributed.
+            if vrf.is_default() {
+                self.redistribute(table_id, prefix, fib.as_ref(), selected.as_ref(),
+                                  ZebraToProto::RouteAddIpv6, ZebraToProto::RouteDeleteIpv6);
+            }
 
-impl ZebraMaster {
+            match (fib, selected) {
+                (Some(old), Some(new)) => {
+                    // Retry if previous attempt failed.
+                    if entry.is_fib_same(&new) && old.is_fib() {
+                        new.set_fib(true);
+                    } else {
+                        self.rib_ipv6_update_kernel(table_id, prefix, &new, &old);
+                    }
+                    Some(new)
+                },
+                (Some(old), None) => {
+                    self.rib_ipv6_uninstall_kernel(table_id, prefix, &old);
+                    None
+                },
+                (None, Some(new)) => {
+                    self.rib_ipv6_install_kernel(table_id, prefix, &new);
+                    Some(new)
+                },
+                (None, None) => None,
+            }
+        });
 
-    /// Constructor.
-    pub fn new() -> ZebraMaster {
-        ZebraMaster {
-            mds: RefCell::new(Rc::new(MdsNode::new("ZebraMaster"))),
-            kernel: RefCell::new(Kernel::new()),
-            clients: RefCell::new(HashMap::new()),
-            link_master: RefCell::new(LinkMaster::new()),
-            rib_ipv4: RefCell::new(RibTable::<Ipv4Addr>::new()),
-            rib_ipv6: RefCell::new(RibTable::<Ipv6Addr>::new()),
+        if vrf.is_default() {
+            self.nht_evaluate_ipv6(&changed);
         }
     }
 
-    pub fn rib_ipv4(&self) -> RefMut<RibTable<Ipv4Addr>> {
-        self.rib_ipv4.borrow_mut()
+    /// Install an IPv4 route for given RIB to kernel.
+    pub fn rib_ipv4_install_kernel(&self, table_id: u32, prefix: &Prefix<Ipv4Addr>, new: &Rib<Ipv4Addr>) {
+        if new.is_installable() {
+            let ret = self.kernel.borrow().ipv4_route_install(table_id, prefix, new);
+            new.set_fib(self.fib_result(table_id, prefix, new, FibOp::Install, ret));
+        } else {
+            new.set_fib(true);
+        }
     }
 
-    pub fn rib_ipv6(&self) -> RefMut<RibTable<Ipv6Addr>> {
-        self.rib_ipv6.borrow_mut()
+    /// Update an IPv4 route for given RIB to kernel.
+    pub fn rib_ipv4_update_kernel(&self, table_id: u32, prefix: &Prefix<Ipv4Addr>, new: &Rib<Ipv4Addr>, old: &Rib<Ipv4Addr>) {
+        if new.is_installable() && old.is_installable() && old.is_fib() {
+            let ret = self.kernel.borrow().ipv4_route_update(table_id, prefix, new, old);
+            new.set_fib(self.fib_result(table_id, prefix, new, FibOp::Update, ret));
+        } else {
+            self.rib_ipv4_uninstall_kernel(table_id, prefix, old);
+            self.rib_ipv4_install_kernel(table_id, prefix, new);
+        }
     }
 
-    /// Get Add link from kernel.
-    pub fn get_add_link(&self, kl: KernelLink) {
-        debug!("New Link");
+    /// Uninstall an IPv4 route for given RIB from kernel.
+    pub fn rib_ipv4_uninstall_kernel(&self, table_id: u32, prefix: &Prefix<Ipv4Addr>, old: &Rib<Ipv4Addr>) {
+        if old.is_installable() && old.is_fib() {
+            let ret = self.kernel.borrow().ipv4_route_uninstall(table_id, prefix, old);
+            self.fib_result(table_id, prefix, old, FibOp::Uninstall, ret);
+        } else {
+            self.fib_failures.borrow_mut().remove(&(table_id, prefix.to_string()));
+        }
+    }
 
-        self.link_master.borrow_mut().add_link(Link::from_kernel(kl));
+    /// Install an IPv6 route for given RIB to kernel.
+    pub fn rib_ipv6_install_kernel(&self, table_id: u32, prefix: &Prefix<Ipv6Addr>, new: &Rib<Ipv6Addr>) {
+        if new.is_installable() {
+            let ret = self.kernel.borrow().ipv6_route_install(table_id, prefix, new);
+            new.set_fib(self.fib_result(table_id, prefix, new, FibOp::Install, ret));
+        } else {
+            new.set_fib(true);
+        }
+    }
 
-        // TODO: notify this to other protocols.
+    /// Update an IPv6 route for given RIB to kernel.
+    pub fn rib_ipv6_update_kernel(&self, table_id: u32, prefix: &Prefix<Ipv6Addr>, new: &Rib<Ipv6Addr>, old: &Rib<Ipv6Addr>) {
+        if new.is_installable() && old.is_installable() && old.is_fib() {
+            let ret = self.kernel.borrow().ipv6_route_update(table_id, prefix, new, old);
+            new.set_fib(self.fib_result(table_id, prefix, new, FibOp::Update, ret));
+        } else {
+            self.rib_ipv6_uninstall_kernel(table_id, prefix, old);
+            self.rib_ipv6_install_kernel(table_id, prefix, new);
+        }
     }
 
-    /// Get Delete link from kernel.
-    pub fn get_delete_link(&self, kl: KernelLink) {
-        debug!("Delete Link");
+    /// Uninstall an IPv6 route for given RIB from kernel.
+    pub fn rib_ipv6_uninstall_kernel(&self, table_id: u32, prefix: &Prefix<Ipv6Addr>, old: &Rib<Ipv6Addr>) {
+        if old.is_installable() && old.is_fib() {
+            let ret = self.kernel.borrow().ipv6_route_uninstall(table_id, prefix, old);
+            self.fib_result(table_id, prefix, old, FibOp::Uninstall, ret);
+        } else {
+            self.fib_failures.borrow_mut().remove(&(table_id, prefix.to_string()));
+        }
+    }
 
-        self.link_master.borrow_mut().delete_link(Link::from_kernel(kl));
+    /// Record result of FIB operation, return true if succeeded.
+    fn fib_result<T>(&self, table_id: u32, prefix: &Prefix<T>, rib: &Rib<T>, op: FibOp, ret: Result<(), KernelError>) -> bool
+    where T: Addressable
+    {
+        match ret {
+            Ok(_) => {
+                self.fib_failures.borrow_mut().remove(&(table_id, prefix.to_string()));
+                true
+            },
+            Err(err) => {
+                self.fib_failure_add(table_id, prefix, Some(rib.rib_type()), op, err.to_string());
+                false
+            }
+        }
+    }
 
-        // TODO: notify this to other protocols.
+    /// Record failed FIB operation, replacing previous one for the prefix.
+    fn fib_failure_add<T>(&self, table_id: u32, prefix: &Prefix<T>, rib_type: Option<RibType>, op: FibOp, error: String)
+    where T: Addressable
+    {
+        let key = prefix.to_string();
+
+        error!("FIB {:?} {} table {} failed: {}", op, key, table_id, error);
+
+        let failure = FibFailure {
+            table_id: table_id,
+            prefix: key.clone(),
+            rib_type: rib_type,
+            op: op,
+            error: error,
+            instant: time::Instant::now(),
+        };
+        self.fib_failures.borrow_mut().insert((table_id, key), failure);
     }
 
-    /// Get Add IPv4 Adress from kernel.
-    pub fn get_add_ipv4_address(&self, ka: KernelAddr<Ipv4Addr>) {
-        debug!("Add Ipv4Address");
+    /// Apply FIB result reported by kernel to RIB entry, if VRF still has it.
+    fn fib_ack<T>(&self, entry: Option<Rc<RibEntry<T>>>, result: KernelFibResult<T>)
+    where T: Addressable
+    {
+        match result.error {
+            None => {
+                if let (FibOp::Install, Some(entry)) = (result.op, entry) {
+                    entry.set_fib_flag(true);
+                }
+                self.fib_failures.borrow_mut().remove(&(result.table_id, result.prefix.to_string()));
+            },
+            Some(error) => {
+                let rib_type = match (result.op, entry) {
+                    (FibOp::Install, Some(entry)) => entry.set_fib_flag(false),
+                    _ => None,
+                };
+                self.fib_failure_add(result.table_id, &result.prefix, rib_type, result.op, error);
+            }
+        }
+    }
 
-        let index = ka.ifindex;
+    /// Send queued route messages, and apply FIB results reported so far.
+    /// Routes whose ACK is lost are processed again and sent once more.
+    fn fib_process(&self) {
+        self.kernel.borrow().fib_flush();
 
-        self.link_master.borrow_mut().add_ipv4_address(index, Connected::<Ipv4Addr>::from_kernel(ka));
+        if self.fib_results_apply(true) {
+            self.kernel.borrow().fib_flush();
+            self.fib_results_apply(false);
+        }
     }
 
-    /// Get Delete IPv4 Adress from kernel.
-    pub fn get_delete_ipv4_address(&self, ka: KernelAddr<Ipv4Addr>) {
-        debug!("Add Ipv4Address");
+    /// Apply FIB results to RIB entries, return true if any route is queued again for lost ACK.
+    fn fib_results_apply(&self, retry: bool) -> bool {
+        let mut queued = false;
 
-        let index = ka.ifindex;
+        for result in self.fib_results_ipv4.replace(Vec::new()) {
+            let vrf = self.vrf_lookup_by_table(result.table_id);
+            let entry = vrf.as_ref().and_then(|vrf| vrf.rib_ipv4().lookup_exact(&result.prefix));
+            let lost = retry && result.lost && result.op == FibOp::Install;
+            let prefix = result.prefix.clone();
 
-        self.link_master.borrow_mut().delete_ipv4_address(index, Connected::<Ipv4Addr>::from_kernel(ka));
-    }
+            self.fib_ack(entry, result);
+            if let (true, Some(vrf)) = (lost, vrf) {
+                self.rib_ipv4_process(&vrf, &prefix);
+                queued = true;
+            }
+        }
 
-    /// Get Add IPv6 Adress from kernel.
-    pub fn get_add_ipv6_address(&self, ka: KernelAddr<Ipv6Addr>) {
-        debug!("Add Ipv6Address");
+        for result in self.fib_results_ipv6.replace(Vec::new()) {
+            let vrf = self.vrf_lookup_by_table(result.table_id);
+            let entry = vrf.as_ref().and_then(|vrf| vrf.rib_ipv6().lookup_exact(&result.prefix));
+            let lost = retry && result.lost && result.op == FibOp::Install;
+            let prefix = result.prefix.clone();
 
-        let index = ka.ifindex;
+            self.fib_ack(entry, result);
+            if let (true, Some(vrf)) = (lost, vrf) {
+                self.rib_ipv6_process(&vrf, &prefix);
+                queued = true;
+            }
+        }
 
-        self.link_master.borrow_mut().add_ipv6_address(index, Connected::<Ipv6Addr>::from_kernel(ka));
+        queued
     }
 
-    /// Get Delete IPv6 Adress from kernel.
-    pub fn get_delete_ipv6_address(&self, ka: KernelAddr<Ipv6Addr>) {
-        debug!("Add Ipv6Address");
+    /// Set time to keep stale routes, effective unless they are already swept.
+    pub fn set_stale_time(&self, stale_time: u64) {
+        self.stale_time.set(stale_time);
+    }
 
-        let index = ka.ifindex;
+    /// Sweep stale routes not replaced by protocols within stale time.
+    fn stale_check(&self) {
+        if let Some(instant) = self.stale_instant.get() {
+            if instant.elapsed() >= Duration::from_secs(self.stale_time.get()) {
+                debug!("Stale time {} expired", self.stale_time.get());
 
-        self.link_master.borrow_mut().delete_ipv6_address(index, Connected::<Ipv6Addr>::from_kernel(ka));
+                self.kernel.borrow().fib_sweep();
+                self.stale_instant.set(None);
+            }
+        }
     }
 
-    /// Add RIB for IPv4 static route.
-    pub fn rib_add_static_ipv4(&self, sr: Arc<StaticRoute<Ipv4Addr>>) {
-        debug!("RIB add static IPv4 {:?}", sr.prefix());
+    /// Set interval to poll link statistics and calculate rates over.
+    pub fn set_stats_interval(&self, interval: u64) {
+        self.link_master.borrow().set_stats_interval(interval);
+    }
 
-        let prefix = sr.prefix().clone();
-        let mut map = Rib::<Ipv4Addr>::from_static_route(sr);
+    /// Return time until stale routes are swept or link statistics are polled, whichever is earlier.
+    fn timer_timeout(&self) -> Option<Duration> {
+        let stats = Duration::from_secs(self.link_master.borrow().stats_interval())
+            .checked_sub(self.stats_instant.get().elapsed())
+            .unwrap_or(Duration::from_secs(0));
+
+        match self.stale_instant.get() {
+            Some(instant) => {
+                let stale = Duration::from_secs(self.stale_time.get())
+                    .checked_sub(instant.elapsed())
+                    .unwrap_or(Duration::from_secs(0));
+                Some(stale.min(stats))
+            },
+            None => Some(stats),
+        }
+    }
 
-        let mut rib_ipv4 = self.rib_ipv4.borrow_mut();
+    /// Poll link statistics from kernel once interval passed.
+    fn stats_check(&self) {
+        let interval = self.link_master.borrow().stats_interval();
 
-        for (_, rib) in map.drain() {
-            rib_ipv4.add(&prefix, rib);
+        if self.stats_instant.get().elapsed() >= Duration::from_secs(interval) {
+            self.stats_instant.set(time::Instant::now());
+            self.kernel.borrow().link_stats_refresh();
         }
+    }
 
-        rib_ipv4.process(&prefix, |prefix: &Prefix<Ipv4Addr>, entry: &RibEntry<Ipv4Addr>| {
-            if let Some(ref mut fib) = *entry.fib() {
-                self.rib_ipv4_uninstall_kernel(prefix, &fib);
-            }
+    /// Return all LSPs sorted by incoming label.
+    pub fn lsps(&self) -> Vec<Rc<Lsp>> {
+        self.lsps.borrow().values().cloned().collect()
+    }
 
-            if let Some(selected) = entry.select() {
-                self.rib_ipv4_install_kernel(prefix, &selected);
-                Some(selected)
-            } else {
-                None
-            }
-        });
+    /// Lookup LSP by incoming label.
+    pub fn lsp_lookup(&self, in_label: u32) -> Option<Rc<Lsp>> {
+        self.lsps.borrow().get(&in_label).cloned()
     }
 
-    /// Delete RIB for IPv4 static route.
-    pub fn rib_delete_static_ipv4(&self, sr: Arc<StaticRoute<Ipv4Addr>>) {
-        debug!("RIB delete static IPv4 {:?}", sr.prefix());
+    /// Add nexthop to LSP, and install whole LSP to kernel.
+    /// Nexthop is identified by address, interface given later replaces the one without.
+    pub fn lsp_add(&self, in_label: u32, nexthop: LspNexthop, op: LabelOp) -> Result<(), CoreError> {
+        if let LabelOp::Push(_) = op {
+            return Err(CoreError::CommandExec(format!("Invalid label operation {}", op)))
+        }
 
-        let prefix = sr.prefix().clone();
-        let mut map = Rib::<Ipv4Addr>::from_static_route(sr);
+        let lsp = self.lsps.borrow_mut().entry(in_label)
+            .or_insert_with(|| Rc::new(Lsp::new(in_label))).clone();
 
-        let mut rib_ipv4 = self.rib_ipv4.borrow_mut();
+        let old: Vec<(LspNexthop, LabelOp)> = lsp.nexthops().iter()
+            .filter(|(n, _)| n.address == nexthop.address)
+            .map(|(n, op)| (n.clone(), op.clone()))
+            .collect();
+        if old.len() == 1 && old[0].0 == nexthop && old[0].1 == op {
+            return Ok(())
+        }
 
-        for (_, rib) in map.drain() {
-            rib_ipv4.delete(&prefix, rib);
+        for (n, _) in old.iter() {
+            lsp.nexthops().remove(n);
         }
+        lsp.nexthops().insert(nexthop.clone(), op);
 
-        rib_ipv4.process(&prefix, |prefix: &Prefix<Ipv4Addr>, entry: &RibEntry<Ipv4Addr>| {
-            if let Some(ref mut fib) = *entry.fib() {
-                self.rib_ipv4_uninstall_kernel(prefix, &fib);
-            }
+        if let Err(err) = self.kernel.borrow().mpls_route_install(&lsp) {
+            error!("Kernel install LSP {} error {}", in_label, err);
 
-            if let Some(selected) = entry.select() {
-                self.rib_ipv4_install_kernel(prefix, &selected);
-                Some(selected)
-            } else {
-                None
+            // Keep config as it is in kernel.
+            lsp.nexthops().remove(&nexthop);
+            lsp.nexthops().extend(old);
+            if lsp.nexthops().is_empty() {
+                self.lsps.borrow_mut().remove(&in_label);
             }
-        });
-    }
-
-    /// Install an IPv4 route for given RIB to kernel.
-    pub fn rib_ipv4_install_kernel(&self, prefix: &Prefix<Ipv4Addr>, new: &Rib<Ipv4Addr>) {
-        self.kernel.borrow_mut().ipv4_route_install(prefix, new);
-    }
 
-    /// Update an IPv4 route for given RIB to kkernel.
-    pub fn rib_ipv4_update_kernel(&self, prefix: &Prefix<Ipv4Addr>, new: &Rib<Ipv4Addr>, old: &Rib<Ipv4Addr>) {
-        self.kernel.borrow_mut().ipv4_route_update(prefix, new, old);
-    }
-
-    /// Uninstall an IPv4 route for given RIB from kernel.
-    pub fn rib_ipv4_uninstall_kernel(&self, prefix: &Prefix<Ipv4Addr>, old: &Rib<Ipv4Addr>) {
-        self.kernel.borrow_mut().ipv4_route_uninstall(prefix, old);
-    }
+            return Err(CoreError::CommandExec(format!("Failed to install LSP {}: {}", in_label, err)))
+        }
 
-    /// Install an IPv6 route for given RIB to kernel.
-    pub fn rib_ipv6_install_kernel(&self, prefix: &Prefix<Ipv6Addr>, new: &Rib<Ipv6Addr>) {
-        self.kernel.borrow_mut().ipv6_route_install(prefix, new);
+        Ok(())
     }
 
-    /// Update an IPv6 route for given RIB to kkernel.
-    pub fn rib_ipv6_update_kernel(&self, prefix: &Prefix<Ipv6Addr>, new: &Rib<Ipv6Addr>, old: &Rib<Ipv6Addr>) {
-        self.kernel.borrow_mut().ipv6_route_update(prefix, new, old);
-    }
+    /// Delete nexthop from LSP, and uninstall LSP from kernel if no nexthop remains.
+    /// Nexthop matches by address, and by interface as well if it is given.
+    pub fn lsp_delete(&self, in_label: u32, nexthop: &LspNexthop) -> Result<(), CoreError> {
+        let lsp = match self.lsp_lookup(in_label) {
+            Some(lsp) => lsp,
+            None => return Err(CoreError::CommandExec(format!("No such LSP {}", in_label))),
+        };
+
+        let key = lsp.nexthops().keys()
+            .find(|n| n.address == nexthop.address && (nexthop.ifname.is_none() || n.ifname == nexthop.ifname))
+            .cloned();
+        let (key, op) = match key {
+            Some(key) => {
+                let op = lsp.nexthops().remove(&key).unwrap();
+                (key, op)
+            },
+            None => return Err(CoreError::CommandExec(format!("No such nexthop {} for LSP {}", nexthop.address, in_label))),
+        };
+
+        let ret = if lsp.nexthops().is_empty() {
+            self.lsps.borrow_mut().remove(&in_label);
+            self.kernel.borrow().mpls_route_uninstall(&lsp)
+        } else {
+            self.kernel.borrow().mpls_route_install(&lsp)
+        };
+
+        if let Err(err) = ret {
+            error!("Kernel update LSP {} error {}", in_label, err);
+
+            // Keep config as it is in kernel.
+            lsp.nexthops().insert(key, op);
+            self.lsps.borrow_mut().insert(in_label, lsp.clone());
+
+            return Err(CoreError::CommandExec(format!("Failed to update LSP {}: {}", in_label, err)))
+        }
 
-    /// Uninstall an IPv6 route for given RIB from kernel.
-    pub fn rib_ipv6_uninstall_kernel(&self, prefix: &Prefix<Ipv6Addr>, old: &Rib<Ipv6Addr>) {
-        self.kernel.borrow_mut().ipv6_route_uninstall(prefix, old);
+        Ok(())
     }
 
     /// Initialization.
@@ -231,6 +1453,12 @@ impl ZebraMaster {
                 clone.get_add_link(kl);
             }));
 
+        let clone = master.clone();
+        master.kernel.borrow_mut().driver().register_link_stats(
+            Box::new(move |ifindex: i32, stats: KernelLinkStats| {
+                clone.get_link_stats(ifindex, stats);
+            }));
+
         let clone = master.clone();
         master.kernel.borrow_mut().driver().register_delete_link(
             Box::new(move |kl: KernelLink| {
@@ -266,6 +1494,72 @@ impl ZebraMaster {
                 clone.get_delete_ipv6_address(ka);
             }));
 
+        let clone = master.clone();
+        master.kernel.borrow_mut().driver().register_add_ipv4_route(
+            Box::new(move |kr: KernelRoute<Ipv4Addr>| {
+                // TBD error handling.
+                clone.get_add_ipv4_route(kr);
+            }));
+
+        let clone = master.clone();
+        master.kernel.borrow_mut().driver().register_delete_ipv4_route(
+            Box::new(move |kr: KernelRoute<Ipv4Addr>| {
+                // TBD error handling.
+                clone.get_delete_ipv4_route(kr);
+            }));
+
+        let clone = master.clone();
+        master.kernel.borrow_mut().driver().register_add_ipv6_route(
+            Box::new(move |kr: KernelRoute<Ipv6Addr>| {
+                // TBD error handling.
+                clone.get_add_ipv6_route(kr);
+            }));
+
+        let clone = master.clone();
+        master.kernel.borrow_mut().driver().register_delete_ipv6_route(
+            Box::new(move |kr: KernelRoute<Ipv6Addr>| {
+                // TBD error handling.
+                clone.get_delete_ipv6_route(kr);
+            }));
+
+        let clone = master.clone();
+        master.kernel.borrow_mut().driver().register_add_ipv4_neighbor(
+            Box::new(move |kn: KernelNeigh<Ipv4Addr>| {
+                clone.get_add_ipv4_neighbor(kn);
+            }));
+
+        let clone = master.clone();
+        master.kernel.borrow_mut().driver().register_delete_ipv4_neighbor(
+            Box::new(move |kn: KernelNeigh<Ipv4Addr>| {
+                clone.get_delete_ipv4_neighbor(kn);
+            }));
+
+        let clone = master.clone();
+        master.kernel.borrow_mut().driver().register_add_ipv6_neighbor(
+            Box::new(move |kn: KernelNeigh<Ipv6Addr>| {
+                clone.get_add_ipv6_neighbor(kn);
+            }));
+
+        let clone = master.clone();
+        master.kernel.borrow_mut().driver().register_delete_ipv6_neighbor(
+            Box::new(move |kn: KernelNeigh<Ipv6Addr>| {
+                clone.get_delete_ipv6_neighbor(kn);
+            }));
+
+        let clone = master.clone();
+        master.kernel.borrow_mut().driver().register_ipv4_fib_result(
+            Box::new(move |result: KernelFibResult<Ipv4Addr>| {
+                // RIB may be in use, apply later.
+                clone.fib_results_ipv4.borrow_mut().push(result);
+            }));
+
+        let clone = master.clone();
+        master.kernel.borrow_mut().driver().register_ipv6_fib_result(
+            Box::new(move |result: KernelFibResult<Ipv6Addr>| {
+                // RIB may be in use, apply later.
+                clone.fib_results_ipv6.borrow_mut().push(result);
+            }));
+
         ZebraMaster::kernel_init(master.clone());
         ZebraMaster::config_init(master.clone());
         ZebraMaster::exec_init(master.clone());
@@ -275,24 +1569,60 @@ impl ZebraMaster {
     /// Kernel layer initialization.
     fn kernel_init(master: Rc<ZebraMaster>) {
         // Init Kernel driver.
-        master.kernel.borrow_mut().init();
+        master.kernel.borrow().init();
     }
 
     /// Initiialize configuration.
     fn config_init(master: Rc<ZebraMaster>) {
         let mds = master.mds.borrow().clone();
-        let ipv4_routes = Rc::new(Ipv4StaticRoute::new(master.clone()));
+        let ipv4_routes = Rc::new(Ipv4StaticRoute::new(master.clone(), VRF_DEFAULT_NAME));
+        let ipv6_routes = Rc::new(Ipv6StaticRoute::new(master.clone(), VRF_DEFAULT_NAME));
 
         MdsNode::register_handler(mds.clone(), "/config/route_ipv4", ipv4_routes.clone());
+        MdsNode::register_handler(mds.clone(), "/config/route_ipv6", ipv6_routes.clone());
+
+        let vrfs = Rc::new(VrfConfig::new(master.clone(), ipv4_routes.clone(), ipv6_routes.clone()));
+        MdsNode::register_handler(mds.clone(), "/config/vrfs", vrfs.clone());
+
+        let lsps = Rc::new(StaticLsp::new(master.clone()));
+        MdsNode::register_handler(mds.clone(), "/config/mpls/lsp", lsps.clone());
+
+        let interfaces = Rc::new(InterfaceConfig::new(master.clone()));
+        MdsNode::register_handler(mds.clone(), "/config/interfaces", interfaces.clone());
+        master.interface_config.replace(Some(interfaces.clone()));
+
+        let graceful_restart = Rc::new(GracefulRestartConfig::new(master.clone()));
+        MdsNode::register_handler(mds.clone(), "/config/graceful_restart", graceful_restart.clone());
+
+        let interface_statistics = Rc::new(InterfaceStatsConfig::new(master.clone()));
+        MdsNode::register_handler(mds.clone(), "/config/interface_statistics", interface_statistics.clone());
     }
 
     /// Initialize exec.
     fn exec_init(master: Rc<ZebraMaster>) {
         let mds = master.mds.borrow().clone();
-        let rib_table_ipv4 = Rc::new(RibTableIpv4::new(master.clone()));
+        let rib_table_ipv4 = Rc::new(RibTableExec::new(master.clone(), Vrf::rib_ipv4));
+        let rib_table_ipv6 = Rc::new(RibTableExec::new(master.clone(), Vrf::rib_ipv6));
 
         MdsNode::register_handler(mds.clone(), "/exec/show/route_ipv4", rib_table_ipv4.clone());
-//        MdsNode::register_handler(mds.clone(), "/exec/show/route_ipv4", rib_table_ipv4.clone());
+        MdsNode::register_handler(mds.clone(), "/exec/show/route_ipv6", rib_table_ipv6.clone());
+
+        let show_vrf = Rc::new(ShowVrf::new(master.clone(), rib_table_ipv4.clone(), rib_table_ipv6.clone()));
+        MdsNode::register_handler(mds.clone(), "/exec/show/vrfs", show_vrf.clone());
+
+        let show_mpls = Rc::new(ShowMpls::new(master.clone()));
+        MdsNode::register_handler(mds.clone(), "/exec/show/mpls/table", show_mpls.clone());
+
+        let fib_failures = Rc::new(ShowFibFailures::new(master.clone()));
+        MdsNode::register_handler(mds.clone(), "/exec/show/fib_failures", fib_failures.clone());
+
+        let show_interface = Rc::new(ShowInterface::new(master.clone()));
+        MdsNode::register_handler(mds.clone(), "/exec/show/interface", show_interface.clone());
+        MdsNode::register_handler(mds.clone(), "/exec/show/interfaces", show_interface.clone());
+
+        let show_neighbor = Rc::new(ShowNeighbor::new(master.clone()));
+        MdsNode::register_handler(mds.clone(), "/exec/show/arp", show_neighbor.clone());
+        MdsNode::register_handler(mds.clone(), "/exec/show/ipv6/neighbors", show_neighbor.clone());
     }
 
     /// Initialize RIB.
@@ -305,24 +1635,117 @@ impl ZebraMaster {
                  sender_p2n: mpsc::Sender<ProtoToNexus>,
                  receiver_n2p: mpsc::Receiver<NexusToProto>,
                  receiver_p2z: mpsc::Receiver<ProtoToZebra>) {
+        // Watch kernel events and channels, and wake up only when any of them is ready or timer expires.
+        let poll = match Poll::new() {
+            Ok(poll) => poll,
+            Err(err) => {
+                error!("Failed to create poll {}", err);
+                return;
+            }
+        };
+        let mut events = Events::with_capacity(ZEBRA_EVENTS_CAPACITY);
+
+        if let Some(fd) = self.kernel.borrow().event_fd() {
+            if let Err(err) = poll.register(&EventedFd(&fd), ZEBRA_KERNEL_TOKEN,
+                                            Ready::readable(), PollOpt::level()) {
+                error!("Failed to register kernel event fd {}", err);
+            }
+        }
+
+        if let Some(fd) = self.kernel.borrow().fib_fd() {
+            if let Err(err) = poll.register(&EventedFd(&fd), ZEBRA_FIB_TOKEN,
+                                            Ready::readable(), PollOpt::level()) {
+                error!("Failed to register kernel FIB fd {}", err);
+            }
+        }
+
+        let (receiver_p2z, registration_p2z) = channel_evented(receiver_p2z);
+        if let Err(err) = poll.register(&registration_p2z, ZEBRA_PROTO_TOKEN,
+                                        Ready::readable(), PollOpt::edge()) {
+            error!("Failed to register ProtoToZebra channel {}", err);
+        }
+
+        let (receiver_n2p, registration_n2p) = channel_evented(receiver_n2p);
+        if let Err(err) = poll.register(&registration_n2p, ZEBRA_NEXUS_TOKEN,
+                                        Ready::readable(), PollOpt::edge()) {
+            error!("Failed to register NexusToProto channel {}", err);
+        }
+
         // Zebra main loop
         'main: loop {
+            if let Err(err) = poll.poll(&mut events, self.timer_timeout()) {
+                error!("Poll error {}", err);
+            }
+
+            // Process kernel events.
+            for event in events.iter() {
+                if event.token() == ZEBRA_KERNEL_TOKEN {
+                    self.kernel.borrow().process_event();
+                } else if event.token() == ZEBRA_FIB_TOKEN {
+                    self.kernel.borrow().process_fib_ack();
+                }
+            }
+
             // Process ProtoToZebra messages through the channel.
             while let Ok(d) = receiver_p2z.try_recv() {
                 match d {
                     ProtoToZebra::RegisterProto((proto, sender_z2p)) => {
-                        self.clients.borrow_mut().insert(proto, ClientTuple { _sender: sender_z2p });
-                        debug!("Register Protocol {}", proto);
+                        self.client_register(proto, sender_z2p);
+                    },
+                    ProtoToZebra::UnregisterProto(proto) => {
+                        self.client_unregister(proto);
+                    },
+                    ProtoToZebra::RouteAddIpv4((proto, route)) => {
+                        self.rib_add_proto_ipv4(proto, route);
+                    },
+                    ProtoToZebra::RouteDeleteIpv4((proto, route)) => {
+                        self.rib_delete_proto_ipv4(proto, route);
+                    },
+                    ProtoToZebra::RouteAddIpv6((proto, route)) => {
+                        self.rib_add_proto_ipv6(proto, route);
+                    },
+                    ProtoToZebra::RouteDeleteIpv6((proto, route)) => {
+                        self.rib_delete_proto_ipv6(proto, route);
+                    },
+                    ProtoToZebra::RedistributeAdd((proto, rib_type)) => {
+                        self.redistribute_add(proto, rib_type);
+                    },
+                    ProtoToZebra::RedistributeDelete((proto, rib_type)) => {
+                        self.redistribute_delete(proto, rib_type);
                     },
-                    ProtoToZebra::RouteAdd(_i) => {
+                    ProtoToZebra::NexthopRegisterIpv4((proto, address)) => {
+                        self.nht_register_ipv4(proto, &address);
                     },
-                    ProtoToZebra::RouteLookup(_i) => {
+                    ProtoToZebra::NexthopUnregisterIpv4((proto, address)) => {
+                        self.nht_ipv4.borrow_mut().unregister(proto, &address);
+                    },
+                    ProtoToZebra::NexthopRegisterIpv6((proto, address)) => {
+                        self.nht_register_ipv6(proto, &address);
+                    },
+                    ProtoToZebra::NexthopUnregisterIpv6((proto, address)) => {
+                        self.nht_ipv6.borrow_mut().unregister(proto, &address);
+                    },
+                    ProtoToZebra::NeighborQueryIpv4((proto, nexthop)) => {
+                        self.client_send(proto, ZebraToProto::NeighborIpv4(self.neighbor_lookup_ipv4(&nexthop)));
+                    },
+                    ProtoToZebra::NeighborQueryIpv6((proto, nexthop)) => {
+                        self.client_send(proto, ZebraToProto::NeighborIpv6(self.neighbor_lookup_ipv6(&nexthop)));
                     },
                 }
             }
 
             // Process NexusToProto messages through the channel.
-            while let Ok(d) = receiver_n2p.try_recv() {
+            loop {
+                let d = match receiver_n2p.try_recv() {
+                    Ok(d) => d,
+                    Err(mpsc::TryRecvError::Empty) => break,
+                    Err(mpsc::TryRecvError::Disconnected) => {
+                        // Nothing would control or terminate zebra any more.
+                        error!("Nexus channel disconnected");
+                        break 'main;
+                    }
+                };
+
                 match d {
                     NexusToProto::TimerExpiration(token) => {
                         debug!("Received TimerExpiration with token {}", token);
@@ -381,12 +1804,580 @@ impl ZebraMaster {
                 }
             }
 
-            thread::sleep(Duration::from_millis(10));
+            // Sweep stale routes once protocols had time to reconverge.
+            self.stale_check();
+
+            // Poll link statistics to update rates.
+            self.stats_check();
+
+            // Withdraw routes of protocols gone.
+            self.clients_cleanup();
+
+            // Program FIB in batch for all changes made in this iteration.
+            self.fib_process();
 
             // TODO: Some cleanup has to be done for inner.
             // inner.finish();
         }
+
+        // Routes are retained in kernel for next instance, just send what is queued.
+        self.kernel.borrow().fib_flush();
+
         debug!("Zebra terminated");
     }
 }
 
+/// Relay messages to a new channel, and make poll ready when they arrive.
+fn channel_evented<M>(receiver: mpsc::Receiver<M>) -> (mpsc::Receiver<M>, Registration)
+where M: Send + 'static
+{
+    let (registration, set_readiness) = Registration::new2();
+    let (sender, relayed) = mpsc::channel::<M>();
+
+    thread::spawn(move || {
+        for msg in receiver.iter() {
+            if sender.send(msg).is_err() {
+                break;
+            }
+            if set_readiness.set_readiness(Ready::readable()).is_err() {
+                break;
+            }
+        }
+
+        // Wake up poll, so that disconnection is noticed.
+        drop(sender);
+        let _ = set_readiness.set_readiness(Ready::readable());
+    });
+
+    (relayed, registration)
+}
+
+/// Return true if the prefix is IPv6 link-local (fe80::/10).
+fn is_ipv6_link_local(prefix: &Prefix<Ipv6Addr>) -> bool {
+    let octets = prefix.octets();
+
+    octets[0] == 0xfe && (octets[1] & 0xc0) == 0x80
+}
+
+///
+/// Unit tests for Zebra master with mock kernel driver.
+///
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zebra::mock::MockKernelDriver;
+    use std::net::IpAddr;
+
+    fn master_with_mock() -> (Rc<ZebraMaster>, Arc<MockKernelDriver>) {
+        let mock = Arc::new(MockKernelDriver::new());
+        let master = Rc::new(ZebraMaster::new(Kernel::from_driver(mock.clone())));
+
+        ZebraMaster::init(master.clone());
+        (master, mock)
+    }
+
+    fn kernel_link(index: i32, name: &str, flags: u32) -> KernelLink {
+        let mut kl = KernelLink::new(index, name, 1, [0, 1, 2, 3, 4, 5], 1500);
+        kl.flags = flags;
+        kl
+    }
+
+    fn kernel_addr(index: i32, address: &str) -> KernelAddr<Ipv4Addr> {
+        KernelAddr::new(index, Prefix::<Ipv4Addr>::from_str(address).unwrap(), None, false, false, None)
+    }
+
+    #[test]
+    pub fn test_master_channel_wakeup() {
+        let (sender, receiver) = mpsc::channel::<u32>();
+        let (receiver, registration) = channel_evented(receiver);
+        let poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(ZEBRA_EVENTS_CAPACITY);
+
+        poll.register(&registration, ZEBRA_PROTO_TOKEN, Ready::readable(), PollOpt::edge()).unwrap();
+
+        // Nothing is ready until a message is sent.
+        poll.poll(&mut events, Some(Duration::from_millis(10))).unwrap();
+        assert!(events.is_empty());
+
+        for msg in 0..2 {
+            sender.send(msg).unwrap();
+            poll.poll(&mut events, Some(Duration::from_secs(10))).unwrap();
+            assert_eq!(events.iter().next().map(|e| e.token()), Some(ZEBRA_PROTO_TOKEN));
+            assert_eq!(receiver.recv_timeout(Duration::from_secs(10)), Ok(msg));
+        }
+    }
+
+    #[test]
+    pub fn test_master_termination() {
+        let (sender_p2n, _receiver_p2n) = mpsc::channel::<ProtoToNexus>();
+        let (sender_n2p, receiver_n2p) = mpsc::channel::<NexusToProto>();
+        let (_sender_p2z, receiver_p2z) = mpsc::channel::<ProtoToZebra>();
+
+        let handle = thread::spawn(move || {
+            let (master, _mock) = master_with_mock();
+            master.start(sender_p2n, receiver_n2p, receiver_p2z);
+        });
+
+        // Blocking main loop wakes up on message.
+        sender_n2p.send(NexusToProto::ProtoTermination).unwrap();
+        assert!(handle.join().is_ok());
+    }
+
+    #[test]
+    pub fn test_master_nexus_disconnect() {
+        let (sender_p2n, _receiver_p2n) = mpsc::channel::<ProtoToNexus>();
+        let (sender_n2p, receiver_n2p) = mpsc::channel::<NexusToProto>();
+        let (_sender_p2z, receiver_p2z) = mpsc::channel::<ProtoToZebra>();
+
+        let handle = thread::spawn(move || {
+            let (master, _mock) = master_with_mock();
+            master.start(sender_p2n, receiver_n2p, receiver_p2z);
+        });
+
+        // Main loop terminates once Nexus is gone.
+        drop(sender_n2p);
+        assert!(handle.join().is_ok());
+    }
+
+    #[test]
+    pub fn test_master_connected() {
+        let (master, mock) = master_with_mock();
+        let up = (libc::IFF_UP | libc::IFF_RUNNING) as u32;
+        let p = Prefix::<Ipv4Addr>::from_str("10.0.0.0/24").unwrap();
+
+        let connected = |master: &ZebraMaster| {
+            master.vrf_default().rib_ipv4().lookup_exact(&p)
+                .and_then(|entry| entry.fib().as_ref().map(|rib| rib.rib_type()))
+        };
+
+        // Connected route is selected once link goes up, kernel has it already.
+        mock.inject_add_link(kernel_link(2, "eth0", 0));
+        mock.inject_add_ipv4_address(kernel_addr(2, "10.0.0.1/24"));
+        assert_eq!(connected(&master), None);
+
+        mock.inject_add_link(kernel_link(2, "eth0", up));
+        assert_eq!(connected(&master), Some(RibType::Connected));
+        assert!(mock.ipv4_route(VRF_DEFAULT_TABLE, &p).is_none());
+
+        mock.inject_add_link(kernel_link(2, "eth0", 0));
+        assert_eq!(connected(&master), None);
+
+        mock.inject_add_link(kernel_link(2, "eth0", up));
+        mock.inject_delete_ipv4_address(kernel_addr(2, "10.0.0.1/24"));
+        assert_eq!(connected(&master), None);
+    }
+
+    #[test]
+    pub fn test_master_vrf_master() {
+        let (master, mock) = master_with_mock();
+        let up = (libc::IFF_UP | libc::IFF_RUNNING) as u32;
+        let p = Prefix::<Ipv4Addr>::from_str("10.0.0.0/24").unwrap();
+        let enslave = |master: Option<i32>| {
+            let mut kl = kernel_link(2, "eth0", up);
+            kl.master = master;
+            kl
+        };
+        let connected = |vrf: &Vrf| {
+            vrf.rib_ipv4().lookup_exact(&p)
+                .and_then(|entry| entry.fib().as_ref().map(|rib| rib.rib_type()))
+        };
+
+        let mut kl = kernel_link(10, "red", up);
+        kl.kind = Some("vrf".to_string());
+        kl.vrf_table = Some(100);
+        mock.inject_add_link(kl);
+        let red = master.vrf_lookup_by_table(100).unwrap();
+        assert_eq!(red.ifindex(), Some(10));
+
+        mock.inject_add_link(enslave(None));
+        mock.inject_add_ipv4_address(kernel_addr(2, "10.0.0.1/24"));
+        assert_eq!(connected(&master.vrf_default()), Some(RibType::Connected));
+
+        // Connected route follows link to VRF and back.
+        mock.inject_add_link(enslave(Some(10)));
+        assert_eq!(connected(&master.vrf_default()), None);
+        assert_eq!(connected(&red), Some(RibType::Connected));
+
+        mock.inject_add_link(enslave(None));
+        assert_eq!(connected(&master.vrf_default()), Some(RibType::Connected));
+        assert_eq!(connected(&red), None);
+
+        // Deleting VRF device unbinds links enslaved to it.
+        mock.inject_add_link(enslave(Some(10)));
+        mock.inject_delete_link(kernel_link(10, "red", up));
+        assert!(master.vrf_lookup_by_table(100).is_none());
+        assert_eq!(red.ifindex(), None);
+        assert_eq!(connected(&master.vrf_default()), Some(RibType::Connected));
+        assert_eq!(connected(&red), None);
+    }
+
+    #[test]
+    pub fn test_master_link_stats() {
+        let (master, mock) = master_with_mock();
+        let mut stats = KernelLinkStats::default();
+
+        mock.inject_add_link(kernel_link(2, "eth0", 0));
+        stats.rx_bytes = 1000;
+        mock.inject_link_stats(2, stats);
+
+        let link = master.link_master.borrow().lookup_by_index(2).unwrap();
+        assert_eq!(link.stats().as_ref().unwrap().counters().rx_bytes, 1000);
+
+        // Unknown link is ignored.
+        mock.inject_link_stats(3, stats);
+    }
+
+    #[test]
+    pub fn test_master_interface_config() {
+        let (master, mock) = master_with_mock();
+        let config = master.interface_config.borrow().clone().unwrap();
+        let params = |s: &str| Some(Box::new(s.to_string()));
+
+        // Config for interface not present yet is applied when it appears.
+        assert!(config.handle_put("/config/interfaces/eth1", params(r#"{"mtu": 9000}"#)).is_ok());
+        assert!(config.handle_put("/config/interfaces/eth1", params(r#"{"shutdown": true}"#)).is_ok());
+        assert!(config.handle_delete("/config/interfaces/eth1", params(r#"{"shutdown": true}"#)).is_ok());
+        assert_eq!(mock.mtu(3), None);
+
+        mock.inject_add_link(kernel_link(3, "eth1", 0));
+        assert_eq!(mock.mtu(3), Some(9000));
+
+        // Removing MTU restores the one when link is added.
+        let mut kl = kernel_link(2, "eth0", 0);
+        kl.mtu = 1400;
+        mock.inject_add_link(kl);
+        assert!(config.handle_put("/config/interfaces/eth0", params(r#"{"mtu": 9000}"#)).is_ok());
+        assert_eq!(mock.mtu(2), Some(9000));
+
+        let mut kl = kernel_link(2, "eth0", 0);
+        kl.mtu = 9000;
+        mock.inject_add_link(kl);
+        assert!(config.handle_delete("/config/interfaces/eth0", params(r#"{"mtu": 9000}"#)).is_ok());
+        assert_eq!(mock.mtu(2), Some(1400));
+    }
+
+    #[test]
+    pub fn test_master_static_route() {
+        let (master, mock) = master_with_mock();
+        mock.inject_add_link(kernel_link(2, "eth0", (libc::IFF_UP | libc::IFF_RUNNING) as u32));
+        let p = Prefix::<Ipv4Addr>::from_str("192.168.0.0/16").unwrap();
+        let json = serde_json::json!({"nexthops": [{"nexthop": {"interface": "eth0"}}]});
+        let sr = Arc::new(StaticRoute::<Ipv4Addr>::from_json(&p, &json).unwrap());
+        let vrf = master.vrf_default();
+
+        master.rib_add_static_ipv4(&vrf, sr.clone());
+        let rib = mock.ipv4_route(VRF_DEFAULT_TABLE, &p).unwrap();
+        assert_eq!(rib.rib_type(), RibType::Static);
+
+        master.rib_delete_static_ipv4(&vrf, sr.clone());
+        assert_eq!(mock.ipv4_route_count(), 0);
+
+        // Failure is recorded, and cleared once route is gone.
+        mock.set_route_error(Some("Network is unreachable"));
+        master.rib_add_static_ipv4(&vrf, sr.clone());
+        assert_eq!(mock.ipv4_route_count(), 0);
+        assert_eq!(master.fib_failures().len(), 1);
+
+        master.rib_delete_static_ipv4(&vrf, sr.clone());
+        assert_eq!(master.fib_failures().len(), 0);
+    }
+
+    #[test]
+    pub fn test_master_static_route_link() {
+        let (master, mock) = master_with_mock();
+        let up = (libc::IFF_UP | libc::IFF_RUNNING) as u32;
+        let p = Prefix::<Ipv4Addr>::from_str("192.168.0.0/16").unwrap();
+        let json = serde_json::json!({"nexthops": [{"nexthop": {"interface": "eth0"}, "distance": 1},
+                                                   {"nexthop": {"interface": "eth1"}, "distance": 10}]});
+        let sr = Arc::new(StaticRoute::<Ipv4Addr>::from_json(&p, &json).unwrap());
+        let vrf = master.vrf_default();
+        let distance = || mock.ipv4_route(VRF_DEFAULT_TABLE, &p).map(|rib| rib.distance());
+
+        // Route through absent interface is not selected.
+        mock.inject_add_link(kernel_link(3, "eth1", up));
+        master.rib_add_static_ipv4(&vrf, sr.clone());
+        assert_eq!(distance(), Some(10));
+
+        mock.inject_add_link(kernel_link(2, "eth0", up));
+        assert_eq!(distance(), Some(1));
+
+        // Backup route takes over while interface is down or deleted.
+        mock.inject_add_link(kernel_link(2, "eth0", 0));
+        assert_eq!(distance(), Some(10));
+
+        mock.inject_add_link(kernel_link(2, "eth0", up));
+        assert_eq!(distance(), Some(1));
+
+        mock.inject_delete_link(kernel_link(2, "eth0", up));
+        assert_eq!(distance(), Some(10));
+
+        mock.inject_add_link(kernel_link(3, "eth1", 0));
+        assert_eq!(distance(), None);
+        assert_eq!(master.fib_failures().len(), 0);
+    }
+
+    #[test]
+    pub fn test_master_stale_sweep() {
+        let (master, mock) = master_with_mock();
+        mock.inject_add_link(kernel_link(2, "eth0", (libc::IFF_UP | libc::IFF_RUNNING) as u32));
+        let p1 = Prefix::<Ipv4Addr>::from_str("192.168.1.0/24").unwrap();
+        let p2 = Prefix::<Ipv4Addr>::from_str("192.168.2.0/24").unwrap();
+        let json = serde_json::json!({"nexthops": [{"nexthop": {"interface": "eth0"}}]});
+        let sr = Arc::new(StaticRoute::<Ipv4Addr>::from_json(&p1, &json).unwrap());
+        let vrf = master.vrf_default();
+
+        // Routes in the dump are kept until stale time expires.
+        mock.inject_stale_ipv4_route(VRF_DEFAULT_TABLE, &p1);
+        mock.inject_stale_ipv4_route(VRF_DEFAULT_TABLE, &p2);
+        master.stale_check();
+        assert_eq!(mock.stale_ipv4_routes().len(), 2);
+        assert_eq!(mock.sweeps(), 0);
+
+        // Route replaced by protocol is no longer stale.
+        master.rib_add_static_ipv4(&vrf, sr.clone());
+        assert_eq!(mock.stale_ipv4_routes(), vec![(VRF_DEFAULT_TABLE, p2.clone())]);
+
+        // Remaining one is swept, replaced one stays.
+        master.set_stale_time(0);
+        master.stale_check();
+        assert!(mock.stale_ipv4_routes().is_empty());
+        assert!(mock.ipv4_route(VRF_DEFAULT_TABLE, &p1).is_some());
+        assert_eq!(mock.sweeps(), 1);
+
+        // Sweep happens only once.
+        master.stale_check();
+        assert_eq!(mock.sweeps(), 1);
+    }
+
+    #[test]
+    pub fn test_master_fib_ack_lost() {
+        let (master, mock) = master_with_mock();
+        mock.inject_add_link(kernel_link(2, "eth0", (libc::IFF_UP | libc::IFF_RUNNING) as u32));
+        let p = Prefix::<Ipv4Addr>::from_str("192.168.0.0/16").unwrap();
+        let json = serde_json::json!({"nexthops": [{"nexthop": {"interface": "eth0"}}]});
+        let sr = Arc::new(StaticRoute::<Ipv4Addr>::from_json(&p, &json).unwrap());
+        let vrf = master.vrf_default();
+
+        master.rib_add_static_ipv4(&vrf, sr.clone());
+        assert_eq!(master.fib_failures().len(), 0);
+
+        // Route is sent again when its ACK is lost.
+        mock.set_route_error(Some("No buffer space available"));
+        master.fib_results_ipv4.borrow_mut().push(KernelFibResult {
+            table_id: VRF_DEFAULT_TABLE,
+            prefix: p.clone(),
+            op: FibOp::Install,
+            error: Some("ACK lost".to_string()),
+            lost: true,
+        });
+        master.fib_process();
+        assert!(master.fib_failures().values().next().unwrap().error.contains("No buffer space available"));
+
+        mock.set_route_error(None);
+        master.fib_results_ipv4.borrow_mut().push(KernelFibResult {
+            table_id: VRF_DEFAULT_TABLE,
+            prefix: p.clone(),
+            op: FibOp::Install,
+            error: Some("ACK lost".to_string()),
+            lost: true,
+        });
+        master.fib_process();
+        assert_eq!(master.fib_failures().len(), 0);
+        assert!(vrf.rib_ipv4().lookup_exact(&p).unwrap().fib().as_ref().unwrap().is_fib());
+    }
+
+    #[test]
+    pub fn test_master_lsp() {
+        let (master, mock) = master_with_mock();
+        let nexthop = |ifname: Option<&str>| LspNexthop {
+            address: "10.0.0.2".parse::<IpAddr>().unwrap(),
+            ifname: ifname.map(|ifname| ifname.to_string()),
+        };
+        let op = LabelOp::Swap(vec![200]);
+
+        // Failed install leaves nothing configured.
+        mock.set_route_error(Some("Network is unreachable"));
+        assert!(master.lsp_add(100, nexthop(None), op.clone()).is_err());
+        assert!(master.lsp_lookup(100).is_none());
+
+        mock.set_route_error(None);
+        assert!(master.lsp_add(100, nexthop(None), op.clone()).is_ok());
+        assert_eq!(mock.lsps(), vec![100]);
+
+        // Same nexthop with interface replaces the one without.
+        assert!(master.lsp_add(100, nexthop(Some("eth0")), op.clone()).is_ok());
+        assert_eq!(master.lsp_lookup(100).unwrap().nexthops().len(), 1);
+
+        mock.set_route_error(Some("Network is unreachable"));
+        assert!(master.lsp_add(100, nexthop(Some("eth1")), op.clone()).is_err());
+        assert!(master.lsp_lookup(100).unwrap().nexthops().contains_key(&nexthop(Some("eth0"))));
+
+        // Failed uninstall keeps LSP as it is in kernel.
+        assert!(master.lsp_delete(100, &nexthop(None)).is_err());
+        assert!(master.lsp_lookup(100).unwrap().nexthops().contains_key(&nexthop(Some("eth0"))));
+        assert_eq!(mock.lsps(), vec![100]);
+
+        mock.set_route_error(None);
+        assert!(master.lsp_delete(100, &nexthop(Some("eth1"))).is_err());
+        assert!(master.lsp_delete(100, &nexthop(None)).is_ok());
+        assert!(master.lsp_lookup(100).is_none());
+        assert!(mock.lsps().is_empty());
+    }
+
+    #[test]
+    pub fn test_master_static_route_tag() {
+        let (master, mock) = master_with_mock();
+        mock.inject_add_link(kernel_link(2, "eth0", (libc::IFF_UP | libc::IFF_RUNNING) as u32));
+        let p = Prefix::<Ipv4Addr>::from_str("192.168.0.0/16").unwrap();
+        let vrf = master.vrf_default();
+        let static_route = |tag: u32| {
+            let json = serde_json::json!({"nexthops": [{"nexthop": {"interface": "eth0"}, "tag": tag}]});
+            Arc::new(StaticRoute::<Ipv4Addr>::from_json(&p, &json).unwrap())
+        };
+
+        master.rib_add_static_ipv4(&vrf, static_route(100));
+        assert_eq!(mock.ipv4_route(VRF_DEFAULT_TABLE, &p).unwrap().tag(), 100);
+
+        // Tag is not programmed in kernel, only RIB is updated.
+        master.rib_add_static_ipv4(&vrf, static_route(0));
+        assert_eq!(mock.ipv4_route(VRF_DEFAULT_TABLE, &p).unwrap().tag(), 100);
+        assert_eq!(mock.ipv4_route_count(), 1);
+        let fib = vrf.rib_ipv4().lookup_exact(&p).and_then(|e| e.fib().clone()).unwrap();
+        assert_eq!(fib.tag(), 0);
+    }
+
+    #[test]
+    pub fn test_master_redistribute() {
+        let (master, mock) = master_with_mock();
+        let (sender, receiver) = mpsc::channel::<ZebraToProto>();
+        let p = Prefix::<Ipv4Addr>::from_str("192.168.0.0/16").unwrap();
+        let json = serde_json::json!({"nexthops": [{"nexthop": {"interface": "eth0"}}]});
+        let sr = Arc::new(StaticRoute::<Ipv4Addr>::from_json(&p, &json).unwrap());
+        let vrf = master.vrf_default();
+
+        master.client_register(ProtocolType::Ospf, sender);
+        mock.inject_add_link(kernel_link(2, "eth0", (libc::IFF_UP | libc::IFF_RUNNING) as u32));
+        match receiver.try_recv() {
+            Ok(ZebraToProto::InterfaceAdd(zi)) => assert_eq!(zi.name, "eth0"),
+            _ => panic!("InterfaceAdd is not received"),
+        }
+        match receiver.try_recv() {
+            Ok(ZebraToProto::InterfaceUp(zi)) => assert_eq!(zi.name, "eth0"),
+            _ => panic!("InterfaceUp is not received"),
+        }
+
+        // Route selected before subscription is sent once redistribution is enabled.
+        master.rib_add_static_ipv4(&vrf, sr.clone());
+        assert!(receiver.try_recv().is_err());
+
+        master.redistribute_add(ProtocolType::Ospf, RibType::Static);
+        match receiver.try_recv() {
+            Ok(ZebraToProto::RouteAddIpv4(route)) => assert_eq!(route.prefix, p),
+            _ => panic!("RouteAddIpv4 is not received"),
+        }
+
+        master.rib_delete_static_ipv4(&vrf, sr.clone());
+        match receiver.try_recv() {
+            Ok(ZebraToProto::RouteDeleteIpv4(route)) => assert_eq!(route.prefix, p),
+            _ => panic!("RouteDeleteIpv4 is not received"),
+        }
+
+        // Own routes are not sent back.
+        let route = ZebraRoute::<Ipv4Addr> {
+            prefix: Prefix::<Ipv4Addr>::from_str("172.16.0.0/12").unwrap(),
+            table_id: VRF_DEFAULT_TABLE,
+            rib_type: RibType::Ospf,
+            distance: 110,
+            metric: 10,
+            tag: 0,
+            sub_type: RibSubType::Default,
+            router_id: None,
+            nexthops: vec![Nexthop::from_ifname("eth0")],
+        };
+        master.redistribute_add(ProtocolType::Ospf, RibType::Ospf);
+        master.rib_add_proto_ipv4(ProtocolType::Ospf, route.clone());
+        assert!(mock.ipv4_route(VRF_DEFAULT_TABLE, &route.prefix).is_some());
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    pub fn test_master_proto_route() {
+        let (master, mock) = master_with_mock();
+        mock.inject_add_link(kernel_link(2, "eth0", (libc::IFF_UP | libc::IFF_RUNNING) as u32));
+        let (sender, receiver) = mpsc::channel::<ZebraToProto>();
+        let route = |s: &str, table_id: u32, rib_type: RibType| ZebraRoute::<Ipv4Addr> {
+            prefix: Prefix::<Ipv4Addr>::from_str(s).unwrap(),
+            table_id: table_id,
+            rib_type: rib_type,
+            distance: 110,
+            metric: 10,
+            tag: 0,
+            sub_type: RibSubType::Default,
+            router_id: None,
+            nexthops: vec![Nexthop::from_ifname("eth0")],
+        };
+        let p1 = Prefix::<Ipv4Addr>::from_str("172.16.0.0/12").unwrap();
+        let p2 = Prefix::<Ipv4Addr>::from_str("10.0.0.0/8").unwrap();
+
+        // Route from unregistered protocol, of other protocol, or for unknown VRF is rejected.
+        master.rib_add_proto_ipv4(ProtocolType::Ospf, route("172.16.0.0/12", VRF_DEFAULT_TABLE, RibType::Ospf));
+        master.client_register(ProtocolType::Ospf, sender);
+        master.rib_add_proto_ipv4(ProtocolType::Ospf, route("172.16.0.0/12", VRF_DEFAULT_TABLE, RibType::Bgp));
+        master.rib_add_proto_ipv4(ProtocolType::Ospf, route("172.16.0.0/12", 100, RibType::Ospf));
+        assert_eq!(mock.ipv4_route_count(), 0);
+
+        master.vrf_add("red", Some(100)).unwrap();
+        master.rib_add_proto_ipv4(ProtocolType::Ospf, route("172.16.0.0/12", VRF_DEFAULT_TABLE, RibType::Ospf));
+        master.rib_add_proto_ipv4(ProtocolType::Ospf, route("10.0.0.0/8", 100, RibType::Ospf));
+        assert!(mock.ipv4_route(VRF_DEFAULT_TABLE, &p1).is_some());
+        assert!(mock.ipv4_route(100, &p2).is_some());
+
+        // Metric change installs route at new metric and deletes the old one.
+        let mut r = route("172.16.0.0/12", VRF_DEFAULT_TABLE, RibType::Ospf);
+        r.metric = 20;
+        master.rib_add_proto_ipv4(ProtocolType::Ospf, r);
+        assert_eq!(mock.ipv4_route(VRF_DEFAULT_TABLE, &p1).unwrap().metric(), 20);
+        assert_eq!(mock.ipv4_route_count(), 2);
+
+        // Routes are flushed once protocol is gone.
+        drop(receiver);
+        mock.inject_add_link(kernel_link(2, "eth0", 0));
+        master.clients_cleanup();
+        assert_eq!(mock.ipv4_route_count(), 0);
+        assert!(master.vrf_default().rib_ipv4().lookup_exact(&p1).and_then(|e| e.fib().clone()).is_none());
+    }
+
+    #[test]
+    pub fn test_master_neighbor() {
+        let (master, mock) = master_with_mock();
+        let up = (libc::IFF_UP | libc::IFF_RUNNING) as u32;
+        let address = Ipv4Addr::new(10, 0, 0, 2);
+        let nexthop = Nexthop::from_address(&address);
+        let kn = |state| KernelNeigh::new(2, address.clone(), Some([2, 0, 0, 0, 0, 2]), state);
+
+        mock.inject_add_link(kernel_link(2, "eth0", up));
+        assert_eq!(master.neighbor_lookup_ipv4(&nexthop).index, None);
+
+        mock.inject_add_ipv4_neighbor(kn(NeighState::Reachable));
+        let state = master.neighbor_lookup_ipv4(&nexthop);
+        assert_eq!(state.index, Some(2));
+        assert_eq!(state.hwaddr, Some([2, 0, 0, 0, 0, 2]));
+        assert!(state.reachable);
+
+        let show = ShowNeighbor::new(master.clone());
+        let json: serde_json::Value = serde_json::from_str(&show.handle_get("/exec/show/arp", None).unwrap().unwrap()).unwrap();
+        assert_eq!(json[0]["interface"], "eth0");
+        assert_eq!(json[0]["address"], "10.0.0.2");
+        assert_eq!(json[0]["state"], "Reachable");
+        assert!(show.handle_get("/exec/show/arp/eth1", None).is_err());
+        assert_eq!(show.handle_get("/exec/show/ipv6/neighbors/eth0", None).unwrap().unwrap(), "[]");
+
+        // Not reachable while link is down.
+        mock.inject_add_link(kernel_link(2, "eth0", 0));
+        assert!(!master.neighbor_lookup_ipv4(&nexthop).reachable);
+
+        mock.inject_add_link(kernel_link(2, "eth0", up));
+        mock.inject_delete_ipv4_neighbor(kn(NeighState::None));
+        assert_eq!(master.neighbor_lookup_ipv4(&nexthop).index, None);
+    }
+}
@@ -0,0 +1,548 @@
+//
+// ReZe.Rs - Router Daemon
+//   Copyright (C) 2018-2020 Toshiaki Takada
+//
+// Zebra - In-memory kernel driver.
+//   Routes are recorded instead of being programmed, and kernel events are
+//   injected through registered callbacks, for testing without Netlink.
+//
+
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::os::unix::io::RawFd;
+
+use log::debug;
+use rtable::prefix::*;
+
+use super::kernel::*;
+use super::rib::Rib;
+use super::mpls::Lsp;
+
+/// Callbacks registered by Zebra.
+struct MockKernelCallback {
+
+    /// Add Link callback.
+    add_link: Option<Box<dyn Fn(KernelLink)>>,
+
+    /// Delete Link callback.
+    delete_link: Option<Box<dyn Fn(KernelLink)>>,
+
+    /// Add IPv4 Address callback.
+    add_ipv4_address: Option<Box<dyn Fn(KernelAddr<Ipv4Addr>)>>,
+
+    /// Delete IPv4 Address callback.
+    delete_ipv4_address: Option<Box<dyn Fn(KernelAddr<Ipv4Addr>)>>,
+
+    /// Add IPv6 Address callback.
+    add_ipv6_address: Option<Box<dyn Fn(KernelAddr<Ipv6Addr>)>>,
+
+    /// Delete IPv6 Address callback.
+    delete_ipv6_address: Option<Box<dyn Fn(KernelAddr<Ipv6Addr>)>>,
+
+    /// Add IPv4 Route callback.
+    add_ipv4_route: Option<Box<dyn Fn(KernelRoute<Ipv4Addr>)>>,
+
+    /// Delete IPv4 Route callback.
+    delete_ipv4_route: Option<Box<dyn Fn(KernelRoute<Ipv4Addr>)>>,
+
+    /// Add IPv6 Route callback.
+    add_ipv6_route: Option<Box<dyn Fn(KernelRoute<Ipv6Addr>)>>,
+
+    /// Delete IPv6 Route callback.
+    delete_ipv6_route: Option<Box<dyn Fn(KernelRoute<Ipv6Addr>)>>,
+
+    /// Add IPv4 Neighbor callback.
+    add_ipv4_neighbor: Option<Box<dyn Fn(KernelNeigh<Ipv4Addr>)>>,
+
+    /// Delete IPv4 Neighbor callback.
+    delete_ipv4_neighbor: Option<Box<dyn Fn(KernelNeigh<Ipv4Addr>)>>,
+
+    /// Add IPv6 Neighbor callback.
+    add_ipv6_neighbor: Option<Box<dyn Fn(KernelNeigh<Ipv6Addr>)>>,
+
+    /// Delete IPv6 Neighbor callback.
+    delete_ipv6_neighbor: Option<Box<dyn Fn(KernelNeigh<Ipv6Addr>)>>,
+
+    /// Link statistics callback.
+    link_stats: Option<Box<dyn Fn(i32, KernelLinkStats)>>,
+}
+
+impl MockKernelCallback {
+
+    /// Constructor.
+    fn new() -> MockKernelCallback {
+        MockKernelCallback {
+            add_link: None,
+            delete_link: None,
+            add_ipv4_address: None,
+            delete_ipv4_address: None,
+            add_ipv6_address: None,
+            delete_ipv6_address: None,
+            add_ipv4_route: None,
+            delete_ipv4_route: None,
+            add_ipv6_route: None,
+            delete_ipv6_route: None,
+            add_ipv4_neighbor: None,
+            delete_ipv4_neighbor: None,
+            add_ipv6_neighbor: None,
+            delete_ipv6_neighbor: None,
+            link_stats: None,
+        }
+    }
+}
+
+/// Call callback if registered.
+fn mock_call<A>(f: &Option<Box<dyn Fn(A)>>, arg: A, name: &str) {
+    match f {
+        Some(f) => (*f)(arg),
+        None => debug!("{} callback function is not set.", name),
+    }
+}
+
+/// In-memory kernel driver.
+pub struct MockKernelDriver {
+
+    /// Callbacks.
+    callback: RefCell<MockKernelCallback>,
+
+    /// IPv4 routes installed per table ID, prefix and metric.
+    routes_ipv4: RefCell<BTreeMap<(u32, Prefix<Ipv4Addr>, u32), Rib<Ipv4Addr>>>,
+
+    /// IPv6 routes installed per table ID, prefix and metric.
+    routes_ipv6: RefCell<BTreeMap<(u32, Prefix<Ipv6Addr>, u32), Rib<Ipv6Addr>>>,
+
+    /// Incoming labels of MPLS routes installed.
+    lsps: RefCell<BTreeSet<u32>>,
+
+    /// MTU set per interface index.
+    mtus: RefCell<BTreeMap<i32, u32>>,
+
+    /// Administrative state set per interface index.
+    link_states: RefCell<BTreeMap<i32, bool>>,
+
+    /// IPv4 routes left by previous instance per table ID and prefix, not replaced or deleted so far.
+    stale_ipv4: RefCell<BTreeSet<(u32, Prefix<Ipv4Addr>)>>,
+
+    /// Number of times stale routes are swept.
+    sweeps: Cell<u32>,
+
+    /// Error returned for route operations, if set.
+    route_error: RefCell<Option<String>>,
+}
+
+impl MockKernelDriver {
+
+    /// Constructor.
+    pub fn new() -> MockKernelDriver {
+        MockKernelDriver {
+            callback: RefCell::new(MockKernelCallback::new()),
+            routes_ipv4: RefCell::new(BTreeMap::new()),
+            routes_ipv6: RefCell::new(BTreeMap::new()),
+            lsps: RefCell::new(BTreeSet::new()),
+            mtus: RefCell::new(BTreeMap::new()),
+            link_states: RefCell::new(BTreeMap::new()),
+            stale_ipv4: RefCell::new(BTreeSet::new()),
+            sweeps: Cell::new(0),
+            route_error: RefCell::new(None),
+        }
+    }
+
+    /// Make route operations fail with the error, or succeed again with None.
+    pub fn set_route_error(&self, error: Option<&str>) {
+        self.route_error.replace(error.map(|s| s.to_string()));
+    }
+
+    /// Return error for route operation, if set.
+    fn route_result(&self) -> Result<(), KernelError> {
+        match *self.route_error.borrow() {
+            Some(ref error) => Err(KernelError::Route(error.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Inject link added or updated.
+    pub fn inject_add_link(&self, link: KernelLink) {
+        mock_call(&self.callback.borrow().add_link, link, "Add link");
+    }
+
+    /// Inject link deleted.
+    pub fn inject_delete_link(&self, link: KernelLink) {
+        mock_call(&self.callback.borrow().delete_link, link, "Delete link");
+    }
+
+    /// Inject IPv4 address added.
+    pub fn inject_add_ipv4_address(&self, addr: KernelAddr<Ipv4Addr>) {
+        mock_call(&self.callback.borrow().add_ipv4_address, addr, "Add IPv4 address");
+    }
+
+    /// Inject IPv4 address deleted.
+    pub fn inject_delete_ipv4_address(&self, addr: KernelAddr<Ipv4Addr>) {
+        mock_call(&self.callback.borrow().delete_ipv4_address, addr, "Delete IPv4 address");
+    }
+
+    /// Inject IPv6 address added.
+    pub fn inject_add_ipv6_address(&self, addr: KernelAddr<Ipv6Addr>) {
+        mock_call(&self.callback.borrow().add_ipv6_address, addr, "Add IPv6 address");
+    }
+
+    /// Inject IPv6 address deleted.
+    pub fn inject_delete_ipv6_address(&self, addr: KernelAddr<Ipv6Addr>) {
+        mock_call(&self.callback.borrow().delete_ipv6_address, addr, "Delete IPv6 address");
+    }
+
+    /// Inject IPv4 route added by others.
+    pub fn inject_add_ipv4_route(&self, route: KernelRoute<Ipv4Addr>) {
+        mock_call(&self.callback.borrow().add_ipv4_route, route, "Add IPv4 route");
+    }
+
+    /// Inject IPv4 route deleted by others.
+    pub fn inject_delete_ipv4_route(&self, route: KernelRoute<Ipv4Addr>) {
+        mock_call(&self.callback.borrow().delete_ipv4_route, route, "Delete IPv4 route");
+    }
+
+    /// Inject IPv6 route added by others.
+    pub fn inject_add_ipv6_route(&self, route: KernelRoute<Ipv6Addr>) {
+        mock_call(&self.callback.borrow().add_ipv6_route, route, "Add IPv6 route");
+    }
+
+    /// Inject IPv6 route deleted by others.
+    pub fn inject_delete_ipv6_route(&self, route: KernelRoute<Ipv6Addr>) {
+        mock_call(&self.callback.borrow().delete_ipv6_route, route, "Delete IPv6 route");
+    }
+
+    /// Inject IPv4 neighbor as if kernel notified it.
+    pub fn inject_add_ipv4_neighbor(&self, neigh: KernelNeigh<Ipv4Addr>) {
+        mock_call(&self.callback.borrow().add_ipv4_neighbor, neigh, "Add IPv4 neighbor");
+    }
+
+    /// Inject IPv4 neighbor deletion.
+    pub fn inject_delete_ipv4_neighbor(&self, neigh: KernelNeigh<Ipv4Addr>) {
+        mock_call(&self.callback.borrow().delete_ipv4_neighbor, neigh, "Delete IPv4 neighbor");
+    }
+
+    /// Inject IPv6 neighbor as if kernel notified it.
+    pub fn inject_add_ipv6_neighbor(&self, neigh: KernelNeigh<Ipv6Addr>) {
+        mock_call(&self.callback.borrow().add_ipv6_neighbor, neigh, "Add IPv6 neighbor");
+    }
+
+    /// Inject IPv6 neighbor deletion.
+    pub fn inject_delete_ipv6_neighbor(&self, neigh: KernelNeigh<Ipv6Addr>) {
+        mock_call(&self.callback.borrow().delete_ipv6_neighbor, neigh, "Delete IPv6 neighbor");
+    }
+
+    /// Inject IPv4 route left by previous instance, as found in the dump.
+    pub fn inject_stale_ipv4_route(&self, table_id: u32, prefix: &Prefix<Ipv4Addr>) {
+        self.stale_ipv4.borrow_mut().insert((table_id, prefix.clone()));
+    }
+
+    /// Return IPv4 routes left by previous instance, not replaced or deleted so far.
+    pub fn stale_ipv4_routes(&self) -> Vec<(u32, Prefix<Ipv4Addr>)> {
+        self.stale_ipv4.borrow().iter().cloned().collect()
+    }
+
+    /// Inject statistics of link.
+    pub fn inject_link_stats(&self, ifindex: i32, stats: KernelLinkStats) {
+        match &self.callback.borrow().link_stats {
+            Some(f) => (*f)(ifindex, stats),
+            None => debug!("Link statistics callback function is not set."),
+        }
+    }
+
+    /// Return IPv4 route installed for the prefix in the table.
+    pub fn ipv4_route(&self, table_id: u32, prefix: &Prefix<Ipv4Addr>) -> Option<Rib<Ipv4Addr>> {
+        self.routes_ipv4.borrow().iter()
+            .find(|((id, p, _), _)| *id == table_id && p == prefix)
+            .map(|(_, rib)| rib.clone())
+    }
+
+    /// Return IPv6 route installed for the prefix in the table.
+    pub fn ipv6_route(&self, table_id: u32, prefix: &Prefix<Ipv6Addr>) -> Option<Rib<Ipv6Addr>> {
+        self.routes_ipv6.borrow().iter()
+            .find(|((id, p, _), _)| *id == table_id && p == prefix)
+            .map(|(_, rib)| rib.clone())
+    }
+
+    /// Return number of IPv4 routes installed.
+    pub fn ipv4_route_count(&self) -> usize {
+        self.routes_ipv4.borrow().len()
+    }
+
+    /// Return number of IPv6 routes installed.
+    pub fn ipv6_route_count(&self) -> usize {
+        self.routes_ipv6.borrow().len()
+    }
+
+    /// Return incoming labels of MPLS routes installed.
+    pub fn lsps(&self) -> Vec<u32> {
+        self.lsps.borrow().iter().cloned().collect()
+    }
+
+    /// Return MTU set on the interface.
+    pub fn mtu(&self, ifindex: i32) -> Option<u32> {
+        self.mtus.borrow().get(&ifindex).cloned()
+    }
+
+    /// Return administrative state set on the interface.
+    pub fn link_state(&self, ifindex: i32) -> Option<bool> {
+        self.link_states.borrow().get(&ifindex).cloned()
+    }
+
+    /// Return number of times stale routes are swept.
+    pub fn sweeps(&self) -> u32 {
+        self.sweeps.get()
+    }
+}
+
+impl KernelDriver for MockKernelDriver {
+
+    /// Register Add Link callback function.
+    fn register_add_link(&self, f: Box<dyn Fn(KernelLink)>) {
+        self.callback.borrow_mut().add_link.replace(f);
+    }
+
+    /// Register Delete Link callback function.
+    fn register_delete_link(&self, f: Box<dyn Fn(KernelLink)>) {
+        self.callback.borrow_mut().delete_link.replace(f);
+    }
+
+    /// Register Add IPv4 Address callback function.
+    fn register_add_ipv4_address(&self, f: Box<dyn Fn(KernelAddr<Ipv4Addr>)>) {
+        self.callback.borrow_mut().add_ipv4_address.replace(f);
+    }
+
+    /// Register Delete IPv4 Address callback function.
+    fn register_delete_ipv4_address(&self, f: Box<dyn Fn(KernelAddr<Ipv4Addr>)>) {
+        self.callback.borrow_mut().delete_ipv4_address.replace(f);
+    }
+
+    /// Register Add IPv6 Address callback function.
+    fn register_add_ipv6_address(&self, f: Box<dyn Fn(KernelAddr<Ipv6Addr>)>) {
+        self.callback.borrow_mut().add_ipv6_address.replace(f);
+    }
+
+    /// Register Delete IPv6 Address callback function.
+    fn register_delete_ipv6_address(&self, f: Box<dyn Fn(KernelAddr<Ipv6Addr>)>) {
+        self.callback.borrow_mut().delete_ipv6_address.replace(f);
+    }
+
+    /// Register Add IPv4 route callback function.
+    fn register_add_ipv4_route(&self, f: Box<dyn Fn(KernelRoute<Ipv4Addr>)>) {
+        self.callback.borrow_mut().add_ipv4_route.replace(f);
+    }
+
+    /// Register Delete IPv4 route callback function.
+    fn register_delete_ipv4_route(&self, f: Box<dyn Fn(KernelRoute<Ipv4Addr>)>) {
+        self.callback.borrow_mut().delete_ipv4_route.replace(f);
+    }
+
+    /// Register Add IPv6 route callback function.
+    fn register_add_ipv6_route(&self, f: Box<dyn Fn(KernelRoute<Ipv6Addr>)>) {
+        self.callback.borrow_mut().add_ipv6_route.replace(f);
+    }
+
+    /// Register Delete IPv6 route callback function.
+    fn register_delete_ipv6_route(&self, f: Box<dyn Fn(KernelRoute<Ipv6Addr>)>) {
+        self.callback.borrow_mut().delete_ipv6_route.replace(f);
+    }
+
+    /// Register Add IPv4 neighbor callback function.
+    fn register_add_ipv4_neighbor(&self, f: Box<dyn Fn(KernelNeigh<Ipv4Addr>)>) {
+        self.callback.borrow_mut().add_ipv4_neighbor.replace(f);
+    }
+
+    /// Register Delete IPv4 neighbor callback function.
+    fn register_delete_ipv4_neighbor(&self, f: Box<dyn Fn(KernelNeigh<Ipv4Addr>)>) {
+        self.callback.borrow_mut().delete_ipv4_neighbor.replace(f);
+    }
+
+    /// Register Add IPv6 neighbor callback function.
+    fn register_add_ipv6_neighbor(&self, f: Box<dyn Fn(KernelNeigh<Ipv6Addr>)>) {
+        self.callback.borrow_mut().add_ipv6_neighbor.replace(f);
+    }
+
+    /// Register Delete IPv6 neighbor callback function.
+    fn register_delete_ipv6_neighbor(&self, f: Box<dyn Fn(KernelNeigh<Ipv6Addr>)>) {
+        self.callback.borrow_mut().delete_ipv6_neighbor.replace(f);
+    }
+
+    /// Results are returned synchronously, no need to register.
+    fn register_ipv4_fib_result(&self, _f: Box<dyn Fn(KernelFibResult<Ipv4Addr>)>) {
+    }
+
+    /// Results are returned synchronously, no need to register.
+    fn register_ipv6_fib_result(&self, _f: Box<dyn Fn(KernelFibResult<Ipv6Addr>)>) {
+    }
+
+    /// Register Link statistics callback function.
+    fn register_link_stats(&self, f: Box<dyn Fn(i32, KernelLinkStats)>) {
+        self.callback.borrow_mut().link_stats.replace(f);
+    }
+
+
+    /// Events are injected, nothing to watch.
+    fn event_fd(&self) -> Option<RawFd> {
+        None
+    }
+
+    /// Process kernel events.
+    fn process_event(&self) -> Result<(), KernelError> {
+        Ok(())
+    }
+
+    /// Routes are programmed synchronously, nothing to watch.
+    fn fib_fd(&self) -> Option<RawFd> {
+        None
+    }
+
+    /// Send route messages queued so far.
+    fn fib_flush(&self) -> Result<(), KernelError> {
+        Ok(())
+    }
+
+    /// Process FIB results.
+    fn process_fib_ack(&self) -> Result<(), KernelError> {
+        Ok(())
+    }
+
+    /// Delete stale routes not replaced so far, and count sweeps.
+    fn fib_sweep(&self) -> Result<(), KernelError> {
+        self.stale_ipv4.borrow_mut().clear();
+        self.sweeps.set(self.sweeps.get() + 1);
+        Ok(())
+    }
+
+
+    /// Nothing to retrieve, links are injected.
+    fn get_link_all(&self) -> Result<(), KernelError> {
+        Ok(())
+    }
+
+    /// Nothing to retrieve, statistics are injected.
+    fn get_link_stats_all(&self) -> Result<(), KernelError> {
+        Ok(())
+    }
+
+    /// Set MTU.
+    fn set_mtu(&self, ifindex: i32, mtu: u32) -> Result<(), KernelError> {
+        self.mtus.borrow_mut().insert(ifindex, mtu);
+        Ok(())
+    }
+
+    /// Set link up.
+    fn set_link_up(&self, ifindex: i32) -> Result<(), KernelError> {
+        self.link_states.borrow_mut().insert(ifindex, true);
+        Ok(())
+    }
+
+    /// Set link down.
+    fn set_link_down(&self, ifindex: i32) -> Result<(), KernelError> {
+        self.link_states.borrow_mut().insert(ifindex, false);
+        Ok(())
+    }
+
+
+    /// Address is not notified back, tests inject it.
+    fn add_ipv4_address(&self, _ifindex: i32, _prefix: &Prefix<Ipv4Addr>, _label: Option<&str>) -> Result<(), KernelError> {
+        Ok(())
+    }
+
+    /// Address is not notified back, tests inject it.
+    fn delete_ipv4_address(&self, _ifindex: i32, _prefix: &Prefix<Ipv4Addr>) -> Result<(), KernelError> {
+        Ok(())
+    }
+
+    /// Address is not notified back, tests inject it.
+    fn add_ipv6_address(&self, _ifindex: i32, _prefix: &Prefix<Ipv6Addr>) -> Result<(), KernelError> {
+        Ok(())
+    }
+
+    /// Address is not notified back, tests inject it.
+    fn delete_ipv6_address(&self, _ifindex: i32, _prefix: &Prefix<Ipv6Addr>) -> Result<(), KernelError> {
+        Ok(())
+    }
+
+
+    /// Nothing to retrieve, addresses are injected.
+    fn get_ipv4_address_all(&self) -> Result<(), KernelError> {
+        Ok(())
+    }
+
+    /// Nothing to retrieve, addresses are injected.
+    fn get_ipv6_address_all(&self) -> Result<(), KernelError> {
+        Ok(())
+    }
+
+
+    /// Nothing to retrieve, neighbors are injected.
+    fn get_ipv4_neighbor_all(&self) -> Result<(), KernelError> {
+        Ok(())
+    }
+
+    /// Nothing to retrieve, neighbors are injected.
+    fn get_ipv6_neighbor_all(&self) -> Result<(), KernelError> {
+        Ok(())
+    }
+
+
+    /// Nothing to retrieve, routes are injected.
+    fn get_ipv4_route_all(&self) -> Result<(), KernelError> {
+        Ok(())
+    }
+
+    /// Nothing to retrieve, routes are injected.
+    fn get_ipv6_route_all(&self) -> Result<(), KernelError> {
+        Ok(())
+    }
+
+
+    /// Record an IPv4 route, replacing the one with the same metric.
+    fn add_ipv4_route(&self, table_id: u32, prefix: &Prefix<Ipv4Addr>, rib: &Rib<Ipv4Addr>) -> Result<(), KernelError> {
+        self.route_result()?;
+        self.routes_ipv4.borrow_mut().insert((table_id, prefix.clone(), rib.metric()), rib.clone());
+        self.stale_ipv4.borrow_mut().remove(&(table_id, prefix.clone()));
+        Ok(())
+    }
+
+    /// Remove an IPv4 route.
+    fn delete_ipv4_route(&self, table_id: u32, prefix: &Prefix<Ipv4Addr>, rib: &Rib<Ipv4Addr>) -> Result<(), KernelError> {
+        self.route_result()?;
+        self.stale_ipv4.borrow_mut().remove(&(table_id, prefix.clone()));
+        match self.routes_ipv4.borrow_mut().remove(&(table_id, prefix.clone(), rib.metric())) {
+            Some(_) => Ok(()),
+            None => Err(KernelError::Route(format!("No such route {}", prefix))),
+        }
+    }
+
+    /// Record an IPv6 route, replacing the one with the same metric.
+    fn add_ipv6_route(&self, table_id: u32, prefix: &Prefix<Ipv6Addr>, rib: &Rib<Ipv6Addr>) -> Result<(), KernelError> {
+        self.route_result()?;
+        self.routes_ipv6.borrow_mut().insert((table_id, prefix.clone(), rib.metric()), rib.clone());
+        Ok(())
+    }
+
+    /// Remove an IPv6 route.
+    fn delete_ipv6_route(&self, table_id: u32, prefix: &Prefix<Ipv6Addr>, rib: &Rib<Ipv6Addr>) -> Result<(), KernelError> {
+        self.route_result()?;
+        match self.routes_ipv6.borrow_mut().remove(&(table_id, prefix.clone(), rib.metric())) {
+            Some(_) => Ok(()),
+            None => Err(KernelError::Route(format!("No such route {}", prefix))),
+        }
+    }
+
+    /// Record an MPLS route.
+    fn add_mpls_route(&self, lsp: &Lsp) -> Result<(), KernelError> {
+        self.route_result()?;
+        self.lsps.borrow_mut().insert(lsp.in_label());
+        Ok(())
+    }
+
+    /// Remove an MPLS route.
+    fn delete_mpls_route(&self, lsp: &Lsp) -> Result<(), KernelError> {
+        self.route_result()?;
+        self.lsps.borrow_mut().remove(&lsp.in_label());
+        Ok(())
+    }
+}
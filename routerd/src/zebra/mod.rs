@@ -8,12 +8,20 @@
 pub mod error;
 pub mod master;
 pub mod link;
+pub mod neighbor;
+pub mod interface;
 pub mod address;   
 pub mod route;
 pub mod rib;
 pub mod static_route;
+pub mod vrf;
+pub mod mpls;
+pub mod client;
+pub mod nht;
+pub mod graceful_restart;
 
 pub mod kernel;
+pub mod mock;
 
 #[cfg(target_os = "linux")]
 pub mod linux;
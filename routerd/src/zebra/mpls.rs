@@ -0,0 +1,244 @@
+//
+// ReZe.Rs - Router Daemon
+//   Copyright (C) 2018-2020 Toshiaki Takada
+//
+// Zebra - MPLS label switched paths.
+//
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::cell::RefMut;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+use log::debug;
+use serde::Serialize;
+use serde::Serializer;
+use serde::ser::SerializeStruct;
+use serde_json;
+
+use common::error::*;
+use common::mpls::*;
+
+use crate::core::mds::*;
+use super::master::ZebraMaster;
+
+/// Nexthop of LSP, labeled packet is forwarded to gateway of either address family.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct LspNexthop {
+
+    /// Gateway address.
+    pub address: IpAddr,
+
+    /// Outgoing interface name, kernel looks it up by gateway if not given.
+    pub ifname: Option<String>,
+}
+
+/// Label switched path, an entry of MPLS label table keyed by incoming label.
+pub struct Lsp {
+
+    /// Incoming label.
+    in_label: u32,
+
+    /// Label operation per nexthop.
+    nexthops: RefCell<BTreeMap<LspNexthop, LabelOp>>,
+}
+
+impl Lsp {
+
+    /// Constructor.
+    pub fn new(in_label: u32) -> Lsp {
+        Lsp {
+            in_label: in_label,
+            nexthops: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn in_label(&self) -> u32 {
+        self.in_label
+    }
+
+    pub fn nexthops(&self) -> RefMut<BTreeMap<LspNexthop, LabelOp>> {
+        self.nexthops.borrow_mut()
+    }
+}
+
+/// Serializer for Lsp.
+impl Serialize for Lsp {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer
+    {
+        let mut s = serializer.serialize_struct("Lsp", 2)?;
+        let nexthops: Vec<serde_json::Value> = self.nexthops.borrow().iter()
+            .map(|(nexthop, op)| serde_json::json!({
+                "address": nexthop.address.to_string(),
+                "interface": nexthop.ifname,
+                "op": op.to_string(),
+            }))
+            .collect();
+
+        s.serialize_field("in_label", &self.in_label)?;
+        s.serialize_field("nexthops", &nexthops)?;
+        s.end()
+    }
+}
+
+/// Static LSP configs.
+pub struct StaticLsp {
+
+    /// Zebra master.
+    master: Rc<ZebraMaster>,
+}
+
+impl StaticLsp {
+
+    /// Constructor.
+    pub fn new(master: Rc<ZebraMaster>) -> StaticLsp {
+        StaticLsp {
+            master: master,
+        }
+    }
+
+    /// Parse incoming label and nexthop from path.
+    fn parse_path(&self, path: &str) -> Result<(u32, IpAddr), CoreError> {
+        let pat = "/config/mpls/lsp";
+        if !path.starts_with(pat) {
+            return Err(CoreError::CommandExec(format!("Invalid path")));
+        }
+
+        let (label_str, address_str) = match split_id_and_path(&path[pat.len()..]) {
+            Some((label_str, Some(address_str))) => (label_str, address_str),
+            _ => return Err(CoreError::CommandExec(format!("Invalid path"))),
+        };
+
+        let in_label = match label_str.parse::<u32>() {
+            Ok(label) if label >= MPLS_LABEL_UNRESERVED_MIN && label <= MPLS_LABEL_MAX => label,
+            _ => return Err(CoreError::CommandExec(format!("Invalid incoming label {}", label_str))),
+        };
+
+        // Trim leading "/" from address_str.
+        match address_str[1..].parse::<IpAddr>() {
+            Ok(address) => Ok((in_label, address)),
+            Err(_) => Err(CoreError::CommandExec(format!("Invalid nexthop {}", &address_str[1..]))),
+        }
+    }
+}
+
+impl MdsHandler for StaticLsp {
+
+    /// Handle PUT method.
+    ///   /config/mpls/lsp/IN_LABEL/NEXTHOP with {"label": OUT_LABEL, "interface": IFNAME},
+    ///   where OUT_LABEL is label stack such as "100/200", or "implicit-null" to pop.
+    fn handle_put(&self, path: &str, params: Option<Box<String>>) -> Result<Option<String>, CoreError> {
+        let (in_label, address) = self.parse_path(path)?;
+
+        let json: serde_json::Value = match params {
+            Some(json_str) => match serde_json::from_str(&json_str) {
+                Ok(json) => json,
+                Err(_) => return Err(CoreError::CommandExec(format!("Invalid JSON"))),
+            },
+            None => return Err(CoreError::CommandExec(format!("No parameters"))),
+        };
+
+        // Label could be given as number or string.
+        let label_str = match json.get("label") {
+            Some(v) => match v.as_u64() {
+                Some(v) => v.to_string(),
+                None => v.as_str().unwrap_or("").to_string(),
+            },
+            None => return Err(CoreError::CommandExec(format!("No outgoing label"))),
+        };
+
+        let op = match LabelOp::from_out_label_str(&label_str) {
+            // Explicit null differs per address family.
+            Some(LabelOp::Swap(ref labels)) if address.is_ipv6() && labels == &[MPLS_LABEL_IPV4_EXPLICIT_NULL] => {
+                LabelOp::Swap(vec![MPLS_LABEL_IPV6_EXPLICIT_NULL])
+            },
+            Some(op) => op,
+            None => return Err(CoreError::CommandExec(format!("Invalid outgoing label {}", label_str))),
+        };
+
+        let nexthop = LspNexthop {
+            address: address,
+            ifname: json["interface"].as_str().map(|ifname| ifname.to_string()),
+        };
+
+        debug!("Configuring static LSP {} nexthop {:?} {}", in_label, nexthop, op);
+
+        self.master.lsp_add(in_label, nexthop, op)?;
+
+        Ok(None)
+    }
+
+    /// Handle DELETE method.
+    fn handle_delete(&self, path: &str, params: Option<Box<String>>) -> Result<Option<String>, CoreError> {
+        let (in_label, address) = self.parse_path(path)?;
+
+        let ifname = match params {
+            Some(json_str) => match serde_json::from_str::<serde_json::Value>(&json_str) {
+                Ok(json) => json["interface"].as_str().map(|ifname| ifname.to_string()),
+                Err(_) => return Err(CoreError::CommandExec(format!("Invalid JSON"))),
+            },
+            None => None,
+        };
+
+        let nexthop = LspNexthop {
+            address: address,
+            ifname: ifname,
+        };
+
+        debug!("Unconfiguring static LSP {} nexthop {:?}", in_label, nexthop);
+
+        self.master.lsp_delete(in_label, &nexthop)?;
+
+        Ok(None)
+    }
+}
+
+/// Show MPLS label table.
+pub struct ShowMpls {
+
+    /// Zebra master.
+    master: Rc<ZebraMaster>,
+}
+
+impl ShowMpls {
+
+    /// Constructor.
+    pub fn new(master: Rc<ZebraMaster>) -> ShowMpls {
+        ShowMpls {
+            master: master,
+        }
+    }
+}
+
+impl MdsHandler for ShowMpls {
+
+    /// Handle GET method.
+    ///   /exec/show/mpls/table returns all LSPs, /exec/show/mpls/table/IN_LABEL returns one.
+    fn handle_get(&self, path: &str, _params: Option<Box<String>>) -> Result<Option<String>, CoreError> {
+        let pat = "/exec/show/mpls/table";
+        if !path.starts_with(pat) {
+            return Err(CoreError::CommandExec(format!("Invalid path")));
+        }
+
+        let s = match split_id_and_path(&path[pat.len()..]) {
+            Some((label_str, _)) => {
+                let lsp = match label_str.parse::<u32>().ok().and_then(|label| self.master.lsp_lookup(label)) {
+                    Some(lsp) => lsp,
+                    None => return Err(CoreError::CommandExec(format!("No such LSP {}", label_str))),
+                };
+
+                serde_json::to_string(&*lsp).unwrap()
+            },
+            None => {
+                let lsps = self.master.lsps();
+                let lsps: Vec<&Lsp> = lsps.iter().map(|lsp| &**lsp).collect();
+
+                serde_json::to_string(&lsps).unwrap()
+            },
+        };
+
+        Ok(Some(s))
+    }
+}
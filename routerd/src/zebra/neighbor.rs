@@ -0,0 +1,321 @@
+//
+// ReZe.Rs - Router Daemon
+//   Copyright (C) 2018-2020 Toshiaki Takada
+//
+// Zebra - Neighbor table.
+//   ARP and IPv6 ND entries are mirrored from kernel per link.
+//
+
+use std::rc::Rc;
+use std::cell::Ref;
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::time;
+
+use serde::Serialize;
+use serde::Serializer;
+use serde::ser::SerializeStruct;
+use serde_json;
+use rtable::prefix::*;
+
+use common::error::*;
+use common::nexthop::*;
+
+use crate::core::mds::*;
+use crate::core::message::zebra::ZebraNeighbor;
+
+use super::master::ZebraMaster;
+use super::link::Link;
+use super::kernel::*;
+
+/// Neighbor, ARP or IPv6 ND entry.
+pub struct Neighbor<T: Addressable> {
+
+    /// Neighbor address.
+    address: T,
+
+    /// Link layer address, if resolved.
+    hwaddr: Option<[u8; 6]>,
+
+    /// State.
+    state: NeighState,
+
+    /// Neighbor is a router.
+    router: bool,
+
+    /// Time updated last.
+    instant: time::Instant,
+}
+
+impl<T: Addressable> Neighbor<T> {
+
+    /// Construct from KernelNeigh.
+    pub fn from_kernel(kn: KernelNeigh<T>) -> Neighbor<T> {
+        Neighbor {
+            address: kn.address,
+            hwaddr: kn.hwaddr,
+            state: kn.state,
+            router: kn.router,
+            instant: time::Instant::now(),
+        }
+    }
+
+    pub fn address(&self) -> &T {
+        &self.address
+    }
+
+    pub fn hwaddr(&self) -> Option<[u8; 6]> {
+        self.hwaddr
+    }
+
+    pub fn state(&self) -> NeighState {
+        self.state
+    }
+
+    pub fn is_router(&self) -> bool {
+        self.router
+    }
+
+    /// Return true if packets can be forwarded to the neighbor.
+    pub fn is_reachable(&self) -> bool {
+        self.state.is_valid()
+    }
+}
+
+/// Serializer for Neighbor.
+impl<T: Addressable> Serialize for Neighbor<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer
+    {
+        let mut s = serializer.serialize_struct("Neighbor", 5)?;
+        let hwaddr_str = self.hwaddr.map(|hwaddr| hwaddr.iter()
+            .map(|b| format!("{:02x}", b)).collect::<Vec<String>>().join(":"));
+
+        s.serialize_field("address", &self.address.to_string())?;
+        s.serialize_field("hwaddr", &hwaddr_str)?;
+        s.serialize_field("state", &format!("{:?}", self.state))?;
+        s.serialize_field("router", &self.router)?;
+        s.serialize_field("age", &self.instant.elapsed().as_secs())?;
+        s.end()
+    }
+}
+
+/// Neighbor table of a link per address family.
+pub struct NeighborTable<T: Addressable> {
+
+    /// Neighbors per address.
+    entries: BTreeMap<T, Neighbor<T>>,
+}
+
+impl<T: Addressable> NeighborTable<T> {
+
+    /// Constructor.
+    pub fn new() -> NeighborTable<T> {
+        NeighborTable {
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// Add neighbor, or update existing one with the same address.
+    pub fn add(&mut self, kn: KernelNeigh<T>) {
+        self.entries.insert(kn.address.clone(), Neighbor::from_kernel(kn));
+    }
+
+    /// Delete neighbor, return deleted one if any.
+    pub fn delete(&mut self, address: &T) -> Option<Neighbor<T>> {
+        self.entries.remove(address)
+    }
+
+    /// Lookup neighbor by address.
+    pub fn lookup(&self, address: &T) -> Option<&Neighbor<T>> {
+        self.entries.get(address)
+    }
+
+    /// Return number of neighbors.
+    pub fn count(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Return neighbors sorted by address.
+    pub fn iter(&self) -> impl Iterator<Item = &Neighbor<T>> {
+        self.entries.values()
+    }
+}
+
+/// Resolve nexthop to neighbor on links, only on the link if nexthop has interface name.
+pub fn neighbor_resolve<T, F>(nexthop: &Nexthop<T>, links: &[Rc<Link>], table: F) -> ZebraNeighbor<T>
+where T: Addressable,
+      F: for<'a> Fn(&'a Link) -> Ref<'a, NeighborTable<T>>
+{
+    let mut state = ZebraNeighbor {
+        nexthop: nexthop.clone(),
+        index: None,
+        hwaddr: None,
+        reachable: false,
+    };
+
+    let address = match nexthop.address() {
+        Some(address) => address,
+        None => return state,
+    };
+
+    let links = links.iter().filter(|link| match nexthop.ifname() {
+        Some(ifname) => link.name() == ifname,
+        None => true,
+    });
+
+    // Reachable one is preferred, address might be seen on multiple links.
+    for link in links {
+        if let Some(neigh) = table(link).lookup(address) {
+            let reachable = link.is_up() && neigh.is_reachable();
+
+            if state.index.is_none() || reachable {
+                state.index = Some(link.index());
+                state.hwaddr = neigh.hwaddr();
+                state.reachable = reachable;
+            }
+
+            if reachable {
+                break;
+            }
+        }
+    }
+
+    state
+}
+
+/// Return neighbors on links as JSON, with interface name.
+fn neighbor_json<T, F>(links: &[Rc<Link>], table: F) -> String
+where T: Addressable,
+      F: for<'a> Fn(&'a Link) -> Ref<'a, NeighborTable<T>>
+{
+    let mut list = Vec::new();
+
+    for link in links {
+        for neigh in table(link).iter() {
+            let mut v = serde_json::to_value(neigh).unwrap();
+            v["interface"] = serde_json::Value::String(link.name());
+            list.push(v);
+        }
+    }
+
+    serde_json::to_string(&list).unwrap()
+}
+
+/// Show ARP and IPv6 neighbor tables.
+pub struct ShowNeighbor {
+
+    /// Zebra master.
+    master: Rc<ZebraMaster>,
+}
+
+impl ShowNeighbor {
+
+    /// Constructor.
+    pub fn new(master: Rc<ZebraMaster>) -> ShowNeighbor {
+        ShowNeighbor {
+            master: master,
+        }
+    }
+}
+
+impl MdsHandler for ShowNeighbor {
+
+    /// Handle GET method.
+    ///   /exec/show/arp and /exec/show/ipv6/neighbors return neighbors on all links,
+    ///   or on one link with /IFNAME.
+    fn handle_get(&self, path: &str, _params: Option<Box<String>>) -> Result<Option<String>, CoreError> {
+        let (ipv4, rest) = if path.starts_with("/exec/show/arp") {
+            (true, &path["/exec/show/arp".len()..])
+        } else if path.starts_with("/exec/show/ipv6/neighbors") {
+            (false, &path["/exec/show/ipv6/neighbors".len()..])
+        } else {
+            return Err(CoreError::CommandExec(format!("Invalid path")));
+        };
+
+        let links = match split_id_and_path(rest) {
+            Some((ifname, _)) => match self.master.link_lookup_by_name(&ifname) {
+                Some(link) => vec![link],
+                None => return Err(CoreError::CommandExec(format!("No such interface {}", ifname))),
+            },
+            None => self.master.links(),
+        };
+
+        let s = if ipv4 {
+            neighbor_json::<Ipv4Addr, _>(&links, Link::neigh4)
+        } else {
+            neighbor_json::<Ipv6Addr, _>(&links, Link::neigh6)
+        };
+
+        Ok(Some(s))
+    }
+}
+
+///
+/// Unit tests for neighbor table.
+///
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use super::*;
+    use super::super::kernel::KernelLink;
+
+    fn link(index: i32, name: &str, flags: u32) -> Rc<Link> {
+        let mut kl = KernelLink::new(index, name, 1, [0, 1, 2, 3, 4, 5], 1500);
+        kl.flags = flags;
+        Rc::new(Link::from_kernel(kl))
+    }
+
+    fn neigh(index: i32, address: &str, state: NeighState) -> KernelNeigh<Ipv4Addr> {
+        let address = Ipv4Addr::from_str(address).unwrap();
+        KernelNeigh::new(index, address, Some([2, 0, 0, 0, 0, index as u8]), state)
+    }
+
+    #[test]
+    pub fn test_neighbor_table() {
+        let mut table = NeighborTable::<Ipv4Addr>::new();
+        let address = Ipv4Addr::from_str("10.0.0.2").unwrap();
+
+        table.add(neigh(2, "10.0.0.2", NeighState::Incomplete));
+        assert!(!table.lookup(&address).unwrap().is_reachable());
+
+        table.add(neigh(2, "10.0.0.2", NeighState::Stale));
+        assert_eq!(table.count(), 1);
+        assert!(table.lookup(&address).unwrap().is_reachable());
+
+        let json: serde_json::Value = serde_json::to_value(table.lookup(&address).unwrap()).unwrap();
+        assert_eq!(json["address"], "10.0.0.2");
+        assert_eq!(json["hwaddr"], "02:00:00:00:00:02");
+        assert_eq!(json["state"], "Stale");
+
+        assert!(table.delete(&address).is_some());
+        assert!(table.lookup(&address).is_none());
+    }
+
+    #[test]
+    pub fn test_neighbor_resolve() {
+        let up = (libc::IFF_UP | libc::IFF_RUNNING) as u32;
+        let links = vec![link(2, "eth0", up), link(3, "eth1", up)];
+        let address = Ipv4Addr::from_str("10.0.0.2").unwrap();
+
+        links[0].add_ipv4_neighbor(neigh(2, "10.0.0.2", NeighState::Failed));
+        links[1].add_ipv4_neighbor(neigh(3, "10.0.0.2", NeighState::Reachable));
+
+        let state = neighbor_resolve(&Nexthop::from_address(&address), &links, Link::neigh4);
+        assert_eq!(state.index, Some(3));
+        assert_eq!(state.hwaddr, Some([2, 0, 0, 0, 0, 3]));
+        assert!(state.reachable);
+
+        let state = neighbor_resolve(&Nexthop::from_address_ifname(&address, "eth0"), &links, Link::neigh4);
+        assert_eq!(state.index, Some(2));
+        assert!(!state.reachable);
+
+        let other = Ipv4Addr::from_str("10.0.0.3").unwrap();
+        let state = neighbor_resolve(&Nexthop::from_address(&other), &links, Link::neigh4);
+        assert_eq!(state.index, None);
+        assert!(!state.reachable);
+
+        let state = neighbor_resolve(&Nexthop::<Ipv4Addr>::from_ifname("eth1"), &links, Link::neigh4);
+        assert!(!state.reachable);
+    }
+}
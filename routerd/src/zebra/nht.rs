@@ -0,0 +1,219 @@
+//
+// ReZe.Rs - Router Daemon
+//   Copyright (C) 2018-2020 Toshiaki Takada
+//
+// Zebra - Nexthop tracking.
+//   Protocols register addresses, and are notified how they are resolved through RIB.
+//
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+use log::debug;
+use rtable::prefix::*;
+
+use crate::core::protocols::ProtocolType;
+use crate::core::message::zebra::ZebraNexthop;
+
+use super::rib::RibTable;
+use super::rib::prefix_range;
+
+/// Tracked address, with registered protocols and last notified resolution.
+struct NexthopTrack<T: Addressable> {
+
+    /// Protocols registered the address.
+    protos: BTreeSet<ProtocolType>,
+
+    /// Resolution notified last time.
+    state: ZebraNexthop<T>,
+}
+
+/// Nexthop tracker per address family.
+pub struct NexthopTracker<T: Addressable> {
+
+    /// Tracked addresses.
+    tracks: BTreeMap<T, NexthopTrack<T>>,
+}
+
+impl<T> NexthopTracker<T>
+where T: Addressable
+{
+    /// Constructor.
+    pub fn new() -> NexthopTracker<T> {
+        NexthopTracker {
+            tracks: BTreeMap::new(),
+        }
+    }
+
+    /// Return number of tracked addresses.
+    pub fn count(&self) -> usize {
+        self.tracks.len()
+    }
+
+    /// Register address for protocol, return current resolution to be notified to it.
+    pub fn register(&mut self, proto: ProtocolType, address: &T, table: &RibTable<T>) -> ZebraNexthop<T> {
+        debug!("NHT register {} by {}", address, proto);
+
+        let track = self.tracks.entry(address.clone()).or_insert_with(|| NexthopTrack {
+            protos: BTreeSet::new(),
+            state: resolve(address, table),
+        });
+
+        track.protos.insert(proto);
+        track.state.clone()
+    }
+
+    /// Unregister all addresses for protocol.
+    pub fn unregister_proto(&mut self, proto: ProtocolType) {
+        let addresses: Vec<T> = self.tracks.iter()
+            .filter(|(_, track)| track.protos.contains(&proto))
+            .map(|(address, _)| address.clone())
+            .collect();
+
+        for address in addresses {
+            self.unregister(proto, &address);
+        }
+    }
+
+    /// Unregister address for protocol, stop tracking when no protocol is interested.
+    pub fn unregister(&mut self, proto: ProtocolType, address: &T) {
+        debug!("NHT unregister {} by {}", address, proto);
+
+        let unused = match self.tracks.get_mut(address) {
+            Some(track) => {
+                track.protos.remove(&proto);
+                track.protos.is_empty()
+            },
+            None => false,
+        };
+
+        if unused {
+            self.tracks.remove(address);
+        }
+    }
+
+    /// Resolve tracked addresses covered by changed prefixes again, return changed resolutions
+    /// with protocols to be notified. An address resolving through a prefix is covered by it.
+    pub fn evaluate(&mut self, changed: &[Prefix<T>], table: &RibTable<T>) -> Vec<(Vec<ProtocolType>, ZebraNexthop<T>)> {
+        let mut v = Vec::new();
+
+        for prefix in changed {
+            let (first, last) = prefix_range(prefix);
+
+            for (address, track) in self.tracks.range_mut(first..=last) {
+                let state = resolve(address, table);
+
+                if state != track.state {
+                    debug!("NHT {} resolved through {:?}", address, state.prefix);
+
+                    track.state = state.clone();
+                    v.push((track.protos.iter().cloned().collect(), state));
+                }
+            }
+        }
+
+        v
+    }
+}
+
+/// Resolve address through longest match route in RIB table.
+fn resolve<T: Addressable>(address: &T, table: &RibTable<T>) -> ZebraNexthop<T> {
+    let mut state = ZebraNexthop::<T> {
+        address: address.clone(),
+        prefix: None,
+        rib_type: None,
+        metric: 0,
+        nexthops: Vec::new(),
+    };
+
+    if let Some(entry) = table.lookup_longest(address, None) {
+        if let Some(ref fib) = *entry.fib() {
+            state.prefix = Some(entry.prefix().clone());
+            state.rib_type = Some(fib.rib_type());
+            state.metric = fib.metric();
+            state.nexthops = fib.resolved().clone();
+        }
+    }
+
+    state
+}
+
+///
+/// Unit tests for nexthop tracking.
+///
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use common::nexthop::*;
+    use crate::zebra::rib::*;
+
+    fn add_rib(table: &mut RibTable<Ipv4Addr>, prefix: &str, rib_type: RibType, nexthop: Nexthop<Ipv4Addr>) -> Prefix<Ipv4Addr> {
+        let p = Prefix::<Ipv4Addr>::from_str(prefix).unwrap();
+        let rib = Rib::<Ipv4Addr>::new(rib_type, 0);
+
+        if let Some(ifname) = nexthop.ifname() {
+            table.link_set(ifname, true);
+        }
+        rib.add_nexthop(nexthop);
+        table.add(&p, rib);
+        table.process(&p, |_, e| { e.select() });
+        p
+    }
+
+    fn delete_rib(table: &mut RibTable<Ipv4Addr>, prefix: &Prefix<Ipv4Addr>, rib_type: RibType) {
+        table.withdraw(prefix, rib_type);
+        table.process(prefix, |_, e| { e.select() });
+    }
+
+    #[test]
+    pub fn test_nht_evaluate() {
+        let mut table = RibTable::<Ipv4Addr>::new();
+        let mut nht = NexthopTracker::<Ipv4Addr>::new();
+        let address = Ipv4Addr::new(10, 1, 0, 1);
+
+        // Unreachable at first.
+        let state = nht.register(ProtocolType::Bgp, &address, &table);
+        assert_eq!(state.prefix, None);
+        assert!(nht.evaluate(&[], &table).is_empty());
+
+        let p1 = add_rib(&mut table, "10.0.0.0/8", RibType::Connected, Nexthop::from_ifname("eth0"));
+        let v = nht.evaluate(&[p1.clone()], &table);
+        assert_eq!(v.len(), 1);
+        assert_eq!(v[0].0, vec![ProtocolType::Bgp]);
+        assert_eq!(v[0].1.prefix, Some(p1.clone()));
+        assert_eq!(v[0].1.rib_type, Some(RibType::Connected));
+        assert_eq!(v[0].1.nexthops, vec![Nexthop::from_ifname("eth0")]);
+
+        // More specific route takes over.
+        let p2 = add_rib(&mut table, "10.1.0.0/16", RibType::Ospf, Nexthop::from_ifname("eth1"));
+        let v = nht.evaluate(&[p2.clone()], &table);
+        assert_eq!(v.len(), 1);
+        assert_eq!(v[0].1.prefix, Some(p2.clone()));
+
+        // Unrelated change is not notified.
+        let p3 = add_rib(&mut table, "192.168.0.0/16", RibType::Static, Nexthop::from_ifname("eth2"));
+        assert!(nht.evaluate(&[p3.clone()], &table).is_empty());
+
+        // Only addresses covered by changed prefixes are resolved again.
+        let other = Ipv4Addr::new(192, 168, 1, 1);
+        nht.register(ProtocolType::Bgp, &other, &table);
+        delete_rib(&mut table, &p3, RibType::Static);
+        assert!(nht.evaluate(&[p2.clone()], &table).is_empty());
+        assert_eq!(nht.evaluate(&[p3.clone()], &table).len(), 1);
+        nht.unregister(ProtocolType::Bgp, &other);
+
+        delete_rib(&mut table, &p2, RibType::Ospf);
+        delete_rib(&mut table, &p1, RibType::Connected);
+        let v = nht.evaluate(&[p2.clone(), p1.clone()], &table);
+        assert_eq!(v.len(), 1);
+        assert_eq!(v[0].1.prefix, None);
+
+        // Tracking stops when the last protocol unregisters.
+        nht.register(ProtocolType::Ospf, &address, &table);
+        nht.unregister(ProtocolType::Bgp, &address);
+        assert_eq!(nht.count(), 1);
+        nht.unregister(ProtocolType::Ospf, &address);
+        assert_eq!(nht.count(), 0);
+    }
+}
@@ -7,6 +7,7 @@
 
 use std::time;
 use std::fmt::Debug;
+use std::str::FromStr;
 use std::cell::Cell;
 use std::cell::RefCell;
 use std::cell::RefMut;
@@ -14,6 +15,9 @@ use std::rc::Rc;
 use std::sync::Arc;
 use std::collections::HashMap;
 use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::VecDeque;
+use std::net::Ipv4Addr;
 
 use log::debug;
 use serde::Serialize;
@@ -28,10 +32,21 @@ use rtable::tree::*;
 
 use common::error::*;
 use common::nexthop::*;
+use common::mpls::*;
 
 use super::master::*;
+use super::kernel::KernelRoute;
+use super::kernel::FibFailure;
 use super::static_route::*;
+use super::vrf::Vrf;
 use super::super::core::mds::*;
+use super::super::core::message::zebra::ZebraRoute;
+
+/// Administrative distance for routes learned from kernel.
+const ZEBRA_KERNEL_DISTANCE: u8 = 0;
+
+/// Maximum depth of recursive nexthop resolution.
+const ZEBRA_NEXTHOP_RESOLVE_DEPTH_MAX: u8 = 8;
 
 /// RIB type.
 #[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Debug)]
@@ -47,6 +62,51 @@ pub enum RibType {
     Bgp,
 }
 
+impl RibType {
+
+    /// Return true if routes of the type are originated by routing protocols.
+    pub fn is_protocol(&self) -> bool {
+        match self {
+            RibType::Eigrp | RibType::Ospf | RibType::Isis | RibType::Rip | RibType::Bgp => true,
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for RibType {
+    type Err = CoreError;
+
+    /// Protocol name as in CLI, such as "static".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "system" => Ok(RibType::System),
+            "kernel" => Ok(RibType::Kernel),
+            "connected" => Ok(RibType::Connected),
+            "static" => Ok(RibType::Static),
+            "eigrp" => Ok(RibType::Eigrp),
+            "ospf" | "ospf6" => Ok(RibType::Ospf),
+            "isis" => Ok(RibType::Isis),
+            "rip" | "ripng" => Ok(RibType::Rip),
+            "bgp" => Ok(RibType::Bgp),
+            _ => Err(CoreError::CommandExec(format!("Unknown route type {}", s))),
+        }
+    }
+}
+
+/// RIB sub-type, to distinguish routes within a protocol.
+#[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Debug)]
+pub enum RibSubType {
+    Default,
+    OspfIntraArea,
+    OspfInterArea,
+    OspfExternal1,
+    OspfExternal2,
+    OspfNssa1,
+    OspfNssa2,
+    BgpInternal,
+    BgpExternal,
+}
+
 /// RIB, store essential routing information with nexthops per single protocol type.
 pub struct Rib<T: Addressable>
 {
@@ -56,20 +116,38 @@ pub struct Rib<T: Addressable>
     /// Administrative distance.
     distance: u8,
 
+    /// Metric, compared when distance is the same.
+    metric: u32,
+
     /// Time updated.
     instant: time::Instant,
 
-    /// Tag -- TBD placeholder.
-    _tag: u32,
+    /// Route tag.
+    tag: u32,
+
+    /// Protocol sub-type.
+    sub_type: RibSubType,
+
+    /// Router-ID of the originator, if protocol provides.
+    router_id: Option<Ipv4Addr>,
 
-    /// TBD: Flag selected.
+    /// Flag selected as the best among candidates.
     selected: Cell<bool>,
 
-    /// TBD: Flag FIB. 
+    /// Flag installed in FIB.
     fib: Cell<bool>,
 
     /// Nexthops.
     nexthops: RefCell<Vec<Nexthop<T>>>,
+
+    /// Nexthops resolved through RIB table, to be installed to kernel.
+    resolved: RefCell<Vec<Nexthop<T>>>,
+
+    /// Label operations per nexthop.
+    labels: RefCell<BTreeMap<Nexthop<T>, LabelOp>>,
+
+    /// Label operations per resolved nexthop, inherited from the nexthop it is resolved from.
+    resolved_labels: RefCell<BTreeMap<Nexthop<T>, LabelOp>>,
 }
 
 impl<T> Clone for Rib<T>
@@ -79,11 +157,17 @@ where T: Addressable
         Rib::<T> {
             rib_type: self.rib_type,
             distance: self.distance,
+            metric: self.metric,
             instant: self.instant,
-            _tag: self._tag,
+            tag: self.tag,
+            sub_type: self.sub_type,
+            router_id: self.router_id,
             selected: Cell::new(self.selected.get()),
             fib: Cell::new(self.fib.get()),
             nexthops: RefCell::new(self.nexthops.borrow().to_vec()),
+            resolved: RefCell::new(self.resolved.borrow().to_vec()),
+            labels: RefCell::new(self.labels.borrow().clone()),
+            resolved_labels: RefCell::new(self.resolved_labels.borrow().clone()),
         }
     }
 }
@@ -96,11 +180,17 @@ where T: Addressable
         Rib {
             rib_type: rib_type,
             distance: distance,
+            metric: 0,
             instant: time::Instant::now(),
-            _tag: 0,
+            tag: 0,
+            sub_type: RibSubType::Default,
+            router_id: None,
             selected: Cell::new(false),
             fib: Cell::new(false),
             nexthops: RefCell::new(Vec::new()),
+            resolved: RefCell::new(Vec::new()),
+            labels: RefCell::new(BTreeMap::new()),
+            resolved_labels: RefCell::new(BTreeMap::new()),
         }
     }
 
@@ -121,14 +211,74 @@ where T: Addressable
                 }
             };
 
+            // Nexthops with the same distance share a RIB, take a non-zero tag.
+            if info.tag() != 0 {
+                rib.set_tag(info.tag());
+            }
+
             rib.add_nexthop(nexthop.clone());
+            if let Some(labels) = info.labels() {
+                rib.set_label(nexthop, LabelOp::Push(labels.clone()));
+            }
         }
 
         map
     }
 
+    /// Construct RIB from route learned from kernel.
+    pub fn from_kernel_route(kr: KernelRoute<T>) -> Rib<T> {
+        let mut rib = Rib::<T>::new(RibType::Kernel, ZEBRA_KERNEL_DISTANCE);
+
+        if let Some(metric) = kr.metric {
+            rib.set_metric(metric);
+        }
+
+        for nexthop in kr.nexthops {
+            rib.add_nexthop(nexthop);
+        }
+
+        rib
+    }
+
+    /// Construct RIB from route given by protocol.
+    pub fn from_zebra_route(route: &ZebraRoute<T>) -> Rib<T> {
+        let mut rib = Rib::<T>::new(route.rib_type, route.distance);
+
+        rib.set_metric(route.metric);
+        rib.set_tag(route.tag);
+        rib.set_sub_type(route.sub_type);
+        if let Some(router_id) = route.router_id {
+            rib.set_router_id(router_id);
+        }
+
+        for nexthop in route.nexthops.iter() {
+            rib.add_nexthop(nexthop.clone());
+        }
+
+        rib
+    }
+
+    /// Construct route to be redistributed to protocol.
+    pub fn to_zebra_route(&self, table_id: u32, prefix: &Prefix<T>) -> ZebraRoute<T> {
+        ZebraRoute::<T> {
+            prefix: prefix.clone(),
+            table_id: table_id,
+            rib_type: self.rib_type,
+            distance: self.distance,
+            metric: self.metric,
+            tag: self.tag,
+            sub_type: self.sub_type,
+            router_id: self.router_id,
+            nexthops: self.nexthops.borrow().clone(),
+        }
+    }
+
+    /// Kernel routes are distinguished by metric as kernel does, others are not.
     pub fn key(&self) -> RibKey {
-        (self.distance, self.rib_type)
+        match self.rib_type {
+            RibType::Kernel => (self.distance, self.rib_type, self.metric),
+            _ => (self.distance, self.rib_type, 0),
+        }
     }
 
     pub fn rib_type(&self) -> RibType {
@@ -139,14 +289,54 @@ where T: Addressable
         self.distance
     }
 
+    pub fn metric(&self) -> u32 {
+        self.metric
+    }
+
+    pub fn set_metric(&mut self, metric: u32) {
+        self.metric = metric
+    }
+
+    pub fn tag(&self) -> u32 {
+        self.tag
+    }
+
+    pub fn set_tag(&mut self, tag: u32) {
+        self.tag = tag
+    }
+
+    pub fn sub_type(&self) -> RibSubType {
+        self.sub_type
+    }
+
+    pub fn set_sub_type(&mut self, sub_type: RibSubType) {
+        self.sub_type = sub_type
+    }
+
+    pub fn router_id(&self) -> Option<Ipv4Addr> {
+        self.router_id
+    }
+
+    pub fn set_router_id(&mut self, router_id: Ipv4Addr) {
+        self.router_id = Some(router_id)
+    }
+
     pub fn uptime(&self) -> time::Duration {
         time::Instant::now() - self.instant
     }
 
+    pub fn is_selected(&self) -> bool {
+        self.selected.get()
+    }
+
     pub fn set_selected(&self, selected: bool) {
         self.selected.set(selected)
     }
 
+    pub fn is_fib(&self) -> bool {
+        self.fib.get()
+    }
+
     pub fn set_fib(&self, fib: bool) {
         self.fib.set(fib)
     }
@@ -166,6 +356,53 @@ where T: Addressable
                 self.nexthops.borrow_mut().push(nh);
             }
         }
+        self.labels.borrow_mut().remove(nexthop);
+    }
+
+    /// Return label operation for the nexthop.
+    pub fn label(&self, nexthop: &Nexthop<T>) -> Option<LabelOp> {
+        self.labels.borrow().get(nexthop).cloned()
+    }
+
+    pub fn set_label(&self, nexthop: &Nexthop<T>, op: LabelOp) {
+        self.labels.borrow_mut().insert(nexthop.clone(), op);
+    }
+
+    /// Return label operation for the resolved nexthop.
+    pub fn resolved_label(&self, nexthop: &Nexthop<T>) -> Option<LabelOp> {
+        self.resolved_labels.borrow().get(nexthop).cloned()
+    }
+
+    pub fn set_resolved_labels(&self, labels: BTreeMap<Nexthop<T>, LabelOp>) {
+        self.resolved_labels.replace(labels);
+    }
+
+    /// Return true if the route should be installed to kernel,
+    /// kernel and connected routes are already there.
+    pub fn is_installable(&self) -> bool {
+        match self.rib_type {
+            RibType::System | RibType::Kernel | RibType::Connected => false,
+            _ => true,
+        }
+    }
+
+    /// Resolved nexthops.
+    pub fn resolved(&self) -> RefMut<Vec<Nexthop<T>>> {
+        self.resolved.borrow_mut()
+    }
+
+    pub fn set_resolved(&self, resolved: Vec<Nexthop<T>>) {
+        self.resolved.replace(resolved);
+    }
+
+    /// Return true if at least one nexthop is resolved.
+    pub fn is_active(&self) -> bool {
+        self.resolved.borrow().len() > 0
+    }
+
+    /// Return key, metric, resolved nexthops and their labels, which determine what is programmed in kernel.
+    fn fib_state(&self) -> FibState<T> {
+        (self.key(), self.metric, self.resolved.borrow().clone(), self.resolved_labels.borrow().clone())
     }
 }
 
@@ -176,21 +413,37 @@ where T: Addressable
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where S: Serializer
     {
-        let mut s = serializer.serialize_struct("Rib", 3)?;
+        let mut s = serializer.serialize_struct("Rib", 11)?;
         let type_str = format!("{:?}", self.rib_type);
+        let sub_type_str = format!("{:?}", self.sub_type);
+        let router_id_str = self.router_id.map(|router_id| router_id.to_string());
         s.serialize_field("type", &type_str)?;
+        s.serialize_field("sub_type", &sub_type_str)?;
         s.serialize_field("distance", &self.distance)?;
+        s.serialize_field("metric", &self.metric)?;
+        s.serialize_field("tag", &self.tag)?;
+        s.serialize_field("router_id", &router_id_str)?;
+        s.serialize_field("active", &self.is_active())?;
+        s.serialize_field("selected", &self.selected.get())?;
+        s.serialize_field("fib", &self.fib.get())?;
         s.serialize_field("nexthops", &self.nexthops)?;
+        let labels: BTreeMap<String, String> = self.labels.borrow().iter()
+            .map(|(nexthop, op)| (nexthop.to_string(), op.to_string())).collect();
+        s.serialize_field("labels", &labels)?;
         s.end()
     }
 }
 
 /// RIB candidate key.
+///   Distance, RIB type and metric, the last one is only set for kernel routes.
 ///
-type RibKey = (u8, RibType);
+type RibKey = (u8, RibType, u32);
+
+/// What a RIB programs in kernel.
+type FibState<T> = (RibKey, u32, Vec<Nexthop<T>>, BTreeMap<Nexthop<T>, LabelOp>);
 
 /// RIB entry.
-///   Store a selected FIB, as well as all candidates per RibKey (distance, RibType).
+///   Store a selected FIB, as well as all candidates per RibKey (distance, RibType, metric).
 ///
 pub struct RibEntry<T: Addressable>
 {
@@ -214,11 +467,14 @@ where T: Addressable
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where S: Serializer
     {
-        let mut s = serializer.serialize_struct("RibEntry", 2)?;
+        let mut s = serializer.serialize_struct("RibEntry", 3)?;
         let prefix_str = self.prefix.to_string();
+        let ribs = self.ribs.borrow();
+        let ribs: Vec<&Rib<T>> = ribs.values().collect();
 
         s.serialize_field("prefix", &prefix_str)?;
         s.serialize_field("entry", &self.fib)?;
+        s.serialize_field("ribs", &ribs)?;
         s.end()
     }
 }
@@ -236,6 +492,11 @@ where T: Addressable
         }
     }
 
+    /// Prefix.
+    pub fn prefix(&self) -> &Prefix<T> {
+        &self.prefix
+    }
+
     /// FIB.
     pub fn fib(&self) -> RefMut<Option<Rib<T>>> {
         self.fib.borrow_mut()
@@ -259,14 +520,55 @@ where T: Addressable
         self.updated.get()
     }
 
-    /// Select RIB.
-    /// TBD: Right now select head of candidate RIBs.
-    ///      We have to do nexthop activate check.
+    /// Select the best RIB among candidates with active nexthops, by distance and then metric.
+    /// Update selected flag of candidates, and return a copy of the selected one.
     pub fn select(&self) -> Option<Rib<T>> {
-        if let Some((_, rib)) = self.ribs().iter().next() {
-            Some(rib.clone())
-        } else {
-            None
+        let ribs = self.ribs();
+        let mut best: Option<&Rib<T>> = None;
+
+        for (_, rib) in ribs.iter() {
+            if !rib.is_active() {
+                continue;
+            }
+
+            best = match best {
+                Some(b) if (b.distance, b.metric) <= (rib.distance, rib.metric) => Some(b),
+                _ => Some(rib),
+            };
+        }
+
+        let key = best.map(|rib| rib.key());
+        for (k, rib) in ribs.iter() {
+            rib.set_selected(Some(*k) == key);
+        }
+
+        best.map(|rib| rib.clone())
+    }
+
+    /// Update FIB flag of the selected RIB and its candidate, return the RIB type.
+    pub fn set_fib_flag(&self, fib: bool) -> Option<RibType> {
+        match *self.fib.borrow() {
+            Some(ref rib) => {
+                rib.set_fib(fib);
+                if let Some(candidate) = self.ribs.borrow().get(&rib.key()) {
+                    candidate.set_fib(fib);
+                }
+                Some(rib.rib_type())
+            },
+            None => None,
+        }
+    }
+
+    /// Return true if given RIB would program kernel the same as current FIB.
+    pub fn is_fib_same(&self, rib: &Rib<T>) -> bool {
+        self.fib_state() == Some(rib.fib_state())
+    }
+
+    /// Return FIB key, metric and resolved nexthops, to detect FIB change.
+    fn fib_state(&self) -> Option<FibState<T>> {
+        match *self.fib.borrow() {
+            Some(ref fib) => Some(fib.fib_state()),
+            None => None,
         }
     }
 }
@@ -280,6 +582,21 @@ pub struct RibTable<T: Addressable>
 {
     /// Table tree.
     tree: Tree<Prefix<T>, Rc<RibEntry<T>>>,
+
+    /// Resolving prefix to prefixes whose nexthops currently resolve through it.
+    dependents: RefCell<BTreeMap<Prefix<T>, BTreeSet<Prefix<T>>>>,
+
+    /// Prefix to resolving prefixes its nexthops go through, to update the index above.
+    resolving: RefCell<BTreeMap<Prefix<T>, BTreeSet<Prefix<T>>>>,
+
+    /// Prefixes having gateway nexthop which does not resolve.
+    unresolved: RefCell<BTreeSet<Prefix<T>>>,
+
+    /// Interfaces operationally up, nexthops through other interfaces are not active.
+    links_up: RefCell<BTreeSet<String>>,
+
+    /// Interface name to prefixes having nexthops through the interface.
+    link_dependents: RefCell<BTreeMap<String, BTreeSet<Prefix<T>>>>,
 }
 
 impl<T> RibTable<T>
@@ -289,6 +606,25 @@ where T: Addressable
     pub fn new() -> RibTable<T> {
         RibTable {
             tree: Tree::new(),
+            dependents: RefCell::new(BTreeMap::new()),
+            resolving: RefCell::new(BTreeMap::new()),
+            unresolved: RefCell::new(BTreeSet::new()),
+            links_up: RefCell::new(BTreeSet::new()),
+            link_dependents: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    /// Set interface state, and return prefixes having nexthops through it, to be processed again.
+    pub fn link_set(&self, ifname: &str, up: bool) -> Vec<Prefix<T>> {
+        if up {
+            self.links_up.borrow_mut().insert(ifname.to_string());
+        } else {
+            self.links_up.borrow_mut().remove(ifname);
+        }
+
+        match self.link_dependents.borrow().get(ifname) {
+            Some(set) => set.iter().cloned().collect(),
+            None => Vec::new(),
         }
     }
 
@@ -312,6 +648,25 @@ where T: Addressable
         }
     }
 
+    /// Add given RIB to tree per prefix, merging nexthops into existing RIB with the same key.
+    pub fn merge(&mut self, prefix: &Prefix<T>, rib: Rib<T>) {
+        if let Some(entry) = self.lookup_exact(prefix) {
+            if let Some(rib_old) = entry.ribs().get(&rib.key()) {
+                for nh in rib.nexthops().iter() {
+                    if !rib_old.nexthops().contains(nh) {
+                        rib_old.add_nexthop(nh.clone());
+                    }
+                    if let Some(op) = rib.label(nh) {
+                        rib_old.set_label(nh, op);
+                    }
+                }
+                return;
+            }
+        }
+
+        self.add(prefix, rib);
+    }
+
     /// Delete given RIBs from tree per prefix.
     pub fn delete(&mut self, prefix: &Prefix<T>, rib: Rib<T>) {
         debug!("rib delete {:?} type {:?} distance {:?}", prefix, rib.rib_type(), rib.distance());
@@ -328,36 +683,283 @@ where T: Addressable
         }
     }
 
+    /// Withdraw all RIBs of the type for the prefix, they are removed when processed.
+    pub fn withdraw(&mut self, prefix: &Prefix<T>, rib_type: RibType) {
+        debug!("rib withdraw {:?} type {:?}", prefix, rib_type);
+
+        if let Some(entry) = self.lookup_exact(prefix) {
+            for (_, rib) in entry.ribs().iter() {
+                if rib.rib_type() == rib_type {
+                    rib.nexthops().clear();
+                    rib.labels.borrow_mut().clear();
+                }
+            }
+        }
+    }
+
     /// Process route selection algorithm per prefix.
     /// Core part of decision mechanism, including nexthop activity check.
-    pub fn process<F>(&mut self, prefix: &Prefix<T>, kfunc: F)
+    /// When FIB for the prefix changes, routes resolved through it are processed as well.
+    /// Return prefixes whose FIB changed.
+    pub fn process<F>(&mut self, prefix: &Prefix<T>, kfunc: F) -> Vec<Prefix<T>>
+    where F: Fn(&Prefix<T>, &RibEntry<T>) -> Option<Rib<T>>
+    {
+        let mut changed = Vec::new();
+        let mut queue = VecDeque::new();
+        let mut limit = (self.count() + 1) * ZEBRA_NEXTHOP_RESOLVE_DEPTH_MAX as usize;
+
+        queue.push_back(prefix.clone());
+
+        while let Some(p) = queue.pop_front() {
+            if limit == 0 {
+                debug!("rib process aborted, too many dependent updates");
+                break;
+            }
+            limit -= 1;
+
+            if self.process_entry(&p, &kfunc) {
+                for dependent in self.dependents(&p) {
+                    if !queue.contains(&dependent) {
+                        queue.push_back(dependent);
+                    }
+                }
+                changed.push(p);
+            }
+        }
+
+        changed
+    }
+
+    /// Process a single RIB entry, return true if FIB is changed.
+    fn process_entry<F>(&self, prefix: &Prefix<T>, kfunc: &F) -> bool
     where F: Fn(&Prefix<T>, &RibEntry<T>) -> Option<Rib<T>>
     {
         debug!("rib process {:?}", prefix);
 
-        let it = self.tree.lookup_exact(prefix);
-        if let Some(ref node) = *it.node() {
-            if let Some (ref mut entry) = *node.data() {
-                let mut to_be_removed = Vec::new();
+        // Nexthops are resolved again below.
+        self.dependents_clear(prefix);
+
+        let entry = match self.lookup_exact(prefix) {
+            Some(entry) => entry,
+            None => return false,
+        };
 
-                for (key, rib) in entry.ribs().iter() {
-                    if rib.nexthops().len() == 0 {
-                        to_be_removed.push(key.clone());
+        let mut to_be_removed = Vec::new();
+
+        for (key, rib) in entry.ribs().iter() {
+            if rib.nexthops().len() == 0 {
+                to_be_removed.push(key.clone());
+            }
+        }
+
+        for key in to_be_removed {
+            entry.ribs().remove(&key);
+        }
+
+        // Resolve nexthops for each candidate.
+        for (_, rib) in entry.ribs().iter() {
+            self.resolve(prefix, rib);
+        }
+
+        let old = entry.fib_state();
+
+        // Call kernel function to install/unistall selected route.
+        if let Some(fib) = kfunc(prefix, &entry) {
+            entry.fib().replace(fib);
+        } else {
+            entry.fib().take();
+        }
+
+        // Reflect FIB flag to candidates.
+        let fib_key = match *entry.fib() {
+            Some(ref fib) if fib.is_fib() => Some(fib.key()),
+            _ => None,
+        };
+        for (key, rib) in entry.ribs().iter() {
+            rib.set_fib(Some(*key) == fib_key);
+        }
+
+        old != entry.fib_state()
+    }
+
+    /// Resolve nexthops of given RIB, and mark it active when any of them is resolved.
+    fn resolve(&self, prefix: &Prefix<T>, rib: &Rib<T>) {
+        let mut resolved = Vec::new();
+        let mut labels = BTreeMap::new();
+        let nexthops = rib.nexthops().clone();
+
+        for nexthop in nexthops {
+            let label = rib.label(&nexthop);
+            let v = match nexthop {
+                Nexthop::Address(ref address) => self.resolve_address(address, prefix, prefix, 0),
+                Nexthop::Network(_) => Vec::new(),
+                Nexthop::Ifname(ref ifname) | Nexthop::AddressIfname(_, ref ifname) => {
+                    self.link_dependents.borrow_mut().entry(ifname.clone())
+                        .or_insert_with(BTreeSet::new).insert(prefix_normalize(prefix));
+
+                    // Interface missing or down cannot forward.
+                    if self.links_up.borrow().contains(ifname) {
+                        vec![nexthop.clone()]
+                    } else {
+                        Vec::new()
                     }
+                },
+                _ => vec![nexthop.clone()],
+            };
+
+            for nh in v {
+                if !resolved.contains(&nh) {
+                    if let Some(ref label) = label {
+                        labels.insert(nh.clone(), label.clone());
+                    }
+                    resolved.push(nh);
+                }
+            }
+        }
+
+        rib.set_resolved(resolved);
+        rib.set_resolved_labels(labels);
+    }
+
+    /// Resolve gateway address recursively through FIBs, excluding the prefix being resolved.
+    /// Resolving prefixes are recorded as what the owner prefix depends on.
+    fn resolve_address(&self, address: &T, owner: &Prefix<T>, exclude: &Prefix<T>, depth: u8) -> Vec<Nexthop<T>> {
+        let mut v = Vec::new();
+
+        if depth >= ZEBRA_NEXTHOP_RESOLVE_DEPTH_MAX {
+            debug!("Nexthop {:?} exceeds resolve depth", address);
+            return v;
+        }
+
+        let entry = match self.lookup_longest(address, Some(exclude)) {
+            Some(entry) => entry,
+            None => {
+                self.unresolved.borrow_mut().insert(prefix_normalize(owner));
+                return v;
+            },
+        };
+
+        self.dependents_add(owner, entry.prefix());
+
+        let nexthops = match *entry.fib() {
+            Some(ref fib) => fib.nexthops().clone(),
+            None => return v,
+        };
+
+        for nexthop in nexthops {
+            let resolved = match nexthop {
+                Nexthop::Ifname(ifname) => vec![Nexthop::AddressIfname(address.clone(), ifname)],
+                Nexthop::AddressIfname(..) => vec![nexthop],
+                Nexthop::Address(ref gateway) => self.resolve_address(gateway, owner, entry.prefix(), depth + 1),
+                _ => Vec::new(),
+            };
+
+            for nh in resolved {
+                if !v.contains(&nh) {
+                    v.push(nh);
                 }
+            }
+        }
+
+        v
+    }
+
+    /// Record that nexthop of owner prefix resolves through given prefix.
+    fn dependents_add(&self, owner: &Prefix<T>, prefix: &Prefix<T>) {
+        let owner = prefix_normalize(owner);
+        let prefix = prefix_normalize(prefix);
+
+        self.resolving.borrow_mut().entry(owner.clone()).or_insert_with(BTreeSet::new).insert(prefix.clone());
+        self.dependents.borrow_mut().entry(prefix).or_insert_with(BTreeSet::new).insert(owner);
+    }
+
+    /// Forget what nexthops of given prefix resolve through.
+    fn dependents_clear(&self, owner: &Prefix<T>) {
+        let owner = prefix_normalize(owner);
+
+        self.unresolved.borrow_mut().remove(&owner);
+
+        self.link_dependents.borrow_mut().retain(|_, set| {
+            set.remove(&owner);
+            !set.is_empty()
+        });
 
-                for key in to_be_removed {
-                    entry.ribs().remove(&key);
+        if let Some(resolving) = self.resolving.borrow_mut().remove(&owner) {
+            let mut dependents = self.dependents.borrow_mut();
+
+            for prefix in resolving {
+                if let Some(set) = dependents.get_mut(&prefix) {
+                    set.remove(&owner);
+                    if set.is_empty() {
+                        dependents.remove(&prefix);
+                    }
                 }
+            }
+        }
+    }
+
+    /// Return prefixes whose nexthops may be affected by FIB change of given prefix.
+    ///   Those currently resolving through the prefix, and those having a nexthop covered by
+    ///   the prefix while resolving through a less specific prefix, or not resolving at all.
+    fn dependents(&self, prefix: &Prefix<T>) -> Vec<Prefix<T>> {
+        let prefix = prefix_normalize(prefix);
+        let address = T::from_slice(prefix.octets());
+        let dependents = self.dependents.borrow();
+        let mut candidates = BTreeSet::new();
+
+        if let Some(set) = dependents.get(&prefix) {
+            candidates.extend(set.iter().cloned());
+        }
+
+        let mut covered = BTreeSet::new();
+        for len in 0..prefix.len() {
+            if let Some(set) = dependents.get(&prefix_from_address(&address, len)) {
+                covered.extend(set.iter().cloned());
+            }
+        }
+        covered.extend(self.unresolved.borrow().iter().cloned());
 
-                // Call kernel function to install/unistall selected route.
-                if let Some(fib) = kfunc(prefix, entry) {
-                    entry.fib().replace(fib);
-                } else {
-                    entry.fib().take();
+        for p in covered {
+            if candidates.contains(&p) {
+                continue;
+            }
+            if let Some(entry) = self.lookup_exact(&p) {
+                let depends = entry.ribs().iter().any(|(_, rib)| {
+                    rib.nexthops().iter().any(|nh| match nh {
+                        Nexthop::Address(address) => prefix_match(&prefix, address),
+                        _ => false,
+                    })
+                });
+
+                if depends {
+                    candidates.insert(p);
                 }
             }
         }
+
+        candidates.remove(&prefix);
+        candidates.into_iter().collect()
+    }
+
+    /// Lookup the longest match RIB entry which has FIB for given address.
+    pub fn lookup_longest(&self, address: &T, exclude: Option<&Prefix<T>>) -> Option<Rc<RibEntry<T>>> {
+        for len in (0..=T::bit_len()).rev() {
+            let p = prefix_from_address(address, len);
+
+            if let Some(exclude) = exclude {
+                if prefix_equal(&p, exclude) {
+                    continue;
+                }
+            }
+
+            if let Some(entry) = self.lookup_exact(&p) {
+                if entry.fib().is_some() {
+                    return Some(entry);
+                }
+            }
+        }
+
+        None
     }
 
     /// Lookup RIB entry per prefix.
@@ -384,6 +986,52 @@ where T: Addressable
     }
 }
 
+/// Construct a prefix from address masked with given length.
+pub fn prefix_from_address<T: Addressable>(address: &T, len: u8) -> Prefix<T> {
+    let mut octets = address.octets_ref().to_vec();
+
+    for (i, octet) in octets.iter_mut().enumerate() {
+        let bits = (i * 8) as u8;
+        if bits >= len {
+            *octet = 0;
+        } else if len - bits < 8 {
+            *octet &= 0xffu8 << (8 - (len - bits));
+        }
+    }
+
+    Prefix::<T>::from_slice(&octets, len)
+}
+
+/// Return the prefix with host bits cleared.
+pub fn prefix_normalize<T: Addressable>(prefix: &Prefix<T>) -> Prefix<T> {
+    prefix_from_address(&T::from_slice(prefix.octets()), prefix.len())
+}
+
+/// Return the first and last addresses covered by the prefix.
+pub fn prefix_range<T: Addressable>(prefix: &Prefix<T>) -> (T, T) {
+    let mut last = prefix.octets().to_vec();
+
+    for (i, octet) in last.iter_mut().enumerate() {
+        let bits = (prefix.len() as usize).saturating_sub(i * 8).min(8);
+        *octet |= 0xffu8.checked_shr(bits as u32).unwrap_or(0);
+    }
+
+    (T::from_slice(prefix_normalize(prefix).octets()), T::from_slice(&last))
+}
+
+/// Return true if the prefix contains the address.
+pub fn prefix_match<T: Addressable>(prefix: &Prefix<T>, address: &T) -> bool {
+    prefix_equal(&prefix_from_address(address, prefix.len()), prefix)
+}
+
+/// Return true if two prefixes are equal, ignoring host bits.
+pub fn prefix_equal<T: Addressable>(p1: &Prefix<T>, p2: &Prefix<T>) -> bool {
+    let a1 = T::from_slice(p1.octets());
+    let a2 = T::from_slice(p2.octets());
+
+    p1.len() == p2.len() && prefix_from_address(&a1, p1.len()) == prefix_from_address(&a2, p2.len())
+}
+
 /// Serializer for RibTable.
 impl<T> Serialize for RibTable<T>
 where T: Addressable
@@ -403,34 +1051,248 @@ where T: Addressable
         s.end()
     }
 }
-/// Wrapper Ipv4 Rib Table.
-pub struct RibTableIpv4 {
+/// RIB table query, given as exec parameters.
+pub struct RibQuery<T: Addressable> {
+
+    /// Exact match prefix, or base prefix for longer prefixes.
+    prefix: Option<Prefix<T>>,
+
+    /// Longest match address.
+    address: Option<T>,
+
+    /// Show prefixes longer than or equal to the prefix.
+    longer_prefixes: bool,
+
+    /// Selected RIB type.
+    rib_type: Option<RibType>,
+
+    /// Show counts per RIB type only.
+    summary: bool,
+}
+
+impl<T> RibQuery<T>
+where T: Addressable
+{
+    /// Constructor, matching all entries.
+    pub fn new() -> RibQuery<T> {
+        RibQuery {
+            prefix: None,
+            address: None,
+            longer_prefixes: false,
+            rib_type: None,
+            summary: false,
+        }
+    }
+
+    /// Construct from JSON parameters.
+    ///   { "prefix": "10.0.0.0/8", "longer_prefixes": true }, { "address": "10.0.0.1" },
+    ///   { "type": "static" } or { "summary": true }.
+    pub fn from_json(json_str: &str) -> Result<RibQuery<T>, CoreError> {
+        let mut query = RibQuery::<T>::new();
+        let json: serde_json::Value = match serde_json::from_str(json_str) {
+            Ok(json) => json,
+            Err(_) => return Err(CoreError::CommandExec(format!("Invalid JSON"))),
+        };
+
+        // CLI sends null when there are no params.
+        if !json.is_object() {
+            return Ok(query)
+        }
+
+        if let Some(prefix) = json["prefix"].as_str() {
+            match Prefix::<T>::from_str(prefix) {
+                Ok(prefix) => query.prefix = Some(prefix),
+                Err(_) => return Err(CoreError::CommandExec(format!("Invalid prefix {}", prefix))),
+            }
+        }
+
+        if let Some(address) = json["address"].as_str() {
+            match T::from_str(address) {
+                Ok(address) => query.address = Some(address),
+                Err(_) => return Err(CoreError::CommandExec(format!("Invalid address {}", address))),
+            }
+        }
+
+        if let Some(rib_type) = json["type"].as_str() {
+            query.rib_type = Some(rib_type.parse()?);
+        }
+
+        query.longer_prefixes = json["longer_prefixes"].as_bool() == Some(true);
+        query.summary = json["summary"].as_bool() == Some(true);
+
+        Ok(query)
+    }
+
+    /// Return true if the entry satisfies the type filter.
+    fn filter_type(&self, entry: &RibEntry<T>) -> bool {
+        match self.rib_type {
+            Some(rib_type) => entry.fib_type() == Some(rib_type),
+            None => true,
+        }
+    }
+}
+
+impl<T> RibTable<T>
+where T: Addressable
+{
+    /// Return list of RIB entries per query.
+    pub fn query(&self, query: &RibQuery<T>) -> Vec<Rc<RibEntry<T>>> {
+        let mut v = Vec::new();
+
+        if let Some(ref address) = query.address {
+            if let Some(entry) = self.lookup_longest(address, None) {
+                v.push(entry);
+            }
+        } else if let (Some(ref prefix), false) = (&query.prefix, query.longer_prefixes) {
+            if let Some(entry) = self.lookup_exact(prefix) {
+                v.push(entry);
+            }
+        } else {
+            for node in self.tree.into_iter() {
+                if let Some(ref entry) = *node.data() {
+                    if let Some(ref prefix) = query.prefix {
+                        if entry.prefix().len() < prefix.len() ||
+                            !prefix_match(prefix, &T::from_slice(entry.prefix().octets())) {
+                            continue;
+                        }
+                    }
+
+                    v.push(entry.clone());
+                }
+            }
+        }
+
+        v.into_iter().filter(|entry| query.filter_type(entry)).collect()
+    }
+
+    /// Return selected RIBs of the type with prefixes.
+    pub fn fib_routes(&self, rib_type: RibType) -> Vec<(Prefix<T>, Rib<T>)> {
+        let mut v = Vec::new();
+
+        for node in self.tree.into_iter() {
+            if let Some(ref entry) = *node.data() {
+                if let Some(ref fib) = *entry.fib() {
+                    if fib.rib_type() == rib_type {
+                        v.push((entry.prefix().clone(), fib.clone()));
+                    }
+                }
+            }
+        }
+
+        v
+    }
+
+    /// Return number of selected routes per RIB type.
+    pub fn summary(&self) -> BTreeMap<RibType, usize> {
+        let mut counts = BTreeMap::new();
+
+        for node in self.tree.into_iter() {
+            if let Some(ref entry) = *node.data() {
+                if let Some(rib_type) = entry.fib_type() {
+                    *counts.entry(rib_type).or_insert(0) += 1;
+                }
+            }
+        }
+
+        counts
+    }
+}
+
+/// RIB table exec handler, for IPv4 and IPv6.
+pub struct RibTableExec<T: Addressable> {
 
     /// Zebra master.
     master: Rc<ZebraMaster>,
+
+    /// RIB table accessor.
+    table: for<'a> fn(&'a Vrf) -> RefMut<'a, RibTable<T>>,
 }
 
-impl RibTableIpv4 {
+impl<T> RibTableExec<T>
+where T: Addressable
+{
+    /// Constructor.
+    pub fn new(master: Rc<ZebraMaster>,
+               table: for<'a> fn(&'a Vrf) -> RefMut<'a, RibTable<T>>) -> RibTableExec<T> {
+        RibTableExec {
+            master: master,
+            table: table,
+        }
+    }
+}
+
+/// MdsHandler implementation for RibTable<T>.
+impl<T> MdsHandler for RibTableExec<T>
+where T: Addressable
+{
+    /// Handle GET method.
+    ///   /exec/show/vrfs/NAME/... returns RIB in the VRF, otherwise in default VRF.
+    fn handle_get(&self, path: &str, params: Option<Box<String>>) -> Result<Option<String>, CoreError> {
+        let query = match params {
+            Some(json_str) => RibQuery::<T>::from_json(&json_str)?,
+            None => RibQuery::<T>::new(),
+        };
+
+        let pat = "/exec/show/vrfs";
+        let vrf = if path.starts_with(pat) {
+            match split_id_and_path(&path[pat.len()..]) {
+                Some((name, _)) => match self.master.vrf_lookup_by_name(&name) {
+                    Some(vrf) => vrf,
+                    None => return Err(CoreError::CommandExec(format!("No such VRF {}", name))),
+                },
+                None => return Err(CoreError::CommandExec(format!("Invalid path"))),
+            }
+        } else {
+            self.master.vrf_default()
+        };
+        let table = (self.table)(&vrf);
+
+        let s = if query.summary {
+            let counts = table.summary();
+            let routes: Vec<serde_json::Value> = counts.iter().map(|(rib_type, count)| {
+                serde_json::json!({ "type": format!("{:?}", rib_type), "count": count })
+            }).collect();
+            let total: usize = counts.values().sum();
+
+            serde_json::json!({ "routes": routes, "total": total }).to_string()
+        } else if query.prefix.is_none() && query.address.is_none() && query.rib_type.is_none() {
+            serde_json::to_string(&*table).unwrap()
+        } else {
+            let entries = table.query(&query);
+            let entries: Vec<&RibEntry<T>> = entries.iter().map(|entry| entry.as_ref()).collect();
+
+            serde_json::to_string(&entries).unwrap()
+        };
+
+        Ok(Some(s))
+    }
+}
+
+/// Show failed FIB operations.
+pub struct ShowFibFailures {
+
+    /// Zebra master.
+    master: Rc<ZebraMaster>,
+}
+
+impl ShowFibFailures {
 
     /// Constructor.
-    pub fn new(master: Rc<ZebraMaster>) -> RibTableIpv4 {
-        RibTableIpv4 {
+    pub fn new(master: Rc<ZebraMaster>) -> ShowFibFailures {
+        ShowFibFailures {
             master: master,
         }
     }
 }
 
-/// MdsHandler implementation for RibTable<Ipv4Addr>
-impl MdsHandler for RibTableIpv4 {
+impl MdsHandler for ShowFibFailures {
 
     /// Handle GET method.
     fn handle_get(&self, _path: &str, _params: Option<Box<String>>) -> Result<Option<String>, CoreError> {
-        let master = self.master.clone();
-
-        let ref rib_ipv4 = *master.rib_ipv4();
-        let s = serde_json::to_string(rib_ipv4).unwrap();
+        let failures = self.master.fib_failures();
+        let failures: Vec<&FibFailure> = failures.values().collect();
 
-        Ok(Some(s))
+        Ok(Some(serde_json::to_string(&failures).unwrap()))
     }
 }
 
@@ -443,9 +1305,33 @@ mod tests {
 //use std::net::Ipv6Addr;
     use super::*;
 
+    fn add_connected(table: &mut RibTable<Ipv4Addr>, prefix: &str, ifname: &str) -> Prefix<Ipv4Addr> {
+        let p = Prefix::<Ipv4Addr>::from_str(prefix).unwrap();
+        let rib = Rib::<Ipv4Addr>::new(RibType::Connected, 0);
+
+        table.link_set(ifname, true);
+        rib.add_nexthop(Nexthop::<Ipv4Addr>::from_ifname(ifname));
+        table.merge(&p, rib);
+        table.process(&p, |_, e| { e.select() });
+        p
+    }
+
+    fn add_static(table: &mut RibTable<Ipv4Addr>, prefix: &str, gateway: &str) -> Prefix<Ipv4Addr> {
+        let p = Prefix::<Ipv4Addr>::from_str(prefix).unwrap();
+        let rib = Rib::<Ipv4Addr>::new(RibType::Static, 1);
+        let addr = gateway.parse().unwrap();
+
+        rib.add_nexthop(Nexthop::<Ipv4Addr>::from_address(&addr));
+        table.add(&p, rib);
+        table.process(&p, |_, e| { e.select() });
+        p
+    }
+
     #[test]
     pub fn test_rib_process() {
         let mut table = RibTable::<Ipv4Addr>::new();
+        add_connected(&mut table, "1.1.1.0/24", "eth0");
+
         let rib1 = Rib::<Ipv4Addr>::new(RibType::Static, 1);
         let rib2 = Rib::<Ipv4Addr>::new(RibType::Static, 200);
         let rib3 = Rib::<Ipv4Addr>::new(RibType::Ospf, 110);
@@ -494,4 +1380,287 @@ mod tests {
             assert_eq!(entry.fib_type(), Some(RibType::Ospf));
         }
     }
+
+    #[test]
+    pub fn test_rib_nexthop_resolve() {
+        let mut table = RibTable::<Ipv4Addr>::new();
+        let p1 = add_static(&mut table, "20.0.0.0/8", "10.0.0.1");
+        let p2 = add_static(&mut table, "30.0.0.0/8", "20.1.1.1");
+
+        // Unresolvable.
+        assert_eq!(table.lookup_exact(&p1).unwrap().fib_type(), None);
+        assert_eq!(table.lookup_exact(&p2).unwrap().fib_type(), None);
+
+        // Connected route resolves both, recursively for p2.
+        let pc = add_connected(&mut table, "10.0.0.0/24", "eth0");
+        let gateway = "10.0.0.1".parse().unwrap();
+        let expected = vec![Nexthop::<Ipv4Addr>::from_address_ifname(&gateway, "eth0")];
+
+        for p in &[&p1, &p2] {
+            let entry = table.lookup_exact(p).unwrap();
+            let fib = entry.fib();
+            assert_eq!(fib.is_some(), true);
+            assert_eq!(*(*fib).as_ref().unwrap().resolved(), expected);
+        }
+
+        // Withdrawing connected route makes dependents inactive.
+        let entry = table.lookup_exact(&pc).unwrap();
+        entry.ribs().clear();
+        table.process(&pc, |_, e| { e.select() });
+
+        assert_eq!(table.lookup_exact(&p1).unwrap().fib_type(), None);
+        assert_eq!(table.lookup_exact(&p2).unwrap().fib_type(), None);
+    }
+
+    #[test]
+    pub fn test_rib_dependents_index() {
+        let mut table = RibTable::<Ipv4Addr>::new();
+        let p1 = add_static(&mut table, "20.0.0.0/8", "10.0.0.1");
+        let p2 = add_static(&mut table, "30.0.0.0/8", "20.1.1.1");
+        let p3 = add_static(&mut table, "40.0.0.0/8", "11.0.0.1");
+        let pc = add_connected(&mut table, "10.0.0.0/16", "eth0");
+
+        // Routes resolving through the prefix, recursively resolved one included.
+        assert_eq!(table.dependents(&pc), vec![p1.clone(), p2.clone()]);
+        assert_eq!(table.dependents(&p1), vec![p2.clone()]);
+
+        // More specific prefix covering the nexthop of a route resolving through a less specific one.
+        let pm = Prefix::<Ipv4Addr>::from_str("10.0.0.0/24").unwrap();
+        assert_eq!(table.dependents(&pm), vec![p1.clone()]);
+
+        // Prefix covering the nexthop of an unresolved route.
+        let pu = Prefix::<Ipv4Addr>::from_str("11.0.0.0/8").unwrap();
+        assert_eq!(table.dependents(&pu), vec![p3.clone()]);
+        add_connected(&mut table, "11.0.0.0/8", "eth1");
+        assert_eq!(table.lookup_exact(&p3).unwrap().fib_type(), Some(RibType::Static));
+        assert_eq!(table.dependents(&pu), vec![p3.clone()]);
+
+        // Unrelated prefix.
+        let pn = Prefix::<Ipv4Addr>::from_str("12.0.0.0/8").unwrap();
+        assert!(table.dependents(&pn).is_empty());
+
+        // Index is updated when the route is gone, the route resolved through it becomes unresolved.
+        let entry = table.lookup_exact(&p1).unwrap();
+        entry.ribs().clear();
+        table.process(&p1, |_, e| { e.select() });
+        assert_eq!(table.lookup_exact(&p2).unwrap().fib_type(), None);
+        assert!(table.dependents(&pc).is_empty());
+        assert_eq!(table.dependents(&p1), vec![p2.clone()]);
+    }
+
+    #[test]
+    pub fn test_rib_nexthop_resolve_loop() {
+        let mut table = RibTable::<Ipv4Addr>::new();
+        let p1 = add_static(&mut table, "20.0.0.0/8", "30.0.0.1");
+        let p2 = add_static(&mut table, "30.0.0.0/8", "20.0.0.1");
+
+        assert_eq!(table.lookup_exact(&p1).unwrap().fib_type(), None);
+        assert_eq!(table.lookup_exact(&p2).unwrap().fib_type(), None);
+    }
+
+    #[test]
+    pub fn test_rib_merge() {
+        let mut table = RibTable::<Ipv4Addr>::new();
+        let p = add_connected(&mut table, "10.0.0.0/24", "eth0");
+        add_connected(&mut table, "10.0.0.0/24", "eth1");
+
+        let entry = table.lookup_exact(&p).unwrap();
+        assert_eq!(entry.ribs().len(), 1);
+        assert_eq!(entry.fib().as_ref().unwrap().nexthops().len(), 2);
+
+        let rib = Rib::<Ipv4Addr>::new(RibType::Connected, 0);
+        rib.add_nexthop(Nexthop::<Ipv4Addr>::from_ifname("eth0"));
+        table.delete(&p, rib);
+        table.process(&p, |_, e| { e.select() });
+
+        assert_eq!(*entry.fib().as_ref().unwrap().nexthops(), vec![Nexthop::<Ipv4Addr>::from_ifname("eth1")]);
+    }
+
+    #[test]
+    pub fn test_rib_prefix_range() {
+        let range = |s: &str| prefix_range(&Prefix::<Ipv4Addr>::from_str(s).unwrap());
+
+        assert_eq!(range("10.1.2.3/20"), (Ipv4Addr::new(10, 1, 0, 0), Ipv4Addr::new(10, 1, 15, 255)));
+        assert_eq!(range("10.1.2.3/32"), (Ipv4Addr::new(10, 1, 2, 3), Ipv4Addr::new(10, 1, 2, 3)));
+        assert_eq!(range("0.0.0.0/0"), (Ipv4Addr::new(0, 0, 0, 0), Ipv4Addr::new(255, 255, 255, 255)));
+    }
+
+    #[test]
+    pub fn test_rib_zebra_route() {
+        let mut table = RibTable::<Ipv4Addr>::new();
+        add_connected(&mut table, "10.0.0.0/24", "eth0");
+
+        let p = Prefix::<Ipv4Addr>::from_str("192.168.0.0/16").unwrap();
+        let route = ZebraRoute::<Ipv4Addr> {
+            prefix: p.clone(),
+            table_id: 254,
+            rib_type: RibType::Ospf,
+            distance: 110,
+            metric: 20,
+            tag: 100,
+            sub_type: RibSubType::OspfExternal2,
+            router_id: Some(Ipv4Addr::new(1, 1, 1, 1)),
+            nexthops: vec![Nexthop::<Ipv4Addr>::from_address(&Ipv4Addr::new(10, 0, 0, 1))],
+        };
+
+        table.add(&p, Rib::<Ipv4Addr>::from_zebra_route(&route));
+        table.process(&p, |_, e| { e.select() });
+
+        let routes = table.fib_routes(RibType::Ospf);
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0].1.sub_type(), RibSubType::OspfExternal2);
+        assert_eq!(routes[0].1.router_id(), Some(Ipv4Addr::new(1, 1, 1, 1)));
+        assert_eq!(routes[0].1.to_zebra_route(254, &routes[0].0), route);
+        assert_eq!(table.fib_routes(RibType::Static).len(), 0);
+
+        table.withdraw(&p, RibType::Ospf);
+        table.process(&p, |_, e| { e.select() });
+
+        assert!(table.fib_routes(RibType::Ospf).is_empty());
+        assert_eq!(table.lookup_exact(&p).unwrap().ribs().len(), 0);
+    }
+
+    #[test]
+    pub fn test_rib_query() {
+        let mut table = RibTable::<Ipv4Addr>::new();
+        add_connected(&mut table, "10.0.0.0/24", "eth0");
+        add_static(&mut table, "10.1.0.0/16", "10.0.0.1");
+        add_static(&mut table, "10.1.1.0/24", "10.0.0.1");
+        add_static(&mut table, "0.0.0.0/0", "10.0.0.254");
+
+        let prefixes = |query: &RibQuery<Ipv4Addr>| -> Vec<String> {
+            table.query(query).iter().map(|e| e.prefix().to_string()).collect()
+        };
+
+        let query = RibQuery::<Ipv4Addr>::from_json(r#"{"prefix": "10.1.0.0/16"}"#).unwrap();
+        assert_eq!(prefixes(&query), vec!["10.1.0.0/16"]);
+
+        let query = RibQuery::<Ipv4Addr>::from_json(r#"{"prefix": "10.1.0.0/16", "longer_prefixes": true}"#).unwrap();
+        assert_eq!(prefixes(&query), vec!["10.1.0.0/16", "10.1.1.0/24"]);
+
+        let query = RibQuery::<Ipv4Addr>::from_json(r#"{"address": "10.1.1.1"}"#).unwrap();
+        assert_eq!(prefixes(&query), vec!["10.1.1.0/24"]);
+
+        let query = RibQuery::<Ipv4Addr>::from_json(r#"{"address": "192.168.0.1"}"#).unwrap();
+        assert_eq!(prefixes(&query), vec!["0.0.0.0/0"]);
+
+        let query = RibQuery::<Ipv4Addr>::from_json(r#"{"type": "connected"}"#).unwrap();
+        assert_eq!(prefixes(&query), vec!["10.0.0.0/24"]);
+
+        assert!(RibQuery::<Ipv4Addr>::from_json(r#"{"type": "unknown"}"#).is_err());
+
+        let counts = table.summary();
+        assert_eq!(counts.get(&RibType::Connected), Some(&1));
+        assert_eq!(counts.get(&RibType::Static), Some(&3));
+    }
+
+    #[test]
+    pub fn test_rib_select() {
+        let mut table = RibTable::<Ipv4Addr>::new();
+        add_connected(&mut table, "10.0.0.0/24", "eth0");
+        let p = Prefix::<Ipv4Addr>::from_str("192.168.0.0/16").unwrap();
+
+        let rib_candidate = |rib_type, distance, metric, gateway: &str| {
+            let mut rib = Rib::<Ipv4Addr>::new(rib_type, distance);
+            rib.set_metric(metric);
+            rib.add_nexthop(Nexthop::<Ipv4Addr>::from_address(&gateway.parse().unwrap()));
+            rib
+        };
+
+        // Unresolvable nexthop with the best distance is skipped.
+        table.add(&p, rib_candidate(RibType::Static, 1, 0, "172.16.0.1"));
+        table.add(&p, rib_candidate(RibType::Ospf, 110, 20, "10.0.0.2"));
+        table.add(&p, rib_candidate(RibType::Rip, 110, 10, "10.0.0.3"));
+        table.process(&p, |_, e| {
+            let selected = e.select();
+            if let Some(ref rib) = selected {
+                rib.set_fib(true);
+            }
+            selected
+        });
+
+        let entry = table.lookup_exact(&p).unwrap();
+        assert_eq!(entry.fib_type(), Some(RibType::Rip));
+
+        for (_, rib) in entry.ribs().iter() {
+            let best = rib.rib_type() == RibType::Rip;
+            assert_eq!(rib.is_selected(), best);
+            assert_eq!(rib.is_fib(), best);
+        }
+
+        let same = rib_candidate(RibType::Rip, 110, 10, "10.0.0.3");
+        assert!(!entry.is_fib_same(&same));
+
+        let resolved = entry.fib().as_ref().unwrap().resolved().clone();
+        same.set_resolved(resolved);
+        assert!(entry.is_fib_same(&same));
+    }
+
+    #[test]
+    pub fn test_rib_kernel_metric() {
+        let mut table = RibTable::<Ipv4Addr>::new();
+        let p = Prefix::<Ipv4Addr>::from_str("192.168.0.0/16").unwrap();
+
+        let rib_kernel = |metric, ifname| {
+            let mut rib = Rib::<Ipv4Addr>::new(RibType::Kernel, ZEBRA_KERNEL_DISTANCE);
+            rib.set_metric(metric);
+            rib.add_nexthop(Nexthop::<Ipv4Addr>::from_ifname(ifname));
+            rib
+        };
+
+        // Kernel routes only differing in metric are kept separately.
+        table.link_set("eth0", true);
+        table.link_set("eth1", true);
+        table.add(&p, rib_kernel(200, "eth1"));
+        table.add(&p, rib_kernel(100, "eth0"));
+        table.process(&p, |_, e| { e.select() });
+
+        let entry = table.lookup_exact(&p).unwrap();
+        assert_eq!(entry.ribs().len(), 2);
+        assert_eq!(entry.fib().as_ref().unwrap().metric(), 100);
+
+        // Deleting one of them leaves the other.
+        table.delete(&p, rib_kernel(100, "eth0"));
+        table.process(&p, |_, e| { e.select() });
+        assert_eq!(entry.ribs().len(), 1);
+        assert_eq!(entry.fib().as_ref().unwrap().metric(), 200);
+
+        // Other RIB types are still keyed by distance and type.
+        let mut rib = Rib::<Ipv4Addr>::new(RibType::Static, 1);
+        rib.set_metric(10);
+        let mut other = Rib::<Ipv4Addr>::new(RibType::Static, 1);
+        other.set_metric(20);
+        assert_eq!(rib.key(), other.key());
+    }
+
+    #[test]
+    pub fn test_rib_label_resolve() {
+        let mut table = RibTable::<Ipv4Addr>::new();
+        add_connected(&mut table, "10.0.0.0/24", "eth0");
+
+        let p = Prefix::<Ipv4Addr>::from_str("192.168.0.0/16").unwrap();
+        let rib = Rib::<Ipv4Addr>::new(RibType::Static, 1);
+        let nh = Nexthop::<Ipv4Addr>::from_address(&"10.0.0.1".parse().unwrap());
+
+        rib.add_nexthop(nh.clone());
+        rib.set_label(&nh, LabelOp::Push(vec![100, 200]));
+        table.add(&p, rib);
+        table.process(&p, |_, e| { e.select() });
+
+        // Resolved nexthop inherits labels.
+        let entry = table.lookup_exact(&p).unwrap();
+        let fib = entry.fib().clone().unwrap();
+        let resolved = Nexthop::<Ipv4Addr>::from_address_ifname(&"10.0.0.1".parse().unwrap(), "eth0");
+        assert_eq!(*fib.resolved(), vec![resolved.clone()]);
+        assert_eq!(fib.resolved_label(&resolved), Some(LabelOp::Push(vec![100, 200])));
+
+        // Label change is FIB change.
+        let same = fib.clone();
+        assert!(entry.is_fib_same(&same));
+        same.set_resolved_labels(BTreeMap::new());
+        assert!(!entry.is_fib_same(&same));
+
+        fib.delete_nexthop(&nh);
+        assert_eq!(fib.label(&nh), None);
+    }
 }
@@ -13,7 +13,7 @@ use std::cell::RefMut;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::net::Ipv4Addr;
-//use std::net::Ipv6Addr;
+use std::net::Ipv6Addr;
 
 use serde_json;
 use log::{debug, error};
@@ -21,36 +21,150 @@ use log::{debug, error};
 use rtable::prefix::*;
 use common::error::*;
 use common::nexthop::*;
+use common::mpls::*;
 
 use crate::core::mds::*;
 use super::master::ZebraMaster;
+use super::vrf::Vrf;
 
 /// Constants.
 const ZEBRA_ADMINISTRATIVE_DISTANCE_DEFAULT: u8 = 1;
 const ZEBRA_STATIC_ROUTE_TAG_DEFAULT: u32 = 0;
+const ZEBRA_NULL_INTERFACE: &str = "Null0";
 
 
+/// Address family specific part of static route config.
+pub trait StaticRouteFamily: Addressable {
+    /// Config path.
+    fn config_path() -> &'static str;
+
+    /// Address family name used in logs.
+    fn family_name() -> &'static str;
+
+    /// Parse destination prefix from path elements.
+    fn prefix_from_path(addr_str: &str, none_or_mask_str: Option<String>) -> Result<Prefix<Self>, CoreError>;
+
+    /// Add static route to RIB.
+    fn rib_add_static(master: &ZebraMaster, vrf: &Vrf, sr: Arc<StaticRoute<Self>>);
+
+    /// Delete static route from RIB.
+    fn rib_delete_static(master: &ZebraMaster, vrf: &Vrf, sr: Arc<StaticRoute<Self>>);
+}
+
+impl StaticRouteFamily for Ipv4Addr {
+    fn config_path() -> &'static str {
+        "/config/route_ipv4"
+    }
+
+    fn family_name() -> &'static str {
+        "IPv4"
+    }
+
+    fn prefix_from_path(addr_str: &str, none_or_mask_str: Option<String>) -> Result<Prefix<Ipv4Addr>, CoreError> {
+        let mask_str = match none_or_mask_str {
+            Some(mask_str) if mask_str.len() > 1 => mask_str,
+            _ => "/255.255.255.255".to_string(),
+        };
+
+        // Mask is either in dotted decimal or prefix length.
+        let prefix = if mask_str[1..].parse::<u8>().is_ok() {
+            Prefix::<Ipv4Addr>::from_str(&format!("{}{}", addr_str, mask_str)).ok()
+        } else {
+            // Trim leading "/" from mask_str.
+            prefix_ipv4_from(addr_str, &mask_str[1..]).ok()
+        };
+
+        match prefix {
+            Some(prefix) => Ok(prefix),
+            None => Err(CoreError::CommandExec(format!("Invalid address or mask {} {}", addr_str, mask_str))),
+        }
+    }
+
+    fn rib_add_static(master: &ZebraMaster, vrf: &Vrf, sr: Arc<StaticRoute<Ipv4Addr>>) {
+        master.rib_add_static_ipv4(vrf, sr);
+    }
+
+    fn rib_delete_static(master: &ZebraMaster, vrf: &Vrf, sr: Arc<StaticRoute<Ipv4Addr>>) {
+        master.rib_delete_static_ipv4(vrf, sr);
+    }
+}
+
+impl StaticRouteFamily for Ipv6Addr {
+    fn config_path() -> &'static str {
+        "/config/route_ipv6"
+    }
+
+    fn family_name() -> &'static str {
+        "IPv6"
+    }
+
+    fn prefix_from_path(addr_str: &str, none_or_len_str: Option<String>) -> Result<Prefix<Ipv6Addr>, CoreError> {
+        let len_str = match none_or_len_str {
+            Some(len_str) if len_str.len() > 1 => len_str,
+            _ => "/128".to_string(),
+        };
+
+        match Prefix::<Ipv6Addr>::from_str(&format!("{}{}", addr_str, len_str)) {
+            Ok(prefix) => Ok(prefix),
+            Err(_) => Err(CoreError::CommandExec(format!("Invalid address or prefix length {} {}", addr_str, len_str))),
+        }
+    }
+
+    fn rib_add_static(master: &ZebraMaster, vrf: &Vrf, sr: Arc<StaticRoute<Ipv6Addr>>) {
+        master.rib_add_static_ipv6(vrf, sr);
+    }
+
+    fn rib_delete_static(master: &ZebraMaster, vrf: &Vrf, sr: Arc<StaticRoute<Ipv6Addr>>) {
+        master.rib_delete_static_ipv6(vrf, sr);
+    }
+}
+
 /// IPv4 Static route configs.
-pub struct Ipv4StaticRoute {
+pub type Ipv4StaticRoute = StaticRouteConfig<Ipv4Addr>;
+
+/// IPv6 Static route configs.
+pub type Ipv6StaticRoute = StaticRouteConfig<Ipv6Addr>;
+
+/// Static route configs.
+pub struct StaticRouteConfig<T: StaticRouteFamily> {
 
     /// Zebra master.
     master: Rc<ZebraMaster>,
 
+    /// VRF name.
+    vrf: String,
+
     /// Config.
-    config: RefCell<BTreeMap<Prefix<Ipv4Addr>, Arc<StaticRoute<Ipv4Addr>>>>,
+    config: RefCell<BTreeMap<Prefix<T>, Arc<StaticRoute<T>>>>,
 }
 
-impl Ipv4StaticRoute {
+impl<T> StaticRouteConfig<T>
+where T: StaticRouteFamily
+{
     /// Constructor.
-    pub fn new(master: Rc<ZebraMaster>) -> Ipv4StaticRoute {
-        Ipv4StaticRoute {
+    pub fn new(master: Rc<ZebraMaster>, vrf: &str) -> StaticRouteConfig<T> {
+        StaticRouteConfig {
             master: master,
+            vrf: vrf.to_string(),
             config: RefCell::new(BTreeMap::new()),
         }
     }
 
+    /// Return VRF this config belongs to.
+    fn vrf(&self) -> Result<Rc<Vrf>, CoreError> {
+        match self.master.vrf_lookup_by_name(&self.vrf) {
+            Some(vrf) => Ok(vrf),
+            None => Err(CoreError::CommandExec(format!("No such VRF {}", self.vrf))),
+        }
+    }
+
+    /// Return true if no static route is configured.
+    pub fn is_empty(&self) -> bool {
+        self.config.borrow().values().all(|sr| sr.nexthops.borrow().is_empty())
+    }
+
     /// Lookup a static route by prefix.
-    pub fn lookup(&self, p: &Prefix<Ipv4Addr>) -> Option<Arc<StaticRoute<Ipv4Addr>>> {
+    pub fn lookup(&self, p: &Prefix<T>) -> Option<Arc<StaticRoute<T>>> {
         match self.config.borrow_mut().get(p) {
             Some(sr) => Some(sr.clone()),
             None => None,
@@ -58,7 +172,7 @@ impl Ipv4StaticRoute {
     }
 
     /// Add a static route config into the tree.
-    pub fn add(&self, p: Prefix<Ipv4Addr>, sr_new: Arc<StaticRoute<Ipv4Addr>>) -> Arc<StaticRoute<Ipv4Addr>> {
+    pub fn add(&self, p: Prefix<T>, sr_new: Arc<StaticRoute<T>>) -> Arc<StaticRoute<T>> {
         match self.lookup(&p) {
             Some(sr) => {
                 for (nh, info) in sr_new.nexthops.borrow_mut().drain() {
@@ -75,7 +189,7 @@ impl Ipv4StaticRoute {
     }
 
     /// Delete a static route config from the tree.
-    pub fn delete(&self, p: Prefix<Ipv4Addr>, sr_new: Arc<StaticRoute<Ipv4Addr>>) -> Arc<StaticRoute<Ipv4Addr>> {
+    pub fn delete(&self, p: Prefix<T>, sr_new: Arc<StaticRoute<T>>) -> Arc<StaticRoute<T>> {
         match self.lookup(&p) {
             Some(sr) => {
                 for (nh, _info) in sr_new.nexthops.borrow_mut().iter() {
@@ -87,94 +201,66 @@ impl Ipv4StaticRoute {
 
         sr_new
     }
-}
-
-impl MdsHandler for Ipv4StaticRoute {
 
-    /// Handle PUT method.
-    fn handle_put(&self, path: &str, params: Option<Box<String>>) -> Result<Option<String>, CoreError> {
-        // TBD: XXXX
-        let pat = "/config/route_ipv4";
+    /// Parse config path and JSON parameters into a static route.
+    fn parse(&self, path: &str, params: Option<Box<String>>) -> Result<(Prefix<T>, Arc<StaticRoute<T>>), CoreError> {
+        let pat = T::config_path();
         if !path.starts_with(pat) {
             return Err(CoreError::CommandExec(format!("Invalid path")));
         }
         let path = &path[pat.len()..];
 
-        match params {
-            Some(json_str) => {
-                debug!("Configuring an IPv4 static route");
-
-                match split_id_and_path(path) {
-                    Some((addr_str, none_or_mask_str)) => {
-                        // TODO: should handle error.
-                        let json: serde_json::Value = serde_json::from_str(&json_str).unwrap();
-                        let mask_str = match none_or_mask_str {
-                            Some(mask_str) => mask_str,
-                            None => "/255.255.255.255".to_string(),
-                        };
-
-                        // Trim leading "/" from mask_str.
-                        if let Ok(prefix) = prefix_ipv4_from(&addr_str, &mask_str[1..]) {
-                            let sr_new = Arc::new(StaticRoute::<Ipv4Addr>::from_json(&prefix, &json)?);
-                            let sr = self.add(prefix, sr_new);
-                            self.master.rib_add_static_ipv4(sr);
-                        } else {
-                            return Err(CoreError::CommandExec(format!("Invalid address or mask {} {}", addr_str, mask_str)))
-                        }
-                    },
-                    None => {
-                        return Err(CoreError::CommandExec(format!("Invalid path")));
-                    }
-                }
-            },
-            None => {
-                return Err(CoreError::CommandExec(format!("No parameters")));
+        let json_str = match params {
+            Some(json_str) => json_str,
+            None => return Err(CoreError::CommandExec(format!("No parameters"))),
+        };
+
+        match split_id_and_path(path) {
+            Some((addr_str, none_or_mask_str)) => {
+                let json: serde_json::Value = match serde_json::from_str(&json_str) {
+                    Ok(json) => json,
+                    Err(_) => return Err(CoreError::CommandExec(format!("Invalid JSON"))),
+                };
+                let prefix = T::prefix_from_path(&addr_str, none_or_mask_str)?;
+                let sr = Arc::new(StaticRoute::<T>::from_json(&prefix, &json)?);
+
+                Ok((prefix, sr))
             },
+            None => Err(CoreError::CommandExec(format!("Invalid path"))),
         }
+    }
+}
+
+impl<T> MdsHandler for StaticRouteConfig<T>
+where T: StaticRouteFamily
+{
+    /// Handle PUT method.
+    fn handle_put(&self, path: &str, params: Option<Box<String>>) -> Result<Option<String>, CoreError> {
+        let vrf = self.vrf()?;
+        let (prefix, sr_new) = self.parse(path, params)?;
+
+        debug!("Configuring an {} static route", T::family_name());
+
+        let sr = self.add(prefix, sr_new);
+        T::rib_add_static(&self.master, &vrf, sr);
 
         Ok(None)
     }
 
     /// Handle DELETE method.
     fn handle_delete(&self, path: &str, params: Option<Box<String>>) -> Result<Option<String>, CoreError> {
-        match params {
-            Some(json_str) => {
-                debug!("Unconfiguring an IPv4 static route");
-
-                match split_id_and_path(path) {
-                    Some((addr_str, none_or_mask_str)) => {
-                        // TODO: should handle error.
-                        let json: serde_json::Value = serde_json::from_str(&json_str).unwrap();
-                        let mask_str = match none_or_mask_str {
-                            Some(mask_str) => mask_str,
-                            None => "/255.255.255.255".to_string(),
-                        };
+        let vrf = self.vrf()?;
+        let (prefix, sr_new) = self.parse(path, params)?;
 
-                        // Trim leading "/" from mask_str.
-                        if let Ok(prefix) = prefix_ipv4_from(&addr_str, &mask_str[1..]) {
-                            let sr_new = Arc::new(StaticRoute::<Ipv4Addr>::from_json(&prefix, &json)?);
-                            let sr_new = self.delete(prefix.clone(), sr_new);
+        debug!("Unconfiguring an {} static route", T::family_name());
 
-                            self.master.rib_delete_static_ipv4(sr_new);
-                        } else {
-                            return Err(CoreError::CommandExec(format!("Invalid address or mask {} {}", addr_str, mask_str)))
-                        }
-                    },
-                    None => {
-                        return Err(CoreError::CommandExec(format!("Invalid path")));
-                    }
-                }
-            },
-            None => {
-                return Err(CoreError::CommandExec(format!("No parameters")));
-            },
-        }
+        let sr_new = self.delete(prefix, sr_new);
+        T::rib_delete_static(&self.master, &vrf, sr_new);
 
         Ok(None)
     }
 }
 
-
 /// Static route.
 pub struct StaticRoute<T: Addressable> {
 
@@ -219,18 +305,46 @@ where T: Addressable
                 let mut nexthop = None;
                 let mut distance = ZEBRA_ADMINISTRATIVE_DISTANCE_DEFAULT;
                 let mut tag = ZEBRA_STATIC_ROUTE_TAG_DEFAULT;
+                let mut labels = None;
 
                 if let Some(nh) = v_nh.get("nexthop") {
                     if nh.is_object() {
-                        if let Some(v) = nh.get("ipv4_address") {
-                            if let Some(address) = Nexthop::<T>::from_address_str(v.as_str().unwrap()) {
-                                nexthop = Some(address.clone());
+                        let mut address = None;
+                        let mut ifname = None;
+
+                        for key in &["ipv4_address", "ipv6_address"] {
+                            if let Some(v) = nh[*key].as_str() {
+                                if let Ok(v) = T::from_str(v) {
+                                    address = Some(v);
+                                }
                             }
                         }
 
-                        if let Some(_v) = nh.get("interface") {
-                            // TBD
+                        if let Some(v) = nh["interface"].as_str() {
+                            ifname = Some(v.to_string());
                         }
+
+                        nexthop = if nh["blackhole"].as_bool() == Some(true) {
+                            Some(Nexthop::<T>::Blackhole)
+                        } else if nh["reject"].as_bool() == Some(true) {
+                            Some(Nexthop::<T>::Reject)
+                        } else {
+                            match (address, ifname) {
+                                (_, Some(ref ifname)) if ifname.eq_ignore_ascii_case(ZEBRA_NULL_INTERFACE) => {
+                                    Some(Nexthop::<T>::Blackhole)
+                                },
+                                (Some(address), Some(ifname)) => {
+                                    Some(Nexthop::<T>::from_address_ifname(&address, &ifname))
+                                },
+                                (Some(address), None) => {
+                                    Some(Nexthop::<T>::from_address(&address))
+                                },
+                                (None, Some(ifname)) => {
+                                    Some(Nexthop::<T>::from_ifname(&ifname))
+                                },
+                                (None, None) => None,
+                            }
+                        };
                     }
                 }
 
@@ -242,8 +356,25 @@ where T: Addressable
                     tag = v as u32;
                 }
 
+                // Label stack to push, such as "100/200".
+                if let Some(v) = v_nh.get("label") {
+                    let label_str = match v.as_u64() {
+                        Some(v) => v.to_string(),
+                        None => v.as_str().unwrap_or("").to_string(),
+                    };
+
+                    match labels_from_str(&label_str) {
+                        Some(v) => labels = Some(v),
+                        None => return Err(CoreError::CommandExec(format!("Invalid label {}", label_str))),
+                    }
+                }
+
                 if let Some(nexthop) = nexthop {
-                    nexthops.insert(nexthop, StaticRouteInfo { distance, tag });
+                    if labels.is_some() && nexthop.is_null() {
+                        return Err(CoreError::CommandExec("Label cannot be set to Null0".to_string()))
+                    }
+
+                    nexthops.insert(nexthop, StaticRouteInfo { distance, tag, labels });
                 }
             }
         } else {
@@ -305,6 +436,9 @@ pub struct StaticRouteInfo {
 
     /// Route tag,
     tag: u32,
+
+    /// Label stack to push.
+    labels: Option<Vec<u32>>,
 }
 
 impl StaticRouteInfo {
@@ -313,6 +447,16 @@ impl StaticRouteInfo {
     pub fn distance(&self) -> u8 {
         self.distance
     }
+
+    /// Return tag.
+    pub fn tag(&self) -> u32 {
+        self.tag
+    }
+
+    /// Return label stack.
+    pub fn labels(&self) -> Option<&Vec<u32>> {
+        self.labels.as_ref()
+    }
 }
 
 ///
@@ -321,6 +465,7 @@ impl StaticRouteInfo {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::super::rib::Rib;
 
     #[test]
     pub fn test_ipv4_static_route_cmp() {
@@ -334,7 +479,7 @@ mod tests {
 
         let addr = "1.1.1.1".parse().unwrap();
         let nh = Nexthop::<Ipv4Addr>::from_address(&addr);
-        let si = StaticRouteInfo { distance: 1, tag: 0 };
+        let si = StaticRouteInfo { distance: 1, tag: 0, labels: None };
         let mut m: HashMap<Nexthop<Ipv4Addr>, StaticRouteInfo> = HashMap::new();
         m.insert(nh, si);
 
@@ -346,4 +491,97 @@ mod tests {
         assert_eq!(s1 < s3, true);
         assert_eq!(s2 < s3, true);
     }
+
+    #[test]
+    pub fn test_ipv6_static_route_from_json() {
+        let p = Prefix::<Ipv6Addr>::from_str("2001:db8::/32").unwrap();
+        let json: serde_json::Value = serde_json::from_str(
+            r#"{"nexthops":[{"nexthop":{"ipv6_address":"fe80::1"},"distance":10},
+                            {"nexthop":{"ipv4_address":"1.1.1.1"}}]}"#).unwrap();
+
+        let sr = StaticRoute::<Ipv6Addr>::from_json(&p, &json).unwrap();
+        let addr = "fe80::1".parse().unwrap();
+        let nh = Nexthop::<Ipv6Addr>::from_address(&addr);
+
+        // IPv4 nexthop should be ignored.
+        assert_eq!(sr.nexthops().len(), 1);
+        assert_eq!(sr.nexthops().get(&nh).unwrap().distance(), 10);
+    }
+
+    #[test]
+    pub fn test_static_route_interface_nexthops() {
+        let p = Prefix::<Ipv4Addr>::from_str("10.0.0.0/8").unwrap();
+        let json: serde_json::Value = serde_json::from_str(
+            r#"{"nexthops":[{"nexthop":{"interface":"eth0"}},
+                            {"nexthop":{"ipv4_address":"1.1.1.1","interface":"eth1"}},
+                            {"nexthop":{"interface":"null0"},"distance":250},
+                            {"nexthop":{"reject":true},"distance":254}]}"#).unwrap();
+
+        let sr = StaticRoute::<Ipv4Addr>::from_json(&p, &json).unwrap();
+        let addr = "1.1.1.1".parse().unwrap();
+
+        assert_eq!(sr.nexthops().len(), 4);
+        assert!(sr.nexthops().contains_key(&Nexthop::<Ipv4Addr>::from_ifname("eth0")));
+        assert!(sr.nexthops().contains_key(&Nexthop::<Ipv4Addr>::from_address_ifname(&addr, "eth1")));
+        assert_eq!(sr.nexthops().get(&Nexthop::<Ipv4Addr>::Blackhole).unwrap().distance(), 250);
+        assert_eq!(sr.nexthops().get(&Nexthop::<Ipv4Addr>::Reject).unwrap().distance(), 254);
+    }
+
+    #[test]
+    pub fn test_static_route_prefix_from_path() {
+        let p = Ipv4Addr::prefix_from_path("10.0.0.0", Some("/255.0.0.0".to_string())).unwrap();
+        assert_eq!(p, Prefix::<Ipv4Addr>::from_str("10.0.0.0/8").unwrap());
+        let p = Ipv4Addr::prefix_from_path("10.0.0.0", Some("/8".to_string())).unwrap();
+        assert_eq!(p, Prefix::<Ipv4Addr>::from_str("10.0.0.0/8").unwrap());
+        let host = Ipv4Addr::prefix_from_path("10.0.0.1", None).unwrap();
+        assert_eq!(host, Prefix::<Ipv4Addr>::from_str("10.0.0.1/32").unwrap());
+        assert!(Ipv4Addr::prefix_from_path("10.0.0.0", Some("/33".to_string())).is_err());
+
+        let p6 = Ipv6Addr::prefix_from_path("2001:db8::", Some("/32".to_string())).unwrap();
+        assert_eq!(p6, Prefix::<Ipv6Addr>::from_str("2001:db8::/32").unwrap());
+
+        // Nexthops generated by "ip route A.B.C.D/M (IFNAME|null0|blackhole)".
+        for (params, nh) in &[(r#"{"nexthops":[{"nexthop":{"interface":"eth0"}}]}"#, Nexthop::<Ipv4Addr>::from_ifname("eth0")),
+                              (r#"{"nexthops":[{"nexthop":{"interface":"null0"}}]}"#, Nexthop::<Ipv4Addr>::Blackhole),
+                              (r#"{"nexthops":[{"nexthop":{"blackhole":true}}]}"#, Nexthop::<Ipv4Addr>::Blackhole)] {
+            let json: serde_json::Value = serde_json::from_str(params).unwrap();
+            let sr = StaticRoute::<Ipv4Addr>::from_json(&p, &json).unwrap();
+            assert!(sr.nexthops().contains_key(nh));
+        }
+    }
+
+    #[test]
+    pub fn test_static_route_rib_tag() {
+        let p = Prefix::<Ipv4Addr>::from_str("10.0.0.0/8").unwrap();
+        let json: serde_json::Value = serde_json::from_str(
+            r#"{"nexthops":[{"nexthop":{"ipv4_address":"1.1.1.1"},"tag":100},
+                            {"nexthop":{"ipv4_address":"2.2.2.2"},"distance":20}]}"#).unwrap();
+
+        let sr = StaticRoute::<Ipv4Addr>::from_json(&p, &json).unwrap();
+        let map = Rib::<Ipv4Addr>::from_static_route(Arc::new(sr));
+
+        assert_eq!(map.get(&1).unwrap().tag(), 100);
+        assert_eq!(map.get(&20).unwrap().tag(), 0);
+    }
+
+    #[test]
+    pub fn test_static_route_labels() {
+        let p = Prefix::<Ipv4Addr>::from_str("10.0.0.0/8").unwrap();
+        let json: serde_json::Value = serde_json::from_str(
+            r#"{"nexthops":[{"nexthop":{"ipv4_address":"1.1.1.1"},"label":"100/200"},
+                            {"nexthop":{"ipv4_address":"2.2.2.2"},"label":300}]}"#).unwrap();
+
+        let sr = StaticRoute::<Ipv4Addr>::from_json(&p, &json).unwrap();
+        let nh1 = Nexthop::<Ipv4Addr>::from_address(&"1.1.1.1".parse().unwrap());
+        let nh2 = Nexthop::<Ipv4Addr>::from_address(&"2.2.2.2".parse().unwrap());
+        let map = Rib::<Ipv4Addr>::from_static_route(Arc::new(sr));
+        let rib = map.get(&1).unwrap();
+
+        assert_eq!(rib.label(&nh1), Some(LabelOp::Push(vec![100, 200])));
+        assert_eq!(rib.label(&nh2), Some(LabelOp::Push(vec![300])));
+
+        let json: serde_json::Value = serde_json::from_str(
+            r#"{"nexthops":[{"nexthop":{"ipv4_address":"1.1.1.1"},"label":"1048576"}]}"#).unwrap();
+        assert!(StaticRoute::<Ipv4Addr>::from_json(&p, &json).is_err());
+    }
 }
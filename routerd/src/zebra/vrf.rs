@@ -0,0 +1,369 @@
+//
+// ReZe.Rs - Router Daemon
+//   Copyright (C) 2018-2020 Toshiaki Takada
+//
+// Zebra - VRF, bound to Linux VRF device and kernel table.
+//
+
+use std::rc::Rc;
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::cell::RefMut;
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use log::debug;
+use serde::Serialize;
+use serde::Serializer;
+use serde::ser::SerializeStruct;
+use serde_json;
+
+use common::error::*;
+
+use crate::core::mds::*;
+use super::master::ZebraMaster;
+use super::rib::*;
+use super::static_route::*;
+
+/// Default VRF name.
+pub const VRF_DEFAULT_NAME: &str = "default";
+
+/// Kernel table for default VRF, RT_TABLE_MAIN.
+pub const VRF_DEFAULT_TABLE: u32 = 254;
+
+/// VRF.
+pub struct Vrf {
+
+    /// Name.
+    name: String,
+
+    /// Kernel table ID.
+    table_id: u32,
+
+    /// VRF device index, if the device exists in kernel.
+    ifindex: Cell<Option<i32>>,
+
+    /// Configured by user, otherwise learned from kernel.
+    configured: Cell<bool>,
+
+    /// IPv4 RIB.
+    rib_ipv4: RefCell<RibTable<Ipv4Addr>>,
+
+    /// IPv6 RIB.
+    rib_ipv6: RefCell<RibTable<Ipv6Addr>>,
+}
+
+impl Vrf {
+
+    /// Constructor.
+    pub fn new(name: &str, table_id: u32) -> Vrf {
+        Vrf {
+            name: name.to_string(),
+            table_id: table_id,
+            ifindex: Cell::new(None),
+            configured: Cell::new(false),
+            rib_ipv4: RefCell::new(RibTable::<Ipv4Addr>::new()),
+            rib_ipv6: RefCell::new(RibTable::<Ipv6Addr>::new()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn table_id(&self) -> u32 {
+        self.table_id
+    }
+
+    pub fn ifindex(&self) -> Option<i32> {
+        self.ifindex.get()
+    }
+
+    pub fn set_ifindex(&self, ifindex: Option<i32>) {
+        self.ifindex.set(ifindex);
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.configured.get()
+    }
+
+    pub fn set_configured(&self, configured: bool) {
+        self.configured.set(configured);
+    }
+
+    /// Return true if this is default VRF.
+    pub fn is_default(&self) -> bool {
+        self.table_id == VRF_DEFAULT_TABLE
+    }
+
+    pub fn rib_ipv4(&self) -> RefMut<RibTable<Ipv4Addr>> {
+        self.rib_ipv4.borrow_mut()
+    }
+
+    pub fn rib_ipv6(&self) -> RefMut<RibTable<Ipv6Addr>> {
+        self.rib_ipv6.borrow_mut()
+    }
+}
+
+/// Serializer for Vrf.
+impl Serialize for Vrf {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer
+    {
+        let mut s = serializer.serialize_struct("Vrf", 6)?;
+        s.serialize_field("name", &self.name)?;
+        s.serialize_field("table", &self.table_id)?;
+        s.serialize_field("ifindex", &self.ifindex.get())?;
+        s.serialize_field("configured", &self.configured.get())?;
+        s.serialize_field("routes_ipv4", &self.rib_ipv4.borrow().count())?;
+        s.serialize_field("routes_ipv6", &self.rib_ipv6.borrow().count())?;
+        s.end()
+    }
+}
+
+/// VRF configs, and static routes per VRF.
+pub struct VrfConfig {
+
+    /// Zebra master.
+    master: Rc<ZebraMaster>,
+
+    /// IPv4 static route configs per VRF name.
+    routes_ipv4: RefCell<HashMap<String, Rc<Ipv4StaticRoute>>>,
+
+    /// IPv6 static route configs per VRF name.
+    routes_ipv6: RefCell<HashMap<String, Rc<Ipv6StaticRoute>>>,
+}
+
+impl VrfConfig {
+
+    /// Constructor, with static route configs for default VRF.
+    pub fn new(master: Rc<ZebraMaster>, routes_ipv4: Rc<Ipv4StaticRoute>, routes_ipv6: Rc<Ipv6StaticRoute>) -> VrfConfig {
+        let config = VrfConfig {
+            master: master,
+            routes_ipv4: RefCell::new(HashMap::new()),
+            routes_ipv6: RefCell::new(HashMap::new()),
+        };
+
+        config.routes_ipv4.borrow_mut().insert(VRF_DEFAULT_NAME.to_string(), routes_ipv4);
+        config.routes_ipv6.borrow_mut().insert(VRF_DEFAULT_NAME.to_string(), routes_ipv6);
+        config
+    }
+
+    /// Return static route handler for the VRF and the path, create one if needed.
+    fn route_handler(&self, name: &str, path: &str) -> Result<Rc<dyn MdsHandler>, CoreError> {
+        if path.starts_with("/route_ipv4") {
+            let mut routes = self.routes_ipv4.borrow_mut();
+            let handler = routes.entry(name.to_string())
+                .or_insert_with(|| Rc::new(Ipv4StaticRoute::new(self.master.clone(), name)));
+
+            Ok(handler.clone())
+        } else if path.starts_with("/route_ipv6") {
+            let mut routes = self.routes_ipv6.borrow_mut();
+            let handler = routes.entry(name.to_string())
+                .or_insert_with(|| Rc::new(Ipv6StaticRoute::new(self.master.clone(), name)));
+
+            Ok(handler.clone())
+        } else {
+            Err(CoreError::CommandExec(format!("Invalid path")))
+        }
+    }
+
+    /// Return true if any static route is configured in the VRF.
+    fn has_routes(&self, name: &str) -> bool {
+        let ipv4 = self.routes_ipv4.borrow().get(name).map_or(false, |routes| !routes.is_empty());
+        let ipv6 = self.routes_ipv6.borrow().get(name).map_or(false, |routes| !routes.is_empty());
+
+        ipv4 || ipv6
+    }
+
+    /// Create or delete VRF.
+    fn apply(&self, name: &str, params: Option<Box<String>>, set: bool) -> Result<Option<String>, CoreError> {
+        if set {
+            let table_id = match params {
+                Some(json_str) => {
+                    let json: serde_json::Value = match serde_json::from_str(&json_str) {
+                        Ok(json) => json,
+                        Err(_) => return Err(CoreError::CommandExec(format!("Invalid JSON"))),
+                    };
+
+                    // Table ID could be given as string from CLI.
+                    match json.get("table") {
+                        Some(table) => match table.as_u64().or(table.as_str().and_then(|t| t.parse().ok())) {
+                            Some(table_id) if table_id > 0 && table_id <= std::u32::MAX as u64 => Some(table_id as u32),
+                            _ => return Err(CoreError::CommandExec(format!("Invalid table"))),
+                        },
+                        None => None,
+                    }
+                },
+                None => None,
+            };
+
+            debug!("Configuring VRF {} table {:?}", name, table_id);
+
+            self.master.vrf_add(name, table_id)?;
+        } else {
+            if self.has_routes(name) {
+                return Err(CoreError::CommandExec(format!("VRF {} has static routes", name)));
+            }
+
+            debug!("Unconfiguring VRF {}", name);
+
+            self.master.vrf_delete(name)?;
+            self.routes_ipv4.borrow_mut().remove(name);
+            self.routes_ipv6.borrow_mut().remove(name);
+        }
+
+        Ok(None)
+    }
+
+    /// Handle PUT and DELETE.
+    ///   /config/vrfs/NAME creates or deletes VRF, and /config/vrfs/NAME/route_ipv4/...
+    ///   or /config/vrfs/NAME/route_ipv6/... configures static route in the VRF.
+    fn handle(&self, path: &str, params: Option<Box<String>>, set: bool) -> Result<Option<String>, CoreError> {
+        let pat = "/config/vrfs";
+        if !path.starts_with(pat) {
+            return Err(CoreError::CommandExec(format!("Invalid path")));
+        }
+
+        let (name, path) = match split_id_and_path(&path[pat.len()..]) {
+            Some((name, path)) => (name, path.unwrap_or(String::new())),
+            None => return Err(CoreError::CommandExec(format!("Invalid path"))),
+        };
+
+        if path.trim_matches('/').len() == 0 {
+            return self.apply(&name, params, set);
+        }
+
+        if self.master.vrf_lookup_by_name(&name).is_none() {
+            return Err(CoreError::CommandExec(format!("No such VRF {}", name)));
+        }
+
+        let handler = self.route_handler(&name, &path)?;
+        let path = format!("/config{}", path);
+
+        if set {
+            handler.handle_put(&path, params)
+        } else {
+            handler.handle_delete(&path, params)
+        }
+    }
+}
+
+impl MdsHandler for VrfConfig {
+
+    /// Handle PUT method.
+    fn handle_put(&self, path: &str, params: Option<Box<String>>) -> Result<Option<String>, CoreError> {
+        self.handle(path, params, true)
+    }
+
+    /// Handle DELETE method.
+    fn handle_delete(&self, path: &str, params: Option<Box<String>>) -> Result<Option<String>, CoreError> {
+        self.handle(path, params, false)
+    }
+}
+
+/// Show VRFs, and routes per VRF.
+pub struct ShowVrf {
+
+    /// Zebra master.
+    master: Rc<ZebraMaster>,
+
+    /// IPv4 RIB exec handler.
+    route_ipv4: Rc<RibTableExec<Ipv4Addr>>,
+
+    /// IPv6 RIB exec handler.
+    route_ipv6: Rc<RibTableExec<Ipv6Addr>>,
+}
+
+impl ShowVrf {
+
+    /// Constructor.
+    pub fn new(master: Rc<ZebraMaster>, route_ipv4: Rc<RibTableExec<Ipv4Addr>>,
+               route_ipv6: Rc<RibTableExec<Ipv6Addr>>) -> ShowVrf {
+        ShowVrf {
+            master: master,
+            route_ipv4: route_ipv4,
+            route_ipv6: route_ipv6,
+        }
+    }
+
+    /// Return VRF with names of interfaces bound to it.
+    fn vrf_json(&self, vrf: &Vrf) -> serde_json::Value {
+        let mut json = serde_json::to_value(vrf).unwrap();
+        let interfaces: Vec<String> = self.master.links().iter()
+            .filter(|link| self.master.link_vrf(link).table_id() == vrf.table_id())
+            .map(|link| link.name())
+            .collect();
+
+        json["interfaces"] = serde_json::json!(interfaces);
+        json
+    }
+}
+
+impl MdsHandler for ShowVrf {
+
+    /// Handle GET method.
+    ///   /exec/show/vrfs returns all VRFs, /exec/show/vrfs/NAME returns one VRF,
+    ///   and /exec/show/vrfs/NAME/route_ipv4 or route_ipv6 returns RIB in the VRF.
+    fn handle_get(&self, path: &str, params: Option<Box<String>>) -> Result<Option<String>, CoreError> {
+        let pat = "/exec/show/vrfs";
+        if !path.starts_with(pat) {
+            return Err(CoreError::CommandExec(format!("Invalid path")));
+        }
+
+        let s = match split_id_and_path(&path[pat.len()..]) {
+            Some((name, sub)) => {
+                let vrf = match self.master.vrf_lookup_by_name(&name) {
+                    Some(vrf) => vrf,
+                    None => return Err(CoreError::CommandExec(format!("No such VRF {}", name))),
+                };
+
+                match sub {
+                    Some(ref sub) if sub.starts_with("/route_ipv4") => return self.route_ipv4.handle_get(path, params),
+                    Some(ref sub) if sub.starts_with("/route_ipv6") => return self.route_ipv6.handle_get(path, params),
+                    Some(ref sub) if sub.trim_matches('/').len() > 0 => return Err(CoreError::CommandExec(format!("Invalid path"))),
+                    _ => self.vrf_json(&vrf).to_string(),
+                }
+            },
+            None => {
+                let vrfs: Vec<serde_json::Value> = self.master.vrfs().iter().map(|vrf| self.vrf_json(vrf)).collect();
+
+                serde_json::to_string(&vrfs).unwrap()
+            },
+        };
+
+        Ok(Some(s))
+    }
+}
+
+///
+/// Unit tests for VRF.
+///
+#[cfg(test)]
+mod tests {
+    use rtable::prefix::*;
+    use common::nexthop::*;
+    use super::*;
+
+    #[test]
+    pub fn test_vrf_rib() {
+        let vrf = Vrf::new("red", 100);
+        let p = Prefix::<Ipv4Addr>::from_str("10.0.0.0/24").unwrap();
+        let rib = Rib::<Ipv4Addr>::new(RibType::Connected, 0);
+
+        rib.add_nexthop(Nexthop::<Ipv4Addr>::from_ifname("eth1"));
+        vrf.rib_ipv4().add(&p, rib);
+
+        assert!(!vrf.is_default());
+        assert_eq!(vrf.rib_ipv4().count(), 1);
+        assert_eq!(vrf.rib_ipv6().count(), 0);
+
+        vrf.set_ifindex(Some(10));
+        let json: serde_json::Value = serde_json::to_value(&vrf).unwrap();
+        assert_eq!(json["name"], "red");
+        assert_eq!(json["table"], 100);
+        assert_eq!(json["ifindex"], 10);
+        assert_eq!(json["configured"], false);
+        assert_eq!(json["routes_ipv4"], 1);
+    }
+}